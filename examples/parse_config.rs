@@ -1,6 +1,6 @@
-/// Example: Parse a deck configuration file
-///
-/// Usage: cargo run --example parse_config [config_file]
+//! Example: Parse a deck configuration file
+//!
+//! Usage: cargo run --example parse_config [config_file]
 
 use deck::DeckConfig;
 use std::{env, fs};
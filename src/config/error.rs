@@ -0,0 +1,153 @@
+use std::fmt;
+
+/// Errors that can occur while loading a configuration
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigError {
+    /// The configuration file could not be read
+    Io {
+        path: String,
+        message: String,
+    },
+
+    /// The configuration is not valid JSON or does not match the schema
+    Parse {
+        message: String,
+    },
+
+    /// A route references middleware that is not defined
+    UnknownMiddleware {
+        route: String,
+        middleware: String,
+    },
+
+    /// A schema references a named schema that is not defined
+    UnresolvedSchemaRef {
+        location: String,
+        reference: String,
+    },
+
+    /// A `${VAR}` reference names an environment variable that is not set
+    UnsetEnvVar {
+        variable: String,
+    },
+
+    /// The configured database could not be opened
+    Database {
+        message: String,
+    },
+}
+
+impl ConfigError {
+    /// Create an Io error
+    pub fn io(path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::Io {
+            path: path.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Create a Parse error
+    pub fn parse(message: impl Into<String>) -> Self {
+        Self::Parse {
+            message: message.into(),
+        }
+    }
+
+    /// Create a Database error
+    pub fn database(message: impl Into<String>) -> Self {
+        Self::Database {
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, message } => {
+                write!(f, "Failed to read config file '{}': {}", path, message)
+            }
+            ConfigError::Parse { message } => {
+                write!(f, "Failed to parse config: {}", message)
+            }
+            ConfigError::UnknownMiddleware { route, middleware } => {
+                write!(f, "Route '{}' references unknown middleware '{}'", route, middleware)
+            }
+            ConfigError::UnresolvedSchemaRef { location, reference } => {
+                write!(f, "Unresolved schema reference '{}' in {}", reference, location)
+            }
+            ConfigError::UnsetEnvVar { variable } => {
+                write!(f, "Environment variable '{}' is not set", variable)
+            }
+            ConfigError::Database { message } => {
+                write!(f, "Failed to open database: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_error() {
+        let err = ConfigError::io("app.json", "No such file or directory");
+        assert_eq!(
+            err.to_string(),
+            "Failed to read config file 'app.json': No such file or directory"
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let err = ConfigError::parse("expected value at line 1 column 1");
+        assert_eq!(
+            err.to_string(),
+            "Failed to parse config: expected value at line 1 column 1"
+        );
+    }
+
+    #[test]
+    fn test_unknown_middleware_error() {
+        let err = ConfigError::UnknownMiddleware {
+            route: "POST /api/posts".to_string(),
+            middleware: "authenticate".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Route 'POST /api/posts' references unknown middleware 'authenticate'"
+        );
+    }
+
+    #[test]
+    fn test_unresolved_schema_ref_error() {
+        let err = ConfigError::UnresolvedSchemaRef {
+            location: "route 'POST /api/posts'".to_string(),
+            reference: "#/schemas/createPost".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Unresolved schema reference '#/schemas/createPost' in route 'POST /api/posts'"
+        );
+    }
+
+    #[test]
+    fn test_unset_env_var_error() {
+        let err = ConfigError::UnsetEnvVar {
+            variable: "DATABASE_URL".to_string(),
+        };
+        assert_eq!(err.to_string(), "Environment variable 'DATABASE_URL' is not set");
+    }
+
+    #[test]
+    fn test_database_error() {
+        let err = ConfigError::database("The 'sqlite' database driver requires a 'url'");
+        assert_eq!(
+            err.to_string(),
+            "Failed to open database: The 'sqlite' database driver requires a 'url'"
+        );
+    }
+}
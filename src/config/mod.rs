@@ -1,15 +1,19 @@
-/// Configuration types for deck
-///
-/// This module contains types for parsing and representing
-/// the declarative JSON configuration format.
+//! Configuration types for deck
+//!
+//! This module contains types for parsing and representing
+//! the declarative JSON configuration format.
 
 mod database;
+mod error;
 mod middleware;
 mod route;
 mod root;
 mod template;
 
-pub use database::{DatabaseConfig, DatabaseSchema, FieldDefinition, FieldType, IndexDefinition};
+pub use database::{
+    DatabaseConfig, DatabaseDriver, DatabaseSchema, FieldDefinition, FieldType, IndexDefinition, PoolConfig,
+};
+pub use error::ConfigError;
 pub use middleware::Middleware;
 pub use route::{HttpMethod, Response, Route};
 pub use root::DeckConfig;
@@ -1,7 +1,9 @@
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::collections::HashMap;
+use std::path::Path;
 
-use super::{DatabaseConfig, Middleware, Route, TemplateConfig};
+use super::{ConfigError, DatabaseConfig, Middleware, Route, TemplateConfig};
 
 /// Top-level configuration for a deck application
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,9 +27,193 @@ pub struct DeckConfig {
 
     /// Reusable validation schemas (JSON Schema format)
     #[serde(default)]
-    pub schemas: HashMap<String, serde_json::Value>,
+    pub schemas: HashMap<String, Value>,
 
     /// Error handlers (TBD in design)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error_handlers: Option<serde_json::Value>,
 }
+
+impl DeckConfig {
+    /// Load a configuration from a JSON file on disk
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| ConfigError::io(path.display().to_string(), e.to_string()))?;
+        Self::from_json(&contents)
+    }
+
+    /// Parse and validate a configuration from a JSON string
+    pub fn from_json(json: &str) -> Result<Self, ConfigError> {
+        let config: Self = serde_json::from_str(json).map_err(|e| ConfigError::parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check references between configuration sections
+    ///
+    /// - Every middleware name used by a route must be defined in `middleware`
+    /// - Every `#/schemas/...` reference in a `$validate` schema or in a named
+    ///   schema must resolve against `schemas`
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        for route in &self.routes {
+            let label = format!("{} {}", route.method.as_str(), route.path);
+            for name in &route.middleware {
+                if !self.middleware.contains_key(name) {
+                    return Err(ConfigError::UnknownMiddleware {
+                        route: label,
+                        middleware: name.clone(),
+                    });
+                }
+            }
+
+            let route_json = serde_json::to_value(route).map_err(|e| ConfigError::parse(e.to_string()))?;
+            self.check_validate_refs(&route_json, &format!("route '{}'", label))?;
+        }
+
+        for (name, middleware) in &self.middleware {
+            let middleware_json =
+                serde_json::to_value(middleware).map_err(|e| ConfigError::parse(e.to_string()))?;
+            self.check_validate_refs(&middleware_json, &format!("middleware '{}'", name))?;
+        }
+
+        for (name, schema) in &self.schemas {
+            self.check_schema_refs(schema, &format!("schema '{}'", name))?;
+        }
+        Ok(())
+    }
+
+    /// Check the schema of every `$validate` operator found in a pipeline value
+    fn check_validate_refs(&self, value: &Value, location: &str) -> Result<(), ConfigError> {
+        match value {
+            Value::Object(obj) => {
+                if let Some(schema) = obj.get("$validate").and_then(|v| v.get("schema")) {
+                    self.check_schema_refs(schema, location)?;
+                }
+                obj.values().try_for_each(|v| self.check_validate_refs(v, location))
+            }
+            Value::Array(items) => items.iter().try_for_each(|v| self.check_validate_refs(v, location)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that every `#/schemas/...` `$ref` inside a schema resolves
+    fn check_schema_refs(&self, schema: &Value, location: &str) -> Result<(), ConfigError> {
+        match schema {
+            Value::Object(obj) => {
+                if let Some(Value::String(reference)) = obj.get("$ref")
+                    && let Some(pointer) = reference.strip_prefix("#/schemas/")
+                    && !self.resolves_schema_pointer(pointer)
+                {
+                    return Err(ConfigError::UnresolvedSchemaRef {
+                        location: location.to_string(),
+                        reference: reference.clone(),
+                    });
+                }
+                obj.values().try_for_each(|v| self.check_schema_refs(v, location))
+            }
+            Value::Array(items) => items.iter().try_for_each(|v| self.check_schema_refs(v, location)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Resolve the JSON pointer following `#/schemas/` against the named schemas
+    fn resolves_schema_pointer(&self, pointer: &str) -> bool {
+        let (name, rest) = match pointer.split_once('/') {
+            Some((name, rest)) => (name, Some(rest)),
+            None => (pointer, None),
+        };
+        let name = name.replace("~1", "/").replace("~0", "~");
+        match (self.schemas.get(&name), rest) {
+            (Some(_), None) => true,
+            (Some(schema), Some(rest)) => schema.pointer(&format!("/{}", rest)).is_some(),
+            (None, _) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_json_accepts_known_middleware() {
+        let config = DeckConfig::from_json(r#"{
+            "middleware": {"auth": {"pipeline": []}},
+            "routes": [{"path": "/", "method": "GET", "middleware": ["auth"], "response": {"status": 204, "body": null}}]
+        }"#);
+        assert!(config.is_ok());
+    }
+
+    #[test]
+    fn test_from_json_rejects_unknown_middleware() {
+        let result = DeckConfig::from_json(r#"{
+            "routes": [{"path": "/posts", "method": "POST", "middleware": ["auth"], "response": {"status": 201, "body": null}}]
+        }"#);
+        assert_eq!(
+            result.unwrap_err(),
+            ConfigError::UnknownMiddleware {
+                route: "POST /posts".to_string(),
+                middleware: "auth".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_from_json_invalid_json() {
+        let result = DeckConfig::from_json("{");
+        assert!(matches!(result, Err(ConfigError::Parse { .. })));
+    }
+
+    #[test]
+    fn test_from_json_resolves_schema_refs() {
+        let config = DeckConfig::from_json(r##"{
+            "schemas": {
+                "address": {"type": "object"},
+                "createUser": {"type": "object", "properties": {"address": {"$ref": "#/schemas/address"}}}
+            },
+            "routes": [{
+                "path": "/users",
+                "method": "POST",
+                "pipeline": [{"value": {"$validate": {"data": {"$get": "body"}, "schema": {"$ref": "#/schemas/createUser"}}}}],
+                "response": {"status": 201, "body": null}
+            }]
+        }"##);
+        assert!(config.is_ok());
+    }
+
+    #[test]
+    fn test_from_json_rejects_unresolved_route_schema_ref() {
+        let result = DeckConfig::from_json(r##"{
+            "routes": [{
+                "path": "/users",
+                "method": "POST",
+                "pipeline": [{"value": {"$validate": {"data": {"$get": "body"}, "schema": {"$ref": "#/schemas/createUser"}}}}],
+                "response": {"status": 201, "body": null}
+            }]
+        }"##);
+        assert_eq!(
+            result.unwrap_err(),
+            ConfigError::UnresolvedSchemaRef {
+                location: "route 'POST /users'".to_string(),
+                reference: "#/schemas/createUser".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_from_json_rejects_unresolved_cross_schema_ref() {
+        let result = DeckConfig::from_json(r##"{
+            "schemas": {
+                "createUser": {"type": "object", "properties": {"address": {"$ref": "#/schemas/address"}}}
+            }
+        }"##);
+        assert_eq!(
+            result.unwrap_err(),
+            ConfigError::UnresolvedSchemaRef {
+                location: "schema 'createUser'".to_string(),
+                reference: "#/schemas/address".to_string(),
+            }
+        );
+    }
+}
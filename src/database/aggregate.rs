@@ -0,0 +1,572 @@
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::ser::{SerializeMap, SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+use std::cmp::Ordering;
+
+use super::{Filter, SortSpec, path, sort};
+use crate::executor::math;
+use crate::operators::OperatorValue;
+use crate::pipeline::ExecutionError;
+
+/// An aggregation pipeline over the documents of a collection
+///
+/// Written as a JSON array of stages, each an object with a single
+/// `$`-prefixed key. Every stage works on the output of the previous one:
+///
+/// ```json
+/// [
+///   {"$match": {"status": "published"}},
+///   {"$group": {
+///     "by": "authorId",
+///     "aggregates": {"count": {"$count": null}, "avgViews": {"$avg": "views"}}
+///   }},
+///   {"$sort": {"count": "desc"}},
+///   {"$limit": 10}
+/// ]
+/// ```
+///
+/// `V` is the type of `$match` condition operands, as with `Filter`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregation<V = Value> {
+    /// Stages in the order they run
+    pub stages: Vec<Stage<V>>,
+}
+
+/// One stage of an aggregation pipeline
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stage<V = Value> {
+    /// `$match` - keep the documents matching a filter
+    Match(Filter<V>),
+    /// `$group` - replace the documents with one summary document per group
+    Group(Group),
+    /// `$sort` - stable-sort the documents
+    Sort(SortSpec),
+    /// `$limit` - keep at most this many documents
+    Limit(u32),
+}
+
+/// A `$group` stage
+///
+/// Documents with equal values (compared like `sort::compare_values`) at
+/// every `by` field form a group; a missing field groups like `null`. Each
+/// group becomes a document holding its `by` values under their own paths
+/// plus one field per aggregate. Groups are output in the order of their
+/// first document. Without `by` fields every document is in one group, and
+/// no documents give no groups.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    /// Dot-paths of the fields identifying a group
+    pub by: Vec<String>,
+    /// Output fields and their accumulators, in output order
+    pub aggregates: Vec<(String, Accumulator)>,
+}
+
+/// How an aggregate is computed over the documents of a group
+///
+/// Only values present and not `null` at the field are considered; `$sum`
+/// and `$avg` also skip values that are not numbers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Accumulator {
+    /// `$count` - the number of documents
+    Count,
+    /// `$sum` - the sum of the numbers (0 if there are none)
+    Sum(String),
+    /// `$avg` - the mean of the numbers (`null` if there are none)
+    Avg(String),
+    /// `$min` - the smallest value (`null` if there are none)
+    Min(String),
+    /// `$max` - the largest value (`null` if there are none)
+    Max(String),
+}
+
+impl<V> Aggregation<V> {
+    /// Create an aggregation from stages in order
+    pub fn new(stages: Vec<Stage<V>>) -> Self {
+        Self { stages }
+    }
+
+    /// Every `$match` operand, in the order `try_map` visits them
+    pub fn operands(&self) -> Vec<&V> {
+        self.stages
+            .iter()
+            .flat_map(|stage| match stage {
+                Stage::Match(filter) => filter.operands(),
+                _ => Vec::new(),
+            })
+            .collect()
+    }
+
+    /// Convert every `$match` operand, keeping the stages
+    pub fn try_map<W, E>(&self, f: &mut impl FnMut(&V) -> Result<W, E>) -> Result<Aggregation<W>, E> {
+        let stages = self
+            .stages
+            .iter()
+            .map(|stage| {
+                Ok(match stage {
+                    Stage::Match(filter) => Stage::Match(filter.try_map(f)?),
+                    Stage::Group(group) => Stage::Group(group.clone()),
+                    Stage::Sort(sort) => Stage::Sort(sort.clone()),
+                    Stage::Limit(limit) => Stage::Limit(*limit),
+                })
+            })
+            .collect::<Result<Vec<_>, E>>()?;
+        Ok(Aggregation { stages })
+    }
+}
+
+impl Aggregation<OperatorValue> {
+    /// Evaluate every `$match` operand and validate the resulting filters
+    ///
+    /// `eval` is called once per operand, in stage order.
+    pub fn resolve(
+        &self,
+        mut eval: impl FnMut(&OperatorValue) -> Result<Value, ExecutionError>,
+    ) -> Result<Aggregation, ExecutionError> {
+        let mut aggregation = self.try_map(&mut eval)?;
+        for stage in &mut aggregation.stages {
+            if let Stage::Match(filter) = stage {
+                filter.compile_patterns()?;
+                filter.validate()?;
+            }
+        }
+        Ok(aggregation)
+    }
+}
+
+impl Aggregation {
+    /// Run every stage over documents held in memory
+    ///
+    /// # Errors
+    /// - `ArithmeticOverflow` if a `$sum` of integers overflows
+    pub fn apply(&self, mut docs: Vec<Value>) -> Result<Vec<Value>, ExecutionError> {
+        for stage in &self.stages {
+            docs = match stage {
+                Stage::Match(filter) => docs.into_iter().filter(|doc| filter.matches(doc)).collect(),
+                Stage::Group(group) => group.apply(&docs)?,
+                Stage::Sort(sort) => {
+                    sort.apply(&mut docs);
+                    docs
+                }
+                Stage::Limit(limit) => {
+                    docs.truncate(*limit as usize);
+                    docs
+                }
+            };
+        }
+        Ok(docs)
+    }
+}
+
+impl Group {
+    /// Group documents and compute the aggregates of each group
+    pub fn apply(&self, docs: &[Value]) -> Result<Vec<Value>, ExecutionError> {
+        let mut groups: Vec<(Vec<Value>, Vec<&Value>)> = Vec::new();
+        for doc in docs {
+            let key: Vec<Value> = self
+                .by
+                .iter()
+                .map(|field| path::get(doc, field).cloned().unwrap_or(Value::Null))
+                .collect();
+            let same_key = |other: &[Value]| {
+                other
+                    .iter()
+                    .zip(&key)
+                    .all(|(a, b)| sort::compare_values(a, b) == Ordering::Equal)
+            };
+            match groups.iter_mut().find(|(other, _)| same_key(other)) {
+                Some((_, members)) => members.push(doc),
+                None => groups.push((key, vec![doc])),
+            }
+        }
+
+        groups
+            .into_iter()
+            .map(|(key, members)| {
+                let mut output = Value::Object(Map::new());
+                for (field, value) in self.by.iter().zip(key) {
+                    path::set(&mut output, field, value)?;
+                }
+                for (name, accumulator) in &self.aggregates {
+                    path::set(&mut output, name, accumulator.apply(&members)?)?;
+                }
+                Ok(output)
+            })
+            .collect()
+    }
+
+    /// Every output field: the `by` fields, then the aggregate names
+    pub fn output_fields(&self) -> impl Iterator<Item = &str> {
+        self.by
+            .iter()
+            .map(String::as_str)
+            .chain(self.aggregates.iter().map(|(name, _)| name.as_str()))
+    }
+}
+
+impl Accumulator {
+    /// Build an accumulator from its `$`-prefixed name and field operand
+    pub fn from_name(name: &str, field: Option<String>) -> Option<Self> {
+        Some(match (name, field) {
+            ("$count", _) => Accumulator::Count,
+            ("$sum", Some(field)) => Accumulator::Sum(field),
+            ("$avg", Some(field)) => Accumulator::Avg(field),
+            ("$min", Some(field)) => Accumulator::Min(field),
+            ("$max", Some(field)) => Accumulator::Max(field),
+            _ => return None,
+        })
+    }
+
+    /// The `$`-prefixed name of this accumulator
+    pub fn name(&self) -> &'static str {
+        match self {
+            Accumulator::Count => "$count",
+            Accumulator::Sum(_) => "$sum",
+            Accumulator::Avg(_) => "$avg",
+            Accumulator::Min(_) => "$min",
+            Accumulator::Max(_) => "$max",
+        }
+    }
+
+    /// The dot-path of the field accumulated, if any
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            Accumulator::Count => None,
+            Accumulator::Sum(field) | Accumulator::Avg(field) | Accumulator::Min(field) | Accumulator::Max(field) => {
+                Some(field)
+            }
+        }
+    }
+
+    /// Compute the aggregate over the documents of a group
+    pub fn apply(&self, docs: &[&Value]) -> Result<Value, ExecutionError> {
+        let values = || {
+            let field = self.field().unwrap_or_default();
+            docs.iter()
+                .filter_map(move |doc| path::get(doc, field))
+                .filter(|value| !value.is_null())
+        };
+
+        Ok(match self {
+            Accumulator::Count => Value::from(docs.len()),
+            Accumulator::Sum(_) => {
+                let numbers: Vec<Value> = values().filter(|value| value.is_number()).cloned().collect();
+                math::add(&numbers).map_err(|_| ExecutionError::arithmetic_overflow("$sum"))?
+            }
+            Accumulator::Avg(_) => {
+                let numbers: Vec<f64> = values().filter_map(Value::as_f64).collect();
+                if numbers.is_empty() {
+                    Value::Null
+                } else {
+                    let mean = numbers.iter().sum::<f64>() / numbers.len() as f64;
+                    Number::from_f64(mean).map_or(Value::Null, Value::Number)
+                }
+            }
+            // `min_by` keeps the first of equal values; reversing the comparison
+            // makes it pick the first largest value as well
+            Accumulator::Min(_) => values().min_by(|a, b| sort::compare_values(a, b)).cloned().unwrap_or(Value::Null),
+            Accumulator::Max(_) => values().min_by(|a, b| sort::compare_values(b, a)).cloned().unwrap_or(Value::Null),
+        })
+    }
+}
+
+// Parsing and serialization
+
+impl<V: DeserializeOwned> Aggregation<V> {
+    /// Parse an aggregation from its JSON array of stages
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let items = value
+            .as_array()
+            .ok_or_else(|| "Aggregation pipeline must be an array of stages".to_string())?;
+        let stages = items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| Stage::from_json(item).map_err(|e| format!("Stage {}: {}", i, e)))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Aggregation { stages })
+    }
+}
+
+impl<V: DeserializeOwned> Stage<V> {
+    fn from_json(value: &Value) -> Result<Self, String> {
+        let (name, operand) = match value.as_object() {
+            Some(obj) if obj.len() == 1 => obj.iter().next().unwrap(),
+            _ => return Err("A stage must be an object with a single stage name".to_string()),
+        };
+        match name.as_str() {
+            "$match" => Ok(Stage::Match(Filter::from_json(operand)?)),
+            "$group" => Ok(Stage::Group(Group::from_json(operand)?)),
+            "$sort" => SortSpec::deserialize(operand)
+                .map(Stage::Sort)
+                .map_err(|e| format!("Invalid $sort: {}", e)),
+            "$limit" => u32::deserialize(operand)
+                .map(Stage::Limit)
+                .map_err(|_| "$limit requires a non-negative integer".to_string()),
+            other => Err(format!("Unknown aggregation stage '{}'", other)),
+        }
+    }
+}
+
+impl Group {
+    fn from_json(value: &Value) -> Result<Self, String> {
+        let obj = value
+            .as_object()
+            .ok_or_else(|| "$group requires an object with 'by' and 'aggregates'".to_string())?;
+        if let Some(key) = obj.keys().find(|key| *key != "by" && *key != "aggregates") {
+            return Err(format!("Unknown $group field '{}'", key));
+        }
+
+        let by = match obj.get("by") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::String(field)) => vec![field.clone()],
+            Some(Value::Array(fields)) => fields
+                .iter()
+                .map(|field| field.as_str().map(str::to_string))
+                .collect::<Option<_>>()
+                .ok_or_else(|| "$group 'by' requires a field name or a list of field names".to_string())?,
+            Some(_) => return Err("$group 'by' requires a field name or a list of field names".to_string()),
+        };
+
+        let mut aggregates = Vec::new();
+        let none = Map::new();
+        let fields = match obj.get("aggregates") {
+            None | Some(Value::Null) => &none,
+            Some(Value::Object(fields)) => fields,
+            Some(_) => return Err("$group 'aggregates' requires an object".to_string()),
+        };
+        for (name, spec) in fields {
+            let (accumulator, operand) = match spec.as_object() {
+                Some(obj) if obj.len() == 1 => obj.iter().next().unwrap(),
+                _ => return Err(format!("Aggregate '{}' must be an object with a single accumulator", name)),
+            };
+            let field = match operand {
+                Value::String(field) => Some(field.clone()),
+                Value::Null => None,
+                Value::Object(obj) if obj.is_empty() => None,
+                _ => return Err(format!("Aggregate '{}' requires a field name", name)),
+            };
+            let accumulator = Accumulator::from_name(accumulator, field).ok_or_else(|| {
+                format!("Unknown accumulator '{}' or missing field name for '{}'", accumulator, name)
+            })?;
+            aggregates.push((name.clone(), accumulator));
+        }
+
+        let group = Group { by, aggregates };
+        let fields: Vec<&str> = group.output_fields().collect();
+        for (i, a) in fields.iter().enumerate() {
+            for b in &fields[i + 1..] {
+                let nested = |outer: &str, inner: &str| inner.strip_prefix(outer).is_some_and(|rest| rest.starts_with('.'));
+                if a == b || nested(a, b) || nested(b, a) {
+                    return Err(format!("$group output fields '{}' and '{}' overlap", a, b));
+                }
+            }
+        }
+        Ok(group)
+    }
+}
+
+impl<'de, V: DeserializeOwned> Deserialize<'de> for Aggregation<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        Self::from_json(&value).map_err(de::Error::custom)
+    }
+}
+
+impl<V: Serialize> Serialize for Aggregation<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.stages.len()))?;
+        for stage in &self.stages {
+            seq.serialize_element(stage)?;
+        }
+        seq.end()
+    }
+}
+
+impl<V: Serialize> Serialize for Stage<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self {
+            Stage::Match(filter) => map.serialize_entry("$match", filter)?,
+            Stage::Group(group) => map.serialize_entry("$group", group)?,
+            Stage::Sort(sort) => map.serialize_entry("$sort", sort)?,
+            Stage::Limit(limit) => map.serialize_entry("$limit", limit)?,
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Group {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let aggregates: Map<String, Value> = self
+            .aggregates
+            .iter()
+            .map(|(name, accumulator)| {
+                let operand = accumulator.field().map_or(Value::Null, |field| Value::String(field.to_string()));
+                let mut spec = Map::new();
+                spec.insert(accumulator.name().to_string(), operand);
+                (name.clone(), Value::Object(spec))
+            })
+            .collect();
+
+        let mut map = serializer.serialize_map(Some(2))?;
+        match self.by.as_slice() {
+            [field] => map.serialize_entry("by", field)?,
+            fields => map.serialize_entry("by", fields)?,
+        }
+        map.serialize_entry("aggregates", &aggregates)?;
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operators::Operator;
+    use serde_json::json;
+
+    fn aggregation(value: Value) -> Aggregation {
+        Aggregation::from_json(&value).unwrap()
+    }
+
+    fn posts() -> Vec<Value> {
+        vec![
+            json!({"_id": "1", "authorId": "u1", "status": "published", "views": 10}),
+            json!({"_id": "2", "authorId": "u2", "status": "published", "views": 3}),
+            json!({"_id": "3", "authorId": "u1", "status": "draft", "views": 7}),
+            json!({"_id": "4", "authorId": "u1", "status": "published", "views": 2.5}),
+            json!({"_id": "5", "status": "published", "views": "many"}),
+        ]
+    }
+
+    #[test]
+    fn test_parse_stages() {
+        let parsed = aggregation(json!([
+            {"$match": {"status": "published"}},
+            {"$group": {"by": "authorId", "aggregates": {"count": {"$count": null}, "total": {"$sum": "views"}}}},
+            {"$sort": {"count": "desc"}},
+            {"$limit": 10}
+        ]));
+        assert_eq!(parsed.stages.len(), 4);
+        assert_eq!(
+            parsed.stages[1],
+            Stage::Group(Group {
+                by: vec!["authorId".to_string()],
+                aggregates: vec![
+                    ("count".to_string(), Accumulator::Count),
+                    ("total".to_string(), Accumulator::Sum("views".to_string())),
+                ],
+            })
+        );
+        assert_eq!(parsed.stages[3], Stage::Limit(10));
+
+        let json = serde_json::to_value(&parsed).unwrap();
+        assert_eq!(Aggregation::from_json(&json).unwrap(), parsed);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for invalid in [
+            json!({"$limit": 1}),
+            json!([{"$limit": 1, "$sort": {"a": "asc"}}]),
+            json!([{"$unwind": "tags"}]),
+            json!([{"$limit": -1}]),
+            json!([{"$group": {"by": 1}}]),
+            json!([{"$group": {"by": "a", "aggregates": {"n": {"$median": "x"}}}}]),
+            json!([{"$group": {"by": "a", "aggregates": {"n": {"$sum": null}}}}]),
+            json!([{"$group": {"by": "a", "aggregates": {"a": {"$count": null}}}}]),
+            json!([{"$group": {"by": "a", "aggregates": {"a.n": {"$count": null}}}}]),
+            json!([{"$group": {"key": "a"}}]),
+        ] {
+            assert!(Aggregation::<Value>::from_json(&invalid).is_err(), "{} should not parse", invalid);
+        }
+    }
+
+    #[test]
+    fn test_resolve_match_operands() {
+        let parsed: Aggregation<OperatorValue> = serde_json::from_value(json!([
+            {"$match": {"authorId": {"$get": "user.id"}}},
+            {"$group": {"aggregates": {"n": {"$count": null}}}},
+            {"$match": {"n": {"$gte": 1}}}
+        ]))
+        .unwrap();
+        assert!(matches!(parsed.operands()[0], OperatorValue::Operator(op) if matches!(**op, Operator::Get(_))));
+
+        let resolved = parsed
+            .resolve(|value| match value {
+                OperatorValue::Operator(_) => Ok(json!("u1")),
+                OperatorValue::Literal(v) => Ok(v.clone()),
+            })
+            .unwrap();
+        assert_eq!(resolved.apply(posts()).unwrap(), vec![json!({"n": 3})]);
+    }
+
+    #[test]
+    fn test_group_accumulators() {
+        let result = aggregation(json!([{"$group": {"by": "authorId", "aggregates": {
+            "count": {"$count": null},
+            "total": {"$sum": "views"},
+            "avg": {"$avg": "views"},
+            "min": {"$min": "views"},
+            "max": {"$max": "views"}
+        }}}]))
+        .apply(posts())
+        .unwrap();
+        assert_eq!(
+            result,
+            vec![
+                json!({"authorId": "u1", "count": 3, "total": 19.5, "avg": 6.5, "min": 2.5, "max": 10}),
+                json!({"authorId": "u2", "count": 1, "total": 3, "avg": 3.0, "min": 3, "max": 3}),
+                json!({"authorId": null, "count": 1, "total": 0, "avg": null, "min": "many", "max": "many"}),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_group_keys() {
+        let docs = vec![
+            json!({"author": {"id": "u1"}, "year": 2024}),
+            json!({"author": {"id": "u1"}, "year": 2024.0}),
+            json!({"author": {"id": "u1"}, "year": 2025}),
+            json!({"author": {"id": "u2"}, "year": 2024}),
+        ];
+        let result = aggregation(json!([{"$group": {"by": ["author.id", "year"], "aggregates": {"n": {"$count": null}}}}]))
+            .apply(docs.clone())
+            .unwrap();
+        assert_eq!(
+            result,
+            vec![
+                json!({"author": {"id": "u1"}, "year": 2024, "n": 2}),
+                json!({"author": {"id": "u1"}, "year": 2025, "n": 1}),
+                json!({"author": {"id": "u2"}, "year": 2024, "n": 1}),
+            ]
+        );
+
+        // Without `by` every document is one group, and nothing gives no groups
+        let total = aggregation(json!([{"$group": {"aggregates": {"n": {"$count": null}}}}]));
+        assert_eq!(total.apply(docs).unwrap(), vec![json!({"n": 4})]);
+        assert_eq!(total.apply(vec![]).unwrap(), Vec::<Value>::new());
+    }
+
+    #[test]
+    fn test_stages_run_in_order() {
+        let result = aggregation(json!([
+            {"$match": {"status": "published"}},
+            {"$group": {"by": "authorId", "aggregates": {"count": {"$count": null}}}},
+            {"$sort": [{"count": "desc"}, {"authorId": "asc"}]},
+            {"$limit": 2}
+        ]))
+        .apply(posts())
+        .unwrap();
+        assert_eq!(
+            result,
+            vec![json!({"authorId": "u1", "count": 2}), json!({"authorId": null, "count": 1})]
+        );
+    }
+
+    #[test]
+    fn test_sum_overflow() {
+        let docs = vec![json!({"n": i64::MAX}), json!({"n": i64::MAX}), json!({"n": i64::MAX})];
+        let result = aggregation(json!([{"$group": {"aggregates": {"total": {"$sum": "n"}}}}])).apply(docs);
+        assert!(matches!(result, Err(ExecutionError::ArithmeticOverflow { .. })));
+    }
+}
@@ -0,0 +1,155 @@
+//! Building a `DatabaseProvider` from `DatabaseConfig`
+
+use crate::config::{ConfigError, DatabaseConfig, DatabaseDriver};
+use crate::executor::traits::{DatabaseProvider, MockDatabase};
+use crate::pipeline::ExecutionError;
+
+use super::{FileDatabase, PostgresDatabase, SqliteDatabase};
+
+/// Create the provider selected by `config.driver`
+///
+/// Environment variables in the connection URL and data path are resolved
+/// here, so a missing variable or a database that cannot be opened is
+/// reported when the server starts.
+pub fn connect(config: &DatabaseConfig) -> Result<Box<dyn DatabaseProvider>, ConfigError> {
+    Ok(match config.driver {
+        DatabaseDriver::Memory => Box::new(MockDatabase::new().with_schemas(&config.schemas)),
+        DatabaseDriver::File => {
+            let path = config.data_path()?;
+            Box::new(FileDatabase::open(path, &config.schemas).map_err(open_error)?)
+        }
+        DatabaseDriver::Sqlite => {
+            let url = required_url(config)?;
+            let db = match sqlite_location(&url)? {
+                None => SqliteDatabase::open_in_memory(&config.schemas),
+                Some(path) => SqliteDatabase::open(path, &config.schemas),
+            };
+            Box::new(db.and_then(|db| db.with_busy_timeout(config.busy_timeout())).map_err(open_error)?)
+        }
+        DatabaseDriver::Postgres => {
+            let url = required_url(config)?;
+            Box::new(PostgresDatabase::connect(&url, &config.pool, &config.schemas).map_err(open_error)?)
+        }
+    })
+}
+
+/// The URL for drivers that need one
+fn required_url(config: &DatabaseConfig) -> Result<String, ConfigError> {
+    config.resolved_url()?.ok_or_else(|| {
+        ConfigError::database(format!(
+            "The '{}' database driver requires a 'url'",
+            config.driver.as_str()
+        ))
+    })
+}
+
+/// The file named by a SQLite URL, or `None` for `sqlite::memory:`
+fn sqlite_location(url: &str) -> Result<Option<&str>, ConfigError> {
+    if url == "sqlite::memory:" {
+        return Ok(None);
+    }
+    url.strip_prefix("sqlite://")
+        .or_else(|| url.strip_prefix("sqlite:"))
+        .filter(|path| !path.is_empty())
+        .map(Some)
+        .ok_or_else(|| {
+            ConfigError::database(format!(
+                "Invalid SQLite URL '{}': expected 'sqlite://<path>' or 'sqlite::memory:'",
+                url
+            ))
+        })
+}
+
+fn open_error(err: ExecutionError) -> ConfigError {
+    match err {
+        ExecutionError::DatabaseError { message, .. } => ConfigError::database(message),
+        other => ConfigError::database(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn config(value: serde_json::Value) -> DatabaseConfig {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn error(result: Result<Box<dyn DatabaseProvider>, ConfigError>) -> ConfigError {
+        match result {
+            Err(err) => err,
+            Ok(_) => panic!("expected an error"),
+        }
+    }
+
+    #[test]
+    fn test_sqlite_location() {
+        assert_eq!(sqlite_location("sqlite::memory:").unwrap(), None);
+        assert_eq!(sqlite_location("sqlite://./deck.db").unwrap(), Some("./deck.db"));
+        assert_eq!(sqlite_location("sqlite:/var/lib/deck.db").unwrap(), Some("/var/lib/deck.db"));
+        assert!(sqlite_location("postgres://localhost").is_err());
+        assert!(sqlite_location("sqlite://").is_err());
+    }
+
+    #[test]
+    fn test_connect_memory_and_sqlite() {
+        let document = HashMap::from([("title".to_string(), json!("hello"))]);
+        for config in [
+            config(json!({})),
+            config(json!({"driver": "sqlite", "url": "sqlite::memory:", "schemas": {
+                "posts": {"fields": {"title": {"type": "string"}}}
+            }})),
+        ] {
+            let db = connect(&config).unwrap();
+            db.insert("posts", &document).unwrap();
+            let posts = db.query("posts", None, None, None, None, None).unwrap();
+            assert_eq!(posts.len(), 1);
+            assert_eq!(posts[0]["title"], json!("hello"));
+        }
+    }
+
+    #[test]
+    fn test_connect_errors() {
+        assert_eq!(
+            error(connect(&config(json!({"driver": "sqlite"})))),
+            ConfigError::database("The 'sqlite' database driver requires a 'url'")
+        );
+        assert_eq!(
+            error(connect(&config(json!({
+                "driver": "sqlite",
+                "url": "sqlite://${DECK_TEST_UNSET_VARIABLE}/deck.db"
+            })))),
+            ConfigError::UnsetEnvVar {
+                variable: "DECK_TEST_UNSET_VARIABLE".to_string()
+            }
+        );
+        assert!(matches!(
+            error(connect(&config(json!({"driver": "sqlite", "url": "sqlite:///nonexistent-deck-dir/deck.db"})))),
+            ConfigError::Database { .. }
+        ));
+
+        assert_eq!(
+            error(connect(&config(json!({"driver": "postgres"})))),
+            ConfigError::database("The 'postgres' database driver requires a 'url'")
+        );
+        assert_eq!(
+            error(connect(&config(json!({"driver": "postgres", "url": "postgres://localhost/app", "pool": {"size": 0}})))),
+            ConfigError::database("The connection pool size must be at least 1")
+        );
+        assert!(matches!(
+            error(connect(&config(json!({"driver": "postgres", "url": "mysql://localhost/app"})))),
+            ConfigError::Database { message } if message.starts_with("Invalid PostgreSQL URL")
+        ));
+        // Nothing listens on port 1, so no connection is made within the timeout
+        assert!(matches!(
+            error(connect(&config(json!({
+                "driver": "postgres",
+                "url": "postgres://deck@127.0.0.1:1/app",
+                "pool": {"connectTimeoutMs": 100}
+            })))),
+            ConfigError::Database { message } if message.starts_with("PostgreSQL error")
+        ));
+    }
+}
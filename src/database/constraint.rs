@@ -0,0 +1,175 @@
+use serde_json::Value;
+use std::cmp::Ordering;
+
+use super::{path, sort};
+use crate::pipeline::ExecutionError;
+
+/// A unique constraint over one or more fields of a collection
+///
+/// Two documents violate the constraint if they have equal values for every
+/// field. As in SQL, documents with a missing or `null` value in any of the
+/// fields are not constrained. Fields may be dot-paths.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UniqueConstraint {
+    /// Constraint name reported in errors (e.g. `users_email_key`)
+    pub name: String,
+    /// Fields that together must be unique
+    pub fields: Vec<String>,
+}
+
+impl UniqueConstraint {
+    /// Create a constraint over the given fields
+    pub fn new(name: impl Into<String>, fields: Vec<String>) -> Self {
+        Self {
+            name: name.into(),
+            fields,
+        }
+    }
+
+    /// The constrained values of a document, or `None` if any is missing or null
+    pub fn key<'a>(&self, doc: &'a Value) -> Option<Vec<&'a Value>> {
+        self.fields
+            .iter()
+            .map(|field| path::get(doc, field).filter(|value| !value.is_null()))
+            .collect()
+    }
+
+    /// Whether two documents have the same key
+    pub fn conflicts(&self, a: &Value, b: &Value) -> bool {
+        match (self.key(a), self.key(b)) {
+            (Some(a), Some(b)) => a
+                .iter()
+                .zip(&b)
+                .all(|(a, b)| sort::compare_values(a, b) == Ordering::Equal),
+            _ => false,
+        }
+    }
+
+    /// The error reported when a document would duplicate another's key
+    pub fn violation(&self, collection: &str, doc: &Value) -> ExecutionError {
+        let values: Vec<String> = self
+            .key(doc)
+            .unwrap_or_default()
+            .iter()
+            .map(|value| value.to_string())
+            .collect();
+        ExecutionError::constraint_violation(
+            &self.name,
+            format!(
+                "Duplicate key in collection '{}' violates unique constraint '{}': ({})=({})",
+                collection,
+                self.name,
+                self.fields.join(", "),
+                values.join(", ")
+            ),
+        )
+    }
+}
+
+/// Check that the changed documents do not conflict with any other document
+///
+/// `changed` holds indices into `docs`; documents that were not changed are
+/// assumed not to conflict with each other.
+pub fn check_unique(
+    constraints: &[UniqueConstraint],
+    collection: &str,
+    docs: &[Value],
+    changed: &[usize],
+) -> Result<(), ExecutionError> {
+    for constraint in constraints {
+        for &index in changed {
+            let doc = &docs[index];
+            let conflict = docs
+                .iter()
+                .enumerate()
+                .any(|(other, existing)| other != index && constraint.conflicts(doc, existing));
+            if conflict {
+                return Err(constraint.violation(collection, doc));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that a document holds no array in the fields its schema declares scalar
+///
+/// Array values match filters element by element, which providers that index
+/// scalar fields do not do, so every provider refuses to store them.
+pub fn check_scalars(scalars: &[String], collection: &str, doc: &Value) -> Result<(), ExecutionError> {
+    match scalars.iter().find(|field| doc.get(field.as_str()).is_some_and(Value::is_array)) {
+        Some(field) => Err(ExecutionError::database_error(format!(
+            "Field '{}' of collection '{}' is declared as a scalar and cannot hold an array",
+            field, collection
+        ))),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn constraint(fields: &[&str]) -> UniqueConstraint {
+        UniqueConstraint::new("test_key", fields.iter().map(|f| f.to_string()).collect())
+    }
+
+    #[test]
+    fn test_conflicts_single_field() {
+        let email = constraint(&["email"]);
+        assert!(email.conflicts(&json!({"email": "a@x"}), &json!({"email": "a@x", "n": 1})));
+        assert!(!email.conflicts(&json!({"email": "a@x"}), &json!({"email": "b@x"})));
+        assert!(email.conflicts(&json!({"email": 1}), &json!({"email": 1.0})));
+    }
+
+    #[test]
+    fn test_missing_and_null_values_do_not_conflict() {
+        let email = constraint(&["email"]);
+        assert!(!email.conflicts(&json!({}), &json!({})));
+        assert!(!email.conflicts(&json!({"email": null}), &json!({"email": null})));
+    }
+
+    #[test]
+    fn test_conflicts_compound() {
+        let slug = constraint(&["tenant", "meta.slug"]);
+        let a = json!({"tenant": "t1", "meta": {"slug": "home"}});
+        assert!(slug.conflicts(&a, &json!({"tenant": "t1", "meta": {"slug": "home"}})));
+        assert!(!slug.conflicts(&a, &json!({"tenant": "t2", "meta": {"slug": "home"}})));
+        assert!(!slug.conflicts(&a, &json!({"tenant": "t1"})));
+    }
+
+    #[test]
+    fn test_check_unique() {
+        let constraints = vec![constraint(&["email"])];
+        let docs = vec![
+            json!({"_id": "1", "email": "a@x"}),
+            json!({"_id": "2", "email": "b@x"}),
+            json!({"_id": "3", "email": "a@x"}),
+        ];
+        assert!(check_unique(&constraints, "users", &docs[..2], &[1]).is_ok());
+
+        let err = check_unique(&constraints, "users", &docs, &[2]).unwrap_err();
+        assert_eq!(
+            err,
+            ExecutionError::constraint_violation(
+                "test_key",
+                "Duplicate key in collection 'users' violates unique constraint 'test_key': (email)=(\"a@x\")"
+            )
+        );
+    }
+
+    #[test]
+    fn test_check_scalars() {
+        let scalars = vec!["email".to_string()];
+        assert!(check_scalars(&scalars, "users", &json!({"email": "a@x", "tags": ["a"]})).is_ok());
+        assert!(check_scalars(&scalars, "users", &json!({"email": null})).is_ok());
+
+        let err = check_scalars(&scalars, "users", &json!({"email": ["a@x"]})).unwrap_err();
+        assert_eq!(
+            err,
+            ExecutionError::database_error(
+                "Field 'email' of collection 'users' is declared as a scalar and cannot hold an array"
+            )
+        );
+    }
+}
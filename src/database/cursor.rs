@@ -0,0 +1,105 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde_json::Value;
+
+use super::{SortKey, SortOrder, SortSpec};
+use crate::pipeline::ExecutionError;
+
+/// A position in a sorted query, for keyset pagination
+///
+/// A cursor holds the sort key values of one document. Cursor queries
+/// always sort by `_id` last (see `keyset_sort`), so a cursor identifies
+/// exactly one position even when other keys tie. Clients see cursors as
+/// opaque strings: the values as JSON, encoded as unpadded URL-safe base64.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    /// One value per key of the keyset sort
+    pub position: Vec<Value>,
+}
+
+impl Cursor {
+    /// The sort used for cursor pagination: `sort` with an ascending `_id` key
+    /// appended unless it already sorts by `_id`
+    pub fn keyset_sort(sort: Option<&SortSpec>) -> SortSpec {
+        let mut sort = sort.cloned().unwrap_or_default();
+        if !sort.keys.iter().any(|key| key.field == "_id") {
+            sort.keys.push(SortKey {
+                field: "_id".to_string(),
+                order: SortOrder::Ascending,
+            });
+        }
+        sort
+    }
+
+    /// The cursor at a document
+    pub fn of(sort: &SortSpec, doc: &Value) -> Self {
+        Self {
+            position: sort.key_of(doc),
+        }
+    }
+
+    /// Encode as an opaque string
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(Value::Array(self.position.clone()).to_string())
+    }
+
+    /// Decode a string made by `encode` for a query with the same sort
+    ///
+    /// # Errors
+    /// - `ValidationError` if the string is not a cursor for this sort
+    pub fn decode(text: &str, sort: &SortSpec) -> Result<Self, ExecutionError> {
+        let invalid = || ExecutionError::validation_error("Invalid cursor", vec![format!("'{}' is not a valid cursor", text)]);
+        let bytes = URL_SAFE_NO_PAD.decode(text).map_err(|_| invalid())?;
+        match serde_json::from_slice(&bytes) {
+            Ok(Value::Array(position)) if position.len() == sort.keys.len() => Ok(Self { position }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_keyset_sort_ends_with_id() {
+        let sort = SortSpec::default().then("createdAt", SortOrder::Descending);
+        assert_eq!(
+            Cursor::keyset_sort(Some(&sort)),
+            sort.clone().then("_id", SortOrder::Ascending)
+        );
+
+        let by_id = SortSpec::default().then("_id", SortOrder::Descending).then("title", SortOrder::Ascending);
+        assert_eq!(Cursor::keyset_sort(Some(&by_id)), by_id);
+        assert_eq!(Cursor::keyset_sort(None), SortSpec::default().then("_id", SortOrder::Ascending));
+    }
+
+    #[test]
+    fn test_encode_and_decode() {
+        let sort = Cursor::keyset_sort(Some(&SortSpec::default().then("meta.views", SortOrder::Descending)));
+        let cursor = Cursor::of(&sort, &json!({"_id": "p/1?", "meta": {"views": 10}}));
+        assert_eq!(cursor.position, vec![json!(10), json!("p/1?")]);
+
+        let text = cursor.encode();
+        assert!(text.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+        assert_eq!(Cursor::decode(&text, &sort).unwrap(), cursor);
+
+        // A missing field is positioned like null
+        assert_eq!(Cursor::of(&sort, &json!({"_id": "2"})).position, vec![Value::Null, json!("2")]);
+    }
+
+    #[test]
+    fn test_decode_rejects_invalid_cursors() {
+        let sort = Cursor::keyset_sort(None);
+        let other = Cursor {
+            position: vec![json!(1), json!("a")],
+        };
+        for text in ["not base64!", "e30", &other.encode()] {
+            assert!(matches!(
+                Cursor::decode(text, &sort),
+                Err(ExecutionError::ValidationError { .. })
+            ));
+        }
+    }
+}
@@ -0,0 +1,821 @@
+//! File-backed `DatabaseProvider`
+//!
+//! Each collection is kept in memory in a `MockDatabase` and persisted in a
+//! directory as two files:
+//! - `<collection>.log` - an append-only JSONL log of every change
+//! - `<collection>.snapshot.json` - the full collection as of the last compaction
+//!
+//! On open, each collection is recovered by loading its snapshot and
+//! replaying its log. Once a log reaches `compact_every` entries it is
+//! compacted: a new snapshot is written atomically (write then rename) and
+//! the log is truncated.
+//!
+//! Changes made in a transaction are logged when it commits. If a log cannot
+//! be written, the entries already written to the other logs are truncated
+//! away and the transaction is rolled back. A crash during the commit of a
+//! transaction that changed several collections can still leave only some of
+//! their logs written.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{Filter, SortSpec, Transaction, TransactionState, Update};
+use crate::config::DatabaseSchema;
+use crate::executor::traits::{Change, DatabaseProvider, MockDatabase};
+use crate::pipeline::ExecutionError;
+
+/// Default number of log entries after which a collection is compacted
+pub const DEFAULT_COMPACT_EVERY: usize = 1000;
+
+/// One change recorded in a collection log
+///
+/// Entries hold the resulting documents and their positions in the
+/// collection rather than the operation, so that replay does not depend on
+/// the time, ID generator, update semantics or on `_id`s being unique.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "camelCase")]
+enum LogEntry {
+    /// A document was appended
+    Insert { doc: Value },
+    /// The documents at these positions were replaced by `docs`
+    Update { positions: Vec<usize>, docs: Vec<Value> },
+    /// The documents at these positions were removed
+    Delete { positions: Vec<usize> },
+}
+
+impl From<&Change> for LogEntry {
+    fn from(change: &Change) -> Self {
+        match change {
+            Change::Insert(doc) => LogEntry::Insert { doc: doc.clone() },
+            Change::Update { positions, after, .. } => LogEntry::Update {
+                positions: positions.clone(),
+                docs: after.clone(),
+            },
+            Change::Delete { positions, .. } => LogEntry::Delete {
+                positions: positions.clone(),
+            },
+        }
+    }
+}
+
+/// The in-memory transaction handle and the log entries waiting for commit
+type Pending = (MockDatabase, Vec<(String, LogEntry)>);
+
+/// A durable document store in a directory of JSONL logs and snapshots
+///
+/// Queries use the same in-memory semantics as `MockDatabase`. Every change
+/// is appended to the collection log and synced to disk before the call
+/// returns; a change that cannot be logged is undone and its error returned.
+#[derive(Clone)]
+pub struct FileDatabase {
+    dir: PathBuf,
+    memory: MockDatabase,
+    compact_every: usize,
+    /// Number of entries in each collection log since its last compaction
+    log_lengths: Arc<Mutex<HashMap<String, usize>>>,
+    /// The in-memory store's view of the open transaction and the log entries
+    /// it has made, written on commit
+    ///
+    /// Its lock is held across every change and log write, so that log order
+    /// matches the order in which changes were applied.
+    pending: Arc<TransactionState<Pending>>,
+    /// The transaction this is the provider of, as returned by `begin`
+    owner: Option<Arc<Transaction<Pending>>>,
+}
+
+impl std::fmt::Debug for FileDatabase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FileDatabase")
+            .field("dir", &self.dir)
+            .field("compact_every", &self.compact_every)
+            .finish_non_exhaustive()
+    }
+}
+
+impl FileDatabase {
+    /// Open a data directory, creating it if needed, and recover every collection
+    pub fn open(dir: impl AsRef<Path>, schemas: &HashMap<String, DatabaseSchema>) -> Result<Self, ExecutionError> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).map_err(|e| io_error(&dir, e))?;
+
+        let mut memory = MockDatabase::new().with_id_generator(id_generator()).with_journal();
+        let mut log_lengths = HashMap::new();
+        for collection in stored_collections(&dir)? {
+            let (docs, log_length) = recover(&dir, &collection)?;
+            memory = memory.with_collection(&collection, docs);
+            log_lengths.insert(collection, log_length);
+        }
+
+        Ok(Self {
+            dir,
+            memory: memory.with_schemas(schemas),
+            compact_every: DEFAULT_COMPACT_EVERY,
+            log_lengths: Arc::new(Mutex::new(log_lengths)),
+            pending: Arc::new(TransactionState::new()),
+            owner: None,
+        })
+    }
+
+    /// Set how many log entries trigger a compaction
+    pub fn with_compact_every(mut self, entries: usize) -> Self {
+        self.compact_every = entries.max(1);
+        self
+    }
+
+    /// Write a snapshot of a collection and truncate its log
+    ///
+    /// Waits for an open transaction to end first.
+    pub fn compact(&self, collection: &str) -> Result<(), ExecutionError> {
+        self.pending
+            .run(None, |_| self.compact_locked(collection, &mut self.log_lengths.lock().unwrap()))
+    }
+
+    fn compact_locked(&self, collection: &str, log_lengths: &mut HashMap<String, usize>) -> Result<(), ExecutionError> {
+        let docs = self.memory.query(collection, None, None, None, None, None)?;
+        let snapshot = snapshot_path(&self.dir, collection);
+        let temporary = snapshot.with_extension("json.tmp");
+
+        let mut file = File::create(&temporary).map_err(|e| io_error(&temporary, e))?;
+        serde_json::to_writer(&mut file, &docs)
+            .map_err(|e| ExecutionError::database_error(format!("Failed to write snapshot: {}", e)))?;
+        file.sync_all().map_err(|e| io_error(&temporary, e))?;
+        fs::rename(&temporary, &snapshot).map_err(|e| io_error(&snapshot, e))?;
+
+        let log = log_path(&self.dir, collection);
+        File::create(&log)
+            .and_then(|file| file.sync_all())
+            .map_err(|e| io_error(&log, e))?;
+        log_lengths.insert(collection.to_string(), 0);
+        Ok(())
+    }
+
+    /// Run a change against the in-memory store and append its log entries
+    ///
+    /// Inside a transaction the entries are kept until `commit` instead. If
+    /// they cannot be written, the change is undone.
+    fn record<T>(
+        &self,
+        collection: &str,
+        change: impl FnOnce(&MockDatabase) -> Result<T, ExecutionError>,
+    ) -> Result<T, ExecutionError> {
+        check_collection_name(collection)?;
+        self.pending.run(self.owner.as_deref(), |open| {
+            let (memory, pending) = match open {
+                Some((memory, pending)) => (&*memory, Some(pending)),
+                None => (&self.memory, None),
+            };
+            let result = change(memory);
+            let changes = memory.take_changes();
+            let result = result?;
+            let entries: Vec<LogEntry> = changes.iter().map(LogEntry::from).collect();
+            if entries.is_empty() {
+                return Ok(result);
+            }
+            match pending {
+                Some(pending) => pending.extend(entries.into_iter().map(|entry| (collection.to_string(), entry))),
+                None => {
+                    let mut log_lengths = self.log_lengths.lock().unwrap();
+                    if let Err(err) = self.append(collection, &entries, &mut log_lengths) {
+                        memory.undo(collection, changes);
+                        return Err(err);
+                    }
+                    self.compact_if_due(collection, &mut log_lengths);
+                }
+            }
+            Ok(result)
+        })
+    }
+
+    /// Run a query against the in-memory store, or against the transaction's
+    /// view of it
+    fn read<T>(&self, query: impl FnOnce(&MockDatabase) -> Result<T, ExecutionError>) -> Result<T, ExecutionError> {
+        self.pending.run(self.owner.as_deref(), |open| match open {
+            Some((memory, _)) => query(memory),
+            None => query(&self.memory),
+        })
+    }
+
+    /// Append entries to a collection log and sync it
+    fn append(
+        &self,
+        collection: &str,
+        entries: &[LogEntry],
+        log_lengths: &mut HashMap<String, usize>,
+    ) -> Result<(), ExecutionError> {
+        let log = log_path(&self.dir, collection);
+        let mut lines = String::new();
+        for entry in entries {
+            lines.push_str(
+                &serde_json::to_string(entry)
+                    .map_err(|e| ExecutionError::database_error(format!("Failed to encode log entry: {}", e)))?,
+            );
+            lines.push('\n');
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log)
+            .and_then(|mut file| {
+                file.write_all(lines.as_bytes())?;
+                file.sync_data()
+            })
+            .map_err(|e| io_error(&log, e))?;
+
+        *log_lengths.entry(collection.to_string()).or_default() += entries.len();
+        Ok(())
+    }
+
+    /// Compact a collection once its log is long enough
+    ///
+    /// The change that made the log long enough is already durable, so a
+    /// failed compaction is reported rather than returned; it is retried
+    /// after the next change.
+    fn compact_if_due(&self, collection: &str, log_lengths: &mut HashMap<String, usize>) {
+        if log_lengths.get(collection).is_some_and(|length| *length >= self.compact_every)
+            && let Err(err) = self.compact_locked(collection, log_lengths)
+        {
+            eprintln!("Failed to compact collection '{}': {}", collection, err);
+        }
+    }
+
+    /// Size of a collection log in bytes (0 if it does not exist yet)
+    fn log_size(&self, collection: &str) -> Result<u64, ExecutionError> {
+        let log = log_path(&self.dir, collection);
+        match fs::metadata(&log) {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(io_error(&log, e)),
+        }
+    }
+
+    /// Cut a collection log back to `size` bytes
+    fn truncate_log(&self, collection: &str, size: u64) -> Result<(), ExecutionError> {
+        let log = log_path(&self.dir, collection);
+        OpenOptions::new()
+            .write(true)
+            .open(&log)
+            .and_then(|file| {
+                file.set_len(size)?;
+                file.sync_all()
+            })
+            .map_err(|e| io_error(&log, e))
+    }
+}
+
+impl DatabaseProvider for FileDatabase {
+    fn query(
+        &self,
+        collection: &str,
+        filter: Option<&Filter>,
+        select: Option<&[String]>,
+        limit: Option<u32>,
+        skip: Option<u32>,
+        sort: Option<&SortSpec>,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        self.read(|memory| memory.query(collection, filter, select, limit, skip, sort))
+    }
+
+    fn query_after(
+        &self,
+        collection: &str,
+        filter: Option<&Filter>,
+        sort: &SortSpec,
+        after: &[Value],
+        limit: Option<u32>,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        self.read(|memory| memory.query_after(collection, filter, sort, after, limit))
+    }
+
+    fn count(&self, collection: &str, filter: Option<&Filter>) -> Result<u64, ExecutionError> {
+        self.read(|memory| memory.count(collection, filter))
+    }
+
+    fn insert(
+        &self,
+        collection: &str,
+        document: &HashMap<String, Value>,
+    ) -> Result<Value, ExecutionError> {
+        self.record(collection, |memory| memory.insert(collection, document))
+    }
+
+    fn update(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        self.record(collection, |memory| memory.update(collection, filter, update))
+    }
+
+    fn upsert(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+        insert: &HashMap<String, Value>,
+    ) -> Result<(Option<Value>, Value), ExecutionError> {
+        self.record(collection, |memory| memory.upsert(collection, filter, update, insert))
+    }
+
+    fn find_one_and_update(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+        sort: Option<&SortSpec>,
+    ) -> Result<Option<(Value, Value)>, ExecutionError> {
+        self.record(collection, |memory| memory.find_one_and_update(collection, filter, update, sort))
+    }
+
+    fn delete(
+        &self,
+        collection: &str,
+        filter: &Filter,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        self.record(collection, |memory| memory.delete(collection, filter))
+    }
+
+    fn begin(&self) -> Result<Box<dyn DatabaseProvider>, ExecutionError> {
+        let transaction = self.pending.begin(
+            || Ok((self.memory.begin_transaction()?, Vec::new())),
+            |(memory, _)| memory.rollback(),
+        )?;
+        Ok(Box::new(FileDatabase {
+            owner: Some(Arc::new(transaction)),
+            ..self.clone()
+        }))
+    }
+
+    fn commit(&self) -> Result<(), ExecutionError> {
+        self.pending.commit(self.owner.as_deref(), |(memory, pending)| {
+            // One write per collection log, in the order collections were first changed
+            let mut collections: Vec<(String, Vec<LogEntry>)> = Vec::new();
+            for (collection, entry) in pending {
+                match collections.iter_mut().find(|(name, _)| *name == collection) {
+                    Some((_, entries)) => entries.push(entry),
+                    None => collections.push((collection, vec![entry])),
+                }
+            }
+
+            let mut log_lengths = self.log_lengths.lock().unwrap();
+            let mut written = Vec::with_capacity(collections.len());
+            for (collection, entries) in &collections {
+                let appended = self.log_size(collection).and_then(|size| {
+                    written.push((collection, size, log_lengths.get(collection).copied().unwrap_or(0)));
+                    self.append(collection, entries, &mut log_lengths)
+                });
+                if let Err(err) = appended {
+                    // Take the entries back out of the logs already written, so
+                    // that they still match the rolled back collections
+                    for (collection, size, length) in written {
+                        if let Err(err) = self.truncate_log(collection, size) {
+                            eprintln!("Failed to take back log entries of collection '{}': {}", collection, err);
+                        }
+                        log_lengths.insert(collection.clone(), length);
+                    }
+                    memory.rollback()?;
+                    return Err(err);
+                }
+            }
+
+            memory.commit()?;
+            for (collection, _) in &collections {
+                self.compact_if_due(collection, &mut log_lengths);
+            }
+            Ok(())
+        })
+    }
+
+    fn rollback(&self) -> Result<(), ExecutionError> {
+        self.pending.rollback(self.owner.as_deref())
+    }
+}
+
+// Recovery
+
+/// Names of the collections with a log or snapshot in the directory
+fn stored_collections(dir: &Path) -> Result<Vec<String>, ExecutionError> {
+    let mut collections: Vec<String> = fs::read_dir(dir)
+        .map_err(|e| io_error(dir, e))?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            name.strip_suffix(".snapshot.json")
+                .or_else(|| name.strip_suffix(".log"))
+                .map(str::to_string)
+        })
+        .collect();
+    collections.sort();
+    collections.dedup();
+    Ok(collections)
+}
+
+/// Load a collection's snapshot and replay its log
+///
+/// A final log line that cannot be parsed is ignored, since it can only be
+/// the result of a write interrupted by a crash. Returns the documents and
+/// the number of log entries replayed.
+fn recover(dir: &Path, collection: &str) -> Result<(Vec<Value>, usize), ExecutionError> {
+    let snapshot = snapshot_path(dir, collection);
+    let mut docs: Vec<Value> = match fs::read_to_string(&snapshot) {
+        Ok(text) => serde_json::from_str(&text).map_err(|e| {
+            ExecutionError::database_error(format!("Corrupt snapshot {}: {}", snapshot.display(), e))
+        })?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(io_error(&snapshot, e)),
+    };
+
+    let log = log_path(dir, collection);
+    let lines: Vec<String> = match File::open(&log) {
+        Ok(file) => BufReader::new(file)
+            .lines()
+            .collect::<Result<_, _>>()
+            .map_err(|e| io_error(&log, e))?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(io_error(&log, e)),
+    };
+
+    let mut replayed = 0;
+    for (number, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: LogEntry = match serde_json::from_str(line) {
+            Ok(entry) => entry,
+            Err(_) if number + 1 == lines.len() => break,
+            Err(e) => {
+                return Err(ExecutionError::database_error(format!(
+                    "Corrupt log {} at line {}: {}",
+                    log.display(),
+                    number + 1,
+                    e
+                )));
+            }
+        };
+        replay(&mut docs, entry).map_err(|e| {
+            ExecutionError::database_error(format!("Corrupt log {} at line {}: {}", log.display(), number + 1, e))
+        })?;
+        replayed += 1;
+    }
+
+    Ok((docs, replayed))
+}
+
+/// Apply a log entry to the documents of a collection
+fn replay(docs: &mut Vec<Value>, entry: LogEntry) -> Result<(), String> {
+    let positions = match &entry {
+        LogEntry::Insert { .. } => &[][..],
+        LogEntry::Update { positions, .. } | LogEntry::Delete { positions } => positions,
+    };
+    if let Some(position) = positions.iter().find(|&&position| position >= docs.len()) {
+        return Err(format!("position {} is past the end of the collection", position));
+    }
+
+    match entry {
+        LogEntry::Insert { doc } => docs.push(doc),
+        LogEntry::Update { positions, docs: updated } => {
+            for (position, doc) in positions.into_iter().zip(updated) {
+                docs[position] = doc;
+            }
+        }
+        LogEntry::Delete { positions } => {
+            let mut position = 0;
+            docs.retain(|_| {
+                position += 1;
+                !positions.contains(&(position - 1))
+            });
+        }
+    }
+    Ok(())
+}
+
+// Helpers
+
+/// IDs that stay unique across restarts: start time plus a counter
+fn id_generator() -> impl Fn() -> String + Send + Sync + 'static {
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros())
+        .unwrap_or_default();
+    let counter = AtomicU64::new(0);
+    move || format!("{:x}{:06x}", started, counter.fetch_add(1, Ordering::Relaxed))
+}
+
+/// Collection names become file names, so only allow a safe character set
+fn check_collection_name(collection: &str) -> Result<(), ExecutionError> {
+    let valid = !collection.is_empty()
+        && collection
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if valid {
+        Ok(())
+    } else {
+        Err(ExecutionError::database_error(format!(
+            "Invalid collection name '{}': use letters, digits, '_' and '-'",
+            collection
+        )))
+    }
+}
+
+fn log_path(dir: &Path, collection: &str) -> PathBuf {
+    dir.join(format!("{}.log", collection))
+}
+
+fn snapshot_path(dir: &Path, collection: &str) -> PathBuf {
+    dir.join(format!("{}.snapshot.json", collection))
+}
+
+fn io_error(path: &Path, err: std::io::Error) -> ExecutionError {
+    ExecutionError::database_error(format!("{}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A fresh directory under the system temp dir, removed on drop
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("deck-file-test-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn document(value: Value) -> HashMap<String, Value> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    fn all(db: &FileDatabase, collection: &str) -> Vec<Value> {
+        db.query(collection, None, None, None, None, None).unwrap()
+    }
+
+    #[test]
+    fn test_recovers_from_log() {
+        let dir = TempDir::new("log");
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        db.insert("posts", &document(json!({"_id": "1", "title": "a", "views": 1}))).unwrap();
+        db.insert("posts", &document(json!({"_id": "2", "title": "b"}))).unwrap();
+        let generated = db.insert("posts", &document(json!({"title": "c"}))).unwrap();
+        let update: Update = serde_json::from_value(json!({"$inc": {"views": 1}})).unwrap();
+        db.update("posts", &Filter::from_json(&json!({"_id": "1"})).unwrap(), &update).unwrap();
+        db.delete("posts", &Filter::from_json(&json!({"_id": "2"})).unwrap()).unwrap();
+        let before = all(&db, "posts");
+        drop(db);
+
+        let log = fs::read_to_string(dir.0.join("posts.log")).unwrap();
+        assert_eq!(log.lines().count(), 5);
+
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        assert_eq!(all(&db, "posts"), before);
+        assert_eq!(before[0], json!({"_id": "1", "title": "a", "views": 2}));
+        assert_eq!(before[1]["_id"], generated["_id"]);
+
+        // IDs generated after a restart do not collide with recovered ones
+        let next = db.insert("posts", &document(json!({"title": "d"}))).unwrap();
+        assert_ne!(next["_id"], generated["_id"]);
+    }
+
+    #[test]
+    fn test_recovers_upserts_from_log() {
+        let dir = TempDir::new("upsert");
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        let by_key = Filter::from_json(&json!({"key": "a"})).unwrap();
+        let set: Update = serde_json::from_value(json!({"$inc": {"hits": 1}})).unwrap();
+        let (before, inserted) = db.upsert("events", &by_key, &set, &document(json!({"key": "a", "hits": 1}))).unwrap();
+        assert!(before.is_none());
+        let (before, _) = db.upsert("events", &by_key, &set, &document(json!({"key": "a", "hits": 1}))).unwrap();
+        assert_eq!(before, Some(inserted));
+        db.find_one_and_update("events", &by_key, &set, None).unwrap();
+        assert!(db.find_one_and_update("events", &Filter::from_json(&json!({"key": "b"})).unwrap(), &set, None).unwrap().is_none());
+        let before = all(&db, "events");
+        drop(db);
+
+        assert_eq!(fs::read_to_string(dir.0.join("events.log")).unwrap().lines().count(), 3);
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        assert_eq!(all(&db, "events"), before);
+        assert_eq!(before[0]["hits"], json!(3));
+    }
+
+    #[test]
+    fn test_recovers_duplicate_and_changed_ids() {
+        let dir = TempDir::new("ids");
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        for n in 1..=4 {
+            db.insert("items", &document(json!({"_id": "x", "n": n}))).unwrap();
+        }
+        let set = |value: Value| serde_json::from_value::<Update>(json!({"$set": value})).unwrap();
+        db.update("items", &Filter::from_json(&json!({"n": 2})).unwrap(), &set(json!({"tag": "b"}))).unwrap();
+        db.update("items", &Filter::from_json(&json!({"n": 3})).unwrap(), &set(json!({"_id": "y"}))).unwrap();
+        db.update("items", &Filter::from_json(&json!({"_id": "y"})).unwrap(), &set(json!({"tag": "c"}))).unwrap();
+        db.delete("items", &Filter::from_json(&json!({"n": {"$in": [1, 4]}})).unwrap()).unwrap();
+        let before = all(&db, "items");
+        drop(db);
+
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        assert_eq!(all(&db, "items"), before);
+        assert_eq!(
+            before,
+            vec![json!({"_id": "x", "n": 2, "tag": "b"}), json!({"_id": "y", "n": 3, "tag": "c"})]
+        );
+    }
+
+    #[test]
+    fn test_unlogged_changes_are_undone() {
+        let dir = TempDir::new("unlogged");
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        for id in ["1", "2", "3"] {
+            db.insert("items", &document(json!({"_id": id}))).unwrap();
+        }
+        let before = all(&db, "items");
+
+        // A directory in place of the log makes every append fail
+        fs::remove_file(dir.0.join("items.log")).unwrap();
+        fs::create_dir(dir.0.join("items.log")).unwrap();
+        assert!(db.insert("items", &document(json!({"_id": "4"}))).is_err());
+        let update: Update = serde_json::from_value(json!({"$set": {"done": true}})).unwrap();
+        assert!(db.update("items", &Filter::from_json(&json!({"_id": {"$ne": "1"}})).unwrap(), &update).is_err());
+        assert!(db.delete("items", &Filter::from_json(&json!({"_id": {"$in": ["1", "3"]}})).unwrap()).is_err());
+        let inc: Update = serde_json::from_value(json!({"$inc": {"n": 1}})).unwrap();
+        let insert = document(json!({"_id": "5"}));
+        assert!(db.upsert("items", &Filter::from_json(&json!({"_id": "2"})).unwrap(), &inc, &insert).is_err());
+        assert_eq!(all(&db, "items"), before);
+    }
+
+    #[test]
+    fn test_failed_commit_takes_back_written_logs() {
+        let dir = TempDir::new("failed_commit");
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        db.insert("accounts", &document(json!({"_id": "a", "balance": 10}))).unwrap();
+        db.insert("audit", &document(json!({"event": "open"}))).unwrap();
+        let accounts_log = fs::read_to_string(dir.0.join("accounts.log")).unwrap();
+
+        // The accounts log is written first, then the audit log fails
+        fs::remove_file(dir.0.join("audit.log")).unwrap();
+        fs::create_dir(dir.0.join("audit.log")).unwrap();
+        let tx = db.begin().unwrap();
+        let update: Update = serde_json::from_value(json!({"$inc": {"balance": -3}})).unwrap();
+        tx.update("accounts", &Filter::from_json(&json!({"_id": "a"})).unwrap(), &update).unwrap();
+        tx.insert("audit", &document(json!({"event": "transfer"}))).unwrap();
+        assert!(tx.commit().is_err());
+
+        assert_eq!(fs::read_to_string(dir.0.join("accounts.log")).unwrap(), accounts_log);
+        assert_eq!(all(&db, "accounts"), vec![json!({"_id": "a", "balance": 10})]);
+        assert_eq!(all(&db, "audit").len(), 1);
+
+        // Later changes are logged after the kept entries
+        db.update("accounts", &Filter::from_json(&json!({"_id": "a"})).unwrap(), &update).unwrap();
+        drop(db);
+        fs::remove_dir(dir.0.join("audit.log")).unwrap();
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        assert_eq!(all(&db, "accounts"), vec![json!({"_id": "a", "balance": 7})]);
+    }
+
+    #[test]
+    fn test_failed_compaction_keeps_the_change() {
+        let dir = TempDir::new("failed_compaction");
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap().with_compact_every(2);
+
+        // A non-empty directory in place of the snapshot makes compaction fail
+        fs::create_dir_all(dir.0.join("items.snapshot.json").join("blocked")).unwrap();
+        db.insert("items", &document(json!({"_id": "1"}))).unwrap();
+        db.insert("items", &document(json!({"_id": "2"}))).unwrap();
+        assert_eq!(all(&db, "items").len(), 2);
+        assert_eq!(fs::read_to_string(dir.0.join("items.log")).unwrap().lines().count(), 2);
+
+        // Compaction is retried after the next change
+        fs::remove_dir_all(dir.0.join("items.snapshot.json")).unwrap();
+        db.insert("items", &document(json!({"_id": "3"}))).unwrap();
+        assert_eq!(fs::read_to_string(dir.0.join("items.log")).unwrap().lines().count(), 0);
+        drop(db);
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        assert_eq!(all(&db, "items").len(), 3);
+    }
+
+    #[test]
+    fn test_compaction_writes_snapshot_and_truncates_log() {
+        let dir = TempDir::new("compact");
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap().with_compact_every(3);
+        for i in 0..4 {
+            db.insert("items", &document(json!({"_id": i}))).unwrap();
+        }
+
+        let snapshot: Value = serde_json::from_str(&fs::read_to_string(dir.0.join("items.snapshot.json")).unwrap()).unwrap();
+        assert_eq!(snapshot, json!([{"_id": 0}, {"_id": 1}, {"_id": 2}]));
+        assert_eq!(fs::read_to_string(dir.0.join("items.log")).unwrap().lines().count(), 1);
+        drop(db);
+
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        assert_eq!(all(&db, "items").len(), 4);
+    }
+
+    #[test]
+    fn test_ignores_torn_final_line() {
+        let dir = TempDir::new("torn");
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        db.insert("items", &document(json!({"_id": "1"}))).unwrap();
+        drop(db);
+
+        let mut log = OpenOptions::new().append(true).open(dir.0.join("items.log")).unwrap();
+        log.write_all(br#"{"op":"insert","doc":{"_id":"#).unwrap();
+        drop(log);
+
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        assert_eq!(all(&db, "items"), vec![json!({"_id": "1"})]);
+    }
+
+    #[test]
+    fn test_rejects_corrupt_log() {
+        let dir = TempDir::new("corrupt");
+        fs::create_dir_all(&dir.0).unwrap();
+        fs::write(dir.0.join("items.log"), "not json\n{\"op\":\"delete\",\"positions\":[]}\n").unwrap();
+        assert!(matches!(
+            FileDatabase::open(&dir.0, &HashMap::new()),
+            Err(ExecutionError::DatabaseError { .. })
+        ));
+
+        fs::write(dir.0.join("items.log"), "{\"op\":\"delete\",\"positions\":[0]}\n{}\n").unwrap();
+        assert!(matches!(
+            FileDatabase::open(&dir.0, &HashMap::new()),
+            Err(ExecutionError::DatabaseError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_transactions_are_logged_on_commit() {
+        let dir = TempDir::new("transaction");
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        db.insert("accounts", &document(json!({"_id": "a", "balance": 10}))).unwrap();
+        let log_lines = |collection: &str| {
+            fs::read_to_string(dir.0.join(format!("{}.log", collection)))
+                .map(|log| log.lines().count())
+                .unwrap_or(0)
+        };
+
+        let tx = db.begin().unwrap();
+        tx.insert("accounts", &document(json!({"_id": "b", "balance": 5}))).unwrap();
+        tx.insert("audit", &document(json!({"event": "open"}))).unwrap();
+        assert_eq!((log_lines("accounts"), log_lines("audit")), (1, 0));
+        tx.rollback().unwrap();
+        assert_eq!(all(&db, "accounts"), vec![json!({"_id": "a", "balance": 10})]);
+        assert!(all(&db, "audit").is_empty());
+        assert_eq!((log_lines("accounts"), log_lines("audit")), (1, 0));
+
+        let tx = db.begin().unwrap();
+        let update: Update = serde_json::from_value(json!({"$inc": {"balance": -3}})).unwrap();
+        tx.update("accounts", &Filter::from_json(&json!({"_id": "a"})).unwrap(), &update).unwrap();
+        tx.insert("accounts", &document(json!({"_id": "b", "balance": 3}))).unwrap();
+        std::thread::scope(|scope| {
+            // Calls outside the transaction wait for it, so reads only see its
+            // changes once committed and writes are logged on their own
+            let read = scope.spawn(|| all(&db, "accounts"));
+            let write = scope.spawn(|| db.insert("audit", &document(json!({"event": "outside"}))));
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            assert!(!read.is_finished());
+            assert!(!write.is_finished());
+            tx.insert("audit", &document(json!({"event": "transfer"}))).unwrap();
+            assert_eq!(tx.count("accounts", None).unwrap(), 2);
+            tx.commit().unwrap();
+            assert_eq!(read.join().unwrap().len(), 2);
+            write.join().unwrap().unwrap();
+        });
+        assert_eq!((log_lines("accounts"), log_lines("audit")), (3, 2));
+
+        let before = all(&db, "accounts");
+        drop(db);
+        let db = FileDatabase::open(&dir.0, &HashMap::new()).unwrap();
+        assert_eq!(all(&db, "accounts"), before);
+        assert_eq!(all(&db, "audit").len(), 2);
+    }
+
+    #[test]
+    fn test_failed_changes_are_not_logged() {
+        let dir = TempDir::new("failed");
+        let schemas = serde_json::from_value(json!({
+            "users": {"fields": {"email": {"type": "string", "unique": true}}}
+        }))
+        .unwrap();
+        let db = FileDatabase::open(&dir.0, &schemas).unwrap();
+        db.insert("users", &document(json!({"email": "a@x"}))).unwrap();
+        assert!(db.insert("users", &document(json!({"email": "a@x"}))).is_err());
+        assert!(db.insert("../escape", &document(json!({}))).is_err());
+        db.delete("users", &Filter::from_json(&json!({"email": "none"})).unwrap()).unwrap();
+
+        assert_eq!(fs::read_to_string(dir.0.join("users.log")).unwrap().lines().count(), 1);
+    }
+}
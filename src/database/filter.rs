@@ -0,0 +1,816 @@
+use regex::Regex;
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use super::{path, sort};
+use crate::operators::OperatorValue;
+use crate::pipeline::ExecutionError;
+
+/// A filter expression over documents
+///
+/// Filters are written as MongoDB-style JSON objects. Top-level fields are
+/// ANDed together, a plain value means equality and an object of `$`-prefixed
+/// conditions compares the field:
+///
+/// ```json
+/// {
+///   "status": "published",
+///   "price": {"$gte": 10, "$lte": 100},
+///   "$or": [{"category": "tech"}, {"tags": {"$contains": "rust"}}]
+/// }
+/// ```
+///
+/// `V` is the type of condition operands. Filters in configuration hold
+/// `OperatorValue`s; the executor resolves them into a `Filter<Value>`,
+/// which is what `DatabaseProvider`s receive.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter<V = Value> {
+    /// Every filter must match (an empty list matches every document)
+    And(Vec<Filter<V>>),
+    /// At least one filter must match
+    Or(Vec<Filter<V>>),
+    /// The filter must not match
+    Not(Box<Filter<V>>),
+    /// A condition on a single field
+    Field { path: String, condition: Condition<V> },
+}
+
+/// A condition on a field value
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition<V = Value> {
+    /// `$eq` - equal to the operand (`null` also matches a missing field)
+    Eq(V),
+    /// `$ne` - not equal to the operand
+    Ne(V),
+    /// `$gt` - greater than the operand
+    Gt(V),
+    /// `$gte` - greater than or equal to the operand
+    Gte(V),
+    /// `$lt` - less than the operand
+    Lt(V),
+    /// `$lte` - less than or equal to the operand
+    Lte(V),
+    /// `$in` - equal to any value in the operand array
+    In(V),
+    /// `$nin` - not equal to any value in the operand array
+    Nin(V),
+    /// `$exists` - the field is present (operand `true`) or absent (`false`)
+    Exists(V),
+    /// `$regex` - the field is a string matching the operand pattern
+    ///
+    /// The pattern is compiled once: when the filter is parsed if the operand
+    /// is a literal string, otherwise when it is resolved. It is `None` until
+    /// then, or if the operand is not a string.
+    Regex(V, Option<Pattern>),
+    /// `$contains` - the field is an array containing the operand
+    Contains(V),
+    /// `$size` - the field is an array with exactly the operand's length
+    Size(V),
+}
+
+/// A compiled `$regex` pattern
+///
+/// Patterns compare and serialize by their source text.
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+    /// Compile a pattern
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(Pattern)
+    }
+
+    /// The source text of the pattern
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+
+    /// Check whether the pattern matches anywhere in `text`
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+}
+
+impl PartialEq for Pattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl<V> Filter<V> {
+    /// A filter that matches every document
+    pub fn all() -> Self {
+        Filter::And(Vec::new())
+    }
+
+    /// A field condition
+    pub fn field(path: impl Into<String>, condition: Condition<V>) -> Self {
+        Filter::Field {
+            path: path.into(),
+            condition,
+        }
+    }
+
+    /// Combine filters with AND, avoiding a wrapper for a single filter
+    fn and(mut filters: Vec<Filter<V>>) -> Self {
+        if filters.len() == 1 {
+            filters.remove(0)
+        } else {
+            Filter::And(filters)
+        }
+    }
+
+    /// Every condition operand, in the order `try_map` visits them
+    pub fn operands(&self) -> Vec<&V> {
+        match self {
+            Filter::And(filters) | Filter::Or(filters) => filters.iter().flat_map(Filter::operands).collect(),
+            Filter::Not(filter) => filter.operands(),
+            Filter::Field { condition, .. } => vec![condition.operand()],
+        }
+    }
+
+    /// Convert every condition operand, keeping the filter structure
+    pub fn try_map<W, E>(&self, f: &mut impl FnMut(&V) -> Result<W, E>) -> Result<Filter<W>, E> {
+        let mut map_all = |filters: &[Filter<V>]| {
+            filters
+                .iter()
+                .map(|filter| filter.try_map(f))
+                .collect::<Result<Vec<_>, E>>()
+        };
+
+        Ok(match self {
+            Filter::And(filters) => Filter::And(map_all(filters)?),
+            Filter::Or(filters) => Filter::Or(map_all(filters)?),
+            Filter::Not(filter) => Filter::Not(Box::new(filter.try_map(f)?)),
+            Filter::Field { path, condition } => Filter::Field {
+                path: path.clone(),
+                condition: condition.try_map(f)?,
+            },
+        })
+    }
+}
+
+impl Filter<OperatorValue> {
+    /// Evaluate every operand and check that it has the shape its condition needs
+    ///
+    /// `eval` is called once per operand, in document order.
+    pub fn resolve(
+        &self,
+        mut eval: impl FnMut(&OperatorValue) -> Result<Value, ExecutionError>,
+    ) -> Result<Filter, ExecutionError> {
+        let mut filter = self.try_map(&mut eval)?;
+        filter.compile_patterns()?;
+        filter.validate()?;
+        Ok(filter)
+    }
+}
+
+impl Filter {
+    /// Compile the `$regex` patterns whose operands were not literal strings
+    ///
+    /// # Errors
+    /// - `ValidationError` naming the field if a pattern is invalid
+    pub(crate) fn compile_patterns(&mut self) -> Result<(), ExecutionError> {
+        match self {
+            Filter::And(filters) | Filter::Or(filters) => filters.iter_mut().try_for_each(Filter::compile_patterns),
+            Filter::Not(filter) => filter.compile_patterns(),
+            Filter::Field { path, condition: Condition::Regex(Value::String(pattern), compiled @ None) } => {
+                *compiled = Some(Pattern::new(pattern).map_err(|e| {
+                    ExecutionError::validation_error(
+                        format!("Invalid $regex pattern for field '{}'", path),
+                        vec![e.to_string()],
+                    )
+                })?);
+                Ok(())
+            }
+            Filter::Field { .. } => Ok(()),
+        }
+    }
+
+    /// Check that every condition operand has the expected type
+    ///
+    /// `$in`/`$nin` need an array, `$exists` a boolean, `$regex` a compiled
+    /// pattern string and `$size` a non-negative integer.
+    pub fn validate(&self) -> Result<(), ExecutionError> {
+        match self {
+            Filter::And(filters) | Filter::Or(filters) => filters.iter().try_for_each(Filter::validate),
+            Filter::Not(filter) => filter.validate(),
+            Filter::Field { condition, .. } => condition.validate(),
+        }
+    }
+
+    /// Check whether a document matches this filter
+    pub fn matches(&self, doc: &Value) -> bool {
+        match self {
+            Filter::And(filters) => filters.iter().all(|f| f.matches(doc)),
+            Filter::Or(filters) => filters.iter().any(|f| f.matches(doc)),
+            Filter::Not(filter) => !filter.matches(doc),
+            Filter::Field { path, condition } => condition.matches(&path::resolve(doc, path)),
+        }
+    }
+
+    /// The field values every matching document has, from `$eq` conditions
+    ///
+    /// Only conditions reachable through `And` count; those under `$or` or
+    /// `$not` don't pin a value. `null` operands are skipped, as they also
+    /// match a missing field.
+    pub fn equalities(&self) -> Vec<(&str, &Value)> {
+        match self {
+            Filter::And(filters) => filters.iter().flat_map(Filter::equalities).collect(),
+            Filter::Field { path, condition: Condition::Eq(value) } if !value.is_null() => vec![(path, value)],
+            _ => vec![],
+        }
+    }
+}
+
+impl<V> Condition<V> {
+    /// Build a condition from its `$`-prefixed name
+    pub fn from_name(name: &str, operand: V) -> Option<Self> {
+        Some(match name {
+            "$eq" => Condition::Eq(operand),
+            "$ne" => Condition::Ne(operand),
+            "$gt" => Condition::Gt(operand),
+            "$gte" => Condition::Gte(operand),
+            "$lt" => Condition::Lt(operand),
+            "$lte" => Condition::Lte(operand),
+            "$in" => Condition::In(operand),
+            "$nin" => Condition::Nin(operand),
+            "$exists" => Condition::Exists(operand),
+            "$regex" => Condition::Regex(operand, None),
+            "$contains" => Condition::Contains(operand),
+            "$size" => Condition::Size(operand),
+            _ => return None,
+        })
+    }
+
+    /// The `$`-prefixed name of this condition
+    pub fn name(&self) -> &'static str {
+        match self {
+            Condition::Eq(_) => "$eq",
+            Condition::Ne(_) => "$ne",
+            Condition::Gt(_) => "$gt",
+            Condition::Gte(_) => "$gte",
+            Condition::Lt(_) => "$lt",
+            Condition::Lte(_) => "$lte",
+            Condition::In(_) => "$in",
+            Condition::Nin(_) => "$nin",
+            Condition::Exists(_) => "$exists",
+            Condition::Regex(..) => "$regex",
+            Condition::Contains(_) => "$contains",
+            Condition::Size(_) => "$size",
+        }
+    }
+
+    /// The condition's operand
+    pub fn operand(&self) -> &V {
+        match self {
+            Condition::Eq(v)
+            | Condition::Ne(v)
+            | Condition::Gt(v)
+            | Condition::Gte(v)
+            | Condition::Lt(v)
+            | Condition::Lte(v)
+            | Condition::In(v)
+            | Condition::Nin(v)
+            | Condition::Exists(v)
+            | Condition::Regex(v, _)
+            | Condition::Contains(v)
+            | Condition::Size(v) => v,
+        }
+    }
+
+    /// Convert the operand, keeping the condition kind
+    ///
+    /// A compiled `$regex` pattern is kept, as it came from a literal operand.
+    pub fn try_map<W, E>(&self, f: &mut impl FnMut(&V) -> Result<W, E>) -> Result<Condition<W>, E> {
+        let operand = f(self.operand())?;
+        Ok(match self {
+            Condition::Regex(_, pattern) => Condition::Regex(operand, pattern.clone()),
+            _ => Condition::from_name(self.name(), operand).expect("condition names round-trip"),
+        })
+    }
+}
+
+impl Condition {
+    /// Check that the operand has the type this condition needs
+    fn validate(&self) -> Result<(), ExecutionError> {
+        let invalid = |message: &str| Err(ExecutionError::invalid_operator(self.name(), message));
+        match self {
+            Condition::In(v) | Condition::Nin(v) if !v.is_array() => invalid("Requires an array of values"),
+            Condition::Exists(v) if !v.is_boolean() => invalid("Requires a boolean"),
+            Condition::Size(v) if v.as_u64().is_none() => invalid("Requires a non-negative integer"),
+            Condition::Regex(Value::String(_), Some(_)) => Ok(()),
+            Condition::Regex(..) => invalid("Requires a pattern string"),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check whether the values at a field path satisfy this condition
+    ///
+    /// `values` holds every value the path resolves to (see `path::resolve`):
+    /// none for a missing field and several when the path crosses an array.
+    /// Value conditions (`$eq`, `$gt`, `$in`, `$regex`, ...) match if any
+    /// value, or any element of an array value, matches; a missing field is
+    /// treated as `null`. `$ne` and `$nin` match when their positive form
+    /// does not. `$contains` and `$size` test array values themselves.
+    pub fn matches(&self, values: &[&Value]) -> bool {
+        match self {
+            Condition::Ne(operand) => !Condition::Eq(operand.clone()).matches(values),
+            Condition::Nin(operand) => !Condition::In(operand.clone()).matches(values),
+            Condition::Exists(operand) => operand.as_bool() == Some(!values.is_empty()),
+            Condition::Contains(_) | Condition::Size(_) => values.iter().any(|v| self.matches_value(v)),
+            _ if values.is_empty() => self.matches_value(&Value::Null),
+            _ => values.iter().any(|v| {
+                self.matches_value(v)
+                    || v.as_array().is_some_and(|items| items.iter().any(|item| self.matches_value(item)))
+            }),
+        }
+    }
+
+    /// Check a single value against this condition
+    fn matches_value(&self, value: &Value) -> bool {
+        let ordered = |operand: &Value, accept: fn(Ordering) -> bool| {
+            compare_values(value, operand).is_some_and(accept)
+        };
+        let is_in = |operand: &Value| {
+            operand
+                .as_array()
+                .is_some_and(|items| items.iter().any(|item| values_equal(value, item)))
+        };
+
+        match self {
+            Condition::Eq(operand) => values_equal(value, operand),
+            Condition::Ne(operand) => !values_equal(value, operand),
+            Condition::Gt(operand) => ordered(operand, Ordering::is_gt),
+            Condition::Gte(operand) => ordered(operand, Ordering::is_ge),
+            Condition::Lt(operand) => ordered(operand, Ordering::is_lt),
+            Condition::Lte(operand) => ordered(operand, Ordering::is_le),
+            Condition::In(operand) => is_in(operand),
+            Condition::Nin(operand) => !is_in(operand),
+            Condition::Exists(operand) => operand.as_bool() == Some(true),
+            Condition::Regex(_, pattern) => match (value, pattern) {
+                (Value::String(s), Some(pattern)) => pattern.is_match(s),
+                _ => false,
+            },
+            Condition::Contains(operand) => match value {
+                Value::Array(items) => items.iter().any(|item| values_equal(item, operand)),
+                _ => false,
+            },
+            Condition::Size(operand) => match value {
+                Value::Array(items) => operand.as_u64() == Some(items.len() as u64),
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Strict equality, except that numbers compare by value (`1 == 1.0`)
+fn values_equal(left: &Value, right: &Value) -> bool {
+    match (left, right) {
+        (Value::Number(_), Value::Number(_)) => compare_values(left, right) == Some(Ordering::Equal),
+        _ => left == right,
+    }
+}
+
+/// Order two values of the same type; values of different types are unordered
+fn compare_values(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Number(l), Value::Number(r)) => Some(sort::compare_numbers(l, r)),
+        (Value::String(l), Value::String(r)) => Some(l.cmp(r)),
+        (Value::Bool(l), Value::Bool(r)) => Some(l.cmp(r)),
+        _ => None,
+    }
+}
+
+// Parsing and serialization
+
+impl<V: DeserializeOwned> Filter<V> {
+    /// Parse a filter from its JSON form (`null` matches every document)
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let obj = match value {
+            Value::Null => return Ok(Filter::all()),
+            Value::Object(obj) => obj,
+            _ => return Err("Filter must be an object".to_string()),
+        };
+
+        let mut filters = Vec::new();
+        for (key, value) in obj {
+            match key.as_str() {
+                "$and" | "$or" => {
+                    let items = value
+                        .as_array()
+                        .ok_or_else(|| format!("{} requires an array of filters", key))?;
+                    let parsed = items.iter().map(Self::from_json).collect::<Result<Vec<_>, _>>()?;
+                    filters.push(if key == "$and" {
+                        Filter::And(parsed)
+                    } else {
+                        Filter::Or(parsed)
+                    });
+                }
+                "$not" => filters.push(Filter::Not(Box::new(Self::from_json(value)?))),
+                _ if key.starts_with('$') => {
+                    return Err(format!("Unknown filter operator '{}'", key));
+                }
+                _ => Self::parse_field(key, value, &mut filters)?,
+            }
+        }
+
+        Ok(Filter::and(filters))
+    }
+
+    /// Parse the conditions for one field
+    ///
+    /// An object whose keys are all condition names (`{"$gt": 1}`) is a set of
+    /// conditions. Anything else, including operators such as `{"$get": ..}`,
+    /// is an equality operand.
+    fn parse_field(path: &str, value: &Value, filters: &mut Vec<Filter<V>>) -> Result<(), String> {
+        let parse_operand =
+            |value: &Value| V::deserialize(value).map_err(|e| format!("Invalid operand for '{}': {}", path, e));
+
+        if let Value::Object(obj) = value {
+            let conditions = obj
+                .keys()
+                .filter(|k| Condition::from_name(k, ()).is_some())
+                .count();
+            if conditions > 0 && conditions == obj.len() {
+                for (name, operand) in obj {
+                    let mut condition = Condition::from_name(name, parse_operand(operand)?)
+                        .expect("condition name checked above");
+                    // Compile literal patterns now; others are compiled when resolved
+                    if let (Condition::Regex(_, compiled), Value::String(pattern)) = (&mut condition, operand) {
+                        *compiled = Some(
+                            Pattern::new(pattern)
+                                .map_err(|e| format!("Invalid $regex pattern for '{}': {}", path, e))?,
+                        );
+                    }
+                    filters.push(Filter::field(path, condition));
+                }
+                return Ok(());
+            }
+            if conditions > 0 {
+                return Err(format!(
+                    "Field '{}' mixes filter conditions with other keys",
+                    path
+                ));
+            }
+        }
+
+        filters.push(Filter::field(path, Condition::Eq(parse_operand(value)?)));
+        Ok(())
+    }
+}
+
+impl<'de, V: DeserializeOwned> Deserialize<'de> for Filter<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        Self::from_json(&value).map_err(de::Error::custom)
+    }
+}
+
+impl<V: Serialize> Serialize for Filter<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        match self {
+            Filter::And(filters) => map.serialize_entry("$and", filters)?,
+            Filter::Or(filters) => map.serialize_entry("$or", filters)?,
+            Filter::Not(filter) => map.serialize_entry("$not", filter)?,
+            Filter::Field { path, condition } => {
+                let mut operand = Map::new();
+                let value = serde_json::to_value(condition.operand()).map_err(serde::ser::Error::custom)?;
+                operand.insert(condition.name().to_string(), value);
+                map.serialize_entry(path, &operand)?;
+            }
+        }
+        map.end()
+    }
+}
+
+impl<V> From<HashMap<String, V>> for Filter<V> {
+    /// Equality on every field (the simple `{"field": value}` form)
+    fn from(fields: HashMap<String, V>) -> Self {
+        let mut fields: Vec<_> = fields.into_iter().collect();
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+        Filter::and(
+            fields
+                .into_iter()
+                .map(|(path, value)| Filter::field(path, Condition::Eq(value)))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operators::Operator;
+    use serde_json::json;
+
+    fn filter(value: Value) -> Filter {
+        let filter: Filter = serde_json::from_value(value).unwrap();
+        filter.validate().unwrap();
+        filter
+    }
+
+    #[test]
+    fn test_parse_equality_and_conditions() {
+        let parsed = filter(json!({"status": "published", "price": {"$gte": 10, "$lte": 100}}));
+        assert_eq!(
+            parsed,
+            Filter::And(vec![
+                Filter::field("price", Condition::Gte(json!(10))),
+                Filter::field("price", Condition::Lte(json!(100))),
+                Filter::field("status", Condition::Eq(json!("published"))),
+            ])
+        );
+
+        assert_eq!(filter(json!({"a": 1})), Filter::field("a", Condition::Eq(json!(1))));
+        assert_eq!(filter(json!({})), Filter::all());
+        assert_eq!(filter(json!(null)), Filter::all());
+    }
+
+    #[test]
+    fn test_parse_logical_operators() {
+        let parsed = filter(json!({
+            "$or": [{"category": "tech"}, {"category": "science"}],
+            "$not": {"deleted": true}
+        }));
+        assert_eq!(
+            parsed,
+            Filter::And(vec![
+                Filter::Not(Box::new(Filter::field("deleted", Condition::Eq(json!(true))))),
+                Filter::Or(vec![
+                    Filter::field("category", Condition::Eq(json!("tech"))),
+                    Filter::field("category", Condition::Eq(json!("science"))),
+                ]),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(serde_json::from_value::<Filter>(json!({"$where": "1"})).is_err());
+        assert!(serde_json::from_value::<Filter>(json!({"$and": {"a": 1}})).is_err());
+        assert!(serde_json::from_value::<Filter>(json!({"a": {"$gt": 1, "b": 2}})).is_err());
+        assert!(serde_json::from_value::<Filter>(json!([1])).is_err());
+    }
+
+    #[test]
+    fn test_parse_operator_operands() {
+        let parsed: Filter<OperatorValue> = serde_json::from_value(json!({
+            "authorId": {"$get": "user.id"},
+            "age": {"$gt": {"$get": "minAge"}},
+            "deletedAt": {"$exists": false}
+        }))
+        .unwrap();
+
+        let Filter::And(filters) = &parsed else {
+            panic!("expected $and");
+        };
+        assert!(matches!(
+            &filters[0],
+            Filter::Field { condition: Condition::Gt(OperatorValue::Operator(op)), .. } if matches!(**op, Operator::Get(_))
+        ));
+        assert!(matches!(
+            &filters[1],
+            Filter::Field { condition: Condition::Eq(OperatorValue::Operator(op)), .. } if matches!(**op, Operator::Get(_))
+        ));
+        assert!(matches!(
+            &filters[2],
+            Filter::Field { condition: Condition::Exists(OperatorValue::Literal(Value::Bool(false))), .. }
+        ));
+    }
+
+    #[test]
+    fn test_resolve_evaluates_and_validates() {
+        let parsed: Filter<OperatorValue> =
+            serde_json::from_value(json!({"status": {"$in": {"$get": "statuses"}}})).unwrap();
+
+        let resolved = parsed.resolve(|_| Ok(json!(["draft", "published"]))).unwrap();
+        assert_eq!(resolved, Filter::field("status", Condition::In(json!(["draft", "published"]))));
+
+        let result = parsed.resolve(|_| Ok(json!("draft")));
+        assert!(matches!(result, Err(ExecutionError::InvalidOperator { .. })));
+    }
+
+    #[test]
+    fn test_operands_follow_try_map_order() {
+        let parsed = filter(json!({"$or": [{"a": {"$gt": 1}}, {"$not": {"b": "x"}}], "c": {"$in": [2, 3]}}));
+        let mut visited = Vec::new();
+        parsed
+            .try_map(&mut |value| {
+                visited.push(value.clone());
+                Ok::<_, ()>(())
+            })
+            .unwrap();
+        assert_eq!(parsed.operands().into_iter().cloned().collect::<Vec<_>>(), visited);
+        assert_eq!(visited, vec![json!(1), json!("x"), json!([2, 3])]);
+    }
+
+    #[test]
+    fn test_validate_operands() {
+        let invalid = [
+            json!({"a": {"$nin": 1}}),
+            json!({"a": {"$exists": "yes"}}),
+            json!({"a": {"$size": -1}}),
+            json!({"a": {"$regex": 1}}),
+        ];
+        for value in invalid {
+            let parsed: Filter = serde_json::from_value(value.clone()).unwrap();
+            assert!(parsed.validate().is_err(), "expected {} to be invalid", value);
+        }
+
+        // Literal patterns are compiled, and rejected, when parsed
+        let err = serde_json::from_value::<Filter>(json!({"a": {"$regex": "("}})).unwrap_err();
+        assert!(err.to_string().contains("Invalid $regex pattern for 'a'"), "{}", err);
+        let err = serde_json::from_value::<Filter<OperatorValue>>(json!({"a": {"$regex": "("}})).unwrap_err();
+        assert!(err.to_string().contains("Invalid $regex pattern for 'a'"), "{}", err);
+    }
+
+    #[test]
+    fn test_resolve_compiles_dynamic_patterns() {
+        let parsed: Filter<OperatorValue> =
+            serde_json::from_value(json!({"name": {"$regex": {"$get": "pattern"}}})).unwrap();
+        assert!(matches!(&parsed, Filter::Field { condition: Condition::Regex(_, None), .. }));
+
+        let resolved = parsed.resolve(|_| Ok(json!("^a"))).unwrap();
+        assert!(matches!(&resolved, Filter::Field { condition: Condition::Regex(_, Some(p)), .. } if p.as_str() == "^a"));
+        assert!(resolved.matches(&json!({"name": "ada"})));
+        assert!(!resolved.matches(&json!({"name": "bob"})));
+
+        match parsed.resolve(|_| Ok(json!("("))) {
+            Err(ExecutionError::ValidationError { message, .. }) => {
+                assert_eq!(message, "Invalid $regex pattern for field 'name'")
+            }
+            other => panic!("Expected ValidationError, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let parsed = filter(json!({"$or": [{"a": {"$gt": 1}}, {"b": "x"}], "c": {"$exists": true}}));
+        let serialized = serde_json::to_value(&parsed).unwrap();
+        assert_eq!(filter(serialized), parsed);
+    }
+
+    #[test]
+    fn test_matches_equality() {
+        let doc = json!({"status": "published", "views": 10, "deletedAt": null});
+
+        assert!(filter(json!({"status": "published"})).matches(&doc));
+        assert!(!filter(json!({"status": "Published"})).matches(&doc));
+        assert!(filter(json!({"views": 10.0})).matches(&doc));
+        assert!(!filter(json!({"views": "10"})).matches(&doc));
+        assert!(filter(json!({"deletedAt": null})).matches(&doc));
+        assert!(filter(json!({"missing": null})).matches(&doc));
+        assert!(filter(json!({"status": {"$ne": "draft"}})).matches(&doc));
+        assert!(filter(json!({"missing": {"$ne": "draft"}})).matches(&doc));
+    }
+
+    #[test]
+    fn test_matches_comparisons() {
+        let doc = json!({"price": 50, "name": "m", "active": true});
+
+        assert!(filter(json!({"price": {"$gte": 10, "$lte": 100}})).matches(&doc));
+        assert!(!filter(json!({"price": {"$gt": 50}})).matches(&doc));
+        assert!(filter(json!({"price": {"$lt": 50.5}})).matches(&doc));
+        assert!(filter(json!({"name": {"$gt": "a", "$lt": "z"}})).matches(&doc));
+        // Values of different types never compare
+        assert!(!filter(json!({"price": {"$gt": "10"}})).matches(&doc));
+        assert!(!filter(json!({"missing": {"$lt": 100}})).matches(&doc));
+    }
+
+    #[test]
+    fn test_matches_in_and_nin() {
+        let doc = json!({"status": "draft"});
+
+        assert!(filter(json!({"status": {"$in": ["draft", "published"]}})).matches(&doc));
+        assert!(!filter(json!({"status": {"$in": []}})).matches(&doc));
+        assert!(filter(json!({"status": {"$nin": ["deleted", "spam"]}})).matches(&doc));
+        assert!(!filter(json!({"status": {"$nin": ["draft"]}})).matches(&doc));
+    }
+
+    #[test]
+    fn test_matches_exists() {
+        let doc = json!({"deletedAt": null});
+
+        assert!(filter(json!({"deletedAt": {"$exists": true}})).matches(&doc));
+        assert!(!filter(json!({"deletedAt": {"$exists": false}})).matches(&doc));
+        assert!(filter(json!({"archivedAt": {"$exists": false}})).matches(&doc));
+    }
+
+    #[test]
+    fn test_matches_regex() {
+        let doc = json!({"email": "Alice@Example.com", "age": 30});
+
+        assert!(!filter(json!({"email": {"$regex": "@example\\.com$"}})).matches(&doc));
+        assert!(filter(json!({"email": {"$regex": "(?i)@example\\.com$"}})).matches(&doc));
+        assert!(!filter(json!({"age": {"$regex": "3"}})).matches(&doc));
+    }
+
+    #[test]
+    fn test_matches_array_conditions() {
+        let doc = json!({"tags": ["rust", "serde", "json"], "title": "rust"});
+
+        assert!(filter(json!({"tags": {"$contains": "serde"}})).matches(&doc));
+        assert!(!filter(json!({"tags": {"$contains": "go"}})).matches(&doc));
+        assert!(!filter(json!({"title": {"$contains": "rust"}})).matches(&doc));
+        assert!(filter(json!({"tags": {"$size": 3}})).matches(&doc));
+        assert!(!filter(json!({"tags": {"$size": 2}})).matches(&doc));
+    }
+
+    #[test]
+    fn test_matches_nested_paths() {
+        let doc = json!({
+            "author": {"id": "u1", "profile": {"verified": true}},
+            "tags": ["rust", "json"],
+            "comments": [
+                {"author": "alice", "votes": 3},
+                {"author": "bob", "votes": 5}
+            ]
+        });
+
+        assert!(filter(json!({"author.id": "u1"})).matches(&doc));
+        assert!(filter(json!({"author.profile.verified": true})).matches(&doc));
+        assert!(filter(json!({"author.email": null})).matches(&doc));
+        assert!(filter(json!({"author.email": {"$exists": false}})).matches(&doc));
+        assert!(filter(json!({"tags.0": "rust"})).matches(&doc));
+        assert!(!filter(json!({"tags.1": "rust"})).matches(&doc));
+        assert!(filter(json!({"comments.1.author": "bob"})).matches(&doc));
+    }
+
+    #[test]
+    fn test_matches_any_array_element() {
+        let doc = json!({
+            "tags": ["rust", "json"],
+            "comments": [
+                {"author": "alice", "votes": 3},
+                {"author": "bob", "votes": 5}
+            ]
+        });
+
+        // A scalar condition on an array field matches any element
+        assert!(filter(json!({"tags": "json"})).matches(&doc));
+        assert!(filter(json!({"tags": {"$in": ["go", "rust"]}})).matches(&doc));
+        assert!(filter(json!({"tags": {"$regex": "^js"}})).matches(&doc));
+        assert!(filter(json!({"tags": ["rust", "json"]})).matches(&doc));
+        assert!(!filter(json!({"tags": {"$ne": "rust"}})).matches(&doc));
+        assert!(filter(json!({"tags": {"$nin": ["go"]}})).matches(&doc));
+
+        // A path through an array of objects matches any element's field
+        assert!(filter(json!({"comments.author": "bob"})).matches(&doc));
+        assert!(filter(json!({"comments.votes": {"$gt": 4}})).matches(&doc));
+        assert!(!filter(json!({"comments.votes": {"$gt": 5}})).matches(&doc));
+        assert!(filter(json!({"comments.author": {"$exists": true}})).matches(&doc));
+    }
+
+    #[test]
+    fn test_matches_logical_operators() {
+        let doc = json!({"category": "science", "published": true, "views": 150});
+
+        let complex = filter(json!({
+            "$and": [
+                {"$or": [{"category": "tech"}, {"category": "science"}]},
+                {"published": true},
+                {"views": {"$gt": 100}}
+            ]
+        }));
+        assert!(complex.matches(&doc));
+
+        assert!(!filter(json!({"$not": {"published": true}})).matches(&doc));
+        assert!(!filter(json!({"$or": []})).matches(&doc));
+        assert!(filter(json!({"$and": []})).matches(&doc));
+    }
+
+    #[test]
+    fn test_equalities() {
+        let filter = filter(json!({
+            "$and": [
+                {"source": "stripe", "event.id": "evt_1"},
+                {"$or": [{"status": "new"}]},
+                {"deletedAt": null, "attempts": {"$lt": 3}}
+            ]
+        }));
+        let mut equalities = filter.equalities();
+        equalities.sort_by_key(|(path, _)| *path);
+        assert_eq!(equalities, vec![("event.id", &json!("evt_1")), ("source", &json!("stripe"))]);
+    }
+
+    #[test]
+    fn test_from_hash_map() {
+        let mut fields = HashMap::new();
+        fields.insert("b".to_string(), json!(2));
+        fields.insert("a".to_string(), json!(1));
+
+        assert_eq!(
+            Filter::from(fields),
+            Filter::And(vec![
+                Filter::field("a", Condition::Eq(json!(1))),
+                Filter::field("b", Condition::Eq(json!(2))),
+            ])
+        );
+    }
+}
@@ -0,0 +1,29 @@
+//! Database query types
+//!
+//! This module contains the typed query language shared by the database
+//! operators and every `DatabaseProvider` implementation.
+
+mod aggregate;
+mod connect;
+mod constraint;
+mod cursor;
+pub mod file;
+mod filter;
+pub mod path;
+pub mod postgres;
+pub mod sort;
+pub mod sqlite;
+mod transaction;
+mod update;
+
+pub use aggregate::{Accumulator, Aggregation, Group, Stage};
+pub use connect::connect;
+pub use constraint::{UniqueConstraint, check_scalars, check_unique};
+pub use cursor::Cursor;
+pub use file::FileDatabase;
+pub use filter::{Condition, Filter, Pattern};
+pub use postgres::PostgresDatabase;
+pub use sort::{SortKey, SortOrder, SortSpec};
+pub use sqlite::SqliteDatabase;
+pub use transaction::{Transaction, TransactionState};
+pub use update::{Update, UpdateOp};
@@ -0,0 +1,332 @@
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+
+use crate::executor::Executor;
+use crate::pipeline::ExecutionError;
+
+/// Find every value a dot-path resolves to in a document
+///
+/// Each segment of `path` (split on `.`) selects an object key or, on an
+/// array, an element index. A non-index segment applied to an array fans out
+/// to every element, so `comments.author` on
+/// `{"comments": [{"author": "a"}, {"author": "b"}]}` yields `"a"` and `"b"`.
+///
+/// Returns no values if the path does not exist.
+pub fn resolve<'a>(doc: &'a Value, path: &str) -> Vec<&'a Value> {
+    let segments: Vec<&str> = path.split('.').collect();
+    let mut found = Vec::new();
+    collect(doc, &segments, &mut found);
+    found
+}
+
+fn collect<'a>(value: &'a Value, segments: &[&str], found: &mut Vec<&'a Value>) {
+    let Some((segment, rest)) = segments.split_first() else {
+        found.push(value);
+        return;
+    };
+
+    match value {
+        Value::Object(map) => {
+            if let Some(child) = map.get(*segment) {
+                collect(child, rest, found);
+            }
+        }
+        Value::Array(items) => match segment.parse::<usize>() {
+            Ok(index) => {
+                if let Some(child) = items.get(index) {
+                    collect(child, rest, found);
+                }
+            }
+            Err(_) => {
+                for item in items {
+                    collect(item, segments, found);
+                }
+            }
+        },
+        _ => {}
+    }
+}
+
+/// Get the single value at a dot-path, without fanning out over arrays
+///
+/// Array elements can only be addressed by index. Used for sort keys.
+pub fn get<'a>(doc: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(doc, |current, segment| match current {
+        Value::Object(map) => map.get(segment),
+        Value::Array(items) => items.get(segment.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+/// Set the value at a dot-path, creating missing intermediate objects
+///
+/// Index segments replace existing array elements. Fails with a `TypeError`
+/// if the path runs through a scalar and with `IndexOutOfBounds` if an index
+/// is past the end of an array.
+pub fn set(doc: &mut Value, path: &str, value: Value) -> Result<(), ExecutionError> {
+    let segments: Vec<&str> = path.split('.').collect();
+    let (last, parents) = segments.split_last().expect("split yields at least one segment");
+
+    let mut current = doc;
+    for segment in parents {
+        current = child_mut(current, segment, path)?;
+        if current.is_null() {
+            *current = Value::Object(Map::new());
+        }
+    }
+
+    match current {
+        Value::Object(map) => {
+            map.insert(last.to_string(), value);
+        }
+        Value::Array(items) => {
+            let index = array_index(last, items.len(), path)?;
+            items[index] = value;
+        }
+        other => return Err(not_a_container(other, path)),
+    }
+    Ok(())
+}
+
+/// Remove the value at a dot-path, returning it if it existed
+///
+/// Object fields are removed; array elements are set to `null` so that the
+/// other elements keep their indices.
+pub fn remove(doc: &mut Value, path: &str) -> Option<Value> {
+    let (parent_path, last) = match path.rsplit_once('.') {
+        Some((parent, last)) => (Some(parent), last),
+        None => (None, path),
+    };
+
+    let parent = match parent_path {
+        Some(parent_path) => parent_path.split('.').try_fold(doc, |current, segment| match current {
+            Value::Object(map) => map.get_mut(segment),
+            Value::Array(items) => items.get_mut(segment.parse::<usize>().ok()?),
+            _ => None,
+        })?,
+        None => doc,
+    };
+
+    match parent {
+        Value::Object(map) => map.remove(last),
+        Value::Array(items) => {
+            let item = items.get_mut(last.parse::<usize>().ok()?)?;
+            Some(std::mem::take(item))
+        }
+        _ => None,
+    }
+}
+
+/// Step into an object field or array element, creating missing fields
+fn child_mut<'a>(current: &'a mut Value, segment: &str, path: &str) -> Result<&'a mut Value, ExecutionError> {
+    match current {
+        Value::Object(map) => Ok(map
+            .entry(segment.to_string())
+            .or_insert_with(|| Value::Object(Map::new()))),
+        Value::Array(items) => {
+            let index = array_index(segment, items.len(), path)?;
+            Ok(&mut items[index])
+        }
+        other => Err(not_a_container(other, path)),
+    }
+}
+
+fn array_index(segment: &str, length: usize, path: &str) -> Result<usize, ExecutionError> {
+    let index: usize = segment.parse().map_err(|_| {
+        ExecutionError::type_error(format!(
+            "Path '{}' uses field '{}' on an array",
+            path, segment
+        ))
+    })?;
+    if index >= length {
+        return Err(ExecutionError::IndexOutOfBounds { index, length });
+    }
+    Ok(index)
+}
+
+fn not_a_container(value: &Value, path: &str) -> ExecutionError {
+    ExecutionError::type_error_with_types(
+        format!("Path '{}' runs through a non-container value", path),
+        "object or array",
+        Executor::type_name(value),
+    )
+}
+
+/// Project a document onto a list of dot-paths
+///
+/// The result keeps the nesting of the original document: selecting
+/// `["title", "meta.views"]` from `{"title": "t", "meta": {"views": 1, "likes": 2}}`
+/// gives `{"title": "t", "meta": {"views": 1}}`. A path that crosses an array
+/// is applied to every element (or to the indexed elements when the next
+/// segments are indices). Paths that do not exist are left out.
+pub fn project(doc: &Value, select: &[String]) -> Value {
+    let mut root = Selection::default();
+    for path in select {
+        root.insert(path);
+    }
+    root.apply(doc).unwrap_or_else(|| Value::Object(Map::new()))
+}
+
+/// A tree of selected paths
+#[derive(Debug, Default)]
+struct Selection {
+    /// Whether the whole value at this node is selected
+    whole: bool,
+    /// Selected children by segment
+    children: BTreeMap<String, Selection>,
+}
+
+impl Selection {
+    fn insert(&mut self, path: &str) {
+        let node = path
+            .split('.')
+            .fold(self, |node, segment| node.children.entry(segment.to_string()).or_default());
+        node.whole = true;
+    }
+
+    fn apply(&self, value: &Value) -> Option<Value> {
+        if self.whole {
+            return Some(value.clone());
+        }
+
+        match value {
+            Value::Object(map) => {
+                let projected = self
+                    .children
+                    .iter()
+                    .filter_map(|(key, child)| {
+                        let projected = child.apply(map.get(key)?)?;
+                        Some((key.clone(), projected))
+                    })
+                    .collect();
+                Some(Value::Object(projected))
+            }
+            Value::Array(items) => {
+                let indices: Option<Vec<usize>> = self.children.keys().map(|k| k.parse().ok()).collect();
+                let projected = match indices {
+                    Some(mut indices) => {
+                        indices.sort_unstable();
+                        indices
+                            .into_iter()
+                            .filter_map(|index| self.children[&index.to_string()].apply(items.get(index)?))
+                            .collect()
+                    }
+                    None => items
+                        .iter()
+                        .filter(|item| item.is_object() || item.is_array())
+                        .filter_map(|item| self.apply(item))
+                        .collect(),
+                };
+                Some(Value::Array(projected))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn doc() -> Value {
+        json!({
+            "title": "Hello",
+            "author": {"id": "u1", "profile": {"verified": true}},
+            "meta": {"views": 10, "likes": 2},
+            "tags": ["rust", "json"],
+            "comments": [
+                {"author": "alice", "votes": 3},
+                {"author": "bob", "votes": 5}
+            ]
+        })
+    }
+
+    #[test]
+    fn test_resolve_nested_objects() {
+        let doc = doc();
+        assert_eq!(resolve(&doc, "author.id"), vec![&json!("u1")]);
+        assert_eq!(resolve(&doc, "author.profile.verified"), vec![&json!(true)]);
+        assert!(resolve(&doc, "author.email").is_empty());
+        assert!(resolve(&doc, "title.length").is_empty());
+    }
+
+    #[test]
+    fn test_resolve_arrays() {
+        let doc = doc();
+        assert_eq!(resolve(&doc, "tags.1"), vec![&json!("json")]);
+        assert_eq!(resolve(&doc, "comments.0.author"), vec![&json!("alice")]);
+        assert_eq!(
+            resolve(&doc, "comments.author"),
+            vec![&json!("alice"), &json!("bob")]
+        );
+        assert!(resolve(&doc, "comments.5.author").is_empty());
+    }
+
+    #[test]
+    fn test_get() {
+        let doc = doc();
+        assert_eq!(get(&doc, "meta.views"), Some(&json!(10)));
+        assert_eq!(get(&doc, "comments.1.votes"), Some(&json!(5)));
+        assert_eq!(get(&doc, "comments.votes"), None);
+    }
+
+    #[test]
+    fn test_set() {
+        let mut doc = doc();
+        set(&mut doc, "meta.views", json!(11)).unwrap();
+        set(&mut doc, "stats.daily.count", json!(1)).unwrap();
+        set(&mut doc, "comments.1.votes", json!(6)).unwrap();
+
+        assert_eq!(doc["meta"], json!({"views": 11, "likes": 2}));
+        assert_eq!(doc["stats"], json!({"daily": {"count": 1}}));
+        assert_eq!(doc["comments"][1]["votes"], json!(6));
+
+        assert!(matches!(
+            set(&mut doc, "title.length", json!(1)),
+            Err(ExecutionError::TypeError { .. })
+        ));
+        assert_eq!(
+            set(&mut doc, "tags.5", json!("x")).unwrap_err(),
+            ExecutionError::IndexOutOfBounds { index: 5, length: 2 }
+        );
+        assert!(matches!(
+            set(&mut doc, "tags.first", json!("x")),
+            Err(ExecutionError::TypeError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut doc = doc();
+        assert_eq!(remove(&mut doc, "meta.likes"), Some(json!(2)));
+        assert_eq!(remove(&mut doc, "title"), Some(json!("Hello")));
+        assert_eq!(remove(&mut doc, "tags.0"), Some(json!("rust")));
+        assert_eq!(remove(&mut doc, "missing.field"), None);
+
+        assert_eq!(doc["meta"], json!({"views": 10}));
+        assert!(doc.get("title").is_none());
+        assert_eq!(doc["tags"], json!([null, "json"]));
+    }
+
+    #[test]
+    fn test_project_nested() {
+        let projected = project(&doc(), &["title".to_string(), "meta.views".to_string()]);
+        assert_eq!(projected, json!({"title": "Hello", "meta": {"views": 10}}));
+
+        let projected = project(
+            &doc(),
+            &["author.id".to_string(), "author.profile".to_string(), "missing.field".to_string()],
+        );
+        assert_eq!(projected, json!({"author": {"id": "u1", "profile": {"verified": true}}}));
+    }
+
+    #[test]
+    fn test_project_arrays() {
+        let projected = project(&doc(), &["comments.author".to_string()]);
+        assert_eq!(projected, json!({"comments": [{"author": "alice"}, {"author": "bob"}]}));
+
+        let projected = project(&doc(), &["comments.1.votes".to_string(), "tags.0".to_string()]);
+        assert_eq!(projected, json!({"comments": [{"votes": 5}], "tags": ["rust"]}));
+    }
+}
@@ -0,0 +1,954 @@
+//! PostgreSQL-backed `DatabaseProvider`
+//!
+//! Each collection is a table with an `_id` primary key column, the
+//! document stored as JSONB in a `doc` column and a `seq` column that keeps
+//! insertion order. Equality conditions on top-level fields are narrowed
+//! down in SQL, through a GIN index on `doc` or the expression indexes built
+//! from the schema; the remaining rows are then matched, sorted and
+//! paginated in memory, so results are the same as with `MockDatabase`.
+//!
+//! Connections come from a pool. Calls block on network I/O, so run the
+//! provider through `BlockingDatabase`.
+
+use postgres::error::SqlState;
+use postgres::types::ToSql;
+use postgres::{Client, NoTls};
+use r2d2_postgres::PostgresConnectionManager;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::{Condition, Filter, SortSpec, Transaction, TransactionState, Update, check_scalars, path};
+use crate::config::{DatabaseSchema, PoolConfig};
+use crate::executor::traits::DatabaseProvider;
+use crate::pipeline::ExecutionError;
+
+type Manager = PostgresConnectionManager<NoTls>;
+type Connection = r2d2::PooledConnection<Manager>;
+
+/// A document store in a PostgreSQL database
+///
+/// Tables and indexes are created from the collection schemas when the
+/// provider connects: unique fields, primary keys and unique indexes become
+/// unique expression indexes, other `IndexDefinition`s become plain indexes.
+/// Collections without a schema get a table on their first insert.
+///
+/// Reads and writes made directly through the provider each use a pooled
+/// connection of their own. Writes lock their table until they finish, so
+/// that no other write comes between the match and the change of an
+/// `upsert`; reads are not blocked. A transaction holds one connection from
+/// `begin` to its end, and the tables it wrote to stay locked until then.
+#[derive(Clone)]
+pub struct PostgresDatabase {
+    pool: Arc<Pool>,
+    /// Name reported when the `_id` column's primary key is violated, per collection
+    id_constraints: Arc<HashMap<String, String>>,
+    /// Top-level fields the schema declares with a scalar type, per collection
+    declared_scalars: Arc<HashMap<String, Vec<String>>>,
+    /// The declared scalar fields that no row holds an array in, per collection
+    scalar_fields: Arc<HashMap<String, Vec<String>>>,
+    /// The connection of the transaction started by `begin`, while it is open
+    transaction: Arc<TransactionState<Connection>>,
+    /// The transaction this is the provider of, as returned by `begin`
+    owner: Option<Arc<Transaction<Connection>>>,
+}
+
+impl std::fmt::Debug for PostgresDatabase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PostgresDatabase").finish_non_exhaustive()
+    }
+}
+
+/// The connection pool, closed off the async runtime when the last provider is dropped
+struct Pool(Option<r2d2::Pool<Manager>>);
+
+impl Pool {
+    fn get(&self) -> Result<Connection, ExecutionError> {
+        let pool = self.0.as_ref().ok_or_else(|| ExecutionError::database_error("The connection pool is closed"))?;
+        pool.get().map_err(|e| ExecutionError::database_error(format!("PostgreSQL error: {}", e)))
+    }
+}
+
+impl Drop for Pool {
+    fn drop(&mut self) {
+        if let Some(pool) = self.0.take() {
+            off_runtime(move || drop(pool));
+        }
+    }
+}
+
+impl PostgresDatabase {
+    /// Connect to the database at `url` and set up tables for the schemas
+    ///
+    /// Fails if no connection can be made within `pool.connect_timeout()`.
+    pub fn connect(
+        url: &str,
+        pool: &PoolConfig,
+        schemas: &HashMap<String, DatabaseSchema>,
+    ) -> Result<Self, ExecutionError> {
+        if pool.size == 0 {
+            return Err(ExecutionError::database_error("The connection pool size must be at least 1"));
+        }
+        let mut config: postgres::Config = url
+            .parse()
+            .map_err(|e| ExecutionError::database_error(format!("Invalid PostgreSQL URL: {}", e)))?;
+        config.connect_timeout(pool.connect_timeout());
+        let connections = r2d2::Pool::builder()
+            .max_size(pool.size)
+            .min_idle(Some(0))
+            .connection_timeout(pool.connect_timeout())
+            .idle_timeout(pool.idle_timeout())
+            .build_unchecked(PostgresConnectionManager::new(config, NoTls));
+        let pool = Pool(Some(connections));
+
+        let (id_constraints, declared_scalars, scalar_fields) = off_runtime(|| {
+            let mut conn = pool.get()?;
+            atomically(&mut conn, false, |conn| set_up(conn, schemas))
+        })?;
+
+        Ok(Self {
+            pool: Arc::new(pool),
+            id_constraints: Arc::new(id_constraints),
+            declared_scalars: Arc::new(declared_scalars),
+            scalar_fields: Arc::new(scalar_fields),
+            transaction: Arc::new(TransactionState::new()),
+            owner: None,
+        })
+    }
+
+    /// Run `f` on the connection of this provider's transaction, or on a pooled one
+    ///
+    /// `f` is told whether it runs inside the transaction.
+    fn with_connection<R>(
+        &self,
+        f: impl FnOnce(&mut Client, bool) -> Result<R, ExecutionError>,
+    ) -> Result<R, ExecutionError> {
+        match &self.owner {
+            Some(owner) => self.transaction.run(Some(owner), |conn| match conn {
+                Some(conn) => f(conn, true),
+                None => Err(ExecutionError::database_error("No transaction in progress")),
+            }),
+            None => f(&mut *self.pool.get()?, false),
+        }
+    }
+
+    /// Run a write on a collection atomically, holding the lock on its table
+    ///
+    /// Creates the table if `create` is set; otherwise `f` is not run and
+    /// the default result is returned if there is no table.
+    fn write<R: Default>(
+        &self,
+        collection: &str,
+        create: bool,
+        f: impl FnOnce(&mut Client) -> Result<R, ExecutionError>,
+    ) -> Result<R, ExecutionError> {
+        self.with_connection(|conn, in_transaction| {
+            atomically(conn, in_transaction, |conn| {
+                if create {
+                    let pkey = self.id_constraint(collection);
+                    create_table(conn, collection, &pkey)?;
+                } else if !table_exists(conn, collection)? {
+                    return Ok(R::default());
+                }
+                conn.batch_execute(&format!("LOCK TABLE {} IN SHARE ROW EXCLUSIVE MODE", quote_ident(collection)))
+                    .map_err(sql_error)?;
+                f(conn)
+            })
+        })
+    }
+
+    /// Map a PostgreSQL error, naming the violated constraint for unique violations
+    fn error(&self, collection: &str, err: postgres::Error) -> ExecutionError {
+        if err.code() != Some(&SqlState::UNIQUE_VIOLATION) {
+            return sql_error(err);
+        }
+
+        let constraint = err
+            .as_db_error()
+            .and_then(|e| e.constraint())
+            .map_or_else(|| self.id_constraint(collection), str::to_string);
+        ExecutionError::constraint_violation(
+            &constraint,
+            format!(
+                "Duplicate key in collection '{}' violates unique constraint '{}'",
+                collection, constraint
+            ),
+        )
+    }
+
+    /// Name of the `_id` column's primary key (see `SqliteDatabase`)
+    fn id_constraint(&self, collection: &str) -> String {
+        self.id_constraints
+            .get(collection)
+            .cloned()
+            .unwrap_or_else(|| format!("{}_pkey", collection))
+    }
+
+    /// Top-level fields the schema declares with a scalar type
+    fn declared_scalars(&self, collection: &str) -> &[String] {
+        self.declared_scalars.get(collection).map_or(&[], Vec::as_slice)
+    }
+
+    /// Declared scalar fields that can be compared without looking for arrays
+    fn scalar_fields(&self, collection: &str) -> &[String] {
+        self.scalar_fields.get(collection).map_or(&[], Vec::as_slice)
+    }
+
+    /// The rows matching a filter with their `seq`, in insertion order
+    fn matching(
+        &self,
+        conn: &mut Client,
+        collection: &str,
+        filter: Option<&Filter>,
+    ) -> Result<Vec<(i64, Value)>, ExecutionError> {
+        let mut sql = Sql::default();
+        sql.push(&format!("SELECT t.seq, t.doc FROM {} AS t", quote_ident(collection)));
+        if let Some(condition) = filter.and_then(|f| narrowing(f, self.scalar_fields(collection), &mut sql.params)) {
+            sql.push(" WHERE ");
+            sql.push(&condition);
+        }
+        sql.push(" ORDER BY t.seq");
+
+        let rows = conn.query(&sql.text, &sql.params()).map_err(|e| self.error(collection, e))?;
+        Ok(rows
+            .iter()
+            .map(|row| (row.get::<_, i64>(0), row.get::<_, Value>(1)))
+            .filter(|(_, doc)| filter.is_none_or(|f| f.matches(doc)))
+            .collect())
+    }
+
+    /// Insert a document into an existing table, generating an `_id` if needed
+    fn insert_row(
+        &self,
+        conn: &mut Client,
+        collection: &str,
+        document: &HashMap<String, Value>,
+    ) -> Result<Value, ExecutionError> {
+        let mut doc: serde_json::Map<String, Value> =
+            document.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        if !doc.contains_key("_id") {
+            let row = conn
+                .query_one("SELECT substr(replace(gen_random_uuid()::text, '-', ''), 1, 24)", &[])
+                .map_err(sql_error)?;
+            doc.insert("_id".to_string(), Value::String(row.get(0)));
+        }
+        let doc = Value::Object(doc);
+        check_scalars(self.declared_scalars(collection), collection, &doc)?;
+
+        conn.execute(
+            &format!("INSERT INTO {} (_id, doc) VALUES ($1, $2)", quote_ident(collection)),
+            &[&doc["_id"], &doc],
+        )
+        .map_err(|e| self.error(collection, e))?;
+
+        Ok(doc)
+    }
+
+    /// Apply an update to the document of a row, returning it after the update
+    fn update_row(
+        &self,
+        conn: &mut Client,
+        collection: &str,
+        seq: i64,
+        doc: &Value,
+        update: &Update,
+    ) -> Result<Value, ExecutionError> {
+        let mut after = doc.clone();
+        update.apply(&mut after)?;
+        check_scalars(self.declared_scalars(collection), collection, &after)?;
+        conn.execute(
+            &format!("UPDATE {} SET _id = $1, doc = $2 WHERE seq = $3", quote_ident(collection)),
+            &[&after["_id"], &after, &seq],
+        )
+        .map_err(|e| self.error(collection, e))?;
+        Ok(after)
+    }
+}
+
+impl DatabaseProvider for PostgresDatabase {
+    fn query(
+        &self,
+        collection: &str,
+        filter: Option<&Filter>,
+        select: Option<&[String]>,
+        limit: Option<u32>,
+        skip: Option<u32>,
+        sort: Option<&SortSpec>,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        let rows = self.with_connection(|conn, _| {
+            if !table_exists(conn, collection)? {
+                return Ok(vec![]);
+            }
+            self.matching(conn, collection, filter)
+        })?;
+
+        let mut docs: Vec<Value> = rows.into_iter().map(|(_, doc)| doc).collect();
+        if let Some(sort) = sort {
+            sort.apply(&mut docs);
+        }
+        Ok(docs
+            .into_iter()
+            .skip(skip.unwrap_or(0) as usize)
+            .take(limit.map_or(usize::MAX, |limit| limit as usize))
+            .map(|doc| match select {
+                Some(fields) => path::project(&doc, fields),
+                None => doc,
+            })
+            .collect())
+    }
+
+    fn insert(
+        &self,
+        collection: &str,
+        document: &HashMap<String, Value>,
+    ) -> Result<Value, ExecutionError> {
+        self.write(collection, true, |conn| self.insert_row(conn, collection, document))
+    }
+
+    fn update(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        self.write(collection, false, |conn| {
+            self.matching(conn, collection, Some(filter))?
+                .into_iter()
+                .map(|(seq, doc)| self.update_row(conn, collection, seq, &doc, update))
+                .collect()
+        })
+    }
+
+    fn upsert(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+        insert: &HashMap<String, Value>,
+    ) -> Result<(Option<Value>, Value), ExecutionError> {
+        self.write(collection, true, |conn| {
+            match self.matching(conn, collection, Some(filter))?.into_iter().next() {
+                Some((seq, before)) => {
+                    let after = self.update_row(conn, collection, seq, &before, update)?;
+                    Ok((Some(before), after))
+                }
+                None => Ok((None, self.insert_row(conn, collection, insert)?)),
+            }
+        })
+    }
+
+    fn find_one_and_update(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+        sort: Option<&SortSpec>,
+    ) -> Result<Option<(Value, Value)>, ExecutionError> {
+        self.write(collection, false, |conn| {
+            // The first match in sort order, keeping insertion order among equals
+            let first = self
+                .matching(conn, collection, Some(filter))?
+                .into_iter()
+                .min_by(|(_, a), (_, b)| sort.map_or(Ordering::Equal, |sort| sort.compare(a, b)));
+            let Some((seq, before)) = first else {
+                return Ok(None);
+            };
+            let after = self.update_row(conn, collection, seq, &before, update)?;
+            Ok(Some((before, after)))
+        })
+    }
+
+    fn delete(
+        &self,
+        collection: &str,
+        filter: &Filter,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        self.write(collection, false, |conn| {
+            let (seqs, docs): (Vec<i64>, Vec<Value>) = self.matching(conn, collection, Some(filter))?.into_iter().unzip();
+            if !seqs.is_empty() {
+                conn.execute(&format!("DELETE FROM {} WHERE seq = ANY($1)", quote_ident(collection)), &[&seqs])
+                    .map_err(sql_error)?;
+            }
+            Ok(docs)
+        })
+    }
+
+    fn begin(&self) -> Result<Box<dyn DatabaseProvider>, ExecutionError> {
+        let transaction = self.transaction.begin(
+            || {
+                let mut conn = self.pool.get()?;
+                conn.batch_execute("BEGIN").map_err(sql_error)?;
+                Ok(conn)
+            },
+            |mut conn: Connection| off_runtime(move || conn.batch_execute("ROLLBACK").map_err(sql_error)),
+        )?;
+        Ok(Box::new(PostgresDatabase {
+            owner: Some(Arc::new(transaction)),
+            ..self.clone()
+        }))
+    }
+
+    fn commit(&self) -> Result<(), ExecutionError> {
+        // A failed COMMIT ends the transaction with a rollback
+        self.transaction
+            .commit(self.owner.as_deref(), |mut conn| conn.batch_execute("COMMIT").map_err(sql_error))
+    }
+
+    fn rollback(&self) -> Result<(), ExecutionError> {
+        self.transaction.rollback(self.owner.as_deref())
+    }
+}
+
+/// Collection settings found by `set_up`: `_id` constraint names, declared
+/// scalar fields and the scalar fields without arrays
+type Setup = (HashMap<String, String>, HashMap<String, Vec<String>>, HashMap<String, Vec<String>>);
+
+/// Create the tables and indexes of the schemas
+fn set_up(conn: &mut Client, schemas: &HashMap<String, DatabaseSchema>) -> Result<Setup, ExecutionError> {
+    let mut id_constraints = HashMap::new();
+    let mut declared_scalars = HashMap::new();
+    let mut scalar_fields = HashMap::new();
+    for (collection, schema) in schemas {
+        let constraints = schema.unique_constraints(collection);
+        let pkey = format!("{}_pkey", collection);
+        let id_is_pkey = constraints
+            .iter()
+            .find(|c| c.name == pkey)
+            .is_none_or(|c| c.fields == ["_id"]);
+        let id_constraint = if id_is_pkey { pkey } else { format!("{}__id_key", collection) };
+        create_table(conn, collection, &id_constraint)?;
+        id_constraints.insert(collection.clone(), id_constraint);
+
+        for constraint in constraints.iter().filter(|c| c.fields != ["_id"]) {
+            create_index(conn, collection, &constraint.name, &constraint.fields, true)?;
+        }
+        for index in schema.indexes.iter().filter(|index| !index.unique) {
+            let name = index.name.clone().unwrap_or_else(|| {
+                let fields: Vec<String> = index.fields.iter().map(|f| f.replace('.', "_")).collect();
+                format!("{}_{}_idx", collection, fields.join("_"))
+            });
+            create_index(conn, collection, &name, &index.fields, false)?;
+        }
+
+        let declared = schema.scalar_fields();
+        let mut scalars = Vec::new();
+        for field in &declared {
+            if !holds_array(conn, collection, field)? {
+                scalars.push(field.clone());
+            }
+        }
+        declared_scalars.insert(collection.clone(), declared);
+        scalar_fields.insert(collection.clone(), scalars);
+    }
+    Ok((id_constraints, declared_scalars, scalar_fields))
+}
+
+/// Run `f` in a transaction of its own, or in a savepoint of the open one
+///
+/// If `f` fails, everything it did is rolled back.
+fn atomically<R>(
+    conn: &mut Client,
+    in_transaction: bool,
+    f: impl FnOnce(&mut Client) -> Result<R, ExecutionError>,
+) -> Result<R, ExecutionError> {
+    let (start, end, undo) = if in_transaction {
+        (
+            "SAVEPOINT deck_write",
+            "RELEASE SAVEPOINT deck_write",
+            "ROLLBACK TO SAVEPOINT deck_write; RELEASE SAVEPOINT deck_write",
+        )
+    } else {
+        ("BEGIN", "COMMIT", "ROLLBACK")
+    };
+
+    conn.batch_execute(start).map_err(sql_error)?;
+    match f(conn) {
+        Ok(result) => {
+            conn.batch_execute(end).map_err(sql_error)?;
+            Ok(result)
+        }
+        Err(err) => {
+            let _ = conn.batch_execute(undo);
+            Err(err)
+        }
+    }
+}
+
+// Schema setup
+
+/// Create a collection's table and the GIN index on its documents
+///
+/// Runs inside a transaction: creating the same table from two connections
+/// at once is serialized with an advisory lock on the table name.
+fn create_table(conn: &mut Client, collection: &str, id_constraint: &str) -> Result<(), ExecutionError> {
+    if table_exists(conn, collection)? {
+        return Ok(());
+    }
+    conn.execute("SELECT pg_advisory_xact_lock(hashtext($1::text))", &[&collection])
+        .map_err(sql_error)?;
+    conn.batch_execute(&format!(
+        "CREATE TABLE IF NOT EXISTS {table} (seq bigserial NOT NULL, _id jsonb NOT NULL, doc jsonb NOT NULL, \
+         CONSTRAINT {pkey} PRIMARY KEY (_id)); \
+         CREATE INDEX IF NOT EXISTS {doc_idx} ON {table} USING gin (doc jsonb_path_ops)",
+        table = quote_ident(collection),
+        pkey = quote_ident(id_constraint),
+        doc_idx = quote_ident(&format!("{}__doc_idx", collection)),
+    ))
+    .map_err(sql_error)
+}
+
+/// Create an index over the fields of a collection
+///
+/// Missing and `null` fields index as SQL `NULL`, so a unique index does not
+/// constrain them, as in `UniqueConstraint`.
+fn create_index(
+    conn: &mut Client,
+    collection: &str,
+    name: &str,
+    fields: &[String],
+    unique: bool,
+) -> Result<(), ExecutionError> {
+    let columns: Vec<String> = fields.iter().map(|field| format!("({})", field_sql("doc", field))).collect();
+    conn.batch_execute(&format!(
+        "CREATE {}INDEX IF NOT EXISTS {} ON {} ({})",
+        if unique { "UNIQUE " } else { "" },
+        quote_ident(name),
+        quote_ident(collection),
+        columns.join(", ")
+    ))
+    .map_err(sql_error)
+}
+
+fn table_exists(conn: &mut Client, collection: &str) -> Result<bool, ExecutionError> {
+    conn.query_one("SELECT to_regclass($1::text) IS NOT NULL", &[&quote_ident(collection)])
+        .map(|row| row.get(0))
+        .map_err(sql_error)
+}
+
+/// Whether a top-level field holds an array in any row of a table
+fn holds_array(conn: &mut Client, collection: &str, field: &str) -> Result<bool, ExecutionError> {
+    conn.query_one(
+        &format!(
+            "SELECT EXISTS (SELECT 1 FROM {} AS t WHERE jsonb_typeof(t.doc -> $1::text) = 'array')",
+            quote_ident(collection)
+        ),
+        &[&field],
+    )
+    .map(|row| row.get(0))
+    .map_err(sql_error)
+}
+
+// Filter narrowing
+
+/// SQL text with its `jsonb` parameters
+#[derive(Default)]
+struct Sql {
+    text: String,
+    params: Vec<Value>,
+}
+
+impl Sql {
+    fn push(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
+    fn params(&self) -> Vec<&(dyn ToSql + Sync)> {
+        self.params.iter().map(|param| param as &(dyn ToSql + Sync)).collect()
+    }
+}
+
+/// A SQL condition on the row `t` that every document matching `filter` meets
+///
+/// Only `$eq` and `$in` conditions with non-null scalar operands on
+/// top-level fields are translated; they compare the indexed expression of
+/// fields in `scalars` (see `create_index`) and otherwise use `@>`, which
+/// the GIN index serves, also matching an array holding the operand. Other
+/// conditions give `None`, leaving the filter to `Filter::matches`.
+/// Parameters are only added to `params` when a condition is returned.
+fn narrowing(filter: &Filter, scalars: &[String], params: &mut Vec<Value>) -> Option<String> {
+    match filter {
+        Filter::And(filters) => {
+            let conditions: Vec<String> = filters.iter().filter_map(|f| narrowing(f, scalars, params)).collect();
+            (!conditions.is_empty()).then(|| format!("({})", conditions.join(" AND ")))
+        }
+        Filter::Or(filters) if filters.is_empty() => Some("false".to_string()),
+        Filter::Or(filters) => {
+            let start = params.len();
+            let conditions: Option<Vec<String>> = filters.iter().map(|f| narrowing(f, scalars, params)).collect();
+            if conditions.is_none() {
+                params.truncate(start);
+            }
+            conditions.map(|conditions| format!("({})", conditions.join(" OR ")))
+        }
+        Filter::Not(_) => None,
+        Filter::Field { path, .. } if path.contains('.') => None,
+        Filter::Field { path, condition } => {
+            let operands = match condition {
+                Condition::Eq(operand) => vec![operand],
+                Condition::In(Value::Array(items)) => items.iter().collect(),
+                _ => return None,
+            };
+            if !operands.iter().all(|operand| is_plain_scalar(operand)) {
+                return None;
+            }
+            if operands.is_empty() {
+                return Some("false".to_string());
+            }
+
+            let conditions: Vec<String> = operands
+                .into_iter()
+                .map(|operand| {
+                    if scalars.contains(path) {
+                        params.push(operand.clone());
+                        format!("{} = ${}", field_sql("t.doc", path), params.len())
+                    } else {
+                        params.push(object(path, operand.clone()));
+                        params.push(object(path, Value::Array(vec![operand.clone()])));
+                        format!("t.doc @> ${} OR t.doc @> ${}", params.len() - 1, params.len())
+                    }
+                })
+                .collect();
+            Some(format!("({})", conditions.join(" OR ")))
+        }
+    }
+}
+
+/// Whether a value is a string, number or boolean
+fn is_plain_scalar(value: &Value) -> bool {
+    matches!(value, Value::String(_) | Value::Number(_) | Value::Bool(_))
+}
+
+/// A document with a single field
+fn object(field: &str, value: Value) -> Value {
+    Value::Object(serde_json::Map::from_iter([(field.to_string(), value)]))
+}
+
+/// The indexed expression for a dot-path into the documents of `column`: the
+/// value at the path, or `NULL` if it is missing or `null`, treating every
+/// segment as an object key
+fn field_sql(column: &str, field: &str) -> String {
+    let value = field
+        .split('.')
+        .fold(column.to_string(), |sql, segment| format!("{} -> {}", sql, quote_literal(segment)));
+    format!("nullif({}, 'null'::jsonb)", value)
+}
+
+/// Run `f` on a thread of its own when called from inside a tokio runtime
+///
+/// The `postgres` client blocks on a runtime of its own, which tokio does not
+/// allow inside another runtime's tasks. Provider calls run on blocking
+/// threads (see `BlockingDatabase`), but the provider can be connected, and
+/// a transaction or the pool dropped, by an async task.
+fn off_runtime<R: Send>(f: impl FnOnce() -> R + Send) -> R {
+    if tokio::runtime::Handle::try_current().is_err() {
+        return f();
+    }
+    std::thread::scope(|scope| match scope.spawn(f).join() {
+        Ok(result) => result,
+        Err(panic) => std::panic::resume_unwind(panic),
+    })
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quote_literal(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+fn sql_error(err: postgres::Error) -> ExecutionError {
+    ExecutionError::database_error(format!("PostgreSQL error: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::traits::MockDatabase;
+    use serde_json::json;
+    use std::time::Duration;
+
+    /// A provider on the server named by `DECK_TEST_POSTGRES_URL`, after
+    /// dropping the tables of `collections`, or `None` to skip the test
+    fn open(collections: &[&str], schemas: &HashMap<String, DatabaseSchema>) -> Option<PostgresDatabase> {
+        let Ok(url) = std::env::var("DECK_TEST_POSTGRES_URL") else {
+            eprintln!("DECK_TEST_POSTGRES_URL is not set, skipping");
+            return None;
+        };
+        let mut client = Client::connect(&url, NoTls).unwrap();
+        for collection in collections {
+            client.batch_execute(&format!("DROP TABLE IF EXISTS {}", quote_ident(collection))).unwrap();
+        }
+        Some(PostgresDatabase::connect(&url, &PoolConfig::default(), schemas).unwrap())
+    }
+
+    fn documents() -> Vec<Value> {
+        vec![
+            json!({"_id": "1", "title": "Rust", "views": 10, "tags": ["rust", "json"], "author": {"id": "u1"}}),
+            json!({"_id": "2", "title": "Serde", "views": 3, "tags": [], "draft": true}),
+            json!({"_id": "3", "title": "Axum", "views": 10.0, "tags": ["web"], "comments": [{"by": "ann"}, {"by": "bob"}]}),
+            json!({"_id": "4", "title": "null views", "views": null, "tags": [["rust"]]}),
+            json!({"_id": "5", "title": "Tokio", "views": "10", "tags": "rust"}),
+        ]
+    }
+
+    /// A mock and a PostgreSQL provider holding `documents()` in `collection`
+    fn providers(collection: &str) -> Option<(MockDatabase, PostgresDatabase)> {
+        let schemas: HashMap<String, DatabaseSchema> = serde_json::from_value(json!({
+            collection: {"fields": {"title": {"type": "string"}}}
+        }))
+        .unwrap();
+        let postgres = open(&[collection], &schemas)?;
+        for doc in documents() {
+            postgres.insert(collection, &serde_json::from_value(doc).unwrap()).unwrap();
+        }
+        let mock = MockDatabase::new().with_collection(collection, documents()).with_schemas(&schemas);
+        Some((mock, postgres))
+    }
+
+    fn ids(docs: &[Value]) -> Vec<String> {
+        docs.iter().map(|d| d["_id"].as_str().unwrap_or_default().to_string()).collect()
+    }
+
+    fn narrowed(filter: Value) -> (Option<String>, Vec<Value>) {
+        let mut params = vec![];
+        let condition = narrowing(&Filter::from_json(&filter).unwrap(), &["email".to_string()], &mut params);
+        (condition, params)
+    }
+
+    #[test]
+    fn test_narrowing() {
+        // Declared scalar fields compare the indexed expression
+        assert_eq!(
+            narrowed(json!({"email": {"$in": ["a@x", 1]}})),
+            (
+                Some("(nullif(t.doc -> 'email', 'null'::jsonb) = $1 OR nullif(t.doc -> 'email', 'null'::jsonb) = $2)".to_string()),
+                vec![json!("a@x"), json!(1)]
+            )
+        );
+        // Other fields may hold an array of the operand
+        assert_eq!(
+            narrowed(json!({"tags": "rust"})),
+            (Some("(t.doc @> $1 OR t.doc @> $2)".to_string()), vec![json!({"tags": "rust"}), json!({"tags": ["rust"]})])
+        );
+        // Conditions that cannot be translated are left out of an `$and`...
+        assert_eq!(
+            narrowed(json!({"author.id": "u1", "email": "a@x", "views": null, "draft": {"$ne": true}})),
+            (Some("((nullif(t.doc -> 'email', 'null'::jsonb) = $1))".to_string()), vec![json!("a@x")])
+        );
+        // ...and keep an `$or` from narrowing anything, without leaving parameters behind
+        assert_eq!(narrowed(json!({"$or": [{"tags": "rust"}, {"views": {"$gt": 1}}]})), (None, vec![]));
+        assert_eq!(narrowed(json!({"$not": {"email": "a@x"}})), (None, vec![]));
+        assert_eq!(narrowed(json!({"email": {"$in": []}})), (Some("false".to_string()), vec![]));
+    }
+
+    #[test]
+    fn test_filters_match_mock_semantics() {
+        let Some((mock, postgres)) = providers("pg_filter_posts") else {
+            return;
+        };
+        let filters = [
+            json!({"_id": "3"}),
+            json!({"title": "Rust"}),
+            json!({"title": {"$in": ["Axum", "Tokio", 10]}}),
+            json!({"views": 10}),
+            json!({"views": {"$in": [3, "10"]}}),
+            json!({"views": null}),
+            json!({"tags": "rust"}),
+            json!({"tags": ["rust", "json"]}),
+            json!({"tags": {"$in": ["web", "json"]}}),
+            json!({"tags": {"$in": []}}),
+            json!({"draft": true}),
+            json!({"author.id": "u1"}),
+            json!({"comments.by": "bob"}),
+            json!({"title": "Rust", "views": {"$gte": 10}}),
+            json!({"$or": [{"title": "Rust"}, {"views": 3}]}),
+            json!({"$or": [{"title": "Rust"}, {"views": {"$gt": 5}}]}),
+            json!({"$not": {"tags": "rust"}}),
+        ];
+
+        for filter in filters {
+            let parsed = Filter::from_json(&filter).unwrap();
+            let expected = mock.query("pg_filter_posts", Some(&parsed), None, None, None, None).unwrap();
+            let actual = postgres.query("pg_filter_posts", Some(&parsed), None, None, None, None).unwrap();
+            assert_eq!(actual, expected, "filter {}", filter);
+            assert_eq!(postgres.count("pg_filter_posts", Some(&parsed)).unwrap(), expected.len() as u64, "filter {}", filter);
+        }
+
+        let sort: SortSpec = serde_json::from_value(json!([{"views": "desc"}, "title"])).unwrap();
+        let select = ["title".to_string()];
+        let expected = mock.query("pg_filter_posts", None, Some(&select), Some(2), Some(1), Some(&sort)).unwrap();
+        let actual = postgres.query("pg_filter_posts", None, Some(&select), Some(2), Some(1), Some(&sort)).unwrap();
+        assert_eq!(actual, expected);
+        assert_eq!(postgres.query("pg_filter_missing", None, None, None, None, None).unwrap(), Vec::<Value>::new());
+    }
+
+    #[test]
+    fn test_writes_match_mock_semantics() {
+        let Some((mock, postgres)) = providers("pg_write_posts") else {
+            return;
+        };
+        let collection = "pg_write_posts";
+        let filter = |value: Value| Filter::from_json(&value).unwrap();
+        let update = |value: Value| Update::from_json(&value).unwrap();
+        let document = |value: Value| serde_json::from_value::<HashMap<String, Value>>(value).unwrap();
+        let sort: SortSpec = serde_json::from_value(json!([{"title": "desc"}])).unwrap();
+
+        for db in [&mock as &dyn DatabaseProvider, &postgres] {
+            assert_eq!(
+                db.update(collection, &filter(json!({"tags": "rust"})), &update(json!({"$inc": {"hits": 1}}))).unwrap().len(),
+                2
+            );
+            let (before, after) = db
+                .find_one_and_update(collection, &filter(json!({"views": 10})), &update(json!({"$set": {"picked": true}})), Some(&sort))
+                .unwrap()
+                .unwrap();
+            assert_eq!((before["_id"].clone(), after["picked"].clone()), (json!("1"), json!(true)));
+            let (matched, written) = db
+                .upsert(collection, &filter(json!({"_id": "6"})), &update(json!({"$set": {"n": 1}})), &document(json!({"_id": "6", "title": "New"})))
+                .unwrap();
+            assert_eq!((matched, written["title"].clone()), (None, json!("New")));
+            assert_eq!(ids(&db.delete(collection, &filter(json!({"title": {"$in": ["Serde", "Axum"]}}))).unwrap()), vec!["2", "3"]);
+            assert_eq!(db.update(collection, &filter(json!({"_id": "9"})), &update(json!({"$set": {"n": 1}}))).unwrap(), Vec::<Value>::new());
+            assert_eq!(db.delete("pg_write_missing", &filter(json!({}))).unwrap(), Vec::<Value>::new());
+
+            // A failed update changes no document
+            let result = db.update(collection, &filter(json!({})), &update(json!({"$inc": {"title": 1}})));
+            assert!(matches!(result, Err(ExecutionError::TypeError { .. })), "{:?}", result);
+        }
+        assert_eq!(
+            postgres.query(collection, None, None, None, None, None).unwrap(),
+            mock.query(collection, None, None, None, None, None).unwrap()
+        );
+
+        // Generated ids are unique
+        let first = postgres.insert(collection, &document(json!({"title": "a"}))).unwrap();
+        let second = postgres.insert(collection, &document(json!({"title": "b"}))).unwrap();
+        assert_ne!(first["_id"], second["_id"]);
+    }
+
+    #[test]
+    fn test_schema_constraints() {
+        let schemas: HashMap<String, DatabaseSchema> = serde_json::from_value(json!({
+            "pg_users": {
+                "fields": {
+                    "_id": {"type": "string", "primary": true},
+                    "email": {"type": "string", "unique": true},
+                    "tenant": {"type": "string"},
+                    "handle": {"type": "string"}
+                },
+                "indexes": [
+                    {"fields": ["tenant", "handle"], "unique": true},
+                    {"fields": ["tenant"]}
+                ]
+            }
+        }))
+        .unwrap();
+        let Some(postgres) = open(&["pg_users"], &schemas) else {
+            return;
+        };
+        let insert = |doc: Value| postgres.insert("pg_users", &serde_json::from_value(doc).unwrap());
+        let constraint = |result: Result<Value, ExecutionError>| match result {
+            Err(ExecutionError::DatabaseError { constraint: Some(name), .. }) => name,
+            other => panic!("Expected constraint violation, got {:?}", other),
+        };
+
+        insert(json!({"_id": "1", "email": "a@x", "tenant": "t1", "handle": "ann"})).unwrap();
+        assert_eq!(constraint(insert(json!({"email": "a@x"}))), "pg_users_email_key");
+        assert_eq!(constraint(insert(json!({"_id": "1"}))), "pg_users_pkey");
+        assert_eq!(constraint(insert(json!({"tenant": "t1", "handle": "ann"}))), "pg_users_tenant_handle_key");
+        assert!(insert(json!({"email": null, "tenant": "t1"})).is_ok());
+        assert!(insert(json!({"email": null, "tenant": "t1"})).is_ok());
+        assert_eq!(
+            insert(json!({"email": ["b@x"]})).unwrap_err(),
+            ExecutionError::database_error(
+                "Field 'email' of collection 'pg_users' is declared as a scalar and cannot hold an array"
+            )
+        );
+
+        // An upsert that breaks a constraint changes nothing
+        let set_email: Update = serde_json::from_value(json!({"email": "a@x"})).unwrap();
+        let insert_doc = serde_json::from_value(json!({"_id": "9", "email": "a@x"})).unwrap();
+        let result = postgres.upsert("pg_users", &Filter::from_json(&json!({"email": null})).unwrap(), &set_email, &insert_doc);
+        assert_eq!(constraint(result.map(|(_, doc)| doc)), "pg_users_email_key");
+        assert_eq!(postgres.count("pg_users", Some(&Filter::from_json(&json!({"email": null})).unwrap())).unwrap(), 2);
+
+        // Lookups on a unique field use its index
+        let mut client = Client::connect(&std::env::var("DECK_TEST_POSTGRES_URL").unwrap(), NoTls).unwrap();
+        let mut params = vec![];
+        let condition = narrowing(&Filter::from_json(&json!({"email": "a@x"})).unwrap(), postgres.scalar_fields("pg_users"), &mut params).unwrap();
+        let sql = format!("EXPLAIN SELECT t.doc FROM \"pg_users\" AS t WHERE {}", condition);
+        client.batch_execute("SET enable_seqscan = off").unwrap();
+        let plan: Vec<String> = client
+            .query(&sql, &[&params[0] as &(dyn ToSql + Sync)])
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        assert!(plan.join("\n").contains("pg_users_email_key"), "{:?}", plan);
+    }
+
+    #[test]
+    fn test_transactions() {
+        let Some(postgres) = open(&["pg_tx_posts"], &HashMap::new()) else {
+            return;
+        };
+        let document = |id: &str| serde_json::from_value::<HashMap<String, Value>>(json!({"_id": id})).unwrap();
+        let all = || ids(&postgres.query("pg_tx_posts", None, None, None, None, None).unwrap());
+        postgres.insert("pg_tx_posts", &document("1")).unwrap();
+
+        // Outside reads do not wait for the transaction nor see its changes
+        let tx = postgres.begin().unwrap();
+        tx.insert("pg_tx_posts", &document("2")).unwrap();
+        assert_eq!(all(), vec!["1"]);
+        assert_eq!(ids(&tx.query("pg_tx_posts", None, None, None, None, None).unwrap()), vec!["1", "2"]);
+
+        // A failed write inside the transaction only undoes itself
+        assert!(matches!(tx.insert("pg_tx_posts", &document("1")), Err(ExecutionError::DatabaseError { constraint: Some(_), .. })));
+        tx.insert("pg_tx_posts", &document("3")).unwrap();
+        tx.commit().unwrap();
+        assert_eq!(all(), vec!["1", "2", "3"]);
+        assert!(tx.commit().is_err());
+
+        let tx = postgres.begin().unwrap();
+        tx.delete("pg_tx_posts", &Filter::all()).unwrap();
+        tx.rollback().unwrap();
+        assert_eq!(all(), vec!["1", "2", "3"]);
+
+        // Outside writes to a table the transaction wrote to wait for it
+        let tx = postgres.begin().unwrap();
+        tx.insert("pg_tx_posts", &document("4")).unwrap();
+        std::thread::scope(|scope| {
+            let write = scope.spawn(|| postgres.insert("pg_tx_posts", &document("5")));
+            std::thread::sleep(Duration::from_millis(50));
+            assert!(!write.is_finished());
+            tx.rollback().unwrap();
+            write.join().unwrap().unwrap();
+        });
+        assert_eq!(all(), vec!["1", "2", "3", "5"]);
+    }
+
+    #[test]
+    fn test_transaction_dropped_by_async_task_rolls_back() {
+        let Some(postgres) = open(&["pg_drop_posts"], &HashMap::new()) else {
+            return;
+        };
+        let tx = postgres.begin().unwrap();
+        tx.insert("pg_drop_posts", &serde_json::from_value(json!({"_id": "1"})).unwrap()).unwrap();
+
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+        let (postgres, dropped) = runtime.block_on(async move {
+            drop(tx);
+            // The last handle on the pool can be dropped here too
+            let dropped = postgres.clone();
+            (postgres, dropped)
+        });
+        runtime.block_on(async move { drop(dropped) });
+
+        let tx = postgres.begin().unwrap();
+        assert_eq!(tx.count("pg_drop_posts", None).unwrap(), 0);
+        tx.commit().unwrap();
+        runtime.block_on(async move { drop(postgres) });
+    }
+}
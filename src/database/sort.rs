@@ -0,0 +1,371 @@
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{SerializeMap, SerializeSeq, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{Number, Value};
+use std::cmp::Ordering;
+use std::fmt;
+
+use super::path;
+
+/// Sort order for database queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortOrder {
+    #[serde(rename = "asc")]
+    Ascending,
+    #[serde(rename = "desc")]
+    Descending,
+}
+
+/// One sort key: a dot-path field and its direction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub field: String,
+    pub order: SortOrder,
+}
+
+/// An ordered list of sort keys
+///
+/// Documents are compared by the first key, ties are broken by the next key
+/// and so on; documents equal on every key keep their original order.
+/// Accepted JSON forms:
+///
+/// ```json
+/// [{"createdAt": "desc"}, {"_id": "asc"}]
+/// ["title", {"views": "desc"}]
+/// {"createdAt": "desc", "_id": "asc"}
+/// ```
+///
+/// A bare string sorts ascending. The object form keeps the order in which
+/// the keys appear in the configuration file.
+///
+/// Values are compared with `compare_values`, so mixed types sort in a fixed
+/// order and a missing field sorts like `null`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SortSpec {
+    pub keys: Vec<SortKey>,
+}
+
+impl SortSpec {
+    /// Create a sort spec from keys in priority order
+    pub fn new(keys: Vec<SortKey>) -> Self {
+        Self { keys }
+    }
+
+    /// Add a key with lower priority than the existing keys
+    pub fn then(mut self, field: impl Into<String>, order: SortOrder) -> Self {
+        self.keys.push(SortKey {
+            field: field.into(),
+            order,
+        });
+        self
+    }
+
+    /// Compare two documents by every key in order
+    pub fn compare(&self, a: &Value, b: &Value) -> Ordering {
+        self.compare_by(|key| {
+            compare_values(
+                path::get(a, &key.field).unwrap_or(&Value::Null),
+                path::get(b, &key.field).unwrap_or(&Value::Null),
+            )
+        })
+    }
+
+    /// Compare a document with a position holding one value per key
+    ///
+    /// Positions are what `key_of` returns for a document.
+    pub fn compare_to_key(&self, doc: &Value, position: &[Value]) -> Ordering {
+        let mut position = position.iter();
+        self.compare_by(|key| {
+            compare_values(
+                path::get(doc, &key.field).unwrap_or(&Value::Null),
+                position.next().unwrap_or(&Value::Null),
+            )
+        })
+    }
+
+    /// The value of every key in a document (`null` for a missing field)
+    pub fn key_of(&self, doc: &Value) -> Vec<Value> {
+        self.keys
+            .iter()
+            .map(|key| path::get(doc, &key.field).cloned().unwrap_or(Value::Null))
+            .collect()
+    }
+
+    /// The same keys in the opposite directions
+    pub fn reversed(&self) -> Self {
+        let keys = self
+            .keys
+            .iter()
+            .map(|key| SortKey {
+                field: key.field.clone(),
+                order: match key.order {
+                    SortOrder::Ascending => SortOrder::Descending,
+                    SortOrder::Descending => SortOrder::Ascending,
+                },
+            })
+            .collect();
+        Self { keys }
+    }
+
+    /// Combine the ascending comparisons of each key, applying its direction
+    fn compare_by(&self, mut compare: impl FnMut(&SortKey) -> Ordering) -> Ordering {
+        for key in &self.keys {
+            let ordering = match key.order {
+                SortOrder::Ascending => compare(key),
+                SortOrder::Descending => compare(key).reverse(),
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        Ordering::Equal
+    }
+
+    /// Stable-sort documents in place
+    pub fn apply(&self, docs: &mut [Value]) {
+        docs.sort_by(|a, b| self.compare(a, b));
+    }
+}
+
+/// Total order over JSON values
+///
+/// Values of different types order as
+/// `null < bool < number < string < array < object`. Within a type, numbers
+/// compare by value, strings by code point, arrays element by element and
+/// objects by their key-sorted entries.
+pub fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => compare_numbers(a, b),
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(a), Value::Array(b)) => a
+            .iter()
+            .zip(b)
+            .map(|(a, b)| compare_values(a, b))
+            .find(|o| o.is_ne())
+            .unwrap_or_else(|| a.len().cmp(&b.len())),
+        (Value::Object(a), Value::Object(b)) => {
+            let mut a: Vec<_> = a.iter().collect();
+            let mut b: Vec<_> = b.iter().collect();
+            a.sort_by(|x, y| x.0.cmp(y.0));
+            b.sort_by(|x, y| x.0.cmp(y.0));
+            a.iter()
+                .zip(&b)
+                .map(|((ak, av), (bk, bv))| ak.cmp(bk).then_with(|| compare_values(av, bv)))
+                .find(|o| o.is_ne())
+                .unwrap_or_else(|| a.len().cmp(&b.len()))
+        }
+        _ => type_rank(a).cmp(&type_rank(b)),
+    }
+}
+
+/// The position of a value's type in the order used by `compare_values`
+pub(crate) fn type_rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
+
+/// Compare numbers by value, exactly for integers
+pub(crate) fn compare_numbers(a: &Number, b: &Number) -> Ordering {
+    if let (Some(a), Some(b)) = (a.as_i64(), b.as_i64()) {
+        a.cmp(&b)
+    } else if let (Some(a), Some(b)) = (a.as_u64(), b.as_u64()) {
+        a.cmp(&b)
+    } else {
+        let a = a.as_f64().unwrap_or(f64::NAN);
+        let b = b.as_f64().unwrap_or(f64::NAN);
+        a.total_cmp(&b)
+    }
+}
+
+// Parsing and serialization
+
+impl<'de> Deserialize<'de> for SortKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SortKeyVisitor;
+
+        impl<'de> Visitor<'de> for SortKeyVisitor {
+            type Value = SortKey;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a field name or an object with a single field and \"asc\"/\"desc\"")
+            }
+
+            fn visit_str<E: de::Error>(self, field: &str) -> Result<SortKey, E> {
+                Ok(SortKey {
+                    field: field.to_string(),
+                    order: SortOrder::Ascending,
+                })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<SortKey, A::Error> {
+                let (field, order) = map
+                    .next_entry::<String, SortOrder>()?
+                    .ok_or_else(|| de::Error::custom("sort key object must have one field"))?;
+                if map.next_key::<String>()?.is_some() {
+                    return Err(de::Error::custom("sort key object must have exactly one field"));
+                }
+                Ok(SortKey { field, order })
+            }
+        }
+
+        deserializer.deserialize_any(SortKeyVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for SortSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SortSpecVisitor;
+
+        impl<'de> Visitor<'de> for SortSpecVisitor {
+            type Value = SortSpec;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a list of sort keys or an object of field to \"asc\"/\"desc\"")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<SortSpec, A::Error> {
+                let mut keys = Vec::new();
+                while let Some(key) = seq.next_element::<SortKey>()? {
+                    keys.push(key);
+                }
+                Ok(SortSpec { keys })
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<SortSpec, A::Error> {
+                let mut keys = Vec::new();
+                while let Some((field, order)) = map.next_entry::<String, SortOrder>()? {
+                    keys.push(SortKey { field, order });
+                }
+                Ok(SortSpec { keys })
+            }
+        }
+
+        deserializer.deserialize_any(SortSpecVisitor)
+    }
+}
+
+impl Serialize for SortKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(1))?;
+        map.serialize_entry(&self.field, &self.order)?;
+        map.end()
+    }
+}
+
+impl Serialize for SortSpec {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.keys.len()))?;
+        for key in &self.keys {
+            seq.serialize_element(key)?;
+        }
+        seq.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn key(field: &str, order: SortOrder) -> SortKey {
+        SortKey {
+            field: field.to_string(),
+            order,
+        }
+    }
+
+    #[test]
+    fn test_parse_list_form() {
+        let spec: SortSpec = serde_json::from_str(r#"[{"views": "desc"}, "title"]"#).unwrap();
+        assert_eq!(
+            spec.keys,
+            vec![key("views", SortOrder::Descending), key("title", SortOrder::Ascending)]
+        );
+
+        assert!(serde_json::from_str::<SortSpec>(r#"[{"a": "asc", "b": "asc"}]"#).is_err());
+        assert!(serde_json::from_str::<SortSpec>(r#"[{"a": "up"}]"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_object_form_keeps_order() {
+        let spec: SortSpec = serde_json::from_str(r#"{"views": "desc", "createdAt": "asc"}"#).unwrap();
+        assert_eq!(
+            spec.keys,
+            vec![key("views", SortOrder::Descending), key("createdAt", SortOrder::Ascending)]
+        );
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let spec = SortSpec::default()
+            .then("views", SortOrder::Descending)
+            .then("_id", SortOrder::Ascending);
+        let json = serde_json::to_string(&spec).unwrap();
+        assert_eq!(json, r#"[{"views":"desc"},{"_id":"asc"}]"#);
+        assert_eq!(serde_json::from_str::<SortSpec>(&json).unwrap(), spec);
+    }
+
+    #[test]
+    fn test_compare_values_cross_type() {
+        let ordered = [
+            json!(null),
+            json!(false),
+            json!(true),
+            json!(-1),
+            json!(2.5),
+            json!(10),
+            json!(""),
+            json!("a"),
+            json!([]),
+            json!([1]),
+            json!([1, 2]),
+            json!({}),
+            json!({"a": 1}),
+        ];
+        for pair in ordered.windows(2) {
+            assert_eq!(compare_values(&pair[0], &pair[1]), Ordering::Less, "{} < {}", pair[0], pair[1]);
+            assert_eq!(compare_values(&pair[1], &pair[0]), Ordering::Greater);
+        }
+        assert_eq!(compare_values(&json!(1), &json!(1.0)), Ordering::Equal);
+        assert_eq!(compare_values(&json!(u64::MAX), &json!(i64::MAX)), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_apply_multi_key_stable() {
+        let mut docs = vec![
+            json!({"_id": "1", "group": "b", "rank": 2}),
+            json!({"_id": "2", "group": "a", "rank": 1}),
+            json!({"_id": "3", "group": "b", "rank": 1}),
+            json!({"_id": "4", "group": "a", "rank": 1}),
+            json!({"_id": "5", "rank": 9}),
+        ];
+        let spec = SortSpec::default()
+            .then("group", SortOrder::Ascending)
+            .then("rank", SortOrder::Descending);
+        spec.apply(&mut docs);
+
+        let ids: Vec<&str> = docs.iter().map(|d| d["_id"].as_str().unwrap()).collect();
+        // Missing group sorts as null (first); equal docs 2 and 4 keep their order
+        assert_eq!(ids, vec!["5", "2", "4", "1", "3"]);
+    }
+
+    #[test]
+    fn test_apply_nested_key() {
+        let mut docs = vec![
+            json!({"_id": "1", "meta": {"views": 5}}),
+            json!({"_id": "2", "meta": {"views": "n/a"}}),
+            json!({"_id": "3", "meta": {"views": 50}}),
+        ];
+        SortSpec::default().then("meta.views", SortOrder::Descending).apply(&mut docs);
+
+        let ids: Vec<&str> = docs.iter().map(|d| d["_id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["2", "3", "1"]);
+    }
+}
@@ -0,0 +1,1670 @@
+//! SQLite-backed `DatabaseProvider`
+//!
+//! Each collection is a table with an `_id` primary key column and the
+//! document stored as JSON text in a `doc` column. Filters and sorts are
+//! translated into SQL over SQLite's JSON functions so that matching,
+//! ordering, pagination and aggregation happen in the database, with the
+//! same semantics as `MockDatabase`.
+
+use regex::Regex;
+use rusqlite::functions::FunctionFlags;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{Connection, OptionalExtension, params_from_iter};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::{
+    Accumulator, Aggregation, Condition, Filter, Group, SortKey, SortOrder, SortSpec, Stage,
+    Transaction, TransactionState, Update, check_scalars, path, sort,
+};
+use crate::config::DatabaseSchema;
+use crate::executor::traits::DatabaseProvider;
+use crate::pipeline::ExecutionError;
+
+/// A document store in a SQLite database file
+///
+/// Tables and indexes are created from the collection schemas when the
+/// database is opened: unique fields, primary keys and unique indexes become
+/// unique expression indexes, other `IndexDefinition`s become plain indexes.
+/// Collections without a schema get a table on their first insert.
+///
+/// Fields the schema declares as `string`, `number`, `boolean` or `datetime`
+/// cannot be written with an array value (see `check_scalars`), so filters on
+/// them compare the field's `json_extract` value and these indexes serve them.
+/// A field that already holds an array in some row when the database is
+/// opened is filtered element by element instead, as in `MockDatabase`.
+#[derive(Clone)]
+pub struct SqliteDatabase {
+    conn: Arc<Mutex<Connection>>,
+    /// Name reported when the `_id` column's primary key is violated, per collection
+    id_constraints: Arc<HashMap<String, String>>,
+    /// Top-level fields the schema declares with a scalar type, per collection
+    declared_scalars: Arc<HashMap<String, Vec<String>>>,
+    /// The declared scalar fields that no row holds an array in, per collection
+    scalar_fields: Arc<HashMap<String, Vec<String>>>,
+    /// Whether a transaction started by `begin` is open on the connection
+    transaction: Arc<TransactionState<()>>,
+    /// The transaction this is the provider of, as returned by `begin`
+    owner: Option<Arc<Transaction<()>>>,
+}
+
+impl std::fmt::Debug for SqliteDatabase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SqliteDatabase").finish_non_exhaustive()
+    }
+}
+
+impl SqliteDatabase {
+    /// Open (or create) a database file and set up tables for the schemas
+    pub fn open(path: impl AsRef<Path>, schemas: &HashMap<String, DatabaseSchema>) -> Result<Self, ExecutionError> {
+        Self::with_connection(Connection::open(path).map_err(sql_error)?, schemas)
+    }
+
+    /// Open a private in-memory database and set up tables for the schemas
+    pub fn open_in_memory(schemas: &HashMap<String, DatabaseSchema>) -> Result<Self, ExecutionError> {
+        Self::with_connection(Connection::open_in_memory().map_err(sql_error)?, schemas)
+    }
+
+    /// Wait up to `timeout` for a locked database instead of failing immediately
+    pub fn with_busy_timeout(self, timeout: Duration) -> Result<Self, ExecutionError> {
+        self.conn.lock().unwrap().busy_timeout(timeout).map_err(sql_error)?;
+        Ok(self)
+    }
+
+    fn with_connection(conn: Connection, schemas: &HashMap<String, DatabaseSchema>) -> Result<Self, ExecutionError> {
+        add_regexp_function(&conn).map_err(sql_error)?;
+
+        let mut id_constraints = HashMap::new();
+        let mut declared_scalars = HashMap::new();
+        let mut scalar_fields = HashMap::new();
+        for (collection, schema) in schemas {
+            create_table(&conn, collection)?;
+            let declared = schema.scalar_fields();
+            let mut scalars = Vec::new();
+            for field in &declared {
+                if !holds_array(&conn, collection, field)? {
+                    scalars.push(field.clone());
+                }
+            }
+            declared_scalars.insert(collection.clone(), declared);
+            scalar_fields.insert(collection.clone(), scalars);
+
+            let constraints = schema.unique_constraints(collection);
+            let pkey = format!("{}_pkey", collection);
+            let id_is_pkey = constraints
+                .iter()
+                .find(|c| c.name == pkey)
+                .is_none_or(|c| c.fields == ["_id"]);
+            id_constraints.insert(
+                collection.clone(),
+                if id_is_pkey { pkey } else { format!("{}__id_key", collection) },
+            );
+
+            for constraint in constraints.iter().filter(|c| c.fields != ["_id"]) {
+                create_index(&conn, collection, &constraint.name, &constraint.fields, true)?;
+            }
+            for index in schema.indexes.iter().filter(|index| !index.unique) {
+                let name = index.name.clone().unwrap_or_else(|| {
+                    let fields: Vec<String> = index.fields.iter().map(|f| f.replace('.', "_")).collect();
+                    format!("{}_{}_idx", collection, fields.join("_"))
+                });
+                create_index(&conn, collection, &name, &index.fields, false)?;
+            }
+        }
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            id_constraints: Arc::new(id_constraints),
+            declared_scalars: Arc::new(declared_scalars),
+            scalar_fields: Arc::new(scalar_fields),
+            transaction: Arc::new(TransactionState::new()),
+            owner: None,
+        })
+    }
+
+    /// Map a SQLite error, naming the violated constraint for unique violations
+    fn error(&self, collection: &str, err: rusqlite::Error) -> ExecutionError {
+        let unique_violation = matches!(
+            err.sqlite_error().map(|e| e.extended_code),
+            Some(rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE | rusqlite::ffi::SQLITE_CONSTRAINT_PRIMARYKEY)
+        );
+        if !unique_violation {
+            return sql_error(err);
+        }
+
+        let message = err.to_string();
+        let constraint = match message.split_once("index '") {
+            Some((_, rest)) => rest.trim_end_matches('\'').to_string(),
+            None => self
+                .id_constraints
+                .get(collection)
+                .cloned()
+                .unwrap_or_else(|| format!("{}_pkey", collection)),
+        };
+        ExecutionError::constraint_violation(
+            &constraint,
+            format!(
+                "Duplicate key in collection '{}' violates unique constraint '{}'",
+                collection, constraint
+            ),
+        )
+    }
+
+    /// Insert a document into an existing table, generating an `_id` if needed
+    fn insert_row(
+        &self,
+        conn: &Connection,
+        collection: &str,
+        document: &HashMap<String, Value>,
+    ) -> Result<Value, ExecutionError> {
+        let mut doc: serde_json::Map<String, Value> =
+            document.iter().map(|(k, v)| (k.clone(), v.clone())).collect();
+        if !doc.contains_key("_id") {
+            let id: String = conn
+                .query_row("SELECT lower(hex(randomblob(12)))", [], |row| row.get(0))
+                .map_err(sql_error)?;
+            doc.insert("_id".to_string(), Value::String(id));
+        }
+        let doc = Value::Object(doc);
+        check_scalars(self.declared_scalars(collection), collection, &doc)?;
+
+        conn.execute(
+            &format!("INSERT INTO {} (_id, doc) VALUES (?1, ?2)", quote_ident(collection)),
+            rusqlite::params![to_sql_value(&doc["_id"]), doc.to_string()],
+        )
+        .map_err(|e| self.error(collection, e))?;
+
+        Ok(doc)
+    }
+
+    /// Replace the document of a row
+    fn write_row(&self, conn: &Connection, collection: &str, rowid: i64, doc: &Value) -> Result<(), ExecutionError> {
+        check_scalars(self.declared_scalars(collection), collection, doc)?;
+        conn.execute(
+            &format!("UPDATE {} SET _id = ?1, doc = ?2 WHERE rowid = ?3", quote_ident(collection)),
+            rusqlite::params![to_sql_value(&doc["_id"]), doc.to_string(), rowid],
+        )
+        .map_err(|e| self.error(collection, e))?;
+        Ok(())
+    }
+
+    /// Top-level fields the schema declares with a scalar type
+    fn declared_scalars(&self, collection: &str) -> &[String] {
+        self.declared_scalars.get(collection).map_or(&[], Vec::as_slice)
+    }
+
+    /// Declared scalar fields that filters can compare without looking for arrays
+    fn scalar_fields(&self, collection: &str) -> &[String] {
+        self.scalar_fields.get(collection).map_or(&[], Vec::as_slice)
+    }
+
+    /// Update the first matching row in sort order (then rowid order), returning
+    /// its document before and after the update
+    ///
+    /// Callers run this inside a savepoint so the read and write are atomic.
+    fn update_first(
+        &self,
+        conn: &Connection,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+        sort: Option<&SortSpec>,
+    ) -> Result<Option<(Value, Value)>, ExecutionError> {
+        let mut sql = Sql::default();
+        sql.push(&format!("SELECT t.rowid, t.doc FROM {} AS t WHERE ", quote_ident(collection)));
+        filter_sql(filter, self.scalar_fields(collection), &mut sql);
+        sql.push(" ORDER BY ");
+        if let Some(sort) = sort {
+            sort_sql(sort, &mut sql);
+        }
+        sql.push("t.rowid LIMIT 1");
+        let Some((rowid, before)) = sql.fetch_rows(conn).map_err(|e| self.error(collection, e))?.pop() else {
+            return Ok(None);
+        };
+
+        let mut after = before.clone();
+        update.apply(&mut after)?;
+        self.write_row(conn, collection, rowid, &after)?;
+        Ok(Some((before, after)))
+    }
+}
+
+impl DatabaseProvider for SqliteDatabase {
+    fn query(
+        &self,
+        collection: &str,
+        filter: Option<&Filter>,
+        select: Option<&[String]>,
+        limit: Option<u32>,
+        skip: Option<u32>,
+        sort: Option<&SortSpec>,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        self.transaction.run(self.owner.as_deref(), |_| {
+            let conn = self.conn.lock().unwrap();
+            if !table_exists(&conn, collection)? {
+                return Ok(vec![]);
+            }
+
+            let mut sql = Sql::default();
+            sql.push(&format!("SELECT t.doc FROM {} AS t WHERE ", quote_ident(collection)));
+            filter_sql(filter.unwrap_or(&Filter::all()), self.scalar_fields(collection), &mut sql);
+            sql.push(" ORDER BY ");
+            if let Some(sort) = sort {
+                sort_sql(sort, &mut sql);
+            }
+            sql.push("t.rowid");
+            if limit.is_some() || skip.is_some() {
+                sql.push(" LIMIT ");
+                sql.param(SqlValue::Integer(limit.map_or(-1, i64::from)));
+                sql.push(" OFFSET ");
+                sql.param(SqlValue::Integer(i64::from(skip.unwrap_or(0))));
+            }
+
+            let docs = sql.fetch_docs(&conn).map_err(|e| self.error(collection, e))?;
+            Ok(match select {
+                Some(fields) => docs.iter().map(|doc| path::project(doc, fields)).collect(),
+                None => docs,
+            })
+        })
+    }
+
+    fn query_after(
+        &self,
+        collection: &str,
+        filter: Option<&Filter>,
+        sort: &SortSpec,
+        after: &[Value],
+        limit: Option<u32>,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        self.transaction.run(self.owner.as_deref(), |_| {
+            let conn = self.conn.lock().unwrap();
+            if !table_exists(&conn, collection)? {
+                return Ok(vec![]);
+            }
+
+            let mut sql = Sql::default();
+            sql.push(&format!("SELECT t.doc FROM {} AS t WHERE ", quote_ident(collection)));
+            filter_sql(filter.unwrap_or(&Filter::all()), self.scalar_fields(collection), &mut sql);
+            sql.push(" AND ");
+            after_sql(&sort.keys, after, &mut sql);
+            sql.push(" ORDER BY ");
+            sort_sql(sort, &mut sql);
+            sql.push("t.rowid");
+            if let Some(limit) = limit {
+                sql.push(" LIMIT ");
+                sql.param(SqlValue::Integer(i64::from(limit)));
+            }
+            sql.fetch_docs(&conn).map_err(|e| self.error(collection, e))
+        })
+    }
+
+    fn count(&self, collection: &str, filter: Option<&Filter>) -> Result<u64, ExecutionError> {
+        self.transaction.run(self.owner.as_deref(), |_| {
+            let conn = self.conn.lock().unwrap();
+            if !table_exists(&conn, collection)? {
+                return Ok(0);
+            }
+
+            let mut sql = Sql::default();
+            sql.push(&format!("SELECT count(*) FROM {} AS t WHERE ", quote_ident(collection)));
+            filter_sql(filter.unwrap_or(&Filter::all()), self.scalar_fields(collection), &mut sql);
+            conn.query_row(&sql.text, params_from_iter(&sql.params), |row| row.get::<_, i64>(0))
+                .map(|count| count as u64)
+                .map_err(|e| self.error(collection, e))
+        })
+    }
+
+    fn insert(
+        &self,
+        collection: &str,
+        document: &HashMap<String, Value>,
+    ) -> Result<Value, ExecutionError> {
+        self.transaction.run(self.owner.as_deref(), |_| {
+            let conn = self.conn.lock().unwrap();
+            create_table(&conn, collection)?;
+            self.insert_row(&conn, collection, document)
+        })
+    }
+
+    fn update(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        self.transaction.run(self.owner.as_deref(), |_| {
+            let mut conn = self.conn.lock().unwrap();
+            if !table_exists(&conn, collection)? {
+                return Ok(vec![]);
+            }
+
+            // Read, apply and write inside one savepoint (which also works inside an
+            // open transaction); any error rolls back every row
+            let tx = conn.savepoint().map_err(sql_error)?;
+            let mut sql = Sql::default();
+            sql.push(&format!("SELECT t.rowid, t.doc FROM {} AS t WHERE ", quote_ident(collection)));
+            filter_sql(filter, self.scalar_fields(collection), &mut sql);
+            sql.push(" ORDER BY t.rowid");
+            let rows = sql.fetch_rows(&tx).map_err(|e| self.error(collection, e))?;
+
+            let mut updated_docs = vec![];
+            for (rowid, mut doc) in rows {
+                update.apply(&mut doc)?;
+                self.write_row(&tx, collection, rowid, &doc)?;
+                updated_docs.push(doc);
+            }
+            tx.commit().map_err(sql_error)?;
+
+            Ok(updated_docs)
+        })
+    }
+
+    fn upsert(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+        insert: &HashMap<String, Value>,
+    ) -> Result<(Option<Value>, Value), ExecutionError> {
+        self.transaction.run(self.owner.as_deref(), |_| {
+            let mut conn = self.conn.lock().unwrap();
+            create_table(&conn, collection)?;
+
+            let tx = conn.savepoint().map_err(sql_error)?;
+            let result = match self.update_first(&tx, collection, filter, update, None)? {
+                Some((before, after)) => (Some(before), after),
+                None => (None, self.insert_row(&tx, collection, insert)?),
+            };
+            tx.commit().map_err(sql_error)?;
+
+            Ok(result)
+        })
+    }
+
+    fn find_one_and_update(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+        sort: Option<&SortSpec>,
+    ) -> Result<Option<(Value, Value)>, ExecutionError> {
+        self.transaction.run(self.owner.as_deref(), |_| {
+            let mut conn = self.conn.lock().unwrap();
+            if !table_exists(&conn, collection)? {
+                return Ok(None);
+            }
+
+            let tx = conn.savepoint().map_err(sql_error)?;
+            let result = self.update_first(&tx, collection, filter, update, sort)?;
+            tx.commit().map_err(sql_error)?;
+
+            Ok(result)
+        })
+    }
+
+    fn delete(
+        &self,
+        collection: &str,
+        filter: &Filter,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        self.transaction.run(self.owner.as_deref(), |_| {
+            let mut conn = self.conn.lock().unwrap();
+            if !table_exists(&conn, collection)? {
+                return Ok(vec![]);
+            }
+
+            let tx = conn.savepoint().map_err(sql_error)?;
+            let mut sql = Sql::default();
+            sql.push(&format!("SELECT t.rowid, t.doc FROM {} AS t WHERE ", quote_ident(collection)));
+            filter_sql(filter, self.scalar_fields(collection), &mut sql);
+            sql.push(" ORDER BY t.rowid");
+            let rows = sql.fetch_rows(&tx).map_err(sql_error)?;
+
+            for (rowid, _) in &rows {
+                tx.execute(
+                    &format!("DELETE FROM {} WHERE rowid = ?1", quote_ident(collection)),
+                    [rowid],
+                )
+                .map_err(sql_error)?;
+            }
+            tx.commit().map_err(sql_error)?;
+
+            Ok(rows.into_iter().map(|(_, doc)| doc).collect())
+        })
+    }
+
+    fn aggregate(&self, collection: &str, aggregation: &Aggregation) -> Result<Vec<Value>, ExecutionError> {
+        self.transaction.run(self.owner.as_deref(), |_| {
+            let conn = self.conn.lock().unwrap();
+            if !table_exists(&conn, collection)? {
+                return aggregation.apply(vec![]);
+            }
+
+            let mut sql = Sql::default();
+            sql.push("SELECT t.doc FROM ");
+            stages_sql(collection, &aggregation.stages, self.scalar_fields(collection), &mut sql);
+            sql.push(" AS t ORDER BY t.ord");
+            sql.fetch_docs(&conn).map_err(|e| self.error(collection, e))
+        })
+    }
+
+    fn begin(&self) -> Result<Box<dyn DatabaseProvider>, ExecutionError> {
+        let conn = Arc::clone(&self.conn);
+        let transaction = self.transaction.begin(
+            || self.conn.lock().unwrap().execute_batch("BEGIN IMMEDIATE").map_err(sql_error),
+            move |()| conn.lock().unwrap().execute_batch("ROLLBACK").map_err(sql_error),
+        )?;
+        Ok(Box::new(SqliteDatabase {
+            owner: Some(Arc::new(transaction)),
+            ..self.clone()
+        }))
+    }
+
+    fn commit(&self) -> Result<(), ExecutionError> {
+        self.transaction.commit(self.owner.as_deref(), |()| {
+            let conn = self.conn.lock().unwrap();
+            conn.execute_batch("COMMIT").map_err(|e| {
+                // Leave the connection usable for the next transaction
+                let _ = conn.execute_batch("ROLLBACK");
+                sql_error(e)
+            })
+        })
+    }
+
+    fn rollback(&self) -> Result<(), ExecutionError> {
+        self.transaction.rollback(self.owner.as_deref())
+    }
+}
+
+// Schema setup
+
+fn create_table(conn: &Connection, collection: &str) -> Result<(), ExecutionError> {
+    conn.execute(
+        &format!(
+            "CREATE TABLE IF NOT EXISTS {} (_id PRIMARY KEY NOT NULL, doc TEXT NOT NULL)",
+            quote_ident(collection)
+        ),
+        [],
+    )
+    .map(|_| ())
+    .map_err(sql_error)
+}
+
+fn create_index(
+    conn: &Connection,
+    collection: &str,
+    name: &str,
+    fields: &[String],
+    unique: bool,
+) -> Result<(), ExecutionError> {
+    let columns: Vec<String> = fields
+        .iter()
+        .map(|field| format!("json_extract(doc, {})", quote_literal(&json_path(field))))
+        .collect();
+    conn.execute(
+        &format!(
+            "CREATE {}INDEX IF NOT EXISTS {} ON {} ({})",
+            if unique { "UNIQUE " } else { "" },
+            quote_ident(name),
+            quote_ident(collection),
+            columns.join(", ")
+        ),
+        [],
+    )
+    .map(|_| ())
+    .map_err(sql_error)
+}
+
+/// Whether a top-level field holds an array in any row of a table
+fn holds_array(conn: &Connection, collection: &str, field: &str) -> Result<bool, ExecutionError> {
+    conn.query_row(
+        &format!(
+            "SELECT 1 FROM {} WHERE json_type(doc, ?1) = 'array' LIMIT 1",
+            quote_ident(collection)
+        ),
+        [json_path(field)],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|found| found.is_some())
+    .map_err(sql_error)
+}
+
+fn table_exists(conn: &Connection, collection: &str) -> Result<bool, ExecutionError> {
+    conn.query_row(
+        "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+        [collection],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|found| found.is_some())
+    .map_err(sql_error)
+}
+
+/// Register `regexp(pattern, text)`, caching each compiled pattern per statement
+fn add_regexp_function(conn: &Connection) -> rusqlite::Result<()> {
+    type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+    conn.create_scalar_function(
+        "regexp",
+        2,
+        FunctionFlags::SQLITE_UTF8 | FunctionFlags::SQLITE_DETERMINISTIC,
+        |ctx| {
+            let regex: Arc<Regex> =
+                ctx.get_or_create_aux(0, |pattern| -> Result<_, BoxError> { Ok(Regex::new(pattern.as_str()?)?) })?;
+            Ok(ctx.get_raw(1).as_str().is_ok_and(|text| regex.is_match(text)))
+        },
+    )
+}
+
+// SQL generation
+
+/// A SQL string with its positional parameters
+#[derive(Default)]
+struct Sql {
+    text: String,
+    params: Vec<SqlValue>,
+}
+
+impl Sql {
+    fn push(&mut self, text: &str) {
+        self.text.push_str(text);
+    }
+
+    fn param(&mut self, value: SqlValue) {
+        self.params.push(value);
+        self.text.push('?');
+    }
+
+    fn fetch_docs(&self, conn: &Connection) -> rusqlite::Result<Vec<Value>> {
+        let mut statement = conn.prepare(&self.text)?;
+        let rows = statement.query_map(params_from_iter(&self.params), |row| row.get::<_, String>(0))?;
+        rows.map(|doc| doc.map(|doc| parse_doc(&doc))).collect()
+    }
+
+    fn fetch_rows(&self, conn: &Connection) -> rusqlite::Result<Vec<(i64, Value)>> {
+        let mut statement = conn.prepare(&self.text)?;
+        let rows = statement.query_map(params_from_iter(&self.params), |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        rows.map(|row| row.map(|(rowid, doc)| (rowid, parse_doc(&doc)))).collect()
+    }
+}
+
+/// Translate a filter into a boolean SQL expression over the row `t`
+///
+/// `scalars` are the top-level fields the collection schema declares with a
+/// scalar type: conditions on them compare `json_extract` of the field, so the
+/// expression indexes built by `create_index` apply. Other key-only paths are
+/// read the same way, also checking the elements of an array node with
+/// `json_each`, unless a parent node is an array. Paths through arrays and
+/// paths with index segments are matched against the `fullkey` of every node
+/// (via `json_tree`) instead, so they fan out over arrays the same way
+/// `path::resolve` does.
+fn filter_sql(filter: &Filter, scalars: &[String], sql: &mut Sql) {
+    match filter {
+        Filter::And(filters) | Filter::Or(filters) if filters.is_empty() => {
+            sql.push(if matches!(filter, Filter::And(_)) { "1" } else { "0" });
+        }
+        Filter::And(filters) | Filter::Or(filters) => {
+            let joiner = if matches!(filter, Filter::And(_)) { " AND " } else { " OR " };
+            sql.push("(");
+            for (i, filter) in filters.iter().enumerate() {
+                if i > 0 {
+                    sql.push(joiner);
+                }
+                filter_sql(filter, scalars, sql);
+            }
+            sql.push(")");
+        }
+        Filter::Not(filter) => {
+            sql.push("NOT coalesce(");
+            filter_sql(filter, scalars, sql);
+            sql.push(", 0)");
+        }
+        Filter::Field { path, condition } => {
+            if path.split('.').any(is_index) {
+                tree_condition_sql(path, condition, sql);
+            } else if scalars.contains(path) {
+                key_condition_sql(path, condition, false, sql);
+            } else {
+                let segments: Vec<&str> = path.split('.').collect();
+                if segments.len() == 1 {
+                    key_condition_sql(path, condition, true, sql);
+                    return;
+                }
+                sql.push("CASE WHEN ");
+                for i in 1..segments.len() {
+                    if i > 1 {
+                        sql.push(" OR ");
+                    }
+                    sql.push(&format!("{} = 'array'", Node::path(&segments[..i].join(".")).kind));
+                }
+                sql.push(" THEN ");
+                tree_condition_sql(path, condition, sql);
+                sql.push(" ELSE ");
+                key_condition_sql(path, condition, true, sql);
+                sql.push(" END");
+            }
+        }
+    }
+}
+
+/// A condition on a key-only path whose parent nodes are not arrays
+///
+/// With `elements`, a value condition also matches an array node with a
+/// matching element.
+fn key_condition_sql(path: &str, condition: &Condition, elements: bool, sql: &mut Sql) {
+    let node = Node::path(path);
+    let json_each = |sql: &mut Sql, predicate: &dyn Fn(&mut Sql)| {
+        sql.push(&format!(
+            "({} = 'array' AND EXISTS (SELECT 1 FROM json_each(t.doc, {}) AS e WHERE ",
+            node.kind,
+            quote_literal(&json_path(path))
+        ));
+        predicate(sql);
+        sql.push("))");
+    };
+
+    match condition {
+        Condition::Ne(operand) => negated_sql(sql, &|sql| {
+            key_condition_sql(path, &Condition::Eq(operand.clone()), elements, sql)
+        }),
+        Condition::Nin(operand) => negated_sql(sql, &|sql| {
+            key_condition_sql(path, &Condition::In(operand.clone()), elements, sql)
+        }),
+        Condition::Exists(operand) => {
+            let not = if operand.as_bool() == Some(true) { "NOT " } else { "" };
+            sql.push(&format!("{} IS {}NULL", node.kind, not));
+        }
+        Condition::Contains(operand) => json_each(sql, &|sql| {
+            any_equal_sql(&Node::row("e"), std::slice::from_ref(operand), sql)
+        }),
+        Condition::Size(operand) => {
+            sql.push(&format!(
+                "({} = 'array' AND json_array_length(t.doc, {}) = ",
+                node.kind,
+                quote_literal(&json_path(path))
+            ));
+            sql.param(SqlValue::Integer(operand.as_u64().map_or(-1, |n| n as i64)));
+            sql.push(")");
+        }
+        _ if elements => {
+            sql.push("(");
+            value_sql(&node, condition, sql);
+            sql.push(" OR ");
+            json_each(sql, &|sql| value_sql(&Node::row("e"), condition, sql));
+            sql.push(")");
+        }
+        _ => value_sql(&node, condition, sql),
+    }
+}
+
+/// A condition on any path, matched against the `fullkey` of every node
+fn tree_condition_sql(path: &str, condition: &Condition, sql: &mut Sql) {
+    // Nodes the path resolves to, optionally with the elements of array nodes
+    let nodes = |sql: &mut Sql, elements: bool, predicate: &dyn Fn(&mut Sql)| {
+        sql.push("EXISTS (SELECT 1 FROM json_tree(t.doc) AS n WHERE regexp(");
+        sql.param(SqlValue::Text(path_pattern(path, true, elements)));
+        sql.push(", n.fullkey) AND ");
+        predicate(sql);
+        sql.push(")");
+    };
+    let missing = |sql: &mut Sql| {
+        sql.push("NOT ");
+        nodes(sql, false, &|sql| sql.push("1"));
+    };
+
+    match condition {
+        Condition::Ne(operand) => negated_sql(sql, &|sql| {
+            tree_condition_sql(path, &Condition::Eq(operand.clone()), sql)
+        }),
+        Condition::Nin(operand) => negated_sql(sql, &|sql| {
+            tree_condition_sql(path, &Condition::In(operand.clone()), sql)
+        }),
+        Condition::Exists(operand) => {
+            if operand.as_bool() == Some(true) {
+                nodes(sql, false, &|sql| sql.push("1"));
+            } else {
+                missing(sql);
+            }
+        }
+        Condition::Contains(operand) => nodes(sql, false, &|sql| {
+            sql.push("n.type = 'array' AND EXISTS (SELECT 1 FROM json_each(n.value) AS e WHERE ");
+            any_equal_sql(&Node::row("e"), std::slice::from_ref(operand), sql);
+            sql.push(")");
+        }),
+        Condition::Size(operand) => nodes(sql, false, &|sql| {
+            sql.push("n.type = 'array' AND json_array_length(n.value) = ");
+            sql.param(SqlValue::Integer(operand.as_u64().map_or(-1, |n| n as i64)));
+        }),
+        Condition::Eq(operand) | Condition::In(operand) if candidates(condition, operand).iter().any(Value::is_null) => {
+            sql.push("(");
+            nodes(sql, true, &|sql| value_sql(&Node::row("n"), condition, sql));
+            sql.push(" OR ");
+            missing(sql);
+            sql.push(")");
+        }
+        _ => nodes(sql, true, &|sql| value_sql(&Node::row("n"), condition, sql)),
+    }
+}
+
+/// Whether a condition that compares values (`$eq`, `$in`, `$gt`, ...,
+/// `$regex`) holds for a node
+fn value_sql(node: &Node, condition: &Condition, sql: &mut Sql) {
+    match condition {
+        Condition::Eq(operand) | Condition::In(operand) => any_equal_sql(node, &candidates(condition, operand), sql),
+        Condition::Gt(operand) | Condition::Gte(operand) | Condition::Lt(operand) | Condition::Lte(operand) => {
+            let op = match condition {
+                Condition::Gt(_) => ">",
+                Condition::Gte(_) => ">=",
+                Condition::Lt(_) => "<",
+                _ => "<=",
+            };
+            ordered_sql(node, op, operand, sql);
+        }
+        Condition::Regex(_, Some(pattern)) => {
+            sql.push(&format!("({} = 'text' AND regexp(", node.kind));
+            sql.param(SqlValue::Text(pattern.as_str().to_string()));
+            sql.push(&format!(", {}))", node.atom));
+        }
+        _ => sql.push("0"),
+    }
+}
+
+/// The values an `$eq` or `$in` condition accepts
+fn candidates(condition: &Condition, operand: &Value) -> Vec<Value> {
+    match condition {
+        Condition::In(_) => operand.as_array().cloned().unwrap_or_default(),
+        _ => vec![operand.clone()],
+    }
+}
+
+/// The negation of an expression, treating `NULL` as false
+fn negated_sql(sql: &mut Sql, expression: &dyn Fn(&mut Sql)) {
+    sql.push("NOT coalesce(");
+    expression(sql);
+    sql.push(", 0)");
+}
+
+/// SQL expressions for the type name, SQL value and JSON text of a JSON node
+struct Node {
+    kind: String,
+    atom: String,
+    json: String,
+    /// Whether the node is `null`
+    null: String,
+}
+
+impl Node {
+    /// The current row of a `json_tree` or `json_each` aliased `alias`
+    fn row(alias: &str) -> Self {
+        Node {
+            kind: format!("{}.type", alias),
+            atom: format!("{}.atom", alias),
+            json: format!("{}.value", alias),
+            null: format!("{}.type = 'null'", alias),
+        }
+    }
+
+    /// The node at a key-only path of the row `t`, where a missing node counts as `null`
+    ///
+    /// The path is inlined so the expressions are the ones `create_index` indexes.
+    fn path(field: &str) -> Self {
+        let path = quote_literal(&json_path(field));
+        let value = format!("json_extract(t.doc, {})", path);
+        Node {
+            kind: format!("json_type(t.doc, {})", path),
+            null: format!("{} IS NULL", value),
+            atom: value.clone(),
+            json: value,
+        }
+    }
+}
+
+/// Whether a JSON node equals any candidate (numbers by value)
+fn any_equal_sql(node: &Node, candidates: &[Value], sql: &mut Sql) {
+    if candidates.is_empty() {
+        sql.push("0");
+        return;
+    }
+    sql.push("(");
+    for (i, candidate) in candidates.iter().enumerate() {
+        if i > 0 {
+            sql.push(" OR ");
+        }
+        match candidate {
+            Value::Null => sql.push(&node.null),
+            Value::Bool(b) => sql.push(&format!("{} = '{}'", node.kind, b)),
+            Value::Number(_) | Value::String(_) => {
+                let types = if candidate.is_number() { "'integer', 'real'" } else { "'text'" };
+                sql.push(&format!("({} IN ({}) AND {} = ", node.kind, types, node.atom));
+                sql.param(to_sql_value(candidate));
+                sql.push(")");
+            }
+            Value::Array(_) | Value::Object(_) => {
+                let kind = if candidate.is_array() { "array" } else { "object" };
+                sql.push(&format!("({} = '{}' AND {} = ", node.kind, kind, node.json));
+                sql.param(SqlValue::Text(candidate.to_string()));
+                sql.push(")");
+            }
+        }
+    }
+    sql.push(")");
+}
+
+/// Compare a JSON node with an operand of the same type
+fn ordered_sql(node: &Node, op: &str, operand: &Value, sql: &mut Sql) {
+    let types = match operand {
+        Value::Number(_) => "'integer', 'real'",
+        Value::String(_) => "'text'",
+        Value::Bool(_) => "'true', 'false'",
+        _ => {
+            sql.push("0");
+            return;
+        }
+    };
+    sql.push(&format!("({} IN ({}) AND {} {} ", node.kind, types, node.atom, op));
+    sql.param(to_sql_value(operand));
+    sql.push(")");
+}
+
+/// ORDER BY terms for a sort spec, ranking types like `sort::compare_values`
+///
+/// Missing fields rank like `null`. Arrays and objects are ordered by their
+/// JSON text rather than element by element.
+fn sort_sql(sort: &SortSpec, sql: &mut Sql) {
+    for key in &sort.keys {
+        let direction = match key.order {
+            SortOrder::Ascending => "ASC",
+            SortOrder::Descending => "DESC",
+        };
+        type_rank_sql(sql, &key.field);
+        sql.push(&format!(" {}, ", direction));
+        node_column(sql, &key.field, NodeColumn::Value);
+        sql.push(&format!(" {}, ", direction));
+    }
+}
+
+/// Whether the row sorts strictly after a position, one value per sort key
+///
+/// Compares keys in order by type rank and value like `sort_sql`: the row is
+/// past the position on the first key where they differ.
+fn after_sql(keys: &[SortKey], position: &[Value], sql: &mut Sql) {
+    let Some((key, rest)) = keys.split_first() else {
+        sql.push("0");
+        return;
+    };
+    let value = position.first().unwrap_or(&Value::Null);
+    let (rank, operand) = (i64::from(sort::type_rank(value)), to_sql_value(value));
+    let beyond = match key.order {
+        SortOrder::Ascending => ">",
+        SortOrder::Descending => "<",
+    };
+
+    sql.push("(");
+    type_rank_sql(sql, &key.field);
+    sql.push(&format!(" {} ", beyond));
+    sql.param(SqlValue::Integer(rank));
+    sql.push(" OR (");
+    type_rank_sql(sql, &key.field);
+    sql.push(" = ");
+    sql.param(SqlValue::Integer(rank));
+    sql.push(" AND (");
+    node_column(sql, &key.field, NodeColumn::Value);
+    sql.push(&format!(" {} ", beyond));
+    sql.param(operand.clone());
+    sql.push(" OR (");
+    node_column(sql, &key.field, NodeColumn::Value);
+    sql.push(" IS ");
+    sql.param(operand);
+    sql.push(" AND ");
+    after_sql(rest, position.get(1..).unwrap_or_default(), sql);
+    sql.push("))))");
+}
+
+/// The rank of the type of the node at a path, as in `sort::compare_values`
+///
+/// A missing node ranks like `null`.
+fn type_rank_sql(sql: &mut Sql, field: &str) {
+    sql.push("CASE ");
+    node_column(sql, field, NodeColumn::Type);
+    sql.push(
+        " WHEN 'true' THEN 1 WHEN 'false' THEN 1 WHEN 'integer' THEN 2 WHEN 'real' THEN 2 \
+         WHEN 'text' THEN 3 WHEN 'array' THEN 4 WHEN 'object' THEN 5 ELSE 0 END",
+    );
+}
+
+/// The node at a path as JSON text, with `null` for a missing node
+fn node_json(sql: &mut Sql, field: &str) {
+    sql.push("CASE coalesce(");
+    node_column(sql, field, NodeColumn::Type);
+    sql.push(", 'null') WHEN 'null' THEN 'null' WHEN 'true' THEN 'true' WHEN 'false' THEN 'false' WHEN 'text' THEN json_quote(");
+    node_column(sql, field, NodeColumn::Value);
+    sql.push(") ELSE CAST(");
+    node_column(sql, field, NodeColumn::Value);
+    sql.push(" AS TEXT) END");
+}
+
+/// A subquery with `doc` and `ord` columns holding the output of the stages
+///
+/// Each stage wraps the subquery of the stages before it. `ord` orders the
+/// documents: rowids for the collection, then row numbers after a `$sort`
+/// and the position of its first document for each group.
+fn stages_sql(collection: &str, stages: &[Stage], scalars: &[String], sql: &mut Sql) {
+    let Some((stage, before)) = stages.split_last() else {
+        sql.push(&format!("(SELECT t.doc AS doc, t.rowid AS ord FROM {} AS t)", quote_ident(collection)));
+        return;
+    };
+
+    match stage {
+        Stage::Match(filter) => {
+            sql.push("(SELECT t.doc AS doc, t.ord AS ord FROM ");
+            stages_sql(collection, before, scalars, sql);
+            sql.push(" AS t WHERE ");
+            // Documents after a `$group` no longer have the schema's fields
+            let scalars = if before.iter().any(|stage| matches!(stage, Stage::Group(_))) { &[] } else { scalars };
+            filter_sql(filter, scalars, sql);
+            sql.push(")");
+        }
+        Stage::Group(group) => group_sql(collection, group, before, scalars, sql),
+        Stage::Sort(sort) => {
+            sql.push("(SELECT t.doc AS doc, row_number() OVER (ORDER BY ");
+            sort_sql(sort, sql);
+            sql.push("t.ord) AS ord FROM ");
+            stages_sql(collection, before, scalars, sql);
+            sql.push(" AS t)");
+        }
+        Stage::Limit(limit) => {
+            sql.push("(SELECT t.doc AS doc, t.ord AS ord FROM ");
+            stages_sql(collection, before, scalars, sql);
+            sql.push(" AS t ORDER BY t.ord LIMIT ");
+            sql.param(SqlValue::Integer(i64::from(*limit)));
+            sql.push(")");
+        }
+    }
+}
+
+/// A `$group` stage over the output of the stages before it
+///
+/// Groups are formed by the type rank and value of each `by` field, so that
+/// numbers compare by value. Values that must come from a particular
+/// document (the `by` values and `$min`/`$max`) are picked by window
+/// functions over each group before grouping.
+fn group_sql(collection: &str, group: &Group, before: &[Stage], scalars: &[String], sql: &mut Sql) {
+    let partition = |sql: &mut Sql| {
+        for (i, field) in group.by.iter().enumerate() {
+            if i > 0 {
+                sql.push(", ");
+            }
+            type_rank_sql(sql, field);
+            sql.push(", ");
+            node_column(sql, field, NodeColumn::Value);
+        }
+    };
+    let window = |sql: &mut Sql| {
+        sql.push(" OVER (");
+        if !group.by.is_empty() {
+            sql.push("PARTITION BY ");
+            partition(sql);
+            sql.push(" ");
+        }
+        sql.push("ORDER BY ");
+    };
+    let numbers = |sql: &mut Sql, field: &str| {
+        sql.push("CASE WHEN ");
+        node_column(sql, field, NodeColumn::Type);
+        sql.push(" IN ('integer', 'real') THEN ");
+        node_column(sql, field, NodeColumn::Value);
+        sql.push(" END");
+    };
+
+    // The output document
+    sql.push("(SELECT json_set('{}'");
+    for (i, field) in group.by.iter().enumerate() {
+        sql.push(", ");
+        sql.param(SqlValue::Text(json_path(field)));
+        sql.push(&format!(", json(max(t.k{}))", i));
+    }
+    for (i, (name, accumulator)) in group.aggregates.iter().enumerate() {
+        sql.push(", ");
+        sql.param(SqlValue::Text(json_path(name)));
+        sql.push(", ");
+        match accumulator {
+            Accumulator::Count => sql.push("count(*)"),
+            Accumulator::Sum(field) => {
+                sql.push("coalesce(sum(");
+                numbers(sql, field);
+                sql.push("), 0)");
+            }
+            Accumulator::Avg(field) => {
+                sql.push("avg(");
+                numbers(sql, field);
+                sql.push(")");
+            }
+            Accumulator::Min(_) | Accumulator::Max(_) => sql.push(&format!("json(max(t.a{}))", i)),
+        }
+    }
+    sql.push(") AS doc, min(t.ord) AS ord FROM ");
+
+    // The input documents with the values picked per group
+    sql.push("(SELECT t.doc AS doc, t.ord AS ord");
+    for (i, field) in group.by.iter().enumerate() {
+        sql.push(", first_value(");
+        node_json(sql, field);
+        sql.push(")");
+        window(sql);
+        sql.push(&format!("t.ord) AS k{}", i));
+    }
+    for (i, (_, accumulator)) in group.aggregates.iter().enumerate() {
+        let (field, direction) = match accumulator {
+            Accumulator::Min(field) => (field, "ASC"),
+            Accumulator::Max(field) => (field, "DESC"),
+            _ => continue,
+        };
+        // Missing and null values last, then by type rank and value
+        sql.push(", first_value(");
+        node_json(sql, field);
+        sql.push(")");
+        window(sql);
+        sql.push("coalesce(");
+        node_column(sql, field, NodeColumn::Type);
+        sql.push(", 'null') = 'null', ");
+        type_rank_sql(sql, field);
+        sql.push(&format!(" {}, ", direction));
+        node_column(sql, field, NodeColumn::Value);
+        sql.push(&format!(" {}, t.ord) AS a{}", direction, i));
+    }
+    sql.push(" FROM ");
+    stages_sql(collection, before, scalars, sql);
+    sql.push(" AS t) AS t");
+
+    if !group.by.is_empty() {
+        sql.push(" GROUP BY ");
+        partition(sql);
+    }
+    sql.push(" HAVING count(*) > 0)");
+}
+
+enum NodeColumn {
+    /// The JSON type name (`null`, `true`, `integer`, `text`, ...)
+    Type,
+    /// The SQL value of a scalar, or the JSON text of an array or object
+    Value,
+}
+
+/// The type or value of the node at a path, without fanning out over arrays
+///
+/// Paths without index segments use `json_type`/`json_extract`, which can use
+/// expression indexes. Paths with an index segment match nodes by `fullkey`,
+/// since a numeric segment may be an array index or an object key.
+fn node_column(sql: &mut Sql, field: &str, column: NodeColumn) {
+    if field.split('.').any(is_index) {
+        let expression = match column {
+            NodeColumn::Type => "n.type",
+            NodeColumn::Value => "CASE WHEN n.atom IS NULL THEN n.value ELSE n.atom END",
+        };
+        sql.push(&format!("(SELECT {} FROM json_tree(t.doc) AS n WHERE regexp(", expression));
+        sql.param(SqlValue::Text(path_pattern(field, false, false)));
+        sql.push(", n.fullkey))");
+    } else {
+        let function = match column {
+            NodeColumn::Type => "json_type",
+            NodeColumn::Value => "json_extract",
+        };
+        sql.push(&format!("{}(t.doc, ", function));
+        sql.param(SqlValue::Text(json_path(field)));
+        sql.push(")");
+    }
+}
+
+/// A regex matching the `fullkey` of the nodes a dot-path resolves to
+///
+/// With `fan_out`, array indices may appear before each key segment (as in
+/// `path::resolve`); with `elements`, the elements of array nodes match too.
+fn path_pattern(path: &str, fan_out: bool, elements: bool) -> String {
+    let mut pattern = String::from(r"^\$");
+    for segment in path.split('.') {
+        let key = regex::escape(segment);
+        if is_index(segment) {
+            pattern.push_str(&format!(r#"(?:\[{0}\]|\."{0}")"#, segment));
+        } else {
+            if fan_out {
+                pattern.push_str(r"(?:\[[0-9]+\])*");
+            }
+            pattern.push_str(&format!(r#"\.(?:{0}|"{0}")"#, key));
+        }
+    }
+    if elements {
+        pattern.push_str(r"(?:\[[0-9]+\])?");
+    }
+    pattern.push('$');
+    pattern
+}
+
+/// A SQLite JSON path treating every segment as an object key
+fn json_path(field: &str) -> String {
+    field
+        .split('.')
+        .fold(String::from("$"), |path, segment| format!("{}.\"{}\"", path, segment.replace('"', "\\\"")))
+}
+
+fn is_index(segment: &str) -> bool {
+    !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit())
+}
+
+// Conversions
+
+fn to_sql_value(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(b) => SqlValue::Integer(i64::from(*b)),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        Value::String(s) => SqlValue::Text(s.clone()),
+        other => SqlValue::Text(other.to_string()),
+    }
+}
+
+fn parse_doc(text: &str) -> Value {
+    serde_json::from_str(text).unwrap_or(Value::Null)
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quote_literal(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "''"))
+}
+
+fn sql_error(err: rusqlite::Error) -> ExecutionError {
+    ExecutionError::database_error(format!("SQLite error: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::Cursor;
+    use crate::executor::traits::MockDatabase;
+    use serde_json::json;
+
+    fn documents() -> Vec<Value> {
+        vec![
+            json!({"_id": "1", "title": "Rust", "views": 10, "tags": ["rust", "json"], "author": {"id": "u1"}, "score": 1.5}),
+            json!({"_id": "2", "title": "Serde", "views": 3, "tags": [], "author": {"id": "u2"}, "draft": true}),
+            json!({"_id": "3", "title": "Axum", "views": 10.0, "tags": ["web"], "comments": [{"by": "ann", "votes": 2}, {"by": "bob", "votes": 7}]}),
+            json!({"_id": "4", "title": "null views", "views": null, "meta": {"0": "key", "list": [[1, 2], [3]]}}),
+            json!({"_id": "5", "title": "Tokio", "views": "many", "author": {"id": "u1"}, "tags": ["rust", "async"]}),
+        ]
+    }
+
+    fn providers() -> (MockDatabase, SqliteDatabase) {
+        providers_with_schemas(&HashMap::new())
+    }
+
+    fn providers_with_schemas(schemas: &HashMap<String, DatabaseSchema>) -> (MockDatabase, SqliteDatabase) {
+        let mock = MockDatabase::new().with_collection("posts", documents());
+        let sqlite = SqliteDatabase::open_in_memory(schemas).unwrap();
+        for doc in documents() {
+            let doc: HashMap<String, Value> = serde_json::from_value(doc).unwrap();
+            sqlite.insert("posts", &doc).unwrap();
+        }
+        (mock, sqlite)
+    }
+
+    fn ids(docs: &[Value]) -> Vec<String> {
+        docs.iter().map(|d| d["_id"].as_str().unwrap_or_default().to_string()).collect()
+    }
+
+    #[test]
+    fn test_filters_match_mock_semantics() {
+        // Declared scalar fields are compared without looking for arrays
+        let schemas: HashMap<String, DatabaseSchema> = serde_json::from_value(json!({
+            "posts": {"fields": {"title": {"type": "string"}, "views": {"type": "number"}, "draft": {"type": "boolean"}}}
+        }))
+        .unwrap();
+        let (mock, sqlite) = providers();
+        let (_, declared) = providers_with_schemas(&schemas);
+        let filters = [
+            json!({"title": "Rust"}),
+            json!({"views": 10}),
+            json!({"views": {"$ne": 10}}),
+            json!({"views": null}),
+            json!({"views": {"$gt": 5}}),
+            json!({"views": {"$lte": 3}}),
+            json!({"title": {"$gte": "S"}}),
+            json!({"draft": true}),
+            json!({"draft": {"$exists": false}}),
+            json!({"tags": "rust"}),
+            json!({"tags": ["rust", "json"]}),
+            json!({"tags": {"$in": ["web", "async"]}}),
+            json!({"tags": {"$nin": ["rust"]}}),
+            json!({"tags": {"$contains": "json"}}),
+            json!({"tags": {"$size": 0}}),
+            json!({"tags.0": "rust"}),
+            json!({"author.id": "u1"}),
+            json!({"author": {"id": "u2"}}),
+            json!({"comments.by": "bob"}),
+            json!({"comments.votes": {"$gt": 5}}),
+            json!({"comments.1.by": "bob"}),
+            json!({"meta.0": "key"}),
+            json!({"meta.list.1": [3]}),
+            json!({"title": {"$regex": "^[A-S]"}}),
+            json!({"score": {"$exists": true}}),
+            json!({"$or": [{"views": {"$lt": 5}}, {"author.id": "u1"}]}),
+            json!({"$not": {"views": 10}, "tags": {"$exists": true}}),
+            json!({"missing": {"$in": [null, 1]}}),
+            json!({"views": {"$gt": "a"}}),
+            json!({"views": {"$nin": [10, null]}}),
+            json!({"author.id": {"$ne": "u1"}}),
+            json!({"comments.votes": {"$exists": true}}),
+            json!({"meta.list": [3]}),
+            json!({"meta.list": 3}),
+            json!({"tags": {"$regex": "^r"}}),
+            json!({"$not": {"missing": 1}}),
+        ];
+
+        for filter in filters {
+            let parsed = Filter::from_json(&filter).unwrap();
+            let expected = mock.query("posts", Some(&parsed), None, None, None, None).unwrap();
+            let actual = sqlite.query("posts", Some(&parsed), None, None, None, None).unwrap();
+            assert_eq!(ids(&actual), ids(&expected), "filter {}", filter);
+            assert_eq!(sqlite.count("posts", Some(&parsed)).unwrap(), expected.len() as u64, "filter {}", filter);
+            let actual = declared.query("posts", Some(&parsed), None, None, None, None).unwrap();
+            assert_eq!(ids(&actual), ids(&expected), "filter {} with a schema", filter);
+        }
+
+        assert_eq!(sqlite.count("posts", None).unwrap(), 5);
+        assert_eq!(sqlite.count("missing", None).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_sort_skip_limit_match_mock_semantics() {
+        let (mock, sqlite) = providers();
+        let sorts = [
+            json!([{"views": "desc"}]),
+            json!(["author.id", {"title": "desc"}]),
+            json!([{"tags.0": "asc"}]),
+            json!([{"draft": "desc"}, "_id"]),
+        ];
+
+        for sort in sorts {
+            let spec: SortSpec = serde_json::from_value(sort.clone()).unwrap();
+            for (limit, skip) in [(None, None), (Some(2), None), (Some(2), Some(1)), (None, Some(3))] {
+                let expected = mock.query("posts", None, None, limit, skip, Some(&spec)).unwrap();
+                let actual = sqlite.query("posts", None, None, limit, skip, Some(&spec)).unwrap();
+                assert_eq!(ids(&actual), ids(&expected), "sort {} limit {:?} skip {:?}", sort, limit, skip);
+            }
+        }
+    }
+
+    #[test]
+    fn test_query_after_matches_mock_semantics() {
+        let (mock, sqlite) = providers();
+        let sorts = [
+            json!([{"views": "desc"}]),
+            json!(["author.id", {"title": "desc"}]),
+            json!([{"tags.0": "asc"}, {"score": "desc"}]),
+        ];
+        let published = Filter::from_json(&json!({"title": {"$ne": "Serde"}})).unwrap();
+
+        for sort in sorts {
+            let spec = Cursor::keyset_sort(Some(&serde_json::from_value(sort.clone()).unwrap()));
+            for spec in [spec.reversed(), spec] {
+                for doc in documents() {
+                    let position = spec.key_of(&doc);
+                    for (filter, limit) in [(None, None), (Some(&published), Some(2))] {
+                        let expected = mock.query_after("posts", filter, &spec, &position, limit).unwrap();
+                        let actual = sqlite.query_after("posts", filter, &spec, &position, limit).unwrap();
+                        assert_eq!(ids(&actual), ids(&expected), "sort {:?} after {}", spec, doc["_id"]);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_aggregation_matches_mock_semantics() {
+        let (mock, sqlite) = providers();
+        let accumulators = json!({
+            "count": {"$count": null},
+            "total": {"$sum": "views"},
+            "avg": {"$avg": "views"},
+            "min": {"$min": "views"},
+            "max": {"$max": "views"},
+            "first": {"$min": "title"}
+        });
+        let pipelines = [
+            json!([{"$group": {"by": "author.id", "aggregates": accumulators}}]),
+            json!([{"$group": {"by": "views", "aggregates": {"n": {"$count": null}}}}]),
+            json!([{"$group": {"by": "tags", "aggregates": {"n": {"$count": null}}}}]),
+            json!([{"$group": {"by": ["author.id", "draft"], "aggregates": {"n": {"$count": null}}}}]),
+            json!([{"$group": {"by": "meta.0", "aggregates": {"n": {"$count": null}}}}]),
+            json!([{"$group": {"aggregates": accumulators}}]),
+            json!([{"$match": {"title": "none"}}, {"$group": {"aggregates": {"n": {"$count": null}}}}]),
+            json!([
+                {"$match": {"views": {"$gte": 3}}},
+                {"$group": {"by": "author.id", "aggregates": {"n": {"$count": null}}}},
+                {"$sort": [{"n": "desc"}, {"author.id": "asc"}]},
+                {"$limit": 2}
+            ]),
+            json!([
+                {"$sort": {"title": "asc"}},
+                {"$limit": 3},
+                {"$group": {"by": "author.id", "aggregates": {"titles": {"$max": "title"}}}}
+            ]),
+            json!([
+                {"$group": {"by": "author.id", "aggregates": {"n": {"$count": null}}}},
+                {"$match": {"n": {"$gt": 1}}}
+            ]),
+            json!([{"$sort": {"views": "desc"}}, {"$limit": 2}]),
+        ];
+        for pipeline in pipelines {
+            let aggregation = Aggregation::from_json(&pipeline).unwrap();
+            let expected = mock.aggregate("posts", &aggregation).unwrap();
+            assert_eq!(sqlite.aggregate("posts", &aggregation).unwrap(), expected, "pipeline {}", pipeline);
+        }
+
+        let count = Aggregation::from_json(&json!([{"$group": {"aggregates": {"n": {"$count": null}}}}])).unwrap();
+        assert_eq!(sqlite.aggregate("missing", &count).unwrap(), Vec::<Value>::new());
+    }
+
+    #[test]
+    fn test_projection() {
+        let (_, sqlite) = providers();
+        let filter = Filter::from_json(&json!({"_id": "1"})).unwrap();
+        let select = ["title".to_string(), "author.id".to_string()];
+        let docs = sqlite.query("posts", Some(&filter), Some(&select), None, None, None).unwrap();
+        assert_eq!(docs, vec![json!({"title": "Rust", "author": {"id": "u1"}})]);
+    }
+
+    #[test]
+    fn test_insert_update_delete() {
+        let (_, sqlite) = providers();
+        let inserted = sqlite
+            .insert("posts", &serde_json::from_value(json!({"title": "New"})).unwrap())
+            .unwrap();
+        assert!(inserted["_id"].as_str().is_some_and(|id| id.len() == 24));
+
+        let update: Update = serde_json::from_value(json!({"$inc": {"views": 1}, "$push": {"tags": "hot"}})).unwrap();
+        let updated = sqlite
+            .update("posts", &Filter::from_json(&json!({"views": {"$gte": 10}})).unwrap(), &update)
+            .unwrap();
+        assert_eq!(ids(&updated), vec!["1", "3"].into_iter().map(String::from).collect::<Vec<_>>());
+        assert_eq!(updated[0]["views"], json!(11));
+        assert_eq!(updated[0]["tags"], json!(["rust", "json", "hot"]));
+
+        // "many" + 1 fails for document 5, and document 1's change is rolled back
+        let filter = Filter::from_json(&json!({"author.id": "u1"})).unwrap();
+        let failed = sqlite.update("posts", &filter, &update);
+        assert!(matches!(failed, Err(ExecutionError::TypeError { .. })));
+        let stored = sqlite.query("posts", Some(&filter), None, None, None, None).unwrap();
+        assert_eq!(stored[0]["views"], json!(11));
+
+        let deleted = sqlite.delete("posts", &Filter::from_json(&json!({"views": {"$gte": 10}})).unwrap()).unwrap();
+        assert_eq!(ids(&deleted), vec!["1", "3"].into_iter().map(String::from).collect::<Vec<_>>());
+        assert_eq!(sqlite.query("posts", None, None, None, None, None).unwrap().len(), 4);
+
+        assert!(sqlite.query("missing", None, None, None, None, None).unwrap().is_empty());
+        assert!(sqlite.delete("missing", &Filter::all()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_upsert_and_find_one_and_update_match_mock_semantics() {
+        let (mock, sqlite) = providers();
+        let update: Update = serde_json::from_value(json!({"seen": true, "$inc": {"hits": 1}})).unwrap();
+        let filters = [
+            json!({"author.id": "u1"}),
+            json!({"views": {"$gte": 5}}),
+            json!({"title": "none"}),
+        ];
+        let sorts = [None, Some(json!([{"views": "desc"}])), Some(json!([{"title": "asc"}]))];
+
+        for filter in &filters {
+            let filter = Filter::from_json(filter).unwrap();
+            for sort in &sorts {
+                let sort: Option<SortSpec> = sort.clone().map(|sort| serde_json::from_value(sort).unwrap());
+                let expected = mock.find_one_and_update("posts", &filter, &update, sort.as_ref()).unwrap();
+                let actual = sqlite.find_one_and_update("posts", &filter, &update, sort.as_ref()).unwrap();
+                assert_eq!(actual, expected, "filter {:?} sort {:?}", filter, sort);
+            }
+        }
+
+        let insert: HashMap<String, Value> = serde_json::from_value(json!({"_id": "6", "title": "New"})).unwrap();
+        for filter in [json!({"_id": "2"}), json!({"title": "New"}), json!({"title": "New"})] {
+            let filter = Filter::from_json(&filter).unwrap();
+            let expected = mock.upsert("posts", &filter, &update, &insert).unwrap();
+            let actual = sqlite.upsert("posts", &filter, &update, &insert).unwrap();
+            assert_eq!(actual, expected, "filter {:?}", filter);
+        }
+
+        let all = |db: &dyn DatabaseProvider| db.query("posts", None, None, None, None, None).unwrap();
+        assert_eq!(all(&sqlite), all(&mock));
+        assert_eq!(all(&sqlite)[5], json!({"_id": "6", "title": "New", "seen": true, "hits": 1}));
+        assert!(sqlite.find_one_and_update("missing", &Filter::all(), &update, None).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_transactions() {
+        let (_, sqlite) = providers();
+        let all = || ids(&sqlite.query("posts", None, None, None, None, None).unwrap());
+        let before = all();
+        let update: Update = serde_json::from_value(json!({"$set": {"title": "Changed"}})).unwrap();
+
+        let tx = sqlite.begin().unwrap();
+        tx.insert("posts", &serde_json::from_value(json!({"_id": "6"})).unwrap()).unwrap();
+        tx.update("posts", &Filter::from_json(&json!({"_id": "1"})).unwrap(), &update).unwrap();
+        tx.delete("posts", &Filter::from_json(&json!({"_id": "2"})).unwrap()).unwrap();
+        // A failed update inside the transaction only undoes itself
+        let inc: Update = serde_json::from_value(json!({"$inc": {"views": 1}})).unwrap();
+        assert!(tx.update("posts", &Filter::from_json(&json!({"_id": "5"})).unwrap(), &inc).is_err());
+        // Only the transaction's provider can end it
+        assert!(matches!(sqlite.rollback(), Err(ExecutionError::DatabaseError { .. })));
+        tx.rollback().unwrap();
+        assert_eq!(all(), before);
+        assert_eq!(sqlite.query("posts", None, None, None, None, None).unwrap()[0]["title"], json!("Rust"));
+
+        let tx = sqlite.begin().unwrap();
+        tx.insert("posts", &serde_json::from_value(json!({"_id": "6"})).unwrap()).unwrap();
+        tx.delete("posts", &Filter::from_json(&json!({"_id": "2"})).unwrap()).unwrap();
+        tx.commit().unwrap();
+        assert_eq!(all(), vec!["1", "3", "4", "5", "6"]);
+
+        assert!(matches!(tx.commit(), Err(ExecutionError::DatabaseError { .. })));
+        assert!(tx.insert("posts", &serde_json::from_value(json!({"_id": "7"})).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_calls_outside_a_transaction_wait_for_it() {
+        let (_, sqlite) = providers();
+        let tx = sqlite.begin().unwrap();
+        tx.insert("posts", &serde_json::from_value(json!({"_id": "6"})).unwrap()).unwrap();
+        assert_eq!(tx.count("posts", None).unwrap(), 6);
+
+        std::thread::scope(|scope| {
+            // Neither sees the transaction's insert, nor is undone by its rollback
+            let read = scope.spawn(|| sqlite.query("posts", None, None, None, None, None));
+            let write = scope.spawn(|| sqlite.insert("posts", &serde_json::from_value(json!({"_id": "7"})).unwrap()));
+            std::thread::sleep(Duration::from_millis(20));
+            assert!(!read.is_finished());
+            assert!(!write.is_finished());
+            tx.rollback().unwrap();
+            assert!(!ids(&read.join().unwrap().unwrap()).contains(&"6".to_string()));
+            write.join().unwrap().unwrap();
+        });
+        assert_eq!(ids(&sqlite.query("posts", None, None, None, None, None).unwrap()), vec!["1", "2", "3", "4", "5", "7"]);
+    }
+
+    #[test]
+    fn test_arrays_in_scalar_fields_match_mock_semantics() {
+        let schemas: HashMap<String, DatabaseSchema> = serde_json::from_value(json!({
+            "posts": {"fields": {"title": {"type": "string"}}}
+        }))
+        .unwrap();
+        let (mock, sqlite) = providers_with_schemas(&schemas);
+        let mock = mock.with_schemas(&schemas);
+        let providers: [&dyn DatabaseProvider; 2] = [&mock, &sqlite];
+
+        // Every provider refuses to write an array into a declared scalar field
+        let expected = ExecutionError::database_error(
+            "Field 'title' of collection 'posts' is declared as a scalar and cannot hold an array",
+        );
+        let set_title: Update = serde_json::from_value(json!({"title": ["Rust"]})).unwrap();
+        let rust = Filter::from_json(&json!({"_id": "1"})).unwrap();
+        let missing = Filter::from_json(&json!({"_id": "9"})).unwrap();
+        let insert = serde_json::from_value(json!({"_id": "9", "title": ["Rust"]})).unwrap();
+        for db in providers {
+            assert_eq!(db.insert("posts", &insert).unwrap_err(), expected);
+            assert_eq!(db.update("posts", &rust, &set_title).unwrap_err(), expected);
+            assert_eq!(db.find_one_and_update("posts", &rust, &set_title, None).unwrap_err(), expected);
+            assert_eq!(db.upsert("posts", &missing, &Update::new(vec![]), &insert).unwrap_err(), expected);
+            assert!(db.insert("posts", &serde_json::from_value(json!({"tags": ["rust"]})).unwrap()).is_ok());
+            assert_eq!(ids(&db.query("posts", Some(&rust), None, None, None, None).unwrap()), vec!["1"]);
+            assert_eq!(db.count("posts", Some(&Filter::from_json(&json!({"title": "Rust"})).unwrap())).unwrap(), 1);
+        }
+
+        // A field that already holds arrays when the database is opened is
+        // filtered element by element
+        let path = std::env::temp_dir().join(format!("deck-sqlite-scalars-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let db = SqliteDatabase::open(&path, &HashMap::new()).unwrap();
+        db.insert("posts", &serde_json::from_value(json!({"_id": "1", "title": ["Rust", "Serde"]})).unwrap())
+            .unwrap();
+        drop(db);
+
+        let db = SqliteDatabase::open(&path, &schemas).unwrap();
+        let mock = MockDatabase::new()
+            .with_collection("posts", vec![json!({"_id": "1", "title": ["Rust", "Serde"]})])
+            .with_schemas(&schemas);
+        assert!(db.scalar_fields("posts").is_empty());
+        for filter in [json!({"title": "Serde"}), json!({"title": {"$in": ["Rust"]}}), json!({"title": {"$ne": "Rust"}})] {
+            let parsed = Filter::from_json(&filter).unwrap();
+            let expected = mock.query("posts", Some(&parsed), None, None, None, None).unwrap();
+            let actual = db.query("posts", Some(&parsed), None, None, None, None).unwrap();
+            assert_eq!(ids(&actual), ids(&expected), "filter {}", filter);
+        }
+
+        drop(db);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_schema_indexes_and_constraints() {
+        let schemas: HashMap<String, DatabaseSchema> = serde_json::from_value(json!({
+            "users": {
+                "fields": {
+                    "_id": {"type": "string", "primary": true},
+                    "email": {"type": "string", "unique": true},
+                    "tenant": {"type": "string"},
+                    "handle": {"type": "string"}
+                },
+                "indexes": [
+                    {"fields": ["tenant", "handle"], "unique": true},
+                    {"fields": ["tenant"]}
+                ]
+            }
+        }))
+        .unwrap();
+        let sqlite = SqliteDatabase::open_in_memory(&schemas).unwrap();
+        let insert = |doc: Value| sqlite.insert("users", &serde_json::from_value(doc).unwrap());
+        let constraint = |result: Result<Value, ExecutionError>| match result {
+            Err(ExecutionError::DatabaseError { constraint: Some(name), .. }) => name,
+            other => panic!("Expected constraint violation, got {:?}", other),
+        };
+
+        let indexes: Vec<String> = {
+            let conn = sqlite.conn.lock().unwrap();
+            let mut statement = conn
+                .prepare("SELECT name FROM sqlite_master WHERE type = 'index' AND sql IS NOT NULL ORDER BY name")
+                .unwrap();
+            statement.query_map([], |row| row.get(0)).unwrap().map(Result::unwrap).collect()
+        };
+        assert_eq!(indexes, vec!["users_email_key", "users_tenant_handle_key", "users_tenant_idx"]);
+
+        insert(json!({"_id": "1", "email": "a@x", "tenant": "t1", "handle": "ann"})).unwrap();
+        assert_eq!(constraint(insert(json!({"email": "a@x"}))), "users_email_key");
+        assert_eq!(constraint(insert(json!({"_id": "1"}))), "users_pkey");
+        assert_eq!(
+            constraint(insert(json!({"tenant": "t1", "handle": "ann"}))),
+            "users_tenant_handle_key"
+        );
+        assert!(insert(json!({"email": null, "tenant": "t1"})).is_ok());
+        assert!(insert(json!({"email": null, "tenant": "t1"})).is_ok());
+
+        // Both branches of an upsert report the violation and change nothing
+        let set_email: Update = serde_json::from_value(json!({"email": "a@x"})).unwrap();
+        let upsert = |filter: Value| {
+            let filter = Filter::from_json(&filter).unwrap();
+            let insert = serde_json::from_value(json!({"_id": "9", "email": "a@x"})).unwrap();
+            sqlite.upsert("users", &filter, &set_email, &insert).map(|(_, doc)| doc)
+        };
+        assert_eq!(constraint(upsert(json!({"_id": "9"}))), "users_email_key");
+        assert_eq!(constraint(upsert(json!({"email": null}))), "users_email_key");
+        assert_eq!(sqlite.count("users", Some(&Filter::from_json(&json!({"email": null})).unwrap())).unwrap(), 2);
+        assert_eq!(sqlite.count("users", None).unwrap(), 3);
+    }
+
+    #[test]
+    fn test_filters_use_expression_indexes() {
+        let schemas: HashMap<String, DatabaseSchema> = serde_json::from_value(json!({
+            "users": {
+                "fields": {"email": {"type": "string", "unique": true}, "age": {"type": "number"}},
+                "indexes": [{"fields": ["age"]}]
+            }
+        }))
+        .unwrap();
+        let sqlite = SqliteDatabase::open_in_memory(&schemas).unwrap();
+        let plan = |filter: Value| {
+            let mut sql = Sql::default();
+            sql.push("EXPLAIN QUERY PLAN SELECT t.doc FROM \"users\" AS t WHERE ");
+            filter_sql(&Filter::from_json(&filter).unwrap(), sqlite.scalar_fields("users"), &mut sql);
+            let conn = sqlite.conn.lock().unwrap();
+            let mut statement = conn.prepare(&sql.text).unwrap();
+            let rows = statement.query_map(params_from_iter(&sql.params), |row| row.get::<_, String>(3)).unwrap();
+            rows.map(Result::unwrap).collect::<Vec<_>>().join("; ")
+        };
+
+        assert!(plan(json!({"email": "a@x"})).contains("USING INDEX users_email_key"));
+        assert!(plan(json!({"age": {"$gte": 18}})).contains("USING INDEX users_age_idx"));
+        assert!(plan(json!({"email": {"$in": ["a@x", "b@x"]}})).contains("USING INDEX users_email_key"));
+        assert!(plan(json!({"nickname": "ann"})).starts_with("SCAN t"));
+    }
+
+    #[test]
+    fn test_persists_across_reopen() {
+        let path = std::env::temp_dir().join(format!("deck-sqlite-test-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let db = SqliteDatabase::open(&path, &HashMap::new()).unwrap();
+        db.insert("notes", &serde_json::from_value(json!({"_id": "n1", "text": "hi"})).unwrap())
+            .unwrap();
+        drop(db);
+
+        let db = SqliteDatabase::open(&path, &HashMap::new()).unwrap();
+        let docs = db.query("notes", None, None, None, None, None).unwrap();
+        assert_eq!(docs, vec![json!({"_id": "n1", "text": "hi"})]);
+
+        drop(db);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_path_pattern() {
+        let pattern = Regex::new(&path_pattern("comments.by", true, false)).unwrap();
+        assert!(pattern.is_match("$.comments[1].by"));
+        assert!(pattern.is_match("$.comments.by"));
+        assert!(!pattern.is_match("$.comments[1].by[0]"));
+
+        let pattern = Regex::new(&path_pattern("tags.0", true, true)).unwrap();
+        assert!(pattern.is_match("$.tags[0]"));
+        assert!(pattern.is_match("$.tags.\"0\""));
+        assert!(pattern.is_match("$.tags[0][2]"));
+        assert!(!pattern.is_match("$.tags[1]"));
+    }
+}
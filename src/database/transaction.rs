@@ -0,0 +1,262 @@
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+
+use crate::pipeline::ExecutionError;
+
+/// The open transaction of a provider, if any
+///
+/// Providers are shared by every request, so they run one transaction at a
+/// time: `begin` waits until the open transaction has ended, and so do reads
+/// and writes from outside it (see `run`), so that they neither see its
+/// uncommitted changes nor are undone by its rollback. `T` is what the
+/// provider keeps for the open transaction, such as a snapshot to restore on
+/// rollback.
+#[derive(Debug)]
+pub struct TransactionState<T> {
+    state: Mutex<State<T>>,
+    ended: Condvar,
+}
+
+#[derive(Debug)]
+struct State<T> {
+    /// Number of the open transaction and what the provider keeps for it
+    open: Option<(u64, T)>,
+    /// Number of transactions opened so far
+    started: u64,
+}
+
+/// A transaction opened by `TransactionState::begin`
+///
+/// Dropping it before its commit or rollback rolls it back, so a request
+/// that is cancelled or panics mid-transaction does not leave the provider
+/// waiting for it forever.
+pub struct Transaction<T> {
+    state: Arc<TransactionState<T>>,
+    id: u64,
+    rollback: Box<dyn Fn(T) -> Result<(), ExecutionError> + Send + Sync>,
+}
+
+impl<T> Default for TransactionState<T> {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(State { open: None, started: 0 }),
+            ended: Condvar::new(),
+        }
+    }
+}
+
+impl<T> TransactionState<T> {
+    /// Create a state with no open transaction
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait for the open transaction to end, then open a new one
+    ///
+    /// `start` runs once no other transaction is open and produces the state
+    /// kept for this one. If it fails, no transaction is opened. `rollback`
+    /// undoes the transaction when it is rolled back or dropped.
+    pub fn begin(
+        self: &Arc<Self>,
+        start: impl FnOnce() -> Result<T, ExecutionError>,
+        rollback: impl Fn(T) -> Result<(), ExecutionError> + Send + Sync + 'static,
+    ) -> Result<Transaction<T>, ExecutionError> {
+        let mut state = self.lock();
+        while state.open.is_some() {
+            state = self.ended.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+        let value = start()?;
+        state.started += 1;
+        let id = state.started;
+        state.open = Some((id, value));
+        Ok(Transaction {
+            state: Arc::clone(self),
+            id,
+            rollback: Box::new(rollback),
+        })
+    }
+
+    /// Run a read or write as part of transaction `owner`, or outside any with `None`
+    ///
+    /// Outside a transaction, `f` waits until none is open, and no
+    /// transaction can begin while it runs. `f` gets the state of the owner's
+    /// transaction; errors if that transaction has ended.
+    pub fn run<R>(
+        &self,
+        owner: Option<&Transaction<T>>,
+        f: impl FnOnce(Option<&mut T>) -> Result<R, ExecutionError>,
+    ) -> Result<R, ExecutionError> {
+        let mut state = self.lock();
+        match owner {
+            None => {
+                while state.open.is_some() {
+                    state = self.ended.wait(state).unwrap_or_else(PoisonError::into_inner);
+                }
+                f(None)
+            }
+            Some(owner) => match &mut state.open {
+                Some((open, value)) if self.owns(owner, *open) => f(Some(value)),
+                _ => Err(not_open()),
+            },
+        }
+    }
+
+    /// End transaction `owner`, handing its state to `finish`
+    ///
+    /// The transaction is closed even if `finish` fails. Errors if it is not
+    /// the open transaction.
+    pub fn commit<R>(
+        &self,
+        owner: Option<&Transaction<T>>,
+        finish: impl FnOnce(T) -> Result<R, ExecutionError>,
+    ) -> Result<R, ExecutionError> {
+        let owner = owner.ok_or_else(not_open)?;
+        self.end(owner, finish)
+    }
+
+    /// Roll back transaction `owner` (see `commit`)
+    pub fn rollback(&self, owner: Option<&Transaction<T>>) -> Result<(), ExecutionError> {
+        let owner = owner.ok_or_else(not_open)?;
+        self.end(owner, &owner.rollback)
+    }
+
+    fn end<R>(&self, owner: &Transaction<T>, finish: impl FnOnce(T) -> Result<R, ExecutionError>) -> Result<R, ExecutionError> {
+        let mut state = self.lock();
+        let value = match state.open.take() {
+            Some((open, value)) if self.owns(owner, open) => value,
+            other => {
+                state.open = other;
+                return Err(not_open());
+            }
+        };
+        let result = finish(value);
+        drop(state);
+        self.ended.notify_all();
+        result
+    }
+
+    /// Whether `owner` is transaction number `open` of this state
+    fn owns(&self, owner: &Transaction<T>, open: u64) -> bool {
+        std::ptr::eq(Arc::as_ptr(&owner.state), self) && owner.id == open
+    }
+
+    /// Lock the state, even if a panic poisoned it: the state is only
+    /// changed while the lock is held and `f` cannot leave it half-updated
+    fn lock(&self) -> MutexGuard<'_, State<T>> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T> Drop for Transaction<T> {
+    fn drop(&mut self) {
+        // Does nothing if the transaction was committed or rolled back
+        let _ = self.state.end(self, &self.rollback);
+    }
+}
+
+fn not_open() -> ExecutionError {
+    ExecutionError::database_error("No transaction in progress")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_begin_and_end() {
+        let state = Arc::new(TransactionState::new());
+        assert!(state.run(None, |open| Ok(open.is_none())).unwrap());
+
+        let transaction = state.begin(|| Ok(1), |_| Ok(())).unwrap();
+        state.run(Some(&transaction), |open| {
+            *open.unwrap() += 1;
+            Ok(())
+        }).unwrap();
+        assert!(matches!(state.commit(None, Ok), Err(ExecutionError::DatabaseError { .. })));
+        assert_eq!(state.commit(Some(&transaction), Ok).unwrap(), 2);
+
+        // A transaction that has ended can no longer be used or ended
+        let next = state.begin(|| Ok(5), |_| Ok(())).unwrap();
+        assert!(matches!(state.run(Some(&transaction), |_| Ok(())), Err(ExecutionError::DatabaseError { .. })));
+        assert!(matches!(state.commit(Some(&transaction), Ok), Err(ExecutionError::DatabaseError { .. })));
+        assert!(matches!(state.rollback(Some(&transaction)), Err(ExecutionError::DatabaseError { .. })));
+        assert_eq!(state.commit(Some(&next), Ok).unwrap(), 5);
+
+        // Nor can a transaction of another state
+        let other = Arc::new(TransactionState::new());
+        let foreign = other.begin(|| Ok(0), |_| Ok(())).unwrap();
+        let own = state.begin(|| Ok(0), |_| Ok(())).unwrap();
+        assert!(matches!(state.run(Some(&foreign), |_| Ok(())), Err(ExecutionError::DatabaseError { .. })));
+        state.rollback(Some(&own)).unwrap();
+    }
+
+    #[test]
+    fn test_failed_start_opens_nothing() {
+        let state = Arc::new(TransactionState::<()>::new());
+        assert!(state.begin(|| Err(ExecutionError::database_error("busy")), |_| Ok(())).is_err());
+        assert!(state.run(None, |open| Ok(open.is_none())).unwrap());
+    }
+
+    #[test]
+    fn test_dropped_transaction_rolls_back() {
+        let state = Arc::new(TransactionState::new());
+        let rolled_back = Arc::new(Mutex::new(Vec::new()));
+
+        let recorded = Arc::clone(&rolled_back);
+        let transaction = state
+            .begin(|| Ok("dropped"), move |value| {
+                recorded.lock().unwrap().push(value);
+                Ok(())
+            })
+            .unwrap();
+        drop(transaction);
+        assert_eq!(*rolled_back.lock().unwrap(), vec!["dropped"]);
+        assert!(state.run(None, |open| Ok(open.is_none())).unwrap());
+
+        // A transaction that has ended is not rolled back again
+        let recorded = Arc::clone(&rolled_back);
+        let transaction = state
+            .begin(|| Ok("committed"), move |value| {
+                recorded.lock().unwrap().push(value);
+                Ok(())
+            })
+            .unwrap();
+        state.commit(Some(&transaction), |_| Ok(())).unwrap();
+        drop(transaction);
+        assert_eq!(*rolled_back.lock().unwrap(), vec!["dropped"]);
+    }
+
+    #[test]
+    fn test_begin_waits_for_open_transaction() {
+        let state = Arc::new(TransactionState::new());
+        let first = state.begin(|| Ok("first"), |_| Ok(())).unwrap();
+
+        let waiting = Arc::clone(&state);
+        let second = std::thread::spawn(move || {
+            let transaction = waiting.begin(|| Ok("second"), |_| Ok(())).unwrap();
+            waiting.commit(Some(&transaction), Ok).unwrap()
+        });
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(state.commit(Some(&first), Ok).unwrap(), "first");
+        assert_eq!(second.join().unwrap(), "second");
+    }
+
+    #[test]
+    fn test_outside_calls_wait_for_open_transaction() {
+        let state = Arc::new(TransactionState::new());
+        let transaction = state.begin(|| Ok(vec!["in transaction"]), |_| Ok(())).unwrap();
+
+        let waiting = Arc::clone(&state);
+        let outside = std::thread::spawn(move || waiting.run(None, |open| Ok(open.is_none())).unwrap());
+
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(!outside.is_finished());
+        state.run(Some(&transaction), |open| {
+            open.unwrap().push("also in transaction");
+            Ok(())
+        }).unwrap();
+        assert_eq!(state.commit(Some(&transaction), Ok).unwrap(), vec!["in transaction", "also in transaction"]);
+        assert!(outside.join().unwrap());
+    }
+}
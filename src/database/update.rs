@@ -0,0 +1,503 @@
+use serde::de::{self, DeserializeOwned, Deserializer};
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use super::{path, sort};
+use crate::executor::{Executor, math};
+use crate::operators::OperatorValue;
+use crate::pipeline::ExecutionError;
+
+/// An update applied to each matched document
+///
+/// Updates are written as MongoDB-style JSON objects. Plain fields are set,
+/// and `$`-prefixed groups apply an update operator to each of their fields:
+///
+/// ```json
+/// {
+///   "title": "New title",
+///   "$set": {"meta.editedBy": "u1"},
+///   "$inc": {"views": 1},
+///   "$push": {"tags": "featured"},
+///   "$pull": {"tags": "draft"},
+///   "$unset": ["meta.draftNotes"]
+/// }
+/// ```
+///
+/// Fields may be dot-paths; missing intermediate objects are created.
+/// `V` is the type of operands, as with `Filter`: the executor resolves
+/// an `Update<OperatorValue>` into the `Update<Value>` that
+/// `DatabaseProvider`s receive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Update<V = Value> {
+    /// Operations in the order they are applied
+    pub ops: Vec<UpdateOp<V>>,
+}
+
+/// A single update operation on one field
+#[derive(Debug, Clone, PartialEq)]
+pub enum UpdateOp<V = Value> {
+    /// `$set` - replace the field value
+    Set { path: String, value: V },
+    /// `$unset` - remove the field
+    Unset { path: String },
+    /// `$inc` - add a number to the field (a missing field starts at 0)
+    Inc { path: String, by: V },
+    /// `$push` - append to the array field (a missing field becomes an array)
+    Push { path: String, value: V },
+    /// `$pull` - remove every array element equal to the value
+    Pull { path: String, value: V },
+}
+
+impl<V> Update<V> {
+    /// Create an update from operations in application order
+    pub fn new(ops: Vec<UpdateOp<V>>) -> Self {
+        Self { ops }
+    }
+
+    /// Every operand, in the order `try_map` visits them
+    pub fn operands(&self) -> Vec<&V> {
+        self.ops.iter().filter_map(UpdateOp::operand).collect()
+    }
+
+    /// Convert every operand, keeping the operations
+    pub fn try_map<W, E>(&self, f: &mut impl FnMut(&V) -> Result<W, E>) -> Result<Update<W>, E> {
+        let ops = self
+            .ops
+            .iter()
+            .map(|op| op.try_map(f))
+            .collect::<Result<Vec<_>, E>>()?;
+        Ok(Update { ops })
+    }
+}
+
+impl Update<OperatorValue> {
+    /// Evaluate every operand and check that `$inc` operands are numbers
+    ///
+    /// `eval` is called once per operand, in operation order.
+    pub fn resolve(
+        &self,
+        mut eval: impl FnMut(&OperatorValue) -> Result<Value, ExecutionError>,
+    ) -> Result<Update, ExecutionError> {
+        let update = self.try_map(&mut eval)?;
+        update.validate()?;
+        Ok(update)
+    }
+}
+
+impl Update {
+    /// Check that every `$inc` operand is a number
+    pub fn validate(&self) -> Result<(), ExecutionError> {
+        for op in &self.ops {
+            if let UpdateOp::Inc { path, by } = op
+                && !by.is_number()
+            {
+                return Err(ExecutionError::invalid_operator(
+                    "$inc",
+                    format!("Operand for '{}' must be a number, got {}", path, Executor::type_name(by)),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply every operation to a document
+    ///
+    /// The document is only changed if every operation succeeds.
+    ///
+    /// # Errors
+    /// - `TypeError` if `$inc` targets a non-number or `$push`/`$pull` a non-array,
+    ///   or a path runs through a scalar
+    /// - `ArithmeticOverflow` if `$inc` overflows
+    /// - `IndexOutOfBounds` if a path indexes past the end of an array
+    pub fn apply(&self, doc: &mut Value) -> Result<(), ExecutionError> {
+        let mut updated = doc.clone();
+        for op in &self.ops {
+            op.apply(&mut updated)?;
+        }
+        *doc = updated;
+        Ok(())
+    }
+}
+
+impl<V> UpdateOp<V> {
+    /// The update operator name (`$set`, `$inc`, ...)
+    pub fn name(&self) -> &'static str {
+        match self {
+            UpdateOp::Set { .. } => "$set",
+            UpdateOp::Unset { .. } => "$unset",
+            UpdateOp::Inc { .. } => "$inc",
+            UpdateOp::Push { .. } => "$push",
+            UpdateOp::Pull { .. } => "$pull",
+        }
+    }
+
+    /// The dot-path the operation targets
+    pub fn path(&self) -> &str {
+        match self {
+            UpdateOp::Set { path, .. }
+            | UpdateOp::Unset { path }
+            | UpdateOp::Inc { path, .. }
+            | UpdateOp::Push { path, .. }
+            | UpdateOp::Pull { path, .. } => path,
+        }
+    }
+
+    /// The operand, if the operation has one
+    pub fn operand(&self) -> Option<&V> {
+        match self {
+            UpdateOp::Set { value, .. } | UpdateOp::Push { value, .. } | UpdateOp::Pull { value, .. } => {
+                Some(value)
+            }
+            UpdateOp::Inc { by, .. } => Some(by),
+            UpdateOp::Unset { .. } => None,
+        }
+    }
+
+    /// Convert the operand, keeping the operation and path
+    pub fn try_map<W, E>(&self, f: &mut impl FnMut(&V) -> Result<W, E>) -> Result<UpdateOp<W>, E> {
+        Ok(match self {
+            UpdateOp::Set { path, value } => UpdateOp::Set {
+                path: path.clone(),
+                value: f(value)?,
+            },
+            UpdateOp::Unset { path } => UpdateOp::Unset { path: path.clone() },
+            UpdateOp::Inc { path, by } => UpdateOp::Inc {
+                path: path.clone(),
+                by: f(by)?,
+            },
+            UpdateOp::Push { path, value } => UpdateOp::Push {
+                path: path.clone(),
+                value: f(value)?,
+            },
+            UpdateOp::Pull { path, value } => UpdateOp::Pull {
+                path: path.clone(),
+                value: f(value)?,
+            },
+        })
+    }
+
+    /// Build an operation from its operator name
+    fn from_name(name: &str, path: String, operand: V) -> Option<Self> {
+        Some(match name {
+            "$set" => UpdateOp::Set { path, value: operand },
+            "$inc" => UpdateOp::Inc { path, by: operand },
+            "$push" => UpdateOp::Push { path, value: operand },
+            "$pull" => UpdateOp::Pull { path, value: operand },
+            _ => return None,
+        })
+    }
+}
+
+impl UpdateOp {
+    fn apply(&self, doc: &mut Value) -> Result<(), ExecutionError> {
+        let current = path::get(doc, self.path());
+        match self {
+            UpdateOp::Set { path, value } => path::set(doc, path, value.clone()),
+            UpdateOp::Unset { path } => {
+                path::remove(doc, path);
+                Ok(())
+            }
+            UpdateOp::Inc { path, by } => {
+                let value = match current {
+                    None | Some(Value::Null) => by.clone(),
+                    Some(current @ Value::Number(_)) => math::add(&[current.clone(), by.clone()])?,
+                    Some(other) => return Err(self.type_error("number", other)),
+                };
+                path::set(doc, path, value)
+            }
+            UpdateOp::Push { path, value } => {
+                let items = match current {
+                    None | Some(Value::Null) => vec![value.clone()],
+                    Some(Value::Array(items)) => {
+                        let mut items = items.clone();
+                        items.push(value.clone());
+                        items
+                    }
+                    Some(other) => return Err(self.type_error("array", other)),
+                };
+                path::set(doc, path, Value::Array(items))
+            }
+            UpdateOp::Pull { path, value } => match current {
+                None | Some(Value::Null) => Ok(()),
+                Some(Value::Array(items)) => {
+                    let items = items
+                        .iter()
+                        .filter(|item| sort::compare_values(item, value) != Ordering::Equal)
+                        .cloned()
+                        .collect();
+                    path::set(doc, path, Value::Array(items))
+                }
+                Some(other) => Err(self.type_error("array", other)),
+            },
+        }
+    }
+
+    fn type_error(&self, expected: &str, actual: &Value) -> ExecutionError {
+        ExecutionError::type_error_with_types(
+            format!("{} requires field '{}' to be of type {}", self.name(), self.path(), expected),
+            expected,
+            Executor::type_name(actual),
+        )
+    }
+}
+
+// Parsing and serialization
+
+impl<V: DeserializeOwned> Update<V> {
+    /// Parse an update from its JSON form
+    pub fn from_json(value: &Value) -> Result<Self, String> {
+        let obj = value
+            .as_object()
+            .ok_or_else(|| "Update must be an object".to_string())?;
+        let parse_operand = |path: &str, value: &Value| {
+            V::deserialize(value).map_err(|e| format!("Invalid operand for '{}': {}", path, e))
+        };
+
+        let mut ops = Vec::new();
+        for (key, value) in obj {
+            match key.as_str() {
+                "$unset" => {
+                    let paths: Vec<String> = match value {
+                        Value::String(path) => vec![path.clone()],
+                        Value::Array(items) => items
+                            .iter()
+                            .map(|item| item.as_str().map(str::to_string))
+                            .collect::<Option<_>>()
+                            .ok_or_else(|| "$unset requires a list of field names".to_string())?,
+                        Value::Object(fields) => fields.keys().cloned().collect(),
+                        _ => return Err("$unset requires a list of field names".to_string()),
+                    };
+                    ops.extend(paths.into_iter().map(|path| UpdateOp::Unset { path }));
+                }
+                _ if key.starts_with('$') => {
+                    let fields = value
+                        .as_object()
+                        .ok_or_else(|| format!("{} requires an object of fields", key))?;
+                    for (path, operand) in fields {
+                        let op = UpdateOp::from_name(key, path.clone(), parse_operand(path, operand)?)
+                            .ok_or_else(|| format!("Unknown update operator '{}'", key))?;
+                        ops.push(op);
+                    }
+                }
+                _ => ops.push(UpdateOp::Set {
+                    path: key.clone(),
+                    value: parse_operand(key, value)?,
+                }),
+            }
+        }
+
+        Ok(Update { ops })
+    }
+}
+
+impl<'de, V: DeserializeOwned> Deserialize<'de> for Update<V> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        Self::from_json(&value).map_err(de::Error::custom)
+    }
+}
+
+impl<V: Serialize> Serialize for Update<V> {
+    /// Serializes in the grouped form (`{"$set": {..}, "$unset": [..]}`)
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut groups: Vec<(&str, Value)> = Vec::new();
+        for op in &self.ops {
+            let name = op.name();
+            let index = match groups.iter().position(|(group, _)| *group == name) {
+                Some(index) => index,
+                None => {
+                    let empty = match op {
+                        UpdateOp::Unset { .. } => Value::Array(Vec::new()),
+                        _ => Value::Object(Map::new()),
+                    };
+                    groups.push((name, empty));
+                    groups.len() - 1
+                }
+            };
+            match (&mut groups[index].1, op.operand()) {
+                (Value::Array(paths), None) => paths.push(Value::String(op.path().to_string())),
+                (Value::Object(fields), Some(operand)) => {
+                    let value = serde_json::to_value(operand).map_err(serde::ser::Error::custom)?;
+                    fields.insert(op.path().to_string(), value);
+                }
+                _ => unreachable!("group shape matches the operation"),
+            }
+        }
+
+        let mut map = serializer.serialize_map(Some(groups.len()))?;
+        for (name, fields) in &groups {
+            map.serialize_entry(name, fields)?;
+        }
+        map.end()
+    }
+}
+
+impl<V> From<HashMap<String, V>> for Update<V> {
+    /// Set every field (the simple `{"field": value}` form)
+    fn from(fields: HashMap<String, V>) -> Self {
+        let mut fields: Vec<_> = fields.into_iter().collect();
+        fields.sort_by(|a, b| a.0.cmp(&b.0));
+        Update {
+            ops: fields
+                .into_iter()
+                .map(|(path, value)| UpdateOp::Set { path, value })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operators::Operator;
+    use serde_json::json;
+
+    fn update(value: Value) -> Update {
+        Update::from_json(&value).unwrap()
+    }
+
+    fn set(path: &str, value: Value) -> UpdateOp {
+        UpdateOp::Set {
+            path: path.to_string(),
+            value,
+        }
+    }
+
+    #[test]
+    fn test_parse_forms() {
+        let parsed = update(json!({
+            "title": "New",
+            "$inc": {"views": 1},
+            "$push": {"tags": "a"},
+            "$pull": {"tags": "b"},
+            "$set": {"meta.editor": "u1"},
+            "$unset": ["draft", "meta.notes"]
+        }));
+        assert_eq!(
+            parsed.ops,
+            vec![
+                UpdateOp::Inc { path: "views".to_string(), by: json!(1) },
+                UpdateOp::Pull { path: "tags".to_string(), value: json!("b") },
+                UpdateOp::Push { path: "tags".to_string(), value: json!("a") },
+                set("meta.editor", json!("u1")),
+                UpdateOp::Unset { path: "draft".to_string() },
+                UpdateOp::Unset { path: "meta.notes".to_string() },
+                set("title", json!("New")),
+            ]
+        );
+
+        assert_eq!(update(json!({"$unset": "a"})).ops, vec![UpdateOp::Unset { path: "a".to_string() }]);
+        assert_eq!(
+            update(json!({"$unset": {"a": ""}})).ops,
+            vec![UpdateOp::Unset { path: "a".to_string() }]
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Update::<Value>::from_json(&json!([1])).is_err());
+        assert!(Update::<Value>::from_json(&json!({"$rename": {"a": "b"}})).is_err());
+        assert!(Update::<Value>::from_json(&json!({"$inc": 1})).is_err());
+        assert!(Update::<Value>::from_json(&json!({"$unset": [1]})).is_err());
+    }
+
+    #[test]
+    fn test_parse_operator_operands() {
+        let parsed: Update<OperatorValue> = serde_json::from_value(json!({
+            "title": {"$get": "body.title"},
+            "$inc": {"views": {"$get": "body.by"}}
+        }))
+        .unwrap();
+        assert!(matches!(
+            &parsed.ops[0],
+            UpdateOp::Inc { by: OperatorValue::Operator(op), .. } if matches!(**op, Operator::Get(_))
+        ));
+        assert!(matches!(
+            &parsed.ops[1],
+            UpdateOp::Set { value: OperatorValue::Operator(_), .. }
+        ));
+    }
+
+    #[test]
+    fn test_resolve_validates_inc() {
+        let parsed: Update<OperatorValue> = serde_json::from_value(json!({"$inc": {"views": "one"}})).unwrap();
+        let result = parsed.resolve(|value| match value {
+            OperatorValue::Literal(v) => Ok(v.clone()),
+            OperatorValue::Operator(_) => unreachable!(),
+        });
+        assert!(matches!(result, Err(ExecutionError::InvalidOperator { .. })));
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let parsed = update(json!({"title": "t", "$inc": {"views": 2}, "$unset": ["draft"]}));
+        let json = serde_json::to_value(&parsed).unwrap();
+        assert_eq!(
+            json,
+            json!({"$inc": {"views": 2}, "$unset": ["draft"], "$set": {"title": "t"}})
+        );
+        assert_eq!(serde_json::to_value(update(json.clone())).unwrap(), json);
+    }
+
+    #[test]
+    fn test_apply_set_and_unset() {
+        let mut doc = json!({"title": "Old", "meta": {"draft": true, "notes": "x"}});
+        update(json!({"title": "New", "meta.editor": "u1", "$unset": ["meta.notes", "missing"]}))
+            .apply(&mut doc)
+            .unwrap();
+        assert_eq!(doc, json!({"title": "New", "meta": {"draft": true, "editor": "u1"}}));
+    }
+
+    #[test]
+    fn test_apply_inc() {
+        let mut doc = json!({"views": 10, "stats": {"score": 1.5}});
+        update(json!({"$inc": {"views": 1, "stats.score": -0.5, "stats.likes": 3}}))
+            .apply(&mut doc)
+            .unwrap();
+        assert_eq!(doc, json!({"views": 11, "stats": {"score": 1.0, "likes": 3}}));
+
+        let mut doc = json!({"views": u64::MAX});
+        assert!(matches!(
+            update(json!({"$inc": {"views": 1}})).apply(&mut doc),
+            Err(ExecutionError::ArithmeticOverflow { .. })
+        ));
+    }
+
+    #[test]
+    fn test_apply_push_and_pull() {
+        let mut doc = json!({"tags": ["a", "b", "a"], "meta": {}});
+        update(json!({"$pull": {"tags": "a"}, "$push": {"meta.history": {"v": 1}}}))
+            .apply(&mut doc)
+            .unwrap();
+        assert_eq!(doc, json!({"tags": ["b"], "meta": {"history": [{"v": 1}]}}));
+
+        update(json!({"$push": {"tags": "c"}, "$pull": {"missing": 1}}))
+            .apply(&mut doc)
+            .unwrap();
+        assert_eq!(doc["tags"], json!(["b", "c"]));
+    }
+
+    #[test]
+    fn test_apply_is_all_or_nothing() {
+        let original = json!({"title": "Old", "views": 1, "tags": "not-an-array"});
+        let mut doc = original.clone();
+        let result = update(json!({"title": "New", "$inc": {"views": 1}, "$push": {"tags": "x"}})).apply(&mut doc);
+
+        assert!(matches!(result, Err(ExecutionError::TypeError { .. })));
+        assert_eq!(doc, original);
+    }
+
+    #[test]
+    fn test_from_hash_map() {
+        let mut fields = HashMap::new();
+        fields.insert("b".to_string(), json!(2));
+        fields.insert("a".to_string(), json!(1));
+        assert_eq!(
+            Update::from(fields).ops,
+            vec![set("a", json!(1)), set("b", json!(2))]
+        );
+    }
+}
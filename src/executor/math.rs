@@ -0,0 +1,369 @@
+use serde_json::{Number, Value};
+use std::cmp::Ordering;
+
+use super::Executor;
+use crate::pipeline::ExecutionError;
+
+/// A JSON number in integer or floating point form
+///
+/// Integers (both i64 and u64 JSON numbers) are widened to i128 so that
+/// intermediate results can be checked before converting back. Integer
+/// inputs produce integer outputs whenever the result is exact.
+#[derive(Debug, Clone, Copy)]
+enum Num {
+    Int(i128),
+    Float(f64),
+}
+
+impl Num {
+    /// Read a number operand, or fail with a TypeError naming the operator
+    fn from_value(value: &Value, operator: &str) -> Result<Self, ExecutionError> {
+        match value {
+            Value::Number(n) => {
+                if let Some(i) = n.as_i64() {
+                    Ok(Num::Int(i128::from(i)))
+                } else if let Some(u) = n.as_u64() {
+                    Ok(Num::Int(i128::from(u)))
+                } else {
+                    Ok(Num::Float(n.as_f64().unwrap_or(f64::NAN)))
+                }
+            }
+            other => Err(ExecutionError::type_error_with_types(
+                format!("{} requires numeric operands", operator),
+                "number",
+                Executor::type_name(other),
+            )),
+        }
+    }
+
+    /// Convert an integral float into an integer when it is representable
+    fn from_integral_f64(f: f64) -> Self {
+        if f.is_finite() && f.fract() == 0.0 && f.abs() < 2f64.powi(63) {
+            Num::Int(f as i128)
+        } else {
+            Num::Float(f)
+        }
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            Num::Int(i) => i as f64,
+            Num::Float(f) => f,
+        }
+    }
+
+    fn is_zero(self) -> bool {
+        match self {
+            Num::Int(i) => i == 0,
+            Num::Float(f) => f == 0.0,
+        }
+    }
+
+    /// Convert back to a JSON value, failing if the result is out of range
+    fn into_value(self, operator: &str) -> Result<Value, ExecutionError> {
+        match self {
+            Num::Int(i) => {
+                if let Ok(v) = i64::try_from(i) {
+                    Ok(Value::from(v))
+                } else if let Ok(v) = u64::try_from(i) {
+                    Ok(Value::from(v))
+                } else {
+                    Err(ExecutionError::arithmetic_overflow(operator))
+                }
+            }
+            Num::Float(f) => Number::from_f64(f).map(Value::Number).ok_or_else(|| {
+                ExecutionError::invalid_operator(operator, "Result is not a finite number")
+            }),
+        }
+    }
+}
+
+/// Apply a binary operation, using checked integer math when both sides are integers
+fn binary(
+    left: Num,
+    right: Num,
+    operator: &str,
+    int_op: impl Fn(i128, i128) -> Option<i128>,
+    float_op: impl Fn(f64, f64) -> f64,
+) -> Result<Num, ExecutionError> {
+    match (left, right) {
+        (Num::Int(l), Num::Int(r)) => int_op(l, r)
+            .map(Num::Int)
+            .ok_or_else(|| ExecutionError::arithmetic_overflow(operator)),
+        _ => Ok(Num::Float(float_op(left.as_f64(), right.as_f64()))),
+    }
+}
+
+/// Compare two numbers, exactly for integers and numerically otherwise
+fn compare(left: Num, right: Num) -> Ordering {
+    match (left, right) {
+        (Num::Int(l), Num::Int(r)) => l.cmp(&r),
+        _ => left
+            .as_f64()
+            .partial_cmp(&right.as_f64())
+            .unwrap_or(Ordering::Equal),
+    }
+}
+
+/// $add - sum of all operands (0 for no operands)
+pub(crate) fn add(values: &[Value]) -> Result<Value, ExecutionError> {
+    let mut total = Num::Int(0);
+    for value in values {
+        let n = Num::from_value(value, "$add")?;
+        total = binary(total, n, "$add", i128::checked_add, |a, b| a + b)?;
+    }
+    total.into_value("$add")
+}
+
+/// $subtract - left minus right
+pub(super) fn subtract(left: &Value, right: &Value) -> Result<Value, ExecutionError> {
+    let l = Num::from_value(left, "$subtract")?;
+    let r = Num::from_value(right, "$subtract")?;
+    binary(l, r, "$subtract", i128::checked_sub, |a, b| a - b)?.into_value("$subtract")
+}
+
+/// $multiply - product of all operands (1 for no operands)
+pub(super) fn multiply(values: &[Value]) -> Result<Value, ExecutionError> {
+    let mut product = Num::Int(1);
+    for value in values {
+        let n = Num::from_value(value, "$multiply")?;
+        product = binary(product, n, "$multiply", i128::checked_mul, |a, b| a * b)?;
+    }
+    product.into_value("$multiply")
+}
+
+/// $divide - left divided by right
+///
+/// Integer division stays an integer when it is exact (`6 / 3 = 2`) and
+/// produces a float otherwise (`7 / 2 = 3.5`).
+pub(super) fn divide(left: &Value, right: &Value) -> Result<Value, ExecutionError> {
+    let l = Num::from_value(left, "$divide")?;
+    let r = Num::from_value(right, "$divide")?;
+    if r.is_zero() {
+        return Err(ExecutionError::DivisionByZero);
+    }
+
+    let result = match (l, r) {
+        (Num::Int(a), Num::Int(b)) if a % b == 0 => a
+            .checked_div(b)
+            .map(Num::Int)
+            .ok_or_else(|| ExecutionError::arithmetic_overflow("$divide"))?,
+        _ => Num::Float(l.as_f64() / r.as_f64()),
+    };
+    result.into_value("$divide")
+}
+
+/// $mod - remainder of left divided by right (sign follows the dividend)
+pub(super) fn modulo(left: &Value, right: &Value) -> Result<Value, ExecutionError> {
+    let l = Num::from_value(left, "$mod")?;
+    let r = Num::from_value(right, "$mod")?;
+    if r.is_zero() {
+        return Err(ExecutionError::DivisionByZero);
+    }
+    binary(l, r, "$mod", i128::checked_rem, |a, b| a % b)?.into_value("$mod")
+}
+
+/// $pow - base raised to exponent
+///
+/// Integer bases with non-negative integer exponents use exact integer math.
+pub(super) fn pow(base: &Value, exponent: &Value) -> Result<Value, ExecutionError> {
+    let b = Num::from_value(base, "$pow")?;
+    let e = Num::from_value(exponent, "$pow")?;
+
+    let result = match (b, e) {
+        (Num::Int(b), Num::Int(e)) if e >= 0 => {
+            let e = u32::try_from(e).map_err(|_| ExecutionError::arithmetic_overflow("$pow"))?;
+            b.checked_pow(e)
+                .map(Num::Int)
+                .ok_or_else(|| ExecutionError::arithmetic_overflow("$pow"))?
+        }
+        _ => Num::Float(b.as_f64().powf(e.as_f64())),
+    };
+    result.into_value("$pow")
+}
+
+/// $abs - absolute value
+pub(super) fn abs(value: &Value) -> Result<Value, ExecutionError> {
+    match Num::from_value(value, "$abs")? {
+        Num::Int(i) => Num::Int(i.abs()).into_value("$abs"),
+        Num::Float(f) => Num::Float(f.abs()).into_value("$abs"),
+    }
+}
+
+/// $round - round half away from zero to the given number of decimal places
+///
+/// Rounding to 0 places produces an integer.
+pub(super) fn round(value: &Value, places: u32) -> Result<Value, ExecutionError> {
+    match Num::from_value(value, "$round")? {
+        Num::Int(i) => Num::Int(i).into_value("$round"),
+        Num::Float(f) if places == 0 => Num::from_integral_f64(f.round()).into_value("$round"),
+        Num::Float(f) => {
+            let factor = 10f64.powi(places.min(i32::MAX as u32) as i32);
+            Num::Float((f * factor).round() / factor).into_value("$round")
+        }
+    }
+}
+
+/// $floor - largest integer less than or equal to the value
+pub(super) fn floor(value: &Value) -> Result<Value, ExecutionError> {
+    match Num::from_value(value, "$floor")? {
+        Num::Int(i) => Num::Int(i).into_value("$floor"),
+        Num::Float(f) => Num::from_integral_f64(f.floor()).into_value("$floor"),
+    }
+}
+
+/// $ceil - smallest integer greater than or equal to the value
+pub(super) fn ceil(value: &Value) -> Result<Value, ExecutionError> {
+    match Num::from_value(value, "$ceil")? {
+        Num::Int(i) => Num::Int(i).into_value("$ceil"),
+        Num::Float(f) => Num::from_integral_f64(f.ceil()).into_value("$ceil"),
+    }
+}
+
+/// $min - smallest operand
+pub(super) fn min(values: &[Value]) -> Result<Value, ExecutionError> {
+    extreme(values, "$min", Ordering::Less)
+}
+
+/// $max - largest operand
+pub(super) fn max(values: &[Value]) -> Result<Value, ExecutionError> {
+    extreme(values, "$max", Ordering::Greater)
+}
+
+/// Find the operand that compares as `wanted` against all others
+fn extreme(values: &[Value], operator: &str, wanted: Ordering) -> Result<Value, ExecutionError> {
+    let mut best: Option<(Num, &Value)> = None;
+    for value in values {
+        let n = Num::from_value(value, operator)?;
+        match best {
+            Some((current, _)) if compare(n, current) != wanted => {}
+            _ => best = Some((n, value)),
+        }
+    }
+
+    best.map(|(_, value)| value.clone()).ok_or_else(|| {
+        ExecutionError::invalid_operator(operator, "Requires at least one operand")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_add_preserves_integers() {
+        let result = add(&[json!(1), json!(2), json!(3)]).unwrap();
+        assert_eq!(result, json!(6));
+        assert!(result.is_i64());
+
+        assert_eq!(add(&[]).unwrap(), json!(0));
+        assert_eq!(add(&[json!(1), json!(0.5)]).unwrap(), json!(1.5));
+    }
+
+    #[test]
+    fn test_add_large_unsigned() {
+        let result = add(&[json!(u64::MAX - 1), json!(1)]).unwrap();
+        assert_eq!(result, json!(u64::MAX));
+        assert!(result.is_u64());
+    }
+
+    #[test]
+    fn test_add_overflow() {
+        let result = add(&[json!(u64::MAX), json!(1)]);
+        assert_eq!(result.unwrap_err(), ExecutionError::arithmetic_overflow("$add"));
+    }
+
+    #[test]
+    fn test_add_type_error() {
+        let result = add(&[json!(1), json!("2")]);
+        assert!(matches!(result, Err(ExecutionError::TypeError { .. })));
+    }
+
+    #[test]
+    fn test_subtract() {
+        assert_eq!(subtract(&json!(10), &json!(15)).unwrap(), json!(-5));
+        assert_eq!(subtract(&json!(1.5), &json!(0.5)).unwrap(), json!(1.0));
+        assert_eq!(
+            subtract(&json!(i64::MIN), &json!(u64::MAX)).unwrap_err(),
+            ExecutionError::arithmetic_overflow("$subtract")
+        );
+    }
+
+    #[test]
+    fn test_multiply() {
+        assert_eq!(multiply(&[json!(3), json!(4)]).unwrap(), json!(12));
+        assert_eq!(multiply(&[]).unwrap(), json!(1));
+        assert_eq!(multiply(&[json!(2), json!(0.25)]).unwrap(), json!(0.5));
+        assert_eq!(
+            multiply(&[json!(i64::MAX), json!(i64::MAX)]).unwrap_err(),
+            ExecutionError::arithmetic_overflow("$multiply")
+        );
+    }
+
+    #[test]
+    fn test_divide() {
+        let exact = divide(&json!(6), &json!(3)).unwrap();
+        assert_eq!(exact, json!(2));
+        assert!(exact.is_i64());
+
+        assert_eq!(divide(&json!(7), &json!(2)).unwrap(), json!(3.5));
+        assert_eq!(divide(&json!(1.0), &json!(4)).unwrap(), json!(0.25));
+    }
+
+    #[test]
+    fn test_divide_by_zero() {
+        assert_eq!(divide(&json!(1), &json!(0)).unwrap_err(), ExecutionError::DivisionByZero);
+        assert_eq!(divide(&json!(1.5), &json!(0.0)).unwrap_err(), ExecutionError::DivisionByZero);
+        assert_eq!(modulo(&json!(1), &json!(0)).unwrap_err(), ExecutionError::DivisionByZero);
+    }
+
+    #[test]
+    fn test_modulo() {
+        assert_eq!(modulo(&json!(7), &json!(3)).unwrap(), json!(1));
+        assert_eq!(modulo(&json!(-7), &json!(3)).unwrap(), json!(-1));
+        assert_eq!(modulo(&json!(7.5), &json!(2)).unwrap(), json!(1.5));
+    }
+
+    #[test]
+    fn test_pow() {
+        assert_eq!(pow(&json!(2), &json!(10)).unwrap(), json!(1024));
+        assert_eq!(pow(&json!(2), &json!(-1)).unwrap(), json!(0.5));
+        assert_eq!(pow(&json!(9), &json!(0.5)).unwrap(), json!(3.0));
+        assert_eq!(
+            pow(&json!(10), &json!(30)).unwrap_err(),
+            ExecutionError::arithmetic_overflow("$pow")
+        );
+    }
+
+    #[test]
+    fn test_abs() {
+        assert_eq!(abs(&json!(-5)).unwrap(), json!(5));
+        assert_eq!(abs(&json!(-2.5)).unwrap(), json!(2.5));
+        assert_eq!(abs(&json!(i64::MIN)).unwrap(), json!(9223372036854775808u64));
+    }
+
+    #[test]
+    fn test_round() {
+        assert_eq!(round(&json!(2.5), 0).unwrap(), json!(3));
+        assert_eq!(round(&json!(-2.5), 0).unwrap(), json!(-3));
+        assert_eq!(round(&json!(1.2345), 2).unwrap(), json!(1.23));
+        assert_eq!(round(&json!(7), 2).unwrap(), json!(7));
+    }
+
+    #[test]
+    fn test_floor_and_ceil() {
+        assert_eq!(floor(&json!(2.7)).unwrap(), json!(2));
+        assert_eq!(floor(&json!(-2.1)).unwrap(), json!(-3));
+        assert_eq!(ceil(&json!(2.1)).unwrap(), json!(3));
+        assert_eq!(ceil(&json!(5)).unwrap(), json!(5));
+    }
+
+    #[test]
+    fn test_min_and_max() {
+        assert_eq!(min(&[json!(3), json!(1.5), json!(2)]).unwrap(), json!(1.5));
+        assert_eq!(max(&[json!(3), json!(1.5), json!(2)]).unwrap(), json!(3));
+        assert_eq!(max(&[json!(u64::MAX), json!(i64::MAX)]).unwrap(), json!(u64::MAX));
+        assert!(matches!(min(&[]), Err(ExecutionError::InvalidOperator { .. })));
+    }
+}
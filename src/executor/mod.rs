@@ -1,15 +1,49 @@
-/// Pipeline executor and dependencies
-///
-/// This module contains the core execution engine for evaluating
-/// operators and pipelines.
-
+//! Pipeline executor and dependencies
+//!
+//! This module contains the core execution engine for evaluating
+//! operators and pipelines.
+
+pub(crate) mod math;
+mod render;
+mod runner;
 pub mod traits;
 
+pub use runner::RouteResponse;
+
 use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::task::Poll;
 
-use crate::operators::{Operator, OperatorValue};
+use crate::config::DatabaseSchema;
+use crate::database::{Cursor, Filter, SortSpec, Update, path};
+use crate::operators::{DbQueryOp, OnError, Operator, OperatorValue, ReturnDocument};
 use crate::pipeline::{Context, ExecutionError};
-use traits::{DatabaseProvider, RequestContext, TimeProvider};
+use traits::{AsyncDatabaseProvider, BoxFuture, RequestContext, TimeProvider};
+
+/// Run a future to completion, sleeping the current thread while it waits
+///
+/// This backs the synchronous executor methods, so they must not be called
+/// from async code; use the async methods there instead.
+fn block_on<T>(future: impl Future<Output = T>) -> T {
+    struct ThreadWaker(std::thread::Thread);
+
+    impl std::task::Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    let waker = std::task::Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+    let mut cx = std::task::Context::from_waker(&waker);
+    let mut future = std::pin::pin!(future);
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+            return output;
+        }
+        std::thread::park();
+    }
+}
 
 /// The pipeline executor
 ///
@@ -17,17 +51,23 @@ use traits::{DatabaseProvider, RequestContext, TimeProvider};
 /// of provided dependencies (database, time, request context).
 pub struct Executor<'a> {
     /// Database provider for query/insert/update/delete operations
-    pub database: &'a dyn DatabaseProvider,
+    pub database: &'a dyn AsyncDatabaseProvider,
     /// Time provider for $now operator
     pub time: &'a dyn TimeProvider,
     /// Request context for accessing params, query, headers, body
     pub request: &'a dyn RequestContext,
+    /// Named validation schemas that `$validate` can reference as `#/schemas/<name>`
+    pub schemas: Option<&'a HashMap<String, Value>>,
+    /// Collection schemas enforced by database operators with `validate: true`
+    pub database_schemas: Option<&'a HashMap<String, DatabaseSchema>>,
+    /// Whether evaluation is inside a `$transaction`
+    in_transaction: bool,
 }
 
 impl<'a> Executor<'a> {
     /// Create a new executor with dependencies
     pub fn new(
-        database: &'a dyn DatabaseProvider,
+        database: &'a dyn AsyncDatabaseProvider,
         time: &'a dyn TimeProvider,
         request: &'a dyn RequestContext,
     ) -> Self {
@@ -35,14 +75,38 @@ impl<'a> Executor<'a> {
             database,
             time,
             request,
+            schemas: None,
+            database_schemas: None,
+            in_transaction: false,
         }
     }
 
+    /// Make named validation schemas available to `$validate`
+    ///
+    /// Schemas passed to `$validate` (and the named schemas themselves) can
+    /// then use `{"$ref": "#/schemas/<name>"}` to reference them.
+    pub fn with_schemas(mut self, schemas: &'a HashMap<String, Value>) -> Self {
+        self.schemas = Some(schemas);
+        self
+    }
+
+    /// Make collection schemas available to `$dbInsert` and `$dbUpdate`
+    ///
+    /// Operators with `validate: true` check their documents against the
+    /// schema of their collection.
+    pub fn with_database_schemas(mut self, schemas: &'a HashMap<String, DatabaseSchema>) -> Self {
+        self.database_schemas = Some(schemas);
+        self
+    }
+
     /// Evaluate an operator value in a given context
     ///
     /// This is the main entry point for operator evaluation.
     /// It recursively evaluates nested operators and returns the result.
     ///
+    /// This blocks the current thread until every database call completes.
+    /// Use `eval_async` from async code.
+    ///
     /// # Arguments
     /// * `context` - The execution context with variable bindings
     /// * `value` - The operator value to evaluate (either an operator or literal)
@@ -62,8 +126,36 @@ impl<'a> Executor<'a> {
         }
     }
 
-    /// Evaluate a specific operator
+    /// Evaluate an operator value, awaiting database calls
+    ///
+    /// Same as `eval`, for use on an async runtime with any
+    /// `AsyncDatabaseProvider`.
+    pub fn eval_async<'s>(
+        &'s self,
+        context: &'s Context,
+        value: &'s OperatorValue,
+    ) -> BoxFuture<'s, Result<Value, ExecutionError>> {
+        Box::pin(async move {
+            match value {
+                OperatorValue::Literal(val) => {
+                    // Literals evaluate to themselves
+                    Ok(val.clone())
+                }
+                OperatorValue::Operator(op) => {
+                    // Evaluate the operator
+                    self.eval_operator_async(context, op).await
+                }
+            }
+        })
+    }
+
+    /// Evaluate a specific operator, blocking the current thread (see `eval`)
     fn eval_operator(&self, context: &Context, operator: &Operator) -> Result<Value, ExecutionError> {
+        block_on(self.eval_operator_async(context, operator))
+    }
+
+    /// Evaluate a specific operator
+    async fn eval_operator_async(&self, context: &Context, operator: &Operator) -> Result<Value, ExecutionError> {
         match operator {
             Operator::Get(op) => self.eval_get(context, &op.path),
 
@@ -71,23 +163,99 @@ impl<'a> Executor<'a> {
 
             Operator::If(op) => {
                 // Evaluate condition
-                let condition = self.eval(context, &op.condition)?;
+                let condition = self.eval_async(context, &op.condition).await?;
                 let is_true = Self::is_truthy(&condition);
 
                 if is_true {
-                    self.eval(context, &op.then)
+                    self.eval_async(context, &op.then).await
                 } else if let Some(else_branch) = &op.r#else {
-                    self.eval(context, else_branch)
+                    self.eval_async(context, else_branch).await
                 } else {
                     Ok(Value::Null)
                 }
             }
 
-            Operator::Merge(op) => self.eval_merge(context, &op.objects),
+            // Collection operators
+            Operator::Map(op) => {
+                let items = self.eval_collection(context, &op.over, "$map").await?;
+                let item_name = op.r#as.as_deref().unwrap_or("item");
+
+                let mut scope = context.clone();
+                let mut results = Vec::with_capacity(items.len());
+                for (index, item) in items.into_iter().enumerate() {
+                    Self::bind_item(&mut scope, item_name, item, index);
+                    results.push(self.eval_async(&scope, &op.r#do).await?);
+                }
+                Ok(Value::Array(results))
+            }
+
+            Operator::Filter(op) => {
+                let items = self.eval_collection(context, &op.over, "$filter").await?;
+                let item_name = op.r#as.as_deref().unwrap_or("item");
+
+                let mut scope = context.clone();
+                let mut results = Vec::new();
+                for (index, item) in items.into_iter().enumerate() {
+                    Self::bind_item(&mut scope, item_name, item.clone(), index);
+                    if Self::is_truthy(&self.eval_async(&scope, &op.r#where).await?) {
+                        results.push(item);
+                    }
+                }
+                Ok(Value::Array(results))
+            }
+
+            Operator::Reduce(op) => {
+                let items = self.eval_collection(context, &op.over, "$reduce").await?;
+                let item_name = op.r#as.as_deref().unwrap_or("item");
+
+                let mut scope = context.clone();
+                let mut accumulator = op.initial.clone();
+                for (index, item) in items.into_iter().enumerate() {
+                    Self::bind_item(&mut scope, item_name, item, index);
+                    scope.bind("accumulator", accumulator);
+                    accumulator = self.eval_async(&scope, &op.with).await?;
+                }
+                Ok(accumulator)
+            }
+
+            Operator::Switch(op) => {
+                // With `on`: compare each case value for equality.
+                // Without `on`: each case is a condition, first truthy wins.
+                let subject = match &op.on {
+                    Some(on) => Some(self.eval_async(context, on).await?),
+                    None => None,
+                };
+
+                for case in &op.cases {
+                    let when = self.eval_async(context, &case.when).await?;
+                    let matched = match &subject {
+                        Some(subject) => &when == subject,
+                        None => Self::is_truthy(&when),
+                    };
+                    if matched {
+                        return self.eval_async(context, &case.then).await;
+                    }
+                }
+
+                match &op.default {
+                    Some(default) => self.eval_async(context, default).await,
+                    None => Err(ExecutionError::invalid_operator(
+                        "$switch",
+                        "No case matched and no default was provided",
+                    )),
+                }
+            }
+
+            Operator::Merge(op) => self.eval_merge(context, &op.objects).await,
+
+            Operator::Exists(op) => match self.eval_async(context, &op.value).await {
+                Ok(value) => Ok(Value::Bool(!value.is_null())),
+                Err(ExecutionError::PathNotFound { .. }) => Ok(Value::Bool(false)),
+                Err(err) => Err(err),
+            },
 
-            Operator::Exists(op) => {
-                let value = self.eval(context, &op.value)?;
-                Ok(Value::Bool(!value.is_null()))
+            Operator::RenderString(op) => {
+                render::render_string(context, &op.template).map(Value::String)
             }
 
             Operator::Now(_) => {
@@ -96,38 +264,38 @@ impl<'a> Executor<'a> {
 
             // Comparison operators
             Operator::Eq { left, right } => {
-                let left_val = self.eval(context, left)?;
-                let right_val = self.eval(context, right)?;
+                let left_val = self.eval_async(context, left).await?;
+                let right_val = self.eval_async(context, right).await?;
                 Ok(Value::Bool(left_val == right_val))
             }
 
             Operator::Ne { left, right } => {
-                let left_val = self.eval(context, left)?;
-                let right_val = self.eval(context, right)?;
+                let left_val = self.eval_async(context, left).await?;
+                let right_val = self.eval_async(context, right).await?;
                 Ok(Value::Bool(left_val != right_val))
             }
 
             Operator::Gt { left, right } => {
-                let left_val = self.eval(context, left)?;
-                let right_val = self.eval(context, right)?;
+                let left_val = self.eval_async(context, left).await?;
+                let right_val = self.eval_async(context, right).await?;
                 Self::compare_values(&left_val, &right_val, |cmp| cmp.is_gt())
             }
 
             Operator::Gte { left, right } => {
-                let left_val = self.eval(context, left)?;
-                let right_val = self.eval(context, right)?;
+                let left_val = self.eval_async(context, left).await?;
+                let right_val = self.eval_async(context, right).await?;
                 Self::compare_values(&left_val, &right_val, |cmp| cmp.is_ge())
             }
 
             Operator::Lt { left, right } => {
-                let left_val = self.eval(context, left)?;
-                let right_val = self.eval(context, right)?;
+                let left_val = self.eval_async(context, left).await?;
+                let right_val = self.eval_async(context, right).await?;
                 Self::compare_values(&left_val, &right_val, |cmp| cmp.is_lt())
             }
 
             Operator::Lte { left, right } => {
-                let left_val = self.eval(context, left)?;
-                let right_val = self.eval(context, right)?;
+                let left_val = self.eval_async(context, left).await?;
+                let right_val = self.eval_async(context, right).await?;
                 Self::compare_values(&left_val, &right_val, |cmp| cmp.is_le())
             }
 
@@ -136,7 +304,7 @@ impl<'a> Executor<'a> {
                 // Return true if all conditions are truthy (short-circuit on first false)
                 // Empty array returns true (vacuous truth)
                 for condition in conditions {
-                    let value = self.eval(context, condition)?;
+                    let value = self.eval_async(context, condition).await?;
                     if !Self::is_truthy(&value) {
                         return Ok(Value::Bool(false));
                     }
@@ -148,7 +316,7 @@ impl<'a> Executor<'a> {
                 // Return true if any condition is truthy (short-circuit on first true)
                 // Empty array returns false
                 for condition in conditions {
-                    let value = self.eval(context, condition)?;
+                    let value = self.eval_async(context, condition).await?;
                     if Self::is_truthy(&value) {
                         return Ok(Value::Bool(true));
                     }
@@ -158,18 +326,86 @@ impl<'a> Executor<'a> {
 
             Operator::Not { condition } => {
                 // Return the negation of the condition's truthiness
-                let value = self.eval(context, condition)?;
+                let value = self.eval_async(context, condition).await?;
                 Ok(Value::Bool(!Self::is_truthy(&value)))
             }
 
+            Operator::Return(op) => {
+                // Evaluate status, headers and body, then unwind via EarlyReturn
+                let status_value = self.eval_async(context, &op.status).await?;
+                let status = status_value.as_u64().ok_or_else(|| {
+                    ExecutionError::type_error_with_types(
+                        "$return status must be an integer",
+                        "number",
+                        Self::type_name(&status_value),
+                    )
+                })?;
+                let status = u16::try_from(status)
+                    .ok()
+                    .filter(|s| (100..=599).contains(s))
+                    .ok_or_else(|| {
+                        ExecutionError::invalid_operator(
+                            "$return",
+                            format!("Invalid HTTP status code: {}", status),
+                        )
+                    })?;
+
+                let mut headers = std::collections::HashMap::new();
+                for (name, value) in &op.headers {
+                    headers.insert(name.clone(), self.eval_async(context, value).await?);
+                }
+
+                let body = self.eval_async(context, &op.body).await?;
+
+                Err(ExecutionError::EarlyReturn {
+                    status,
+                    headers,
+                    body,
+                })
+            }
+
+            // Math operators
+            Operator::Add { operands } => math::add(&self.eval_operands(context, operands).await?),
+
+            Operator::Subtract { left, right } => {
+                math::subtract(&self.eval_async(context, left).await?, &self.eval_async(context, right).await?)
+            }
+
+            Operator::Multiply { operands } => math::multiply(&self.eval_operands(context, operands).await?),
+
+            Operator::Divide { left, right } => {
+                math::divide(&self.eval_async(context, left).await?, &self.eval_async(context, right).await?)
+            }
+
+            Operator::Mod { left, right } => {
+                math::modulo(&self.eval_async(context, left).await?, &self.eval_async(context, right).await?)
+            }
+
+            Operator::Pow { base, exponent } => {
+                math::pow(&self.eval_async(context, base).await?, &self.eval_async(context, exponent).await?)
+            }
+
+            Operator::Abs { value } => math::abs(&self.eval_async(context, value).await?),
+
+            Operator::Round { value, places } => {
+                math::round(&self.eval_async(context, value).await?, places.unwrap_or(0))
+            }
+
+            Operator::Floor { value } => math::floor(&self.eval_async(context, value).await?),
+
+            Operator::Ceil { value } => math::ceil(&self.eval_async(context, value).await?),
+
+            Operator::Min { operands } => math::min(&self.eval_operands(context, operands).await?),
+
+            Operator::Max { operands } => math::max(&self.eval_operands(context, operands).await?),
+
             // Validation operator
             Operator::Validate(op) => {
                 // 1. Evaluate the data to be validated
-                let data = self.eval(context, &op.data)?;
+                let data = self.eval_async(context, &op.data).await?;
 
-                // 2. Compile the JSON Schema validator
-                let validator = jsonschema::validator_for(&op.schema)
-                    .map_err(|e| ExecutionError::custom(format!("Failed to compile schema: {}", e)))?;
+                // 2. Compile the JSON Schema validator, with named schemas available to $ref
+                let validator = self.schema_validator(&op.schema)?;
 
                 // 3. Validate the data
                 if validator.is_valid(&data) {
@@ -184,7 +420,7 @@ impl<'a> Executor<'a> {
 
                     // If onFail is specified, evaluate it
                     if let Some(on_fail) = &op.on_fail {
-                        return self.eval(context, on_fail);
+                        return self.eval_async(context, on_fail).await;
                     }
 
                     // Otherwise, return ValidationError
@@ -198,17 +434,16 @@ impl<'a> Executor<'a> {
             // Database operators
             Operator::DbQuery(op) => {
                 // 1. Evaluate filter OperatorValues to concrete Values
-                let filter = if let Some(filter_map) = &op.filter {
-                    let mut evaluated_filter = std::collections::HashMap::new();
-                    for (key, value) in filter_map {
-                        let evaluated_value = self.eval(context, value)?;
-                        evaluated_filter.insert(key.clone(), evaluated_value);
-                    }
-                    Some(evaluated_filter)
-                } else {
-                    None
+                let filter = match &op.filter {
+                    Some(filter) => Some(filter.resolve(self.eval_in_order(context, filter.operands()).await?)?),
+                    None => None,
                 };
 
+                // Cursor pagination returns a page with cursors instead
+                if op.after.is_some() || op.before.is_some() {
+                    return self.eval_cursor_query(context, op, filter.as_ref()).await;
+                }
+
                 // 2. Call database provider
                 let results = self.database.query(
                     &op.collection,
@@ -217,70 +452,305 @@ impl<'a> Executor<'a> {
                     op.limit,
                     op.skip,
                     op.sort.as_ref(),
-                )?;
+                ).await?;
 
-                // 3. Return results as array
-                Ok(Value::Array(results))
+                // 3. Return results as array, or with the total count if requested
+                if !op.with_total {
+                    return Ok(Value::Array(results));
+                }
+                let total = self.database.count(&op.collection, filter.as_ref()).await?;
+                Ok(serde_json::json!({
+                    "items": results,
+                    "total": total,
+                    "limit": op.limit,
+                    "skip": op.skip.unwrap_or(0),
+                }))
+            }
+
+            Operator::DbCount(op) => {
+                // 1. Evaluate filter OperatorValues to concrete Values
+                let filter = match &op.filter {
+                    Some(filter) => Some(filter.resolve(self.eval_in_order(context, filter.operands()).await?)?),
+                    None => None,
+                };
+
+                // 2. Ask the database provider for the count
+                let count = self.database.count(&op.collection, filter.as_ref()).await?;
+                Ok(Value::from(count))
             }
 
             Operator::DbInsert(op) => {
                 // 1. Evaluate all document OperatorValues to concrete Values
-                let mut evaluated_document = std::collections::HashMap::new();
-                for (key, value) in &op.document {
-                    let evaluated_value = self.eval(context, value)?;
-                    evaluated_document.insert(key.clone(), evaluated_value);
+                let mut evaluated_document = self.eval_document(context, &op.document).await?;
+
+                // 2. Fill defaults and check the collection schema
+                if op.validate {
+                    let schema = self.database_schema(&op.collection)?;
+                    schema.apply_defaults(&mut evaluated_document);
+                    Self::check_schema(&op.collection, schema.check_document(&evaluated_document))?;
                 }
 
-                // 2. Call database provider to insert
-                let inserted = self.database.insert(&op.collection, &evaluated_document)?;
+                // 3. Call database provider to insert
+                let inserted = self.database.insert(&op.collection, &evaluated_document).await?;
 
-                // 3. Return the inserted document (includes generated _id)
+                // 4. Return the inserted document (includes generated _id)
                 Ok(inserted)
             }
 
             Operator::DbUpdate(op) => {
                 // 1. Evaluate filter OperatorValues
-                let mut evaluated_filter = std::collections::HashMap::new();
-                for (key, value) in &op.filter {
-                    let evaluated_value = self.eval(context, value)?;
-                    evaluated_filter.insert(key.clone(), evaluated_value);
-                }
+                let evaluated_filter = op.filter.resolve(self.eval_in_order(context, op.filter.operands()).await?)?;
 
                 // 2. Evaluate update OperatorValues
-                let mut evaluated_update = std::collections::HashMap::new();
-                for (key, value) in &op.update {
-                    let evaluated_value = self.eval(context, value)?;
-                    evaluated_update.insert(key.clone(), evaluated_value);
+                let evaluated_update = op.update.resolve(self.eval_in_order(context, op.update.operands()).await?)?;
+
+                // 3. Check the values written against the collection schema
+                if op.validate {
+                    let schema = self.database_schema(&op.collection)?;
+                    Self::check_schema(&op.collection, schema.check_update(&evaluated_update))?;
                 }
 
-                // 3. Call database provider to update
-                let updated = self.database.update(&op.collection, &evaluated_filter, &evaluated_update)?;
+                // 4. Call database provider to update
+                let updated = self.database.update(&op.collection, &evaluated_filter, &evaluated_update).await?;
 
-                // 4. Return updated documents as array
+                // 5. Return updated documents as array
                 Ok(Value::Array(updated))
             }
 
+            Operator::DbUpsert(op) => {
+                // 1. Evaluate filter, document and defaults OperatorValues
+                let evaluated_filter = op.filter.resolve(self.eval_in_order(context, op.filter.operands()).await?)?;
+                let document = self.eval_document(context, &op.document).await?;
+                let defaults = self.eval_document(context, &op.defaults).await?;
+
+                // 2. Build the update of a matched document and the document to insert otherwise
+                let mut inserted = Self::upsert_document(&evaluated_filter, &defaults, &document)?;
+                let update = Update::from(document);
+
+                // 3. Fill defaults and check both writes against the collection schema
+                if op.validate {
+                    let schema = self.database_schema(&op.collection)?;
+                    schema.apply_defaults(&mut inserted);
+                    Self::check_schema(&op.collection, schema.check_document(&inserted))?;
+                    Self::check_schema(&op.collection, schema.check_update(&update))?;
+                }
+
+                // 4. Call database provider to update or insert atomically
+                let (_, written) = self
+                    .database
+                    .upsert(&op.collection, &evaluated_filter, &update, &inserted)
+                    .await?;
+
+                // 5. Return the document as written
+                Ok(written)
+            }
+
+            Operator::DbFindOneAndUpdate(op) => {
+                // 1. Evaluate filter and update OperatorValues
+                let evaluated_filter = op.filter.resolve(self.eval_in_order(context, op.filter.operands()).await?)?;
+                let evaluated_update = op.update.resolve(self.eval_in_order(context, op.update.operands()).await?)?;
+
+                // 2. Check the values written against the collection schema
+                if op.validate {
+                    let schema = self.database_schema(&op.collection)?;
+                    Self::check_schema(&op.collection, schema.check_update(&evaluated_update))?;
+                }
+
+                // 3. Call database provider to update the first match atomically
+                let result = self
+                    .database
+                    .find_one_and_update(&op.collection, &evaluated_filter, &evaluated_update, op.sort.as_ref())
+                    .await?;
+
+                // 4. Return the requested image, or null if nothing matched
+                Ok(match (result, op.return_document) {
+                    (None, _) => Value::Null,
+                    (Some((before, _)), ReturnDocument::Before) => before,
+                    (Some((_, after)), ReturnDocument::After) => after,
+                })
+            }
+
             Operator::DbDelete(op) => {
                 // 1. Evaluate filter OperatorValues
-                let mut evaluated_filter = std::collections::HashMap::new();
-                for (key, value) in &op.filter {
-                    let evaluated_value = self.eval(context, value)?;
-                    evaluated_filter.insert(key.clone(), evaluated_value);
-                }
+                let evaluated_filter = op.filter.resolve(self.eval_in_order(context, op.filter.operands()).await?)?;
 
                 // 2. Call database provider to delete
-                let deleted = self.database.delete(&op.collection, &evaluated_filter)?;
+                let deleted = self.database.delete(&op.collection, &evaluated_filter).await?;
 
                 // 3. Return deleted documents as array (for audit trail)
                 Ok(Value::Array(deleted))
             }
 
-            // TODO: Implement remaining operators
-            _ => Err(ExecutionError::custom(format!(
-                "Operator not yet implemented: {:?}",
-                operator
-            ))),
+            Operator::DbAggregate(op) => {
+                // 1. Evaluate $match OperatorValues
+                let pipeline = op.pipeline.resolve(self.eval_in_order(context, op.pipeline.operands()).await?)?;
+
+                // 2. Call database provider to run the stages
+                let results = self.database.aggregate(&op.collection, &pipeline).await?;
+
+                // 3. Return the documents output by the last stage
+                Ok(Value::Array(results))
+            }
+
+            Operator::Transaction(op) => {
+                // A nested transaction joins the one already open
+                if self.in_transaction {
+                    return self.eval_transaction_operations(context, &op.operations).await;
+                }
+
+                // 1. Start the transaction and run the operations through it
+                let transaction = self.database.begin().await?;
+                let inner = Executor {
+                    database: &*transaction,
+                    in_transaction: true,
+                    ..*self
+                };
+                let result = inner.eval_transaction_operations(context, &op.operations).await;
+
+                // 2. Commit on success, roll back on any error (including $return)
+                match result {
+                    Ok(results) => {
+                        transaction.commit().await?;
+                        Ok(results)
+                    }
+                    Err(err) => match op.on_error {
+                        OnError::Rollback => {
+                            transaction.rollback().await?;
+                            Err(err)
+                        }
+                    },
+                }
+            }
+        }
+    }
+
+    /// Run a `$dbQuery` with `after`/`before` cursors (keyset pagination)
+    ///
+    /// Returns `{items, nextCursor, prevCursor}`, where a cursor is `null`
+    /// if there is no page in that direction. One document more than `limit`
+    /// is fetched to tell whether another page follows.
+    async fn eval_cursor_query(
+        &self,
+        context: &Context,
+        op: &DbQueryOp,
+        filter: Option<&Filter>,
+    ) -> Result<Value, ExecutionError> {
+        if op.skip.is_some() {
+            return Err(ExecutionError::invalid_operator(
+                "$dbQuery",
+                "'skip' cannot be combined with 'after' or 'before'",
+            ));
+        }
+
+        // 1. Decode the cursors against the sort, which always ends with _id
+        let sort = Cursor::keyset_sort(op.sort.as_ref());
+        let after = self.eval_cursor(context, op.after.as_ref(), &sort).await?;
+        let before = self.eval_cursor(context, op.before.as_ref(), &sort).await?;
+        let fetch = op.limit.map(|limit| limit.saturating_add(1));
+        let truncate = |items: &mut Vec<Value>| match op.limit {
+            Some(limit) if items.len() > limit as usize => {
+                items.truncate(limit as usize);
+                true
+            }
+            _ => false,
+        };
+
+        // 2. Fetch the page and work out which neighbouring pages exist
+        let (items, has_next, has_prev) = match (after, before) {
+            (Some(_), Some(_)) => {
+                return Err(ExecutionError::invalid_operator(
+                    "$dbQuery",
+                    "'after' and 'before' cannot both be set",
+                ));
+            }
+            (None, Some(before)) => {
+                // Walk backwards from the cursor, then restore the sort order
+                let mut items = self
+                    .database
+                    .query_after(&op.collection, filter, &sort.reversed(), &before.position, fetch)
+                    .await?;
+                let has_prev = truncate(&mut items);
+                items.reverse();
+                (items, true, has_prev)
+            }
+            (after, None) => {
+                let mut items = match &after {
+                    Some(after) => {
+                        self.database
+                            .query_after(&op.collection, filter, &sort, &after.position, fetch)
+                            .await?
+                    }
+                    None => {
+                        self.database
+                            .query(&op.collection, filter, None, fetch, None, Some(&sort))
+                            .await?
+                    }
+                };
+                let has_next = truncate(&mut items);
+                (items, has_next, after.is_some())
+            }
+        };
+
+        // 3. Build the cursors from the full documents, then project
+        let cursor = |doc: Option<&Value>, exists: bool| match doc {
+            Some(doc) if exists => Value::String(Cursor::of(&sort, doc).encode()),
+            _ => Value::Null,
+        };
+        let next_cursor = cursor(items.last(), has_next);
+        let prev_cursor = cursor(items.first(), has_prev);
+        let items: Vec<Value> = match &op.select {
+            Some(fields) => items.iter().map(|doc| path::project(doc, fields)).collect(),
+            None => items,
+        };
+
+        let mut page = serde_json::json!({
+            "items": items,
+            "nextCursor": next_cursor,
+            "prevCursor": prev_cursor,
+        });
+        if op.with_total {
+            page["total"] = Value::from(self.database.count(&op.collection, filter).await?);
+        }
+        Ok(page)
+    }
+
+    /// Evaluate an `after`/`before` cursor (`null` or `""` means no cursor)
+    async fn eval_cursor(
+        &self,
+        context: &Context,
+        cursor: Option<&OperatorValue>,
+        sort: &SortSpec,
+    ) -> Result<Option<Cursor>, ExecutionError> {
+        let Some(cursor) = cursor else {
+            return Ok(None);
+        };
+        match self.eval_async(context, cursor).await? {
+            Value::Null => Ok(None),
+            Value::String(text) if text.is_empty() => Ok(None),
+            Value::String(text) => Cursor::decode(&text, sort).map(Some),
+            other => Err(ExecutionError::validation_error(
+                "Invalid cursor",
+                vec![format!("A cursor must be a string, got {}", Self::type_name(&other))],
+            )),
+        }
+    }
+
+    /// Evaluate the operations of a `$transaction` in order
+    ///
+    /// Each operation sees the results of the earlier ones as `results`.
+    async fn eval_transaction_operations(
+        &self,
+        context: &Context,
+        operations: &[OperatorValue],
+    ) -> Result<Value, ExecutionError> {
+        let mut scope = context.clone();
+        scope.bind("results", Value::Array(Vec::new()));
+        let mut results = Vec::with_capacity(operations.len());
+        for operation in operations {
+            results.push(self.eval_async(&scope, operation).await?);
+            scope.bind("results", Value::Array(results.clone()));
         }
+        Ok(Value::Array(results))
     }
 
     /// Evaluate $get operator - retrieve value from context by path
@@ -296,7 +766,7 @@ impl<'a> Executor<'a> {
         use jsonpath_rust::JsonPath;
 
         // Convert context to a single JSON object
-        let context_json = serde_json::to_value(context.variables())
+        let context_json = serde_json::to_value(context.visible_variables())
             .map_err(|e| ExecutionError::custom(format!("Failed to serialize context: {}", e)))?;
 
         // Query using JSONPath trait method on Value
@@ -305,16 +775,16 @@ impl<'a> Executor<'a> {
             .map_err(|e| ExecutionError::custom(format!("JSONPath query failed for '{}': {}", path, e)))?;
 
         // Convert Vec<&Value> to Value::Array
-        let result_values: Vec<Value> = results.into_iter().map(|v| v.clone()).collect();
+        let result_values: Vec<Value> = results.into_iter().cloned().collect();
         Ok(Value::Array(result_values))
     }
 
     /// Evaluate $merge operator - combine multiple objects
-    fn eval_merge(&self, context: &Context, objects: &[OperatorValue]) -> Result<Value, ExecutionError> {
+    async fn eval_merge(&self, context: &Context, objects: &[OperatorValue]) -> Result<Value, ExecutionError> {
         let mut result = serde_json::Map::new();
 
         for obj_value in objects {
-            let obj = self.eval(context, obj_value)?;
+            let obj = self.eval_async(context, obj_value).await?;
 
             match obj {
                 Value::Object(map) => {
@@ -336,6 +806,83 @@ impl<'a> Executor<'a> {
         Ok(Value::Object(result))
     }
 
+    /// Compile the JSON Schema validator used by `$validate`
+    ///
+    /// Each named schema is registered as a resource of its own, so its `$id`
+    /// and internal references (such as `#/definitions/...`) resolve within
+    /// it. `#/schemas/<name>` references, in the schema and in the named
+    /// schemas, are rewritten to the URI of that resource.
+    fn schema_validator(&self, schema: &Value) -> Result<jsonschema::Validator, ExecutionError> {
+        let compile_error = |e: &dyn std::fmt::Display| ExecutionError::custom(format!("Failed to compile schema: {}", e));
+
+        let mut options = jsonschema::options();
+        for (name, named) in self.schemas.into_iter().flatten() {
+            let resource = jsonschema::Resource::from_contents(link_named_schemas(named))
+                .map_err(|e| compile_error(&format!("schema '{}': {}", name, e)))?;
+            options = options.with_resource(named_schema_uri(name), resource);
+        }
+        options.build(&link_named_schemas(schema)).map_err(|e| compile_error(&e))
+    }
+
+    /// Evaluate a list of operands in order
+    async fn eval_operands(&self, context: &Context, operands: &[OperatorValue]) -> Result<Vec<Value>, ExecutionError> {
+        let mut values = Vec::with_capacity(operands.len());
+        for operand in operands {
+            values.push(self.eval_async(context, operand).await?);
+        }
+        Ok(values)
+    }
+
+    /// Evaluate the operands of a filter or update ahead of its `resolve`
+    ///
+    /// `resolve` takes a synchronous callback, so the operands are evaluated
+    /// first and the returned callback hands out their values in order. It
+    /// errors if asked for an operand other than the next one evaluated.
+    async fn eval_in_order<'op>(
+        &self,
+        context: &Context,
+        operands: Vec<&'op OperatorValue>,
+    ) -> Result<impl FnMut(&OperatorValue) -> Result<Value, ExecutionError> + use<'op>, ExecutionError> {
+        let mut values = Vec::with_capacity(operands.len());
+        for operand in &operands {
+            values.push(self.eval_async(context, operand).await?);
+        }
+        let mut evaluated = operands.into_iter().zip(values);
+        Ok(move |operand: &OperatorValue| match evaluated.next() {
+            Some((expected, value)) if std::ptr::eq(expected, operand) => Ok(value),
+            _ => Err(ExecutionError::custom("Operands were not resolved in the order they were evaluated")),
+        })
+    }
+
+    /// Evaluate the `over` value of a collection operator into its items
+    ///
+    /// Arrays yield their elements; objects yield `{key, value}` entries.
+    async fn eval_collection(
+        &self,
+        context: &Context,
+        over: &OperatorValue,
+        operator: &str,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        match self.eval_async(context, over).await? {
+            Value::Array(items) => Ok(items),
+            Value::Object(map) => Ok(map
+                .into_iter()
+                .map(|(key, value)| serde_json::json!({"key": key, "value": value}))
+                .collect()),
+            other => Err(ExecutionError::type_error_with_types(
+                format!("{} can only iterate over arrays or objects", operator),
+                "array or object",
+                Self::type_name(&other),
+            )),
+        }
+    }
+
+    /// Bind the current item and index in a collection operator's scope
+    fn bind_item(scope: &mut Context, item_name: &str, item: Value, index: usize) {
+        scope.bind(item_name, item);
+        scope.bind("index", Value::from(index));
+    }
+
     /// Check if a value is truthy (used for conditionals)
     fn is_truthy(value: &Value) -> bool {
         match value {
@@ -348,8 +895,70 @@ impl<'a> Executor<'a> {
         }
     }
 
+    /// Evaluate the field values of a document
+    async fn eval_document(
+        &self,
+        context: &Context,
+        document: &HashMap<String, OperatorValue>,
+    ) -> Result<HashMap<String, Value>, ExecutionError> {
+        let mut evaluated = HashMap::with_capacity(document.len());
+        for (key, value) in document {
+            evaluated.insert(key.clone(), self.eval_async(context, value).await?);
+        }
+        Ok(evaluated)
+    }
+
+    /// The document `$dbUpsert` inserts when no document matches its filter
+    ///
+    /// Starts from the filter's equality conditions, then sets the `defaults`
+    /// and `document` fields (as dot-paths) in turn, so later values win.
+    fn upsert_document(
+        filter: &Filter,
+        defaults: &HashMap<String, Value>,
+        document: &HashMap<String, Value>,
+    ) -> Result<HashMap<String, Value>, ExecutionError> {
+        let mut inserted = Value::Object(serde_json::Map::new());
+        for (field, value) in filter.equalities() {
+            path::set(&mut inserted, field, value.clone())?;
+        }
+        for fields in [defaults, document] {
+            let mut fields: Vec<_> = fields.iter().collect();
+            fields.sort_by_key(|(field, _)| *field);
+            for (field, value) in fields {
+                path::set(&mut inserted, field, value.clone())?;
+            }
+        }
+        let Value::Object(inserted) = inserted else {
+            unreachable!("path::set keeps the root an object");
+        };
+        Ok(inserted.into_iter().collect())
+    }
+
+    /// Look up the schema of a collection for `validate: true`
+    fn database_schema(&self, collection: &str) -> Result<&'a DatabaseSchema, ExecutionError> {
+        self.database_schemas
+            .and_then(|schemas| schemas.get(collection))
+            .ok_or_else(|| {
+                ExecutionError::database_error(format!(
+                    "Cannot validate: no schema defined for collection '{}'",
+                    collection
+                ))
+            })
+    }
+
+    /// Turn schema violations into a `ValidationError`
+    fn check_schema(collection: &str, errors: Vec<String>) -> Result<(), ExecutionError> {
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(ExecutionError::validation_error(
+            format!("Document does not match the schema for collection '{}'", collection),
+            errors,
+        ))
+    }
+
     /// Get the type name of a value for error messages
-    fn type_name(value: &Value) -> &'static str {
+    pub(crate) fn type_name(value: &Value) -> &'static str {
         match value {
             Value::Null => "null",
             Value::Bool(_) => "boolean",
@@ -399,13 +1008,51 @@ impl<'a> Executor<'a> {
     }
 }
 
+/// The URI a named schema is registered under for `$validate`
+fn named_schema_uri(name: &str) -> String {
+    format!(
+        "deck:///schemas/{}",
+        percent_encoding::utf8_percent_encode(name, percent_encoding::NON_ALPHANUMERIC)
+    )
+}
+
+/// Copy a schema, pointing its `#/schemas/<name>/...` references at the named schema resources
+fn link_named_schemas(schema: &Value) -> Value {
+    match schema {
+        Value::Object(obj) => Value::Object(
+            obj.iter()
+                .map(|(key, value)| {
+                    let linked = match (key.as_str(), value) {
+                        ("$ref", Value::String(reference)) if reference.starts_with("#/schemas/") => {
+                            let pointer = &reference["#/schemas/".len()..];
+                            let (name, rest) = pointer.split_at(pointer.find('/').unwrap_or(pointer.len()));
+                            let name = name.replace("~1", "/").replace("~0", "~");
+                            Value::String(format!("{}#{}", named_schema_uri(&name), rest))
+                        }
+                        _ => link_named_schemas(value),
+                    };
+                    (key.clone(), linked)
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.iter().map(link_named_schemas).collect()),
+        other => other.clone(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::operators::*;
+    use crate::database::SortSpec;
     use crate::executor::traits::{MockDatabase, FixedTimeProvider, MockRequestContext};
     use serde_json::json;
 
+    /// Every document in a collection
+    fn stored(database: &dyn AsyncDatabaseProvider, collection: &str) -> Vec<Value> {
+        block_on(database.query(collection, None, None, None, None, None)).unwrap()
+    }
+
     fn create_test_executor() -> (Executor<'static>, Context) {
         let db = Box::leak(Box::new(MockDatabase::new()));
         let time = Box::leak(Box::new(FixedTimeProvider::new(
@@ -496,11 +1143,15 @@ mod tests {
             }))),
         })));
 
-        // $get returns error, so $exists should handle it
-        let result = executor.eval(&context, &value);
-        // Actually, this will fail because $get throws an error
-        // Let's fix this behavior in the exists implementation
-        assert!(result.is_err());
+        // A path that $get cannot find does not exist
+        let result = executor.eval(&context, &value).unwrap();
+        assert_eq!(result, json!(false));
+
+        // Other errors still propagate
+        let value = OperatorValue::Operator(Box::new(Operator::Exists(ExistsOp {
+            value: serde_json::from_value(json!({"$add": {"operands": ["a", 1]}})).unwrap(),
+        })));
+        assert!(executor.eval(&context, &value).is_err());
     }
 
     #[test]
@@ -1675,71 +2326,918 @@ mod tests {
         assert!(result.is_err());
     }
 
-    // Database operator tests - $dbQuery
-
     #[test]
-    fn test_eval_dbquery_all_documents() {
-        // Create executor with database containing test data
-        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
-            "posts",
-            vec![
-                json!({"_id": "1", "title": "First Post", "views": 100}),
-                json!({"_id": "2", "title": "Second Post", "views": 200}),
-                json!({"_id": "3", "title": "Third Post", "views": 150}),
-            ],
-        )));
-        let time = Box::leak(Box::new(FixedTimeProvider::new(
-            "2025-01-01T00:00:00Z",
-            1735689600,
-        )));
-        let request = Box::leak(Box::new(MockRequestContext::new()));
-        let executor = Executor::new(db, time, request);
-        let context = Context::new();
-
-        // Query all documents (no filter)
-        let op = Operator::DbQuery(DbQueryOp {
-            collection: "posts".to_string(),
-            filter: None,
-            select: None,
-            limit: None,
-            skip: None,
-            sort: None,
-        });
+    fn test_eval_validate_named_schema_ref() {
+        let (executor, context) = create_test_executor();
+        let schemas: &'static HashMap<String, Value> = Box::leak(Box::new(
+            serde_json::from_value(json!({
+                "address": {
+                    "type": "object",
+                    "properties": {"city": {"type": "string"}},
+                    "required": ["city"]
+                },
+                "createUser": {
+                    "type": "object",
+                    "properties": {
+                        "name": {"type": "string", "minLength": 1},
+                        "address": {"$ref": "#/schemas/address"}
+                    },
+                    "required": ["name", "address"]
+                }
+            }))
+            .unwrap(),
+        ));
+        let executor = executor.with_schemas(schemas);
 
-        let result = executor.eval_operator(&context, &op).unwrap();
-        let result_array = result.as_array().unwrap();
-        assert_eq!(result_array.len(), 3);
+        let op: Operator = serde_json::from_value(json!({
+            "$validate": {
+                "data": {"name": "Ada", "address": {"city": "London"}},
+                "schema": {"$ref": "#/schemas/createUser"}
+            }
+        }))
+        .unwrap();
+        assert!(executor.eval_operator(&context, &op).is_ok());
+
+        // The nested reference to #/schemas/address is enforced too
+        let op: Operator = serde_json::from_value(json!({
+            "$validate": {
+                "data": {"name": "Ada", "address": {}},
+                "schema": {"$ref": "#/schemas/createUser"}
+            }
+        }))
+        .unwrap();
+        assert!(matches!(
+            executor.eval_operator(&context, &op),
+            Err(ExecutionError::ValidationError { .. })
+        ));
     }
 
     #[test]
-    fn test_eval_dbquery_with_simple_filter() {
-        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
-            "posts",
-            vec![
-                json!({"_id": "1", "title": "First Post", "status": "published"}),
-                json!({"_id": "2", "title": "Second Post", "status": "draft"}),
-                json!({"_id": "3", "title": "Third Post", "status": "published"}),
-            ],
-        )));
-        let time = Box::leak(Box::new(FixedTimeProvider::new(
-            "2025-01-01T00:00:00Z",
-            1735689600,
-        )));
-        let request = Box::leak(Box::new(MockRequestContext::new()));
-        let executor = Executor::new(db, time, request);
-        let context = Context::new();
+    fn test_eval_validate_named_schema_with_id_and_definitions() {
+        let (executor, context) = create_test_executor();
+        let schemas: &'static HashMap<String, Value> = Box::leak(Box::new(
+            serde_json::from_value(json!({
+                "money": {
+                    "$id": "https://example.com/schemas/money.json",
+                    "type": "object",
+                    "properties": {
+                        "amount": {"$ref": "#/definitions/amount"},
+                        "currency": {"$ref": "#/definitions/currency"}
+                    },
+                    "required": ["amount", "currency"],
+                    "definitions": {
+                        "amount": {"type": "integer", "minimum": 0},
+                        "currency": {"enum": ["EUR", "USD"]}
+                    }
+                },
+                "order": {
+                    "type": "object",
+                    "properties": {
+                        "total": {"$ref": "#/schemas/money"},
+                        "currency": {"$ref": "#/schemas/money/definitions/currency"},
+                        "quantity": {"$ref": "#/definitions/positive"}
+                    },
+                    "definitions": {"positive": {"type": "integer", "minimum": 1}}
+                }
+            }))
+            .unwrap(),
+        ));
+        let executor = executor.with_schemas(schemas);
+        let validate = |data: Value| {
+            let op: Operator = serde_json::from_value(json!({
+                "$validate": {"data": data, "schema": {"$ref": "#/schemas/order"}}
+            }))
+            .unwrap();
+            executor.eval_operator(&context, &op)
+        };
 
-        // Query with simple equality filter
-        let mut filter = std::collections::HashMap::new();
-        filter.insert("status".to_string(), OperatorValue::Literal(json!("published")));
+        assert!(validate(json!({"total": {"amount": 5, "currency": "EUR"}, "currency": "USD"})).is_ok());
+        assert!(matches!(
+            validate(json!({"total": {"amount": -1, "currency": "EUR"}})),
+            Err(ExecutionError::ValidationError { .. })
+        ));
+        assert!(matches!(
+            validate(json!({"currency": "GBP"})),
+            Err(ExecutionError::ValidationError { .. })
+        ));
+        assert!(matches!(
+            validate(json!({"quantity": 0})),
+            Err(ExecutionError::ValidationError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_eval_validate_ref_without_schemas() {
+        let (executor, context) = create_test_executor();
+
+        let op: Operator = serde_json::from_value(json!({
+            "$validate": {
+                "data": {"name": "Ada"},
+                "schema": {"$ref": "#/schemas/createUser"}
+            }
+        }))
+        .unwrap();
+        assert!(executor.eval_operator(&context, &op).is_err());
+    }
+
+    // $switch operator tests
+
+    #[test]
+    fn test_eval_switch_literal_cases() {
+        let (executor, context) = create_test_executor();
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$switch": {
+                "on": {"$get": "role"},
+                "cases": [
+                    {"when": "admin", "then": "full"},
+                    {"when": "user", "then": "limited"}
+                ],
+                "default": "none"
+            }
+        }))
+        .unwrap();
+
+        let context_user = context.clone().with_var("role", json!("user"));
+        assert_eq!(executor.eval(&context_user, &value).unwrap(), json!("limited"));
+
+        let context_guest = context.with_var("role", json!("guest"));
+        assert_eq!(executor.eval(&context_guest, &value).unwrap(), json!("none"));
+    }
+
+    #[test]
+    fn test_eval_switch_literal_is_strict() {
+        let (executor, context) = create_test_executor();
+        let context = context.with_var("code", json!(1));
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$switch": {
+                "on": {"$get": "code"},
+                "cases": [{"when": "1", "then": "string"}, {"when": 1, "then": "number"}]
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(executor.eval(&context, &value).unwrap(), json!("number"));
+    }
+
+    #[test]
+    fn test_eval_switch_predicate_cases() {
+        let (executor, context) = create_test_executor();
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$switch": {
+                "cases": [
+                    {"when": {"$gte": {"left": {"$get": "score"}, "right": 90}}, "then": "A"},
+                    {"when": {"$gte": {"left": {"$get": "score"}, "right": 80}}, "then": "B"}
+                ],
+                "default": "C"
+            }
+        }))
+        .unwrap();
+
+        let grade = |score: i64| {
+            let context = context.clone().with_var("score", json!(score));
+            executor.eval(&context, &value).unwrap()
+        };
+        assert_eq!(grade(95), json!("A"));
+        assert_eq!(grade(85), json!("B"));
+        assert_eq!(grade(50), json!("C"));
+    }
+
+    #[test]
+    fn test_eval_switch_no_match_without_default() {
+        let (executor, context) = create_test_executor();
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$switch": {"on": "x", "cases": [{"when": "y", "then": 1}]}
+        }))
+        .unwrap();
+
+        let result = executor.eval(&context, &value);
+        assert!(matches!(
+            result,
+            Err(ExecutionError::InvalidOperator { ref operator, .. }) if operator == "$switch"
+        ));
+    }
+
+    #[test]
+    fn test_eval_switch_default_return() {
+        let (executor, context) = create_test_executor();
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$switch": {
+                "on": "guest",
+                "cases": [{"when": "admin", "then": "ok"}],
+                "default": {"$return": {"status": 403}}
+            }
+        }))
+        .unwrap();
+
+        let result = executor.eval(&context, &value);
+        assert!(matches!(result, Err(ExecutionError::EarlyReturn { status: 403, .. })));
+    }
+
+    // Collection operator tests
+
+    #[test]
+    fn test_eval_map_array() {
+        let (executor, context) = create_test_executor();
+        let context = context.with_var("posts", json!([{"id": 1}, {"id": 2}]));
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$map": {"over": {"$get": "posts"}, "do": {"$get": "item.id"}}
+        }))
+        .unwrap();
+        let result = executor.eval(&context, &value).unwrap();
+        assert_eq!(result, json!([1, 2]));
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$map": {"over": {"$get": "posts"}, "do": {"$get": "index"}}
+        }))
+        .unwrap();
+        let result = executor.eval(&context, &value).unwrap();
+        assert_eq!(result, json!([0, 1]));
+    }
+
+    #[test]
+    fn test_eval_map_nested_with_as() {
+        let (executor, context) = create_test_executor();
+        let context = context.with_var("posts", json!([
+            {"id": "p1", "comments": [{"id": "c1"}, {"id": "c2"}]},
+            {"id": "p2", "comments": [{"id": "c3"}]}
+        ]));
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$map": {
+                "over": {"$get": "posts"},
+                "as": "post",
+                "do": {
+                    "$map": {
+                        "over": {"$get": "post.comments"},
+                        "as": "comment",
+                        "do": {"$merge": [{"$get": "post"}, {"$get": "comment"}]}
+                    }
+                }
+            }
+        }))
+        .unwrap();
+
+        // The comment id overrides the post id, but both items stay bound
+        let result = executor.eval(&context, &value).unwrap();
+        let ids = result
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|comments| {
+                comments
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|merged| merged["id"].clone())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![vec![json!("c1"), json!("c2")], vec![json!("c3")]]);
+        assert_eq!(result[0][0]["comments"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_eval_map_object_entries() {
+        let (executor, context) = create_test_executor();
+        let context = context.with_var("counts", json!({"a": 1, "b": 2}));
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$map": {"over": {"$get": "counts"}, "do": {"$get": "item.key"}}
+        }))
+        .unwrap();
+
+        let result = executor.eval(&context, &value).unwrap();
+        assert_eq!(result, json!(["a", "b"]));
+    }
+
+    #[test]
+    fn test_eval_map_bindings_do_not_leak() {
+        let (executor, context) = create_test_executor();
+        let context = context
+            .with_var("item", json!("outer"))
+            .with_var("numbers", json!([1, 2]));
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$map": {"over": {"$get": "numbers"}, "do": {"$get": "item"}}
+        }))
+        .unwrap();
+
+        let result = executor.eval(&context, &value).unwrap();
+        assert_eq!(result, json!([1, 2]));
+        assert_eq!(context.get("item"), Some(&json!("outer")));
+        assert!(!context.has("index"));
+    }
+
+    #[test]
+    fn test_eval_map_non_collection() {
+        let (executor, context) = create_test_executor();
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$map": {"over": "text", "do": {"$get": "item"}}
+        }))
+        .unwrap();
+
+        let result = executor.eval(&context, &value);
+        assert!(matches!(result, Err(ExecutionError::TypeError { .. })));
+    }
+
+    #[test]
+    fn test_eval_filter() {
+        let (executor, context) = create_test_executor();
+        let context = context.with_var("posts", json!([
+            {"id": 1, "published": true},
+            {"id": 2, "published": false},
+            {"id": 3, "published": true}
+        ]));
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$filter": {
+                "over": {"$get": "posts"},
+                "as": "post",
+                "where": {"$eq": {"left": {"$get": "post.published"}, "right": true}}
+            }
+        }))
+        .unwrap();
+
+        let result = executor.eval(&context, &value).unwrap();
+        assert_eq!(result, json!([{"id": 1, "published": true}, {"id": 3, "published": true}]));
+    }
+
+    #[test]
+    fn test_eval_filter_object_entries() {
+        let (executor, context) = create_test_executor();
+        let context = context.with_var("flags", json!({"a": true, "b": false}));
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$filter": {"over": {"$get": "flags"}, "where": {"$get": "item.value"}}
+        }))
+        .unwrap();
+
+        let result = executor.eval(&context, &value).unwrap();
+        assert_eq!(result, json!([{"key": "a", "value": true}]));
+    }
+
+    #[test]
+    fn test_eval_reduce() {
+        let (executor, context) = create_test_executor();
+        let context = context.with_var("words", json!(["b", "c", "a"]));
+
+        // Keep whichever of accumulator/item is greater
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$reduce": {
+                "over": {"$get": "words"},
+                "with": {
+                    "$if": {
+                        "condition": {"$gt": {"left": {"$get": "item"}, "right": {"$get": "accumulator"}}},
+                        "then": {"$get": "item"},
+                        "else": {"$get": "accumulator"}
+                    }
+                },
+                "initial": ""
+            }
+        }))
+        .unwrap();
+
+        let result = executor.eval(&context, &value).unwrap();
+        assert_eq!(result, json!("c"));
+        assert!(!context.has("accumulator"));
+    }
+
+    #[test]
+    fn test_eval_reduce_empty_returns_initial() {
+        let (executor, context) = create_test_executor();
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$reduce": {"over": [], "with": {"$get": "item"}, "initial": 42}
+        }))
+        .unwrap();
+
+        let result = executor.eval(&context, &value).unwrap();
+        assert_eq!(result, json!(42));
+    }
+
+    // Math operator tests
+
+    #[test]
+    fn test_eval_reduce_with_add() {
+        let (executor, context) = create_test_executor();
+        let context = context.with_var("numbers", json!([1, 2, 3, 4]));
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$reduce": {
+                "over": {"$get": "numbers"},
+                "with": {"$add": {"operands": [{"$get": "accumulator"}, {"$get": "item"}]}},
+                "initial": 0
+            }
+        }))
+        .unwrap();
+
+        let result = executor.eval(&context, &value).unwrap();
+        assert_eq!(result, json!(10));
+        assert!(result.is_i64());
+    }
+
+    #[test]
+    fn test_eval_pagination_math() {
+        let (executor, context) = create_test_executor();
+        let context = context.with_var("total", json!(45)).with_var("pageSize", json!(10));
+
+        // pages = ceil(total / pageSize)
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$ceil": {"value": {"$divide": {"left": {"$get": "total"}, "right": {"$get": "pageSize"}}}}
+        }))
+        .unwrap();
+
+        assert_eq!(executor.eval(&context, &value).unwrap(), json!(5));
+    }
+
+    #[test]
+    fn test_eval_divide_by_zero() {
+        let (executor, context) = create_test_executor();
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$divide": {"left": 10, "right": {"$subtract": {"left": 2, "right": 2}}}
+        }))
+        .unwrap();
+
+        assert_eq!(executor.eval(&context, &value).unwrap_err(), ExecutionError::DivisionByZero);
+    }
+
+    #[test]
+    fn test_eval_round_and_min_max() {
+        let (executor, context) = create_test_executor();
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$round": {"value": {"$multiply": {"operands": [19.99, 0.15]}}, "places": 2}
+        }))
+        .unwrap();
+        assert_eq!(executor.eval(&context, &value).unwrap(), json!(3.0));
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$max": {"operands": [1, {"$min": {"operands": [100, 20]}}]}
+        }))
+        .unwrap();
+        assert_eq!(executor.eval(&context, &value).unwrap(), json!(20));
+    }
+
+    // $renderString operator tests
+
+    #[test]
+    fn test_eval_render_string() {
+        let (executor, context) = create_test_executor();
+        let context = context.with_var("user", json!({"name": "Ada", "id": 7}));
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$renderString": "Hello {{ user.name | upper }} (#{{user.id}})"
+        }))
+        .unwrap();
+
+        assert_eq!(executor.eval(&context, &value).unwrap(), json!("Hello ADA (#7)"));
+    }
+
+    #[test]
+    fn test_eval_render_string_in_map() {
+        let (executor, context) = create_test_executor();
+        let context = context.with_var("posts", json!([
+            {"slug": "hello world"},
+            {"slug": "second"}
+        ]));
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$map": {
+                "over": {"$get": "posts"},
+                "as": "post",
+                "do": {"$renderString": "/posts/{{ post.slug | urlencode }}?i={{ index }}"}
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(
+            executor.eval(&context, &value).unwrap(),
+            json!(["/posts/hello%20world?i=0", "/posts/second?i=1"])
+        );
+    }
+
+    #[test]
+    fn test_eval_render_string_missing_path() {
+        let (executor, context) = create_test_executor();
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$renderString": "Hello {{ user.name }}"
+        }))
+        .unwrap();
+
+        assert_eq!(
+            executor.eval(&context, &value).unwrap_err(),
+            ExecutionError::path_not_found("user.name")
+        );
+    }
+
+    // $return operator tests
+
+    #[test]
+    fn test_eval_return_raises_early_return() {
+        let (executor, context) = create_test_executor();
+        let context = context.with_var("requestId", json!("req-1"));
+
+        let op: Operator = serde_json::from_value(json!({
+            "$return": {
+                "status": 404,
+                "headers": {"X-Request-Id": {"$get": "requestId"}},
+                "body": {"error": "Not found"}
+            }
+        }))
+        .unwrap();
+
+        let result = executor.eval_operator(&context, &op);
+        match result.unwrap_err() {
+            ExecutionError::EarlyReturn { status, headers, body } => {
+                assert_eq!(status, 404);
+                assert_eq!(headers.get("X-Request-Id"), Some(&json!("req-1")));
+                assert_eq!(body, json!({"error": "Not found"}));
+            }
+            other => panic!("Expected EarlyReturn, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_eval_return_defaults_and_dynamic_status() {
+        let (executor, context) = create_test_executor();
+        let context = context.with_var("code", json!(403));
+
+        let op: Operator = serde_json::from_value(json!({
+            "$return": {"status": {"$get": "code"}}
+        }))
+        .unwrap();
+
+        let result = executor.eval_operator(&context, &op);
+        assert_eq!(
+            result.unwrap_err(),
+            ExecutionError::EarlyReturn {
+                status: 403,
+                headers: std::collections::HashMap::new(),
+                body: json!(null),
+            }
+        );
+    }
+
+    #[test]
+    fn test_eval_return_invalid_status() {
+        let (executor, context) = create_test_executor();
+
+        let op: Operator = serde_json::from_value(json!({"$return": {"status": "oops"}})).unwrap();
+        let result = executor.eval_operator(&context, &op);
+        assert!(matches!(result, Err(ExecutionError::TypeError { .. })));
+
+        let op: Operator = serde_json::from_value(json!({"$return": {"status": 1000}})).unwrap();
+        let result = executor.eval_operator(&context, &op);
+        assert!(matches!(result, Err(ExecutionError::InvalidOperator { .. })));
+    }
+
+    #[test]
+    fn test_eval_return_nested_in_if() {
+        let (executor, context) = create_test_executor();
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$if": {
+                "condition": {"$exists": {"$get": "headers.authorization"}},
+                "then": "ok",
+                "else": {"$return": {"status": 401, "body": {"error": "Unauthorized"}}}
+            }
+        }))
+        .unwrap();
+
+        let context = context.with_var("headers", json!({"authorization": null}));
+        let result = executor.eval(&context, &value);
+        assert!(matches!(result, Err(ExecutionError::EarlyReturn { status: 401, .. })));
+    }
+
+    #[test]
+    fn test_eval_return_nested_in_validate_on_fail() {
+        let (executor, context) = create_test_executor();
+
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$validate": {
+                "data": {"title": ""},
+                "schema": {"type": "object", "properties": {"title": {"type": "string", "minLength": 1}}},
+                "onFail": {"$return": {"status": 400, "body": {"error": "Invalid input"}}}
+            }
+        }))
+        .unwrap();
+
+        let result = executor.eval(&context, &value);
+        match result.unwrap_err() {
+            ExecutionError::EarlyReturn { status, body, .. } => {
+                assert_eq!(status, 400);
+                assert_eq!(body, json!({"error": "Invalid input"}));
+            }
+            other => panic!("Expected EarlyReturn, got {:?}", other),
+        }
+    }
+
+    // Async evaluation tests
+
+    /// A provider whose calls yield to the runtime once before completing
+    struct YieldingDatabase(Box<dyn AsyncDatabaseProvider>);
+
+    impl AsyncDatabaseProvider for YieldingDatabase {
+        fn query<'a>(
+            &'a self,
+            collection: &'a str,
+            filter: Option<&'a crate::database::Filter>,
+            select: Option<&'a [String]>,
+            limit: Option<u32>,
+            skip: Option<u32>,
+            sort: Option<&'a SortSpec>,
+        ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                AsyncDatabaseProvider::query(&*self.0, collection, filter, select, limit, skip, sort).await
+            })
+        }
+
+        fn query_after<'a>(
+            &'a self,
+            collection: &'a str,
+            filter: Option<&'a crate::database::Filter>,
+            sort: &'a SortSpec,
+            after: &'a [Value],
+            limit: Option<u32>,
+        ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+            AsyncDatabaseProvider::query_after(&*self.0, collection, filter, sort, after, limit)
+        }
+
+        fn count<'a>(
+            &'a self,
+            collection: &'a str,
+            filter: Option<&'a crate::database::Filter>,
+        ) -> BoxFuture<'a, Result<u64, ExecutionError>> {
+            AsyncDatabaseProvider::count(&*self.0, collection, filter)
+        }
+
+        fn insert<'a>(
+            &'a self,
+            collection: &'a str,
+            document: &'a HashMap<String, Value>,
+        ) -> BoxFuture<'a, Result<Value, ExecutionError>> {
+            Box::pin(async move {
+                tokio::task::yield_now().await;
+                AsyncDatabaseProvider::insert(&*self.0, collection, document).await
+            })
+        }
+
+        fn update<'a>(
+            &'a self,
+            collection: &'a str,
+            filter: &'a crate::database::Filter,
+            update: &'a crate::database::Update,
+        ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+            AsyncDatabaseProvider::update(&*self.0, collection, filter, update)
+        }
+
+        fn upsert<'a>(
+            &'a self,
+            collection: &'a str,
+            filter: &'a crate::database::Filter,
+            update: &'a crate::database::Update,
+            insert: &'a HashMap<String, Value>,
+        ) -> BoxFuture<'a, Result<(Option<Value>, Value), ExecutionError>> {
+            AsyncDatabaseProvider::upsert(&*self.0, collection, filter, update, insert)
+        }
+
+        fn find_one_and_update<'a>(
+            &'a self,
+            collection: &'a str,
+            filter: &'a crate::database::Filter,
+            update: &'a crate::database::Update,
+            sort: Option<&'a SortSpec>,
+        ) -> BoxFuture<'a, Result<Option<(Value, Value)>, ExecutionError>> {
+            AsyncDatabaseProvider::find_one_and_update(&*self.0, collection, filter, update, sort)
+        }
+
+        fn delete<'a>(
+            &'a self,
+            collection: &'a str,
+            filter: &'a crate::database::Filter,
+        ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+            AsyncDatabaseProvider::delete(&*self.0, collection, filter)
+        }
+
+        fn aggregate<'a>(
+            &'a self,
+            collection: &'a str,
+            aggregation: &'a crate::database::Aggregation,
+        ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+            AsyncDatabaseProvider::aggregate(&*self.0, collection, aggregation)
+        }
+
+        fn begin(&self) -> BoxFuture<'_, Result<Box<dyn AsyncDatabaseProvider>, ExecutionError>> {
+            Box::pin(async move {
+                let transaction = self.0.begin().await?;
+                Ok(Box::new(YieldingDatabase(transaction)) as Box<dyn AsyncDatabaseProvider>)
+            })
+        }
+
+        fn commit(&self) -> BoxFuture<'_, Result<(), ExecutionError>> {
+            AsyncDatabaseProvider::commit(&*self.0)
+        }
+
+        fn rollback(&self) -> BoxFuture<'_, Result<(), ExecutionError>> {
+            AsyncDatabaseProvider::rollback(&*self.0)
+        }
+    }
+
+    fn posts_pipeline() -> OperatorValue {
+        serde_json::from_value(json!({
+            "$map": {
+                "over": {"$dbQuery": {"collection": "posts", "filter": {"status": {"$get": "status"}}}},
+                "as": "post",
+                "do": {"$get": "post.title"}
+            }
+        }))
+        .unwrap()
+    }
+
+    fn posts_database() -> MockDatabase {
+        MockDatabase::new().with_collection(
+            "posts",
+            vec![
+                json!({"_id": "1", "title": "First", "status": "published"}),
+                json!({"_id": "2", "title": "Draft", "status": "draft"}),
+                json!({"_id": "3", "title": "Second", "status": "published"}),
+            ],
+        )
+    }
+
+    #[tokio::test]
+    async fn test_eval_async_awaits_provider() {
+        let db = YieldingDatabase(Box::new(posts_database()));
+        let time = FixedTimeProvider::new("2025-01-01T00:00:00Z", 1735689600);
+        let request = MockRequestContext::new();
+        let executor = Executor::new(&db, &time, &request);
+        let context = Context::new().with_var("status", json!("published"));
+
+        let result = executor.eval_async(&context, &posts_pipeline()).await.unwrap();
+        assert_eq!(result, json!(["First", "Second"]));
+    }
+
+    #[test]
+    fn test_eval_waits_for_provider() {
+        let db = YieldingDatabase(Box::new(posts_database()));
+        let time = FixedTimeProvider::new("2025-01-01T00:00:00Z", 1735689600);
+        let request = MockRequestContext::new();
+        let executor = Executor::new(&db, &time, &request);
+        let context = Context::new().with_var("status", json!("published"));
+
+        let result = executor.eval(&context, &posts_pipeline()).unwrap();
+        assert_eq!(result, json!(["First", "Second"]));
+    }
+
+    #[test]
+    fn test_eval_with_blocking_database() {
+        let db = traits::BlockingDatabase::new(std::sync::Arc::new(posts_database()));
+        let time = FixedTimeProvider::new("2025-01-01T00:00:00Z", 1735689600);
+        let request = MockRequestContext::new();
+        let executor = Executor::new(&db, &time, &request);
+        let context = Context::new().with_var("status", json!("draft"));
+        let insert: OperatorValue = serde_json::from_value(json!({
+            "$dbInsert": {"collection": "posts", "document": {"title": {"$get": "title"}, "status": "draft"}}
+        }))
+        .unwrap();
+
+        // Outside a runtime the calls run on this thread
+        executor.eval(&context.clone().with_var("title", json!("Outside")), &insert).unwrap();
+
+        // Inside one they run on its blocking pool, and `eval` waits for them
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+        executor.eval(&context.clone().with_var("title", json!("Inside")), &insert).unwrap();
+        let result = executor.eval(&context, &posts_pipeline()).unwrap();
+        assert_eq!(result, json!(["Draft", "Outside", "Inside"]));
+    }
+
+    #[tokio::test]
+    async fn test_eval_async_with_blocking_database() {
+        let db = traits::BlockingDatabase::new(std::sync::Arc::new(posts_database()));
+        let time = FixedTimeProvider::new("2025-01-01T00:00:00Z", 1735689600);
+        let request = MockRequestContext::new();
+        let executor = Executor::new(&db, &time, &request);
+        let context = Context::new().with_var("status", json!("draft"));
+
+        let result = executor.eval_async(&context, &posts_pipeline()).await.unwrap();
+        assert_eq!(result, json!(["Draft"]));
+
+        let insert: OperatorValue = serde_json::from_value(json!({
+            "$dbInsert": {"collection": "posts", "document": {"title": "Third", "status": {"$get": "status"}}}
+        }))
+        .unwrap();
+        executor.eval_async(&context, &insert).await.unwrap();
+        let result = executor.eval_async(&context, &posts_pipeline()).await.unwrap();
+        assert_eq!(result, json!(["Draft", "Third"]));
+    }
+
+    #[test]
+    fn test_eval_in_order_refuses_other_operands() {
+        let (executor, context) = create_test_executor();
+        let first = OperatorValue::Literal(json!(1));
+        let second = OperatorValue::Literal(json!(2));
+
+        let mut next = block_on(executor.eval_in_order(&context, vec![&first, &second])).unwrap();
+        assert_eq!(next(&first).unwrap(), json!(1));
+        assert!(matches!(next(&first), Err(ExecutionError::Custom { .. })));
+        assert!(matches!(next(&second), Err(ExecutionError::Custom { .. })));
+    }
+
+    #[test]
+    fn test_eval_sync_and_async_agree() {
+        let db = posts_database();
+        let time = FixedTimeProvider::new("2025-01-01T00:00:00Z", 1735689600);
+        let request = MockRequestContext::new();
+        let executor = Executor::new(&db, &time, &request);
+        let context = Context::new().with_var("status", json!("published"));
+
+        let sync = executor.eval(&context, &posts_pipeline()).unwrap();
+        let with_async = block_on(executor.eval_async(&context, &posts_pipeline())).unwrap();
+        assert_eq!(sync, with_async);
+    }
+
+    // Database operator tests - $dbQuery
+
+    #[test]
+    fn test_eval_dbquery_all_documents() {
+        // Create executor with database containing test data
+        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
+            "posts",
+            vec![
+                json!({"_id": "1", "title": "First Post", "views": 100}),
+                json!({"_id": "2", "title": "Second Post", "views": 200}),
+                json!({"_id": "3", "title": "Third Post", "views": 150}),
+            ],
+        )));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        let executor = Executor::new(db, time, request);
+        let context = Context::new();
+
+        // Query all documents (no filter)
+        let op = Operator::DbQuery(DbQueryOp {
+            collection: "posts".to_string(),
+            filter: None,
+            select: None,
+            limit: None,
+            skip: None,
+            sort: None,
+            with_total: false,
+            after: None,
+            before: None,
+        });
+
+        let result = executor.eval_operator(&context, &op).unwrap();
+        let result_array = result.as_array().unwrap();
+        assert_eq!(result_array.len(), 3);
+    }
+
+    #[test]
+    fn test_eval_dbquery_with_simple_filter() {
+        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
+            "posts",
+            vec![
+                json!({"_id": "1", "title": "First Post", "status": "published"}),
+                json!({"_id": "2", "title": "Second Post", "status": "draft"}),
+                json!({"_id": "3", "title": "Third Post", "status": "published"}),
+            ],
+        )));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        let executor = Executor::new(db, time, request);
+        let context = Context::new();
+
+        // Query with simple equality filter
+        let mut filter = std::collections::HashMap::new();
+        filter.insert("status".to_string(), OperatorValue::Literal(json!("published")));
 
         let op = Operator::DbQuery(DbQueryOp {
             collection: "posts".to_string(),
-            filter: Some(filter),
+            filter: Some(filter.into()),
             select: None,
             limit: None,
             skip: None,
             sort: None,
+            with_total: false,
+            after: None,
+            before: None,
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
@@ -1779,11 +3277,14 @@ mod tests {
 
         let op = Operator::DbQuery(DbQueryOp {
             collection: "posts".to_string(),
-            filter: Some(filter),
+            filter: Some(filter.into()),
             select: None,
             limit: None,
             skip: None,
             sort: None,
+            with_total: false,
+            after: None,
+            before: None,
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
@@ -1819,11 +3320,14 @@ mod tests {
 
         let op = Operator::DbQuery(DbQueryOp {
             collection: "posts".to_string(),
-            filter: Some(filter),
+            filter: Some(filter.into()),
             select: None,
             limit: None,
             skip: None,
             sort: None,
+            with_total: false,
+            after: None,
+            before: None,
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
@@ -1859,6 +3363,9 @@ mod tests {
             limit: Some(2),
             skip: None,
             sort: None,
+            with_total: false,
+            after: None,
+            before: None,
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
@@ -1893,6 +3400,9 @@ mod tests {
             limit: None,
             skip: Some(2),
             sort: None,
+            with_total: false,
+            after: None,
+            before: None,
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
@@ -1930,6 +3440,9 @@ mod tests {
             limit: Some(2),
             skip: Some(2),
             sort: None,
+            with_total: false,
+            after: None,
+            before: None,
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
@@ -1958,8 +3471,7 @@ mod tests {
         let context = Context::new();
 
         // Sort by views descending
-        let mut sort = std::collections::HashMap::new();
-        sort.insert("views".to_string(), SortOrder::Descending);
+        let sort = SortSpec::default().then("views", SortOrder::Descending);
 
         let op = Operator::DbQuery(DbQueryOp {
             collection: "posts".to_string(),
@@ -1968,6 +3480,9 @@ mod tests {
             limit: None,
             skip: None,
             sort: Some(sort),
+            with_total: false,
+            after: None,
+            before: None,
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
@@ -1978,6 +3493,45 @@ mod tests {
         assert_eq!(result_array[2].get("views").unwrap(), &json!(100));
     }
 
+    #[test]
+    fn test_eval_dbquery_with_multi_key_sort() {
+        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
+            "posts",
+            vec![
+                json!({"_id": "1", "status": "draft", "views": 10}),
+                json!({"_id": "2", "status": "published", "views": 30}),
+                json!({"_id": "3", "status": "draft", "views": 30}),
+                json!({"_id": "4", "status": "published", "views": 30}),
+                json!({"_id": "5", "status": null, "views": 5}),
+            ],
+        )));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        let executor = Executor::new(db, time, request);
+        let context = Context::new();
+
+        // Object form parsed from text keeps its key order: views first, then status
+        let value: OperatorValue = serde_json::from_str(
+            r#"{"$dbQuery": {"collection": "posts", "sort": {"views": "desc", "status": "asc"}}}"#,
+        )
+        .unwrap();
+        let result = executor.eval(&context, &value).unwrap();
+        let ids: Vec<&str> = result.as_array().unwrap().iter().map(|d| d["_id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["3", "2", "4", "1", "5"]);
+
+        // List form
+        let value: OperatorValue = serde_json::from_value(json!({
+            "$dbQuery": {"collection": "posts", "sort": ["status", {"_id": "desc"}]}
+        }))
+        .unwrap();
+        let result = executor.eval(&context, &value).unwrap();
+        let ids: Vec<&str> = result.as_array().unwrap().iter().map(|d| d["_id"].as_str().unwrap()).collect();
+        assert_eq!(ids, vec!["5", "3", "1", "4", "2"]);
+    }
+
     #[test]
     fn test_eval_dbquery_with_select() {
         let db = Box::leak(Box::new(MockDatabase::new().with_collection(
@@ -2003,6 +3557,9 @@ mod tests {
             limit: None,
             skip: None,
             sort: None,
+            with_total: false,
+            after: None,
+            before: None,
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
@@ -2042,11 +3599,14 @@ mod tests {
 
         let op = Operator::DbQuery(DbQueryOp {
             collection: "posts".to_string(),
-            filter: Some(filter),
+            filter: Some(filter.into()),
             select: None,
             limit: None,
             skip: None,
             sort: None,
+            with_total: false,
+            after: None,
+            before: None,
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
@@ -2073,6 +3633,9 @@ mod tests {
             limit: None,
             skip: None,
             sort: None,
+            with_total: false,
+            after: None,
+            before: None,
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
@@ -2081,6 +3644,302 @@ mod tests {
         assert_eq!(result_array.len(), 0);
     }
 
+    #[test]
+    fn test_eval_dbquery_range_and_exists_filter() {
+        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
+            "products",
+            vec![
+                json!({"_id": "1", "price": 5, "tags": ["sale"]}),
+                json!({"_id": "2", "price": 50, "tags": ["new", "sale"]}),
+                json!({"_id": "3", "price": 150, "tags": []}),
+                json!({"_id": "4", "price": 75, "deletedAt": "2025-01-01T00:00:00Z"}),
+            ],
+        )));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        let executor = Executor::new(db, time, request);
+        let context = Context::new().with_var("minPrice", json!(10));
+
+        let op: Operator = serde_json::from_value(json!({
+            "$dbQuery": {
+                "collection": "products",
+                "filter": {
+                    "price": {"$gte": {"$get": "minPrice"}, "$lte": 100},
+                    "deletedAt": {"$exists": false}
+                }
+            }
+        }))
+        .unwrap();
+
+        let result = executor.eval_operator(&context, &op).unwrap();
+        assert_eq!(result, json!([{"_id": "2", "price": 50, "tags": ["new", "sale"]}]));
+
+        let op: Operator = serde_json::from_value(json!({
+            "$dbQuery": {
+                "collection": "products",
+                "filter": {"$or": [{"tags": {"$size": 0}}, {"_id": {"$in": ["1"]}}]}
+            }
+        }))
+        .unwrap();
+
+        let result = executor.eval_operator(&context, &op).unwrap();
+        let ids: Vec<&Value> = result.as_array().unwrap().iter().map(|d| &d["_id"]).collect();
+        assert_eq!(ids, vec![&json!("1"), &json!("3")]);
+    }
+
+    #[test]
+    fn test_eval_dbquery_nested_paths() {
+        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
+            "posts",
+            vec![
+                json!({"_id": "1", "author": {"id": "u1"}, "meta": {"views": 10, "likes": 1}}),
+                json!({"_id": "2", "author": {"id": "u2"}, "meta": {"views": 30, "likes": 2}}),
+                json!({"_id": "3", "author": {"id": "u1"}, "meta": {"views": 20, "likes": 3}}),
+            ],
+        )));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        let executor = Executor::new(db, time, request);
+        let context = Context::new();
+
+        let op: Operator = serde_json::from_value(json!({
+            "$dbQuery": {
+                "collection": "posts",
+                "filter": {"author.id": "u1"},
+                "select": ["_id", "meta.views"],
+                "sort": {"meta.views": "desc"}
+            }
+        }))
+        .unwrap();
+
+        let result = executor.eval_operator(&context, &op).unwrap();
+        assert_eq!(
+            result,
+            json!([
+                {"_id": "3", "meta": {"views": 20}},
+                {"_id": "1", "meta": {"views": 10}}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_eval_dbquery_invalid_filter_operand() {
+        let (executor, context) = create_test_executor();
+        let context = context.with_var("status", json!("draft"));
+
+        // $in must evaluate to an array
+        let op: Operator = serde_json::from_value(json!({
+            "$dbQuery": {
+                "collection": "posts",
+                "filter": {"status": {"$in": {"$get": "status"}}}
+            }
+        }))
+        .unwrap();
+
+        let result = executor.eval_operator(&context, &op);
+        assert!(matches!(result, Err(ExecutionError::InvalidOperator { .. })));
+    }
+
+    // Database operator tests - $dbCount and withTotal
+
+    fn published_posts_executor() -> (Executor<'static>, Context) {
+        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
+            "posts",
+            vec![
+                json!({"_id": "1", "status": "published"}),
+                json!({"_id": "2", "status": "draft"}),
+                json!({"_id": "3", "status": "published"}),
+                json!({"_id": "4", "status": "published"}),
+            ],
+        )));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        let executor = Executor::new(db, time, request);
+        (executor, Context::new().with_var("status", json!("published")))
+    }
+
+    #[test]
+    fn test_eval_dbcount() {
+        let (executor, context) = published_posts_executor();
+
+        let op: Operator = serde_json::from_value(json!({
+            "$dbCount": {"collection": "posts", "filter": {"status": {"$get": "status"}}}
+        }))
+        .unwrap();
+        assert_eq!(executor.eval_operator(&context, &op).unwrap(), json!(3));
+
+        let op: Operator = serde_json::from_value(json!({"$dbCount": {"collection": "posts"}})).unwrap();
+        assert_eq!(executor.eval_operator(&context, &op).unwrap(), json!(4));
+
+        let op: Operator = serde_json::from_value(json!({"$dbCount": {"collection": "comments"}})).unwrap();
+        assert_eq!(executor.eval_operator(&context, &op).unwrap(), json!(0));
+    }
+
+    #[test]
+    fn test_eval_dbquery_with_total() {
+        let (executor, context) = published_posts_executor();
+        let op: Operator = serde_json::from_value(json!({
+            "$dbQuery": {
+                "collection": "posts",
+                "filter": {"status": {"$get": "status"}},
+                "select": ["_id"],
+                "limit": 2,
+                "skip": 1,
+                "withTotal": true
+            }
+        }))
+        .unwrap();
+
+        let result = executor.eval_operator(&context, &op).unwrap();
+        assert_eq!(
+            result,
+            json!({"items": [{"_id": "3"}, {"_id": "4"}], "total": 3, "limit": 2, "skip": 1})
+        );
+    }
+
+    #[test]
+    fn test_eval_dbquery_with_total_defaults() {
+        let (executor, context) = published_posts_executor();
+        let op: Operator = serde_json::from_value(json!({
+            "$dbQuery": {"collection": "posts", "select": ["_id"], "withTotal": true}
+        }))
+        .unwrap();
+
+        let result = executor.eval_operator(&context, &op).unwrap();
+        assert_eq!(result["total"], json!(4));
+        assert_eq!(result["items"].as_array().unwrap().len(), 4);
+        assert_eq!(result["limit"], Value::Null);
+        assert_eq!(result["skip"], json!(0));
+    }
+
+    // Database operator tests - cursor pagination
+
+    fn feed_executor() -> (Executor<'static>, Context) {
+        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
+            "posts",
+            vec![
+                json!({"_id": "a", "day": 3, "title": "A"}),
+                json!({"_id": "b", "day": 1, "title": "B"}),
+                json!({"_id": "c", "day": 3, "title": "C"}),
+                json!({"_id": "d", "day": 2, "title": "D"}),
+                json!({"_id": "e", "day": 3, "title": "E"}),
+                json!({"_id": "f", "title": "F"}),
+            ],
+        )));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        (Executor::new(db, time, request), Context::new())
+    }
+
+    /// Fetch a page of posts by day (newest first), selecting only titles
+    fn feed_page(executor: &Executor, direction: &str, cursor: Value) -> Value {
+        let op: Operator = serde_json::from_value(json!({
+            "$dbQuery": {
+                "collection": "posts",
+                "select": ["title"],
+                "sort": [{"day": "desc"}],
+                "limit": 2,
+                direction: {"$get": "cursor"}
+            }
+        }))
+        .unwrap();
+        let context = Context::new().with_var("cursor", cursor);
+        executor.eval_operator(&context, &op).unwrap()
+    }
+
+    fn titles(page: &Value) -> Vec<&str> {
+        page["items"].as_array().unwrap().iter().map(|item| item["title"].as_str().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_eval_dbquery_cursor_pages_forward_and_back() {
+        let (executor, _) = feed_executor();
+
+        // Ties on day are broken by _id; a missing day sorts last when descending
+        let first = feed_page(&executor, "after", Value::Null);
+        assert_eq!(titles(&first), vec!["A", "C"]);
+        assert_eq!(first["prevCursor"], Value::Null);
+
+        let second = feed_page(&executor, "after", first["nextCursor"].clone());
+        assert_eq!(titles(&second), vec!["E", "D"]);
+
+        let third = feed_page(&executor, "after", second["nextCursor"].clone());
+        assert_eq!(titles(&third), vec!["B", "F"]);
+        assert_eq!(third["nextCursor"], Value::Null);
+
+        let back = feed_page(&executor, "before", third["prevCursor"].clone());
+        assert_eq!(titles(&back), vec!["E", "D"]);
+        assert_eq!(back["nextCursor"], second["nextCursor"]);
+
+        let start = feed_page(&executor, "before", back["prevCursor"].clone());
+        assert_eq!(titles(&start), vec!["A", "C"]);
+        assert_eq!(start["prevCursor"], Value::Null);
+    }
+
+    #[test]
+    fn test_eval_dbquery_cursor_with_filter_and_total() {
+        let (executor, context) = feed_executor();
+        let op: Operator = serde_json::from_value(json!({
+            "$dbQuery": {
+                "collection": "posts",
+                "filter": {"day": 3},
+                "sort": [{"day": "desc"}, {"title": "desc"}],
+                "after": "",
+                "withTotal": true
+            }
+        }))
+        .unwrap();
+
+        let page = executor.eval_operator(&context, &op).unwrap();
+        assert_eq!(titles(&page), vec!["E", "C", "A"]);
+        assert_eq!(page["total"], json!(3));
+        assert_eq!(page["nextCursor"], Value::Null);
+        assert_eq!(page["prevCursor"], Value::Null);
+    }
+
+    #[test]
+    fn test_eval_dbquery_cursor_errors() {
+        let (executor, context) = feed_executor();
+        let query = |extra: Value| {
+            let mut op = json!({"collection": "posts", "limit": 2});
+            op.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            let op: Operator = serde_json::from_value(json!({"$dbQuery": op})).unwrap();
+            executor.eval_operator(&context, &op)
+        };
+
+        assert!(matches!(query(json!({"after": "garbage!"})), Err(ExecutionError::ValidationError { .. })));
+        assert!(matches!(query(json!({"after": 5})), Err(ExecutionError::ValidationError { .. })));
+
+        // A cursor from a query with a different sort is rejected
+        let page = feed_page(&executor, "after", Value::Null);
+        assert!(matches!(
+            query(json!({"after": page["nextCursor"]})),
+            Err(ExecutionError::ValidationError { .. })
+        ));
+
+        assert!(matches!(
+            query(json!({"after": "", "skip": 2})),
+            Err(ExecutionError::InvalidOperator { .. })
+        ));
+        let cursor = page["nextCursor"].clone();
+        assert!(matches!(
+            query(json!({"sort": [{"day": "desc"}], "after": cursor, "before": cursor})),
+            Err(ExecutionError::InvalidOperator { .. })
+        ));
+    }
+
     // Database operator tests - $dbInsert
 
     #[test]
@@ -2256,6 +4115,9 @@ mod tests {
             limit: None,
             skip: None,
             sort: None,
+            with_total: false,
+            after: None,
+            before: None,
         });
 
         let results = executor.eval_operator(&context, &query_op).unwrap();
@@ -2375,8 +4237,8 @@ mod tests {
 
         let op = Operator::DbUpdate(DbUpdateOp {
             collection: "posts".to_string(),
-            filter,
-            update,
+            filter: filter.into(),
+            update: update.into(),
             validate: false,
         });
 
@@ -2421,26 +4283,148 @@ mod tests {
 
         let op = Operator::DbUpdate(DbUpdateOp {
             collection: "posts".to_string(),
-            filter,
-            update,
+            filter: filter.into(),
+            update: update.into(),
+            validate: false,
+        });
+
+        let result = executor.eval_operator(&context, &op).unwrap();
+        let results_array = result.as_array().unwrap();
+
+        assert_eq!(results_array.len(), 1);
+        assert_eq!(results_array[0].get("updatedAt").unwrap(), &json!("2025-01-01T00:00:00Z"));
+    }
+
+    #[test]
+    fn test_eval_dbupdate_multiple_documents() {
+        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
+            "posts",
+            vec![
+                json!({"_id": "1", "status": "draft", "featured": false}),
+                json!({"_id": "2", "status": "draft", "featured": false}),
+                json!({"_id": "3", "status": "published", "featured": false}),
+            ],
+        )));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        let executor = Executor::new(db, time, request);
+        let context = Context::new();
+
+        // Update all draft posts
+        let mut filter = std::collections::HashMap::new();
+        filter.insert("status".to_string(), OperatorValue::Literal(json!("draft")));
+
+        let mut update = std::collections::HashMap::new();
+        update.insert("featured".to_string(), OperatorValue::Literal(json!(true)));
+
+        let op = Operator::DbUpdate(DbUpdateOp {
+            collection: "posts".to_string(),
+            filter: filter.into(),
+            update: update.into(),
+            validate: false,
+        });
+
+        let result = executor.eval_operator(&context, &op).unwrap();
+        let results_array = result.as_array().unwrap();
+
+        // Should update both draft posts
+        assert_eq!(results_array.len(), 2);
+        assert!(results_array.iter().all(|doc| doc.get("featured").unwrap() == &json!(true)));
+    }
+
+    #[test]
+    fn test_eval_dbupdate_empty_results() {
+        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
+            "posts",
+            vec![json!({"_id": "1", "status": "published"})],
+        )));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        let executor = Executor::new(db, time, request);
+        let context = Context::new();
+
+        // Update with filter that matches nothing
+        let mut filter = std::collections::HashMap::new();
+        filter.insert("status".to_string(), OperatorValue::Literal(json!("draft")));
+
+        let mut update = std::collections::HashMap::new();
+        update.insert("title".to_string(), OperatorValue::Literal(json!("Updated")));
+
+        let op = Operator::DbUpdate(DbUpdateOp {
+            collection: "posts".to_string(),
+            filter: filter.into(),
+            update: update.into(),
             validate: false,
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
-        let results_array = result.as_array().unwrap();
+        let results_array = result.as_array().unwrap();
+
+        // Should return empty array
+        assert_eq!(results_array.len(), 0);
+    }
+
+    #[test]
+    fn test_eval_dbupdate_operators() {
+        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
+            "posts",
+            vec![
+                json!({"_id": "1", "views": 10, "tags": ["draft", "rust"], "meta": {"notes": "x"}}),
+                json!({"_id": "2", "views": 3, "tags": []}),
+            ],
+        )));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        let executor = Executor::new(db, time, request);
+        let context = Context::new().with_var("params", json!({"id": "1"}));
+
+        let op: Operator = serde_json::from_value(json!({
+            "$dbUpdate": {
+                "collection": "posts",
+                "filter": {"_id": {"$get": "params.id"}},
+                "update": {
+                    "$inc": {"views": 1, "meta.revisions": 1},
+                    "$push": {"tags": "featured"},
+                    "$pull": {"tags": "draft"},
+                    "$unset": ["meta.notes"],
+                    "meta.editor": {"$get": "params.id"}
+                }
+            }
+        }))
+        .unwrap();
+
+        let result = executor.eval_operator(&context, &op).unwrap();
+        assert_eq!(
+            result,
+            json!([{
+                "_id": "1",
+                "views": 11,
+                "tags": ["rust", "featured"],
+                "meta": {"revisions": 1, "editor": "1"}
+            }])
+        );
 
-        assert_eq!(results_array.len(), 1);
-        assert_eq!(results_array[0].get("updatedAt").unwrap(), &json!("2025-01-01T00:00:00Z"));
+        let stored = stored(db, "posts");
+        assert_eq!(stored[0]["views"], json!(11));
+        assert_eq!(stored[1], json!({"_id": "2", "views": 3, "tags": []}));
     }
 
     #[test]
-    fn test_eval_dbupdate_multiple_documents() {
+    fn test_eval_dbupdate_failure_changes_nothing() {
         let db = Box::leak(Box::new(MockDatabase::new().with_collection(
             "posts",
             vec![
-                json!({"_id": "1", "status": "draft", "featured": false}),
-                json!({"_id": "2", "status": "draft", "featured": false}),
-                json!({"_id": "3", "status": "published", "featured": false}),
+                json!({"_id": "1", "views": 10}),
+                json!({"_id": "2", "views": "many"}),
             ],
         )));
         let time = Box::leak(Box::new(FixedTimeProvider::new(
@@ -2451,61 +4435,243 @@ mod tests {
         let executor = Executor::new(db, time, request);
         let context = Context::new();
 
-        // Update all draft posts
-        let mut filter = std::collections::HashMap::new();
-        filter.insert("status".to_string(), OperatorValue::Literal(json!("draft")));
+        let op: Operator = serde_json::from_value(json!({
+            "$dbUpdate": {
+                "collection": "posts",
+                "filter": null,
+                "update": {"$inc": {"views": 1}}
+            }
+        }))
+        .unwrap();
 
-        let mut update = std::collections::HashMap::new();
-        update.insert("featured".to_string(), OperatorValue::Literal(json!(true)));
+        let result = executor.eval_operator(&context, &op);
+        assert!(matches!(result, Err(ExecutionError::TypeError { .. })));
 
-        let op = Operator::DbUpdate(DbUpdateOp {
-            collection: "posts".to_string(),
-            filter,
-            update,
-            validate: false,
-        });
+        let stored = stored(db, "posts");
+        assert_eq!(stored[0]["views"], json!(10));
+    }
+
+    // Database operator tests - schema validation
+
+    fn posts_schemas() -> HashMap<String, DatabaseSchema> {
+        serde_json::from_value(json!({
+            "posts": {"fields": {
+                "title": {"type": "string", "required": true},
+                "status": {"type": "string", "enum": ["draft", "published"], "default": "draft"},
+                "createdAt": {"type": "datetime", "required": true},
+                "tags": {"type": "array", "items": {"type": "string"}}
+            }}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_eval_dbinsert_validate_fills_defaults() {
+        let (executor, context) = create_test_executor();
+        let schemas = posts_schemas();
+        let executor = executor.with_database_schemas(&schemas);
+
+        let op: Operator = serde_json::from_value(json!({
+            "$dbInsert": {
+                "collection": "posts",
+                "document": {"title": "Hello", "createdAt": {"$now": {}}},
+                "validate": true
+            }
+        }))
+        .unwrap();
 
         let result = executor.eval_operator(&context, &op).unwrap();
-        let results_array = result.as_array().unwrap();
+        assert_eq!(result["status"], json!("draft"));
+        assert_eq!(result["createdAt"], json!("2025-01-01T00:00:00Z"));
+    }
 
-        // Should update both draft posts
-        assert_eq!(results_array.len(), 2);
-        assert!(results_array.iter().all(|doc| doc.get("featured").unwrap() == &json!(true)));
+    #[test]
+    fn test_eval_dbinsert_validate_errors() {
+        let (executor, context) = create_test_executor();
+        let schemas = posts_schemas();
+        let executor = executor.with_database_schemas(&schemas);
+
+        let op: Operator = serde_json::from_value(json!({
+            "$dbInsert": {
+                "collection": "posts",
+                "document": {"status": "archived", "createdAt": "soon", "tags": ["a", 1]},
+                "validate": true
+            }
+        }))
+        .unwrap();
+
+        match executor.eval_operator(&context, &op) {
+            Err(ExecutionError::ValidationError { errors, .. }) => assert_eq!(
+                errors,
+                vec![
+                    "createdAt: expected datetime (RFC 3339 string), got string",
+                    "status: must be one of \"draft\", \"published\"",
+                    "tags: item 1 expected string, got number",
+                    "title: is required",
+                ]
+            ),
+            other => panic!("Expected ValidationError, got {:?}", other),
+        }
+        assert!(stored(executor.database, "posts").is_empty());
+
+        // Without validate the document is stored as given
+        let op: Operator = serde_json::from_value(json!({
+            "$dbInsert": {"collection": "posts", "document": {"status": "archived"}}
+        }))
+        .unwrap();
+        assert!(executor.eval_operator(&context, &op).is_ok());
     }
 
     #[test]
-    fn test_eval_dbupdate_empty_results() {
+    fn test_eval_dbupdate_validate() {
         let db = Box::leak(Box::new(MockDatabase::new().with_collection(
             "posts",
-            vec![json!({"_id": "1", "status": "published"})],
+            vec![json!({"_id": "1", "title": "Hello", "status": "draft", "createdAt": "2025-01-01T00:00:00Z"})],
         )));
         let time = Box::leak(Box::new(FixedTimeProvider::new(
             "2025-01-01T00:00:00Z",
             1735689600,
         )));
         let request = Box::leak(Box::new(MockRequestContext::new()));
-        let executor = Executor::new(db, time, request);
+        let schemas = posts_schemas();
+        let executor = Executor::new(db, time, request).with_database_schemas(&schemas);
         let context = Context::new();
 
-        // Update with filter that matches nothing
-        let mut filter = std::collections::HashMap::new();
-        filter.insert("status".to_string(), OperatorValue::Literal(json!("draft")));
+        let invalid: Operator = serde_json::from_value(json!({
+            "$dbUpdate": {
+                "collection": "posts",
+                "filter": {"_id": "1"},
+                "update": {"status": "gone", "$unset": ["title"]},
+                "validate": true
+            }
+        }))
+        .unwrap();
+        match executor.eval_operator(&context, &invalid) {
+            Err(ExecutionError::ValidationError { errors, .. }) => assert_eq!(
+                errors,
+                vec!["status: must be one of \"draft\", \"published\"", "title: is required"]
+            ),
+            other => panic!("Expected ValidationError, got {:?}", other),
+        }
 
-        let mut update = std::collections::HashMap::new();
-        update.insert("title".to_string(), OperatorValue::Literal(json!("Updated")));
+        let valid: Operator = serde_json::from_value(json!({
+            "$dbUpdate": {
+                "collection": "posts",
+                "filter": {"_id": "1"},
+                "update": {"status": "published", "$push": {"tags": "rust"}},
+                "validate": true
+            }
+        }))
+        .unwrap();
+        let result = executor.eval_operator(&context, &valid).unwrap();
+        assert_eq!(result[0]["status"], json!("published"));
+        assert_eq!(result[0]["tags"], json!(["rust"]));
+    }
 
-        let op = Operator::DbUpdate(DbUpdateOp {
-            collection: "posts".to_string(),
-            filter,
-            update,
-            validate: false,
-        });
+    #[test]
+    fn test_eval_db_validate_without_schema() {
+        let (executor, context) = create_test_executor();
+        let op: Operator = serde_json::from_value(json!({
+            "$dbInsert": {"collection": "posts", "document": {"title": "x"}, "validate": true}
+        }))
+        .unwrap();
+        assert!(matches!(
+            executor.eval_operator(&context, &op),
+            Err(ExecutionError::DatabaseError { .. })
+        ));
+    }
 
-        let result = executor.eval_operator(&context, &op).unwrap();
-        let results_array = result.as_array().unwrap();
+    // Database operator tests - unique constraints
 
-        // Should return empty array
-        assert_eq!(results_array.len(), 0);
+    fn users_executor() -> (Executor<'static>, Context) {
+        let schema: DatabaseSchema = serde_json::from_value(json!({
+            "fields": {
+                "_id": {"type": "string", "primary": true},
+                "email": {"type": "string", "unique": true},
+                "tenant": {"type": "string"},
+                "handle": {"type": "string"}
+            },
+            "indexes": [{"fields": ["tenant", "handle"], "unique": true}]
+        }))
+        .unwrap();
+        let db = Box::leak(Box::new(
+            MockDatabase::new()
+                .with_collection(
+                    "users",
+                    vec![
+                        json!({"_id": "1", "email": "a@x", "tenant": "t1", "handle": "ann"}),
+                        json!({"_id": "2", "email": "b@x", "tenant": "t1", "handle": "bob"}),
+                    ],
+                )
+                .with_schema("users", &schema),
+        ));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        (Executor::new(db, time, request), Context::new())
+    }
+
+    fn constraint_of(result: Result<Value, ExecutionError>) -> String {
+        match result {
+            Err(ExecutionError::DatabaseError { constraint: Some(name), .. }) => name,
+            other => panic!("Expected constraint violation, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_eval_dbinsert_unique_constraints() {
+        let (executor, context) = users_executor();
+        let insert = |document: Value| {
+            let op: Operator =
+                serde_json::from_value(json!({"$dbInsert": {"collection": "users", "document": document}})).unwrap();
+            executor.eval_operator(&context, &op)
+        };
+
+        assert_eq!(constraint_of(insert(json!({"email": "a@x"}))), "users_email_key");
+        assert_eq!(constraint_of(insert(json!({"_id": "2", "email": "c@x"}))), "users_pkey");
+        assert_eq!(
+            constraint_of(insert(json!({"email": "c@x", "tenant": "t1", "handle": "bob"}))),
+            "users_tenant_handle_key"
+        );
+
+        // Other tenants, missing fields and nulls are not constrained
+        assert!(insert(json!({"email": "c@x", "tenant": "t2", "handle": "bob"})).is_ok());
+        assert!(insert(json!({"tenant": "t1"})).is_ok());
+        assert!(insert(json!({"email": null, "tenant": "t1"})).is_ok());
+
+        let stored = stored(executor.database, "users");
+        assert_eq!(stored.len(), 5);
+    }
+
+    #[test]
+    fn test_eval_dbupdate_unique_constraints() {
+        let (executor, context) = users_executor();
+
+        let op: Operator = serde_json::from_value(json!({
+            "$dbUpdate": {"collection": "users", "filter": {"_id": "2"}, "update": {"email": "a@x"}}
+        }))
+        .unwrap();
+        assert_eq!(constraint_of(executor.eval_operator(&context, &op)), "users_email_key");
+
+        // Setting the same value on several documents conflicts between them
+        let op: Operator = serde_json::from_value(json!({
+            "$dbUpdate": {"collection": "users", "filter": null, "update": {"handle": "same"}}
+        }))
+        .unwrap();
+        assert_eq!(constraint_of(executor.eval_operator(&context, &op)), "users_tenant_handle_key");
+
+        let stored = stored(executor.database, "users");
+        assert_eq!(stored[1]["email"], json!("b@x"));
+        assert_eq!(stored[1]["handle"], json!("bob"));
+
+        // Updating a document without changing its key is fine
+        let op: Operator = serde_json::from_value(json!({
+            "$dbUpdate": {"collection": "users", "filter": {"_id": "1"}, "update": {"email": "a@x", "name": "Ann"}}
+        }))
+        .unwrap();
+        assert!(executor.eval_operator(&context, &op).is_ok());
     }
 
     // Database operator tests - $dbDelete
@@ -2533,7 +4699,7 @@ mod tests {
 
         let op = Operator::DbDelete(DbDeleteOp {
             collection: "posts".to_string(),
-            filter,
+            filter: filter.into(),
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
@@ -2569,7 +4735,7 @@ mod tests {
 
         let op = Operator::DbDelete(DbDeleteOp {
             collection: "posts".to_string(),
-            filter,
+            filter: filter.into(),
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
@@ -2608,7 +4774,7 @@ mod tests {
 
         let op = Operator::DbDelete(DbDeleteOp {
             collection: "posts".to_string(),
-            filter,
+            filter: filter.into(),
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
@@ -2638,7 +4804,7 @@ mod tests {
 
         let op = Operator::DbDelete(DbDeleteOp {
             collection: "posts".to_string(),
-            filter,
+            filter: filter.into(),
         });
 
         let result = executor.eval_operator(&context, &op).unwrap();
@@ -2672,7 +4838,7 @@ mod tests {
 
         let delete_op = Operator::DbDelete(DbDeleteOp {
             collection: "posts".to_string(),
-            filter,
+            filter: filter.into(),
         });
 
         executor.eval_operator(&context, &delete_op).unwrap();
@@ -2685,6 +4851,9 @@ mod tests {
             limit: None,
             skip: None,
             sort: None,
+            with_total: false,
+            after: None,
+            before: None,
         });
 
         let results = executor.eval_operator(&context, &query_op).unwrap();
@@ -2694,4 +4863,592 @@ mod tests {
         assert_eq!(results_array.len(), 1);
         assert_eq!(results_array[0].get("_id").unwrap(), &json!("2"));
     }
+
+    // Database operator tests - $dbUpsert and $dbFindOneAndUpdate
+
+    fn webhook_executor() -> (Executor<'static>, Context) {
+        let db = Box::leak(Box::new(
+            MockDatabase::new()
+                .with_collection(
+                    "events",
+                    vec![json!({
+                        "_id": "1",
+                        "eventId": "evt_1",
+                        "status": "pending",
+                        "attempts": 1,
+                        "receivedAt": "2024-12-31T00:00:00Z"
+                    })],
+                )
+                .with_id_generator(|| "generated".to_string()),
+        ));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        (Executor::new(db, time, request), Context::new())
+    }
+
+    fn upsert_event(event_id: &str, status: &str) -> Operator {
+        serde_json::from_value(json!({
+            "$dbUpsert": {
+                "collection": "events",
+                "filter": {"eventId": event_id, "source": "stripe"},
+                "document": {"status": status, "meta.lastStatus": status},
+                "defaults": {"receivedAt": {"$now": {}}, "attempts": 0, "status": "pending"}
+            }
+        }))
+        .unwrap()
+    }
+
+    fn all_docs(executor: &Executor, collection: &str) -> Vec<Value> {
+        let op: Operator = serde_json::from_value(json!({"$dbQuery": {"collection": collection}})).unwrap();
+        match executor.eval_operator(&Context::new(), &op).unwrap() {
+            Value::Array(docs) => docs,
+            other => panic!("Expected array, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_eval_dbupsert_inserts_with_defaults() {
+        let (executor, context) = webhook_executor();
+
+        let result = executor.eval_operator(&context, &upsert_event("evt_2", "paid")).unwrap();
+        assert_eq!(
+            result,
+            json!({
+                "_id": "generated",
+                "eventId": "evt_2",
+                "source": "stripe",
+                "status": "paid",
+                "meta": {"lastStatus": "paid"},
+                "attempts": 0,
+                "receivedAt": "2025-01-01T00:00:00Z"
+            })
+        );
+        assert_eq!(all_docs(&executor, "events").len(), 2);
+    }
+
+    #[test]
+    fn test_eval_dbupsert_updates_match_without_defaults() {
+        let (executor, context) = webhook_executor();
+        let op: Operator = serde_json::from_value(json!({
+            "$dbUpsert": {
+                "collection": "events",
+                "filter": {"eventId": "evt_1"},
+                "document": {"status": "paid"},
+                "defaults": {"receivedAt": {"$now": {}}, "attempts": 0}
+            }
+        }))
+        .unwrap();
+
+        let result = executor.eval_operator(&context, &op).unwrap();
+        assert_eq!(
+            result,
+            json!({
+                "_id": "1",
+                "eventId": "evt_1",
+                "status": "paid",
+                "attempts": 1,
+                "receivedAt": "2024-12-31T00:00:00Z"
+            })
+        );
+        assert_eq!(all_docs(&executor, "events"), vec![result]);
+    }
+
+    #[test]
+    fn test_eval_dbupsert_is_idempotent() {
+        let (executor, context) = webhook_executor();
+
+        let first = executor.eval_operator(&context, &upsert_event("evt_2", "pending")).unwrap();
+        let second = executor.eval_operator(&context, &upsert_event("evt_2", "paid")).unwrap();
+        assert_eq!(second["_id"], first["_id"]);
+        assert_eq!(second["status"], json!("paid"));
+        assert_eq!(second["meta"], json!({"lastStatus": "paid"}));
+        assert_eq!(all_docs(&executor, "events").len(), 2);
+    }
+
+    #[test]
+    fn test_eval_dbupsert_unique_violation_changes_nothing() {
+        let (executor, context) = users_executor();
+
+        let op: Operator = serde_json::from_value(json!({
+            "$dbUpsert": {"collection": "users", "filter": {"_id": "2"}, "document": {"email": "a@x"}}
+        }))
+        .unwrap();
+        assert_eq!(constraint_of(executor.eval_operator(&context, &op)), "users_email_key");
+
+        let op: Operator = serde_json::from_value(json!({
+            "$dbUpsert": {"collection": "users", "filter": {"_id": "3"}, "document": {"email": "b@x"}}
+        }))
+        .unwrap();
+        assert_eq!(constraint_of(executor.eval_operator(&context, &op)), "users_email_key");
+
+        let emails: Vec<Value> = all_docs(&executor, "users").iter().map(|doc| doc["email"].clone()).collect();
+        assert_eq!(emails, vec![json!("a@x"), json!("b@x")]);
+    }
+
+    #[test]
+    fn test_eval_dbupsert_validate() {
+        let (executor, context) = create_test_executor();
+        let schemas = posts_schemas();
+        let executor = executor.with_database_schemas(&schemas);
+
+        // Inserting checks the whole document, with schema defaults filled in
+        let op: Operator = serde_json::from_value(json!({
+            "$dbUpsert": {
+                "collection": "posts",
+                "filter": {"slug": "hello"},
+                "document": {"title": "Hello"},
+                "defaults": {"createdAt": {"$now": {}}},
+                "validate": true
+            }
+        }))
+        .unwrap();
+        let result = executor.eval_operator(&context, &op).unwrap();
+        assert_eq!(result["status"], json!("draft"));
+
+        let op: Operator = serde_json::from_value(json!({
+            "$dbUpsert": {
+                "collection": "posts",
+                "filter": {"slug": "other"},
+                "document": {"title": "Other"},
+                "validate": true
+            }
+        }))
+        .unwrap();
+        assert!(matches!(
+            executor.eval_operator(&context, &op),
+            Err(ExecutionError::ValidationError { .. })
+        ));
+
+        // The fields set on a matched document are checked too
+        let op: Operator = serde_json::from_value(json!({
+            "$dbUpsert": {
+                "collection": "posts",
+                "filter": {"slug": "hello"},
+                "document": {"status": "archived"},
+                "defaults": {"title": "Hello", "createdAt": {"$now": {}}},
+                "validate": true
+            }
+        }))
+        .unwrap();
+        assert!(matches!(
+            executor.eval_operator(&context, &op),
+            Err(ExecutionError::ValidationError { .. })
+        ));
+    }
+
+    fn jobs_executor() -> (Executor<'static>, Context) {
+        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
+            "jobs",
+            vec![
+                json!({"_id": "1", "status": "queued", "createdAt": 2}),
+                json!({"_id": "2", "status": "queued", "createdAt": 1}),
+                json!({"_id": "3", "status": "done", "createdAt": 0}),
+            ],
+        )));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        (Executor::new(db, time, request), Context::new().with_var("worker", json!("w1")))
+    }
+
+    fn claim_job(return_document: &str) -> Operator {
+        serde_json::from_value(json!({
+            "$dbFindOneAndUpdate": {
+                "collection": "jobs",
+                "filter": {"status": "queued"},
+                "sort": [{"createdAt": "asc"}],
+                "update": {"status": "running", "worker": {"$get": "worker"}, "$inc": {"attempts": 1}},
+                "returnDocument": return_document
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_eval_dbfindoneandupdate_returns_after_image() {
+        let (executor, context) = jobs_executor();
+
+        let result = executor.eval_operator(&context, &claim_job("after")).unwrap();
+        assert_eq!(
+            result,
+            json!({"_id": "2", "status": "running", "createdAt": 1, "worker": "w1", "attempts": 1})
+        );
+    }
+
+    #[test]
+    fn test_eval_dbfindoneandupdate_returns_before_image() {
+        let (executor, context) = jobs_executor();
+
+        let result = executor.eval_operator(&context, &claim_job("before")).unwrap();
+        assert_eq!(result, json!({"_id": "2", "status": "queued", "createdAt": 1}));
+
+        // Only the first match is updated
+        let statuses: Vec<Value> = all_docs(&executor, "jobs").iter().map(|doc| doc["status"].clone()).collect();
+        assert_eq!(statuses, vec![json!("queued"), json!("running"), json!("done")]);
+    }
+
+    #[test]
+    fn test_eval_dbfindoneandupdate_no_match() {
+        let (executor, context) = jobs_executor();
+
+        assert_eq!(executor.eval_operator(&context, &claim_job("after")).unwrap()["_id"], json!("2"));
+        assert_eq!(executor.eval_operator(&context, &claim_job("after")).unwrap()["_id"], json!("1"));
+        assert_eq!(executor.eval_operator(&context, &claim_job("after")).unwrap(), Value::Null);
+
+        let op: Operator = serde_json::from_value(json!({
+            "$dbFindOneAndUpdate": {"collection": "missing", "filter": {}, "update": {"status": "running"}}
+        }))
+        .unwrap();
+        assert_eq!(executor.eval_operator(&context, &op).unwrap(), Value::Null);
+    }
+
+    #[test]
+    fn test_eval_dbfindoneandupdate_defaults_to_collection_order() {
+        let (executor, context) = jobs_executor();
+        let op: Operator = serde_json::from_value(json!({
+            "$dbFindOneAndUpdate": {"collection": "jobs", "filter": {"status": "queued"}, "update": {"status": "running"}}
+        }))
+        .unwrap();
+
+        assert_eq!(executor.eval_operator(&context, &op).unwrap()["_id"], json!("1"));
+    }
+
+    // Database operator tests - $dbAggregate
+
+    fn posts_by_author_executor() -> (Executor<'static>, Context) {
+        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
+            "posts",
+            vec![
+                json!({"_id": "1", "authorId": "u1", "status": "published", "views": 10}),
+                json!({"_id": "2", "authorId": "u2", "status": "published", "views": 4}),
+                json!({"_id": "3", "authorId": "u1", "status": "draft", "views": 100}),
+                json!({"_id": "4", "authorId": "u1", "status": "published", "views": 5}),
+                json!({"_id": "5", "authorId": "u3", "status": "published"}),
+            ],
+        )));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        let executor = Executor::new(db, time, request);
+        (executor, Context::new().with_var("status", json!("published")))
+    }
+
+    #[test]
+    fn test_eval_dbaggregate() {
+        let (executor, context) = posts_by_author_executor();
+        let op: Operator = serde_json::from_value(json!({
+            "$dbAggregate": {
+                "collection": "posts",
+                "pipeline": [
+                    {"$match": {"status": {"$get": "status"}}},
+                    {"$group": {
+                        "by": "authorId",
+                        "aggregates": {
+                            "count": {"$count": null},
+                            "totalViews": {"$sum": "views"},
+                            "avgViews": {"$avg": "views"},
+                            "maxViews": {"$max": "views"}
+                        }
+                    }},
+                    {"$sort": [{"count": "desc"}, {"authorId": "asc"}]},
+                    {"$limit": 2}
+                ]
+            }
+        }))
+        .unwrap();
+
+        let result = executor.eval_operator(&context, &op).unwrap();
+        assert_eq!(
+            result,
+            json!([
+                {"authorId": "u1", "count": 2, "totalViews": 15, "avgViews": 7.5, "maxViews": 10},
+                {"authorId": "u2", "count": 1, "totalViews": 4, "avgViews": 4.0, "maxViews": 4}
+            ])
+        );
+    }
+
+    #[test]
+    fn test_eval_dbaggregate_count_all() {
+        let (executor, context) = posts_by_author_executor();
+        let op: Operator = serde_json::from_value(json!({
+            "$dbAggregate": {
+                "collection": "posts",
+                "pipeline": [{"$group": {"aggregates": {"total": {"$count": null}}}}]
+            }
+        }))
+        .unwrap();
+        assert_eq!(executor.eval_operator(&context, &op).unwrap(), json!([{"total": 5}]));
+
+        let op: Operator = serde_json::from_value(json!({
+            "$dbAggregate": {
+                "collection": "comments",
+                "pipeline": [{"$group": {"aggregates": {"total": {"$count": null}}}}]
+            }
+        }))
+        .unwrap();
+        assert_eq!(executor.eval_operator(&context, &op).unwrap(), json!([]));
+    }
+
+    #[test]
+    fn test_eval_dbaggregate_invalid_match_operand() {
+        let (executor, context) = posts_by_author_executor();
+        let op: Operator = serde_json::from_value(json!({
+            "$dbAggregate": {
+                "collection": "posts",
+                "pipeline": [{"$match": {"status": {"$in": {"$get": "status"}}}}]
+            }
+        }))
+        .unwrap();
+        assert!(executor.eval_operator(&context, &op).is_err());
+    }
+
+    #[test]
+    fn test_parse_dbaggregate_rejects_unknown_stage() {
+        let result = serde_json::from_value::<Operator>(json!({
+            "$dbAggregate": {"collection": "posts", "pipeline": [{"$unwind": "tags"}]}
+        }));
+        assert!(result.is_err());
+    }
+
+    // Database operator tests - $transaction
+
+    fn accounts_executor() -> (Executor<'static>, Context, &'static MockDatabase) {
+        let db = Box::leak(Box::new(MockDatabase::new().with_collection(
+            "accounts",
+            vec![
+                json!({"_id": "acc-1", "balance": 150}),
+                json!({"_id": "acc-2", "balance": 20}),
+                json!({"_id": "acc-3", "balance": "frozen"}),
+            ],
+        )));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        (Executor::new(db, time, request), Context::new(), db)
+    }
+
+    /// A transfer that returns 409 when the source account lacks funds
+    fn transfer(from: &str, to: &str, amount: i64) -> Operator {
+        serde_json::from_value(json!({
+            "$transaction": {
+                "operations": [
+                    {
+                        "$dbUpdate": {
+                            "collection": "accounts",
+                            "filter": {"_id": from, "balance": {"$gte": amount}},
+                            "update": {"$inc": {"balance": -amount}}
+                        }
+                    },
+                    {
+                        "$if": {
+                            "condition": {"$get": "results.0"},
+                            "then": {
+                                "$dbUpdate": {
+                                    "collection": "accounts",
+                                    "filter": {"_id": to},
+                                    "update": {"$inc": {"balance": amount}}
+                                }
+                            },
+                            "else": {"$return": {"status": 409, "body": {"error": "Insufficient funds"}}}
+                        }
+                    }
+                ],
+                "onError": "rollback"
+            }
+        }))
+        .unwrap()
+    }
+
+    fn balances(db: &MockDatabase) -> Vec<Value> {
+        stored(db, "accounts").iter().map(|account| account["balance"].clone()).collect()
+    }
+
+    #[test]
+    fn test_eval_transaction_commits() {
+        let (executor, context, db) = accounts_executor();
+
+        let result = executor.eval_operator(&context, &transfer("acc-1", "acc-2", 100)).unwrap();
+        assert_eq!(
+            result,
+            json!([[{"_id": "acc-1", "balance": 50}], [{"_id": "acc-2", "balance": 120}]])
+        );
+        assert_eq!(balances(db), vec![json!(50), json!(120), json!("frozen")]);
+
+        // The transaction has ended, so another can start
+        executor.eval_operator(&context, &transfer("acc-2", "acc-1", 20)).unwrap();
+        assert_eq!(balances(db), vec![json!(70), json!(100), json!("frozen")]);
+    }
+
+    #[test]
+    fn test_eval_transaction_rolls_back_on_error() {
+        let (executor, context, db) = accounts_executor();
+
+        // Crediting a non-numeric balance fails after the debit succeeded
+        let result = executor.eval_operator(&context, &transfer("acc-1", "acc-3", 100));
+        assert!(matches!(result, Err(ExecutionError::TypeError { .. })));
+        assert_eq!(balances(db), vec![json!(150), json!(20), json!("frozen")]);
+    }
+
+    #[test]
+    fn test_eval_transaction_rolls_back_on_return() {
+        let (executor, context, db) = accounts_executor();
+
+        let op: Operator = serde_json::from_value(json!({
+            "$transaction": {
+                "operations": [
+                    {"$dbInsert": {"collection": "audit", "document": {"event": "transfer"}}},
+                    {"$dbDelete": {"collection": "accounts", "filter": {"_id": "acc-3"}}},
+                    {"$return": {"status": 409, "body": {"error": "Stopped"}}}
+                ]
+            }
+        }))
+        .unwrap();
+        match executor.eval_operator(&context, &op) {
+            Err(ExecutionError::EarlyReturn { status, .. }) => assert_eq!(status, 409),
+            other => panic!("Expected EarlyReturn, got {:?}", other),
+        }
+        assert!(stored(db, "audit").is_empty());
+        assert_eq!(balances(db), vec![json!(150), json!(20), json!("frozen")]);
+
+        // Insufficient funds: nothing was debited either
+        let result = executor.eval_operator(&context, &transfer("acc-2", "acc-1", 100));
+        assert!(matches!(result, Err(ExecutionError::EarlyReturn { status: 409, .. })));
+        assert_eq!(balances(db), vec![json!(150), json!(20), json!("frozen")]);
+    }
+
+    /// Holds `$now` until the test lets it continue
+    struct PausedTimeProvider {
+        entered: std::sync::Barrier,
+        release: std::sync::Barrier,
+    }
+
+    impl TimeProvider for PausedTimeProvider {
+        fn now(&self) -> String {
+            self.entered.wait();
+            self.release.wait();
+            "2025-01-01T00:00:00Z".to_string()
+        }
+
+        fn unix_timestamp(&self) -> i64 {
+            1735689600
+        }
+    }
+
+    #[test]
+    fn test_eval_transaction_is_isolated_from_concurrent_requests() {
+        let db = MockDatabase::new();
+        let paused = PausedTimeProvider {
+            entered: std::sync::Barrier::new(2),
+            release: std::sync::Barrier::new(2),
+        };
+        let time = FixedTimeProvider::new("2025-01-01T00:00:00Z", 1735689600);
+        let request = MockRequestContext::new();
+
+        // Request A pauses in `$now` with its transaction open, then rolls back
+        let rolled_back = Operator::Transaction(TransactionOp {
+            operations: vec![
+                serde_json::from_value(json!({"$dbInsert": {"collection": "audit", "document": {"event": "a"}}})).unwrap(),
+                OperatorValue::Operator(Box::new(Operator::Now(NowOp::default()))),
+                serde_json::from_value(json!({"$return": {"status": 409, "body": {"error": "Stopped"}}})).unwrap(),
+            ],
+            on_error: OnError::Rollback,
+        });
+        let insert: Operator = serde_json::from_value(json!({
+            "$dbInsert": {"collection": "audit", "document": {"event": "b"}}
+        }))
+        .unwrap();
+        let query: Operator = serde_json::from_value(json!({"$dbQuery": {"collection": "audit"}})).unwrap();
+
+        std::thread::scope(|scope| {
+            let a = scope.spawn(|| Executor::new(&db, &paused, &request).eval_operator(&Context::new(), &rolled_back));
+            // Request B writes while A's transaction is open
+            paused.entered.wait();
+            let b = scope.spawn(|| Executor::new(&db, &time, &request).eval_operator(&Context::new(), &insert));
+            // Request C reads while it is open
+            let c = scope.spawn(|| Executor::new(&db, &time, &request).eval_operator(&Context::new(), &query));
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            assert!(!b.is_finished());
+            assert!(!c.is_finished());
+            paused.release.wait();
+
+            assert!(matches!(a.join().unwrap(), Err(ExecutionError::EarlyReturn { status: 409, .. })));
+            b.join().unwrap().unwrap();
+            // C never sees A's uncommitted insert
+            let read = c.join().unwrap().unwrap();
+            assert!(read.as_array().unwrap().iter().all(|entry| entry["event"] == json!("b")));
+        });
+
+        let events: Vec<Value> = stored(&db, "audit").iter().map(|entry| entry["event"].clone()).collect();
+        assert_eq!(events, vec![json!("b")]);
+    }
+
+    #[test]
+    fn test_eval_transaction_rolls_back_when_dropped() {
+        let db = MockDatabase::new();
+        let yielding = YieldingDatabase(Box::new(db.clone()));
+        let time = FixedTimeProvider::new("2025-01-01T00:00:00Z", 1735689600);
+        let request = MockRequestContext::new();
+        let executor = Executor::new(&yielding, &time, &request);
+        let context = Context::new();
+
+        let op: OperatorValue = serde_json::from_value(json!({
+            "$transaction": {
+                "operations": [
+                    {"$dbInsert": {"collection": "audit", "document": {"event": "a"}}},
+                    {"$dbQuery": {"collection": "audit"}}
+                ]
+            }
+        }))
+        .unwrap();
+        {
+            let mut future = std::pin::pin!(executor.eval_async(&context, &op));
+            let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+            // The insert yields, then completes and the query yields
+            assert!(future.as_mut().poll(&mut cx).is_pending());
+            assert!(future.as_mut().poll(&mut cx).is_pending());
+        } // Dropped with its transaction open, as when the client disconnects
+
+        let insert: OperatorValue = serde_json::from_value(json!({
+            "$dbInsert": {"collection": "audit", "document": {"event": "b"}}
+        }))
+        .unwrap();
+        executor.eval(&context, &insert).unwrap();
+        let events: Vec<Value> = stored(&db, "audit").iter().map(|entry| entry["event"].clone()).collect();
+        assert_eq!(events, vec![json!("b")]);
+    }
+
+    #[test]
+    fn test_eval_transaction_nested_joins_outer() {
+        let (executor, context, db) = accounts_executor();
+
+        let op: Operator = serde_json::from_value(json!({
+            "$transaction": {
+                "operations": [
+                    {"$dbInsert": {"collection": "audit", "document": {"event": "outer"}}},
+                    {"$transaction": {"operations": [
+                        {"$dbInsert": {"collection": "audit", "document": {"event": "inner"}}}
+                    ]}},
+                    {"$dbUpdate": {
+                        "collection": "accounts",
+                        "filter": {"_id": "acc-3"},
+                        "update": {"$inc": {"balance": 1}}
+                    }}
+                ]
+            }
+        }))
+        .unwrap();
+        assert!(executor.eval_operator(&context, &op).is_err());
+
+        // The inner transaction's insert is rolled back with the outer one
+        assert!(stored(db, "audit").is_empty());
+    }
 }
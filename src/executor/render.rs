@@ -0,0 +1,253 @@
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use serde_json::Value;
+
+use crate::pipeline::{Context, ExecutionError};
+
+/// Characters escaped by the `urlencode` filter (everything but RFC 3986 unreserved)
+const URL_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// Render a `$renderString` template against the context
+///
+/// Placeholders have the form `{{ path | filter | filter: arg }}`. The path
+/// is resolved with `Context::get_path` and the filters are applied left to
+/// right. Supported filters:
+/// - `default: <json>` - used when the value is missing or null
+/// - `upper` / `lower` - change the case of the rendered text
+/// - `json` - render the value as JSON (strings keep their quotes)
+/// - `urlencode` - percent-encode the rendered text
+///
+/// Strings render without quotes, null renders as an empty string and
+/// arrays and objects render as JSON.
+///
+/// # Errors
+/// - `PathNotFound` naming the placeholder path if it is missing and has no `default`
+/// - `TemplateError` for unclosed placeholders, empty paths or unknown filters
+pub(super) fn render_string(context: &Context, template: &str) -> Result<String, ExecutionError> {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+        let end = find_closing(after_open).ok_or_else(|| {
+            ExecutionError::template_error(format!("Unclosed placeholder in template: {}", template))
+        })?;
+
+        output.push_str(&render_placeholder(context, &after_open[..end])?);
+        rest = &after_open[end + 2..];
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Render a single placeholder expression (the text between `{{` and `}}`)
+fn render_placeholder(context: &Context, expression: &str) -> Result<String, ExecutionError> {
+    let mut segments = split_filters(expression).into_iter();
+    let path = segments.next().unwrap_or_default();
+    if path.is_empty() {
+        return Err(ExecutionError::template_error(format!(
+            "Empty placeholder path in '{{{{{}}}}}'",
+            expression
+        )));
+    }
+
+    let mut current = context.get_path(path).cloned();
+
+    for segment in segments {
+        let (name, argument) = match segment.split_once(':') {
+            Some((name, argument)) => (name.trim(), Some(argument.trim())),
+            None => (segment, None),
+        };
+
+        if name == "default" {
+            let argument = argument.ok_or_else(|| {
+                ExecutionError::template_error("Filter 'default' requires an argument")
+            })?;
+            if matches!(current, None | Some(Value::Null)) {
+                current = Some(serde_json::from_str(argument).map_err(|e| {
+                    ExecutionError::template_error(format!(
+                        "Invalid argument for filter 'default': {}",
+                        e
+                    ))
+                })?);
+            }
+            continue;
+        }
+
+        let value = current.ok_or_else(|| ExecutionError::path_not_found(path))?;
+        current = Some(match name {
+            "upper" => Value::String(to_text(&value).to_uppercase()),
+            "lower" => Value::String(to_text(&value).to_lowercase()),
+            "json" => Value::String(value.to_string()),
+            "urlencode" => {
+                Value::String(utf8_percent_encode(&to_text(&value), URL_ENCODE_SET).to_string())
+            }
+            other => {
+                return Err(ExecutionError::template_error(format!("Unknown filter '{}'", other)));
+            }
+        });
+    }
+
+    current
+        .map(|value| to_text(&value))
+        .ok_or_else(|| ExecutionError::path_not_found(path))
+}
+
+/// Find the `}}` closing a placeholder, ignoring braces inside double-quoted arguments
+fn find_closing(text: &str) -> Option<usize> {
+    unquoted_chars(text)
+        .map(|(i, _)| i)
+        .find(|&i| text[i..].starts_with("}}"))
+}
+
+/// Split a placeholder on `|`, ignoring pipes inside double-quoted arguments
+fn split_filters(expression: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut start = 0;
+
+    for (i, c) in unquoted_chars(expression) {
+        if c == '|' {
+            segments.push(expression[start..i].trim());
+            start = i + 1;
+        }
+    }
+    segments.push(expression[start..].trim());
+    segments
+}
+
+/// The characters of `text` outside double-quoted strings, with their byte offsets
+///
+/// The quotes themselves are skipped, and a backslash escapes the next
+/// character inside a string.
+fn unquoted_chars(text: &str) -> impl Iterator<Item = (usize, char)> + '_ {
+    let mut in_string = false;
+    let mut escaped = false;
+    text.char_indices().filter(move |&(_, c)| match c {
+        _ if escaped => {
+            escaped = false;
+            false
+        }
+        '\\' if in_string => {
+            escaped = true;
+            false
+        }
+        '"' => {
+            in_string = !in_string;
+            false
+        }
+        _ => !in_string,
+    })
+}
+
+/// Text form of a value as it appears in rendered output
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn context() -> Context {
+        Context::new()
+            .with_var("user", json!({"name": "Ada", "tags": ["a", "b"], "nickname": null}))
+            .with_var("count", json!(3))
+    }
+
+    #[test]
+    fn test_render_plain_paths() {
+        let result = render_string(&context(), "Hello {{user.name}}, you have {{ count }} messages").unwrap();
+        assert_eq!(result, "Hello Ada, you have 3 messages");
+
+        assert_eq!(render_string(&context(), "no placeholders").unwrap(), "no placeholders");
+        assert_eq!(render_string(&context(), "{{user.tags.1}}").unwrap(), "b");
+        assert_eq!(render_string(&context(), "[{{user.nickname}}]").unwrap(), "[]");
+    }
+
+    #[test]
+    fn test_render_missing_path() {
+        let result = render_string(&context(), "Hi {{ user.email }}");
+        assert_eq!(result.unwrap_err(), ExecutionError::path_not_found("user.email"));
+    }
+
+    #[test]
+    fn test_render_default_filter() {
+        let result = render_string(&context(), r#"{{ user.email | default: "anon" }}"#).unwrap();
+        assert_eq!(result, "anon");
+
+        let result = render_string(&context(), r#"{{ user.nickname | default: "none" }}"#).unwrap();
+        assert_eq!(result, "none");
+
+        let result = render_string(&context(), r#"{{ user.name | default: "anon" }}"#).unwrap();
+        assert_eq!(result, "Ada");
+
+        let result = render_string(&context(), r#"{{ user.email | default: "a|b" | upper }}"#).unwrap();
+        assert_eq!(result, "A|B");
+
+        let result = render_string(&context(), "{{ missing | default: 0 }}").unwrap();
+        assert_eq!(result, "0");
+    }
+
+    #[test]
+    fn test_render_quoted_closing_braces() {
+        let result = render_string(&context(), r#"[{{ user.email | default: "}}" }}]"#).unwrap();
+        assert_eq!(result, "[}}]");
+
+        let result = render_string(&context(), r#"{{ user.email | default: "say \"}}\"" }} {{ count }}"#).unwrap();
+        assert_eq!(result, r#"say "}}" 3"#);
+
+        assert!(matches!(
+            render_string(&context(), r#"{{ user.email | default: "}} }}"#),
+            Err(ExecutionError::TemplateError { .. })
+        ));
+    }
+
+    #[test]
+    fn test_render_case_filters() {
+        assert_eq!(render_string(&context(), "{{ user.name | upper }}").unwrap(), "ADA");
+        assert_eq!(render_string(&context(), "{{ user.name | lower }}").unwrap(), "ada");
+    }
+
+    #[test]
+    fn test_render_json_filter() {
+        assert_eq!(render_string(&context(), "{{ user.tags | json }}").unwrap(), r#"["a","b"]"#);
+        assert_eq!(render_string(&context(), "{{ user.name | json }}").unwrap(), r#""Ada""#);
+    }
+
+    #[test]
+    fn test_render_urlencode_filter() {
+        let context = Context::new().with_var("q", json!("rust & serde/json ~ok"));
+        let result = render_string(&context, "/search?q={{ q | urlencode }}").unwrap();
+        assert_eq!(result, "/search?q=rust%20%26%20serde%2Fjson%20~ok");
+    }
+
+    #[test]
+    fn test_render_template_errors() {
+        assert!(matches!(
+            render_string(&context(), "{{ user.name | shout }}"),
+            Err(ExecutionError::TemplateError { .. })
+        ));
+        assert!(matches!(
+            render_string(&context(), "Hello {{ user.name"),
+            Err(ExecutionError::TemplateError { .. })
+        ));
+        assert!(matches!(
+            render_string(&context(), "{{ | upper }}"),
+            Err(ExecutionError::TemplateError { .. })
+        ));
+        assert!(matches!(
+            render_string(&context(), "{{ user.name | default }}"),
+            Err(ExecutionError::TemplateError { .. })
+        ));
+    }
+}
@@ -0,0 +1,392 @@
+use serde_json::Value;
+use std::collections::HashMap;
+
+use super::{Executor, block_on};
+use crate::config::{Middleware, Response, Route};
+use crate::pipeline::{Context, ExecutionError, PipelineStep};
+
+/// A fully evaluated route response
+#[derive(Debug, Clone, PartialEq)]
+pub struct RouteResponse {
+    /// HTTP status code
+    pub status: u16,
+    /// Response headers
+    pub headers: HashMap<String, Value>,
+    /// Response body
+    pub body: Value,
+}
+
+impl<'a> Executor<'a> {
+    /// Run pipeline steps in order
+    ///
+    /// Each step is evaluated in the context produced by the previous steps.
+    /// Steps with a `name` store their result in the context under that name.
+    /// A `$return` stops the pipeline and is propagated as `EarlyReturn`.
+    ///
+    /// # Returns
+    /// The context after all steps have run, or the first error
+    pub async fn run_pipeline_async(&self, mut context: Context, steps: &[PipelineStep]) -> Result<Context, ExecutionError> {
+        for step in steps {
+            let value = self.eval_async(&context, &step.value).await?;
+            if let Some(name) = &step.name {
+                context.set_var(name.clone(), value);
+            }
+        }
+        Ok(context)
+    }
+
+    /// Evaluate a route response definition into a concrete response
+    ///
+    /// `Static` responses evaluate their headers and body. `Conditional`
+    /// responses must evaluate to an object with a numeric `status` and
+    /// optional `headers` and `body`.
+    pub async fn eval_response_async(&self, context: &Context, response: &Response) -> Result<RouteResponse, ExecutionError> {
+        match response {
+            Response::Static {
+                status,
+                headers,
+                body,
+            } => {
+                let mut evaluated_headers = HashMap::new();
+                for (name, value) in headers {
+                    evaluated_headers.insert(name.clone(), self.eval_async(context, value).await?);
+                }
+
+                Ok(RouteResponse {
+                    status: *status,
+                    headers: evaluated_headers,
+                    body: self.eval_async(context, body).await?,
+                })
+            }
+            Response::Conditional(value) => {
+                let evaluated = self.eval_async(context, value).await?;
+                let obj = evaluated.as_object().ok_or_else(|| {
+                    ExecutionError::type_error_with_types(
+                        "Conditional response must evaluate to an object",
+                        "object",
+                        Self::type_name(&evaluated),
+                    )
+                })?;
+
+                let status = obj
+                    .get("status")
+                    .and_then(Value::as_u64)
+                    .and_then(|s| u16::try_from(s).ok())
+                    .ok_or_else(|| {
+                        ExecutionError::type_error("Conditional response must have a numeric status")
+                    })?;
+
+                let headers = match obj.get("headers") {
+                    None | Some(Value::Null) => HashMap::new(),
+                    Some(Value::Object(map)) => map.iter().map(|(k, v)| (k.clone(), v.clone())).collect(),
+                    Some(other) => {
+                        return Err(ExecutionError::type_error_with_types(
+                            "Conditional response headers must be an object",
+                            "object",
+                            Self::type_name(other),
+                        ));
+                    }
+                };
+
+                Ok(RouteResponse {
+                    status,
+                    headers,
+                    body: obj.get("body").cloned().unwrap_or(Value::Null),
+                })
+            }
+        }
+    }
+
+    /// Run named middleware in order
+    ///
+    /// Each middleware pipeline runs in the context produced by the previous
+    /// one, so variables it sets are visible to later middleware and to the
+    /// route pipeline. A `$return` stops the chain as `EarlyReturn`.
+    pub async fn run_middleware_async(
+        &self,
+        mut context: Context,
+        names: &[String],
+        definitions: &HashMap<String, Middleware>,
+    ) -> Result<Context, ExecutionError> {
+        for name in names {
+            let middleware = definitions
+                .get(name)
+                .ok_or_else(|| ExecutionError::custom(format!("Unknown middleware: {}", name)))?;
+            context = self.run_pipeline_async(context, &middleware.pipeline).await?;
+        }
+        Ok(context)
+    }
+
+    /// Run a route's middleware and pipeline, then evaluate its response
+    ///
+    /// An `EarlyReturn` raised anywhere in the middleware, pipeline or response
+    /// becomes the route's response. Any other error is returned unchanged.
+    pub async fn run_route_async(
+        &self,
+        context: Context,
+        route: &Route,
+        middleware: &HashMap<String, Middleware>,
+    ) -> Result<RouteResponse, ExecutionError> {
+        let result = async {
+            let context = self.run_middleware_async(context, &route.middleware, middleware).await?;
+            let context = self.run_pipeline_async(context, &route.pipeline).await?;
+            self.eval_response_async(&context, &route.response).await
+        }
+        .await;
+
+        match result {
+            Err(ExecutionError::EarlyReturn {
+                status,
+                headers,
+                body,
+            }) => Ok(RouteResponse {
+                status,
+                headers,
+                body,
+            }),
+            other => other,
+        }
+    }
+
+    /// Run pipeline steps in order (see `run_pipeline_async`)
+    pub fn run_pipeline(&self, context: Context, steps: &[PipelineStep]) -> Result<Context, ExecutionError> {
+        block_on(self.run_pipeline_async(context, steps))
+    }
+
+    /// Evaluate a route response definition (see `eval_response_async`)
+    pub fn eval_response(&self, context: &Context, response: &Response) -> Result<RouteResponse, ExecutionError> {
+        block_on(self.eval_response_async(context, response))
+    }
+
+    /// Run named middleware in order (see `run_middleware_async`)
+    pub fn run_middleware(
+        &self,
+        context: Context,
+        names: &[String],
+        definitions: &HashMap<String, Middleware>,
+    ) -> Result<Context, ExecutionError> {
+        block_on(self.run_middleware_async(context, names, definitions))
+    }
+
+    /// Run a route's middleware, pipeline and response (see `run_route_async`)
+    ///
+    /// Like `Executor::eval`, this blocks the current thread until every
+    /// database call completes.
+    pub fn run_route(
+        &self,
+        context: Context,
+        route: &Route,
+        middleware: &HashMap<String, Middleware>,
+    ) -> Result<RouteResponse, ExecutionError> {
+        block_on(self.run_route_async(context, route, middleware))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::traits::{FixedTimeProvider, MockDatabase, MockRequestContext};
+    use crate::operators::{GetOp, Operator, OperatorValue};
+    use serde_json::json;
+
+    fn create_test_executor() -> Executor<'static> {
+        let db = Box::leak(Box::new(MockDatabase::new()));
+        let time = Box::leak(Box::new(FixedTimeProvider::new(
+            "2025-01-01T00:00:00Z",
+            1735689600,
+        )));
+        let request = Box::leak(Box::new(MockRequestContext::new()));
+        Executor::new(db, time, request)
+    }
+
+    fn get(path: &str) -> OperatorValue {
+        OperatorValue::Operator(Box::new(Operator::Get(GetOp {
+            path: path.to_string(),
+        })))
+    }
+
+    fn route(json: Value) -> Route {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_run_pipeline_accumulates_named_steps() {
+        let executor = create_test_executor();
+        let steps = vec![
+            PipelineStep {
+                name: Some("a".to_string()),
+                value: OperatorValue::Literal(json!({"value": 1})),
+            },
+            PipelineStep {
+                name: None,
+                value: OperatorValue::Literal(json!("ignored")),
+            },
+            PipelineStep {
+                name: Some("b".to_string()),
+                value: get("a.value"),
+            },
+        ];
+
+        let context = executor.run_pipeline(Context::new(), &steps).unwrap();
+        assert_eq!(context.get("a"), Some(&json!({"value": 1})));
+        assert_eq!(context.get("b"), Some(&json!(1)));
+        assert_eq!(context.variables().len(), 2);
+    }
+
+    #[test]
+    fn test_run_pipeline_stops_on_error() {
+        let executor = create_test_executor();
+        let steps = vec![
+            PipelineStep {
+                name: Some("a".to_string()),
+                value: get("missing"),
+            },
+            PipelineStep {
+                name: Some("b".to_string()),
+                value: OperatorValue::Literal(json!(1)),
+            },
+        ];
+
+        let result = executor.run_pipeline(Context::new(), &steps);
+        assert_eq!(result.unwrap_err(), ExecutionError::path_not_found("missing"));
+    }
+
+    #[test]
+    fn test_run_route_static_response() {
+        let executor = create_test_executor();
+        let route = route(json!({
+            "path": "/",
+            "method": "GET",
+            "pipeline": [{"name": "greeting", "value": "hello"}],
+            "response": {
+                "status": 200,
+                "headers": {"X-Greeting": {"$get": "greeting"}},
+                "body": {"message": "static"}
+            }
+        }));
+
+        let response = executor.run_route(Context::new(), &route, &HashMap::new()).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.headers.get("X-Greeting"), Some(&json!("hello")));
+        assert_eq!(response.body, json!({"message": "static"}));
+    }
+
+    #[test]
+    fn test_run_route_conditional_response() {
+        let executor = create_test_executor();
+        let route = route(json!({
+            "path": "/",
+            "method": "GET",
+            "response": {
+                "$if": {
+                    "condition": {"$exists": {"$get": "post"}},
+                    "then": {"status": 200, "body": {"found": true}},
+                    "else": {"status": 404, "headers": {"X-Reason": "missing"}, "body": {"error": "Not found"}}
+                }
+            }
+        }));
+
+        let response = executor
+            .run_route(Context::new().with_var("post", json!(null)), &route, &HashMap::new())
+            .unwrap();
+        assert_eq!(response.status, 404);
+        assert_eq!(response.headers.get("X-Reason"), Some(&json!("missing")));
+        assert_eq!(response.body, json!({"error": "Not found"}));
+
+        let response = executor
+            .run_route(Context::new().with_var("post", json!({"id": 1})), &route, &HashMap::new())
+            .unwrap();
+        assert_eq!(response.status, 200);
+        assert!(response.headers.is_empty());
+        assert_eq!(response.body, json!({"found": true}));
+    }
+
+    #[test]
+    fn test_eval_response_conditional_requires_status() {
+        let executor = create_test_executor();
+        let response = Response::Conditional(OperatorValue::Literal(json!({"body": "no status"})));
+
+        let result = executor.eval_response(&Context::new(), &response);
+        assert!(matches!(result, Err(ExecutionError::TypeError { .. })));
+    }
+
+    #[test]
+    fn test_run_route_middleware_sets_variables() {
+        let executor = create_test_executor();
+        let middleware: HashMap<String, Middleware> = serde_json::from_value(json!({
+            "loadUser": {"pipeline": [{"name": "user", "value": {"id": "u1"}}]},
+            "loadRole": {"pipeline": [{"name": "role", "value": {"$get": "user.id"}}]}
+        }))
+        .unwrap();
+        let route = route(json!({
+            "path": "/",
+            "method": "GET",
+            "middleware": ["loadUser", "loadRole"],
+            "pipeline": [{"name": "owner", "value": {"$get": "role"}}],
+            "response": {"status": 200, "body": {"$get": "owner"}}
+        }));
+
+        let response = executor.run_route(Context::new(), &route, &middleware).unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, json!("u1"));
+    }
+
+    #[test]
+    fn test_run_route_middleware_error_stops_chain() {
+        let executor = create_test_executor();
+        let middleware: HashMap<String, Middleware> = serde_json::from_value(json!({
+            "broken": {"pipeline": [{"value": {"$get": "missing"}}]}
+        }))
+        .unwrap();
+        let route = route(json!({
+            "path": "/",
+            "method": "GET",
+            "middleware": ["broken"],
+            "pipeline": [{"name": "never", "value": {"$get": "alsoMissing"}}],
+            "response": {"status": 200, "body": null}
+        }));
+
+        let result = executor.run_route(Context::new(), &route, &middleware);
+        assert_eq!(result.unwrap_err(), ExecutionError::path_not_found("missing"));
+    }
+
+    #[test]
+    fn test_run_middleware_unknown_name() {
+        let executor = create_test_executor();
+
+        let result = executor.run_middleware(Context::new(), &["nope".to_string()], &HashMap::new());
+        assert!(matches!(result, Err(ExecutionError::Custom { .. })));
+    }
+
+    #[test]
+    fn test_run_route_middleware_return_short_circuits() {
+        let executor = create_test_executor();
+        let middleware: HashMap<String, Middleware> = serde_json::from_value(json!({
+            "authenticate": {"pipeline": [{
+                "name": "user",
+                "value": {
+                    "$if": {
+                        "condition": {"$exists": {"$get": "headers.authorization"}},
+                        "then": {"$get": "headers.authorization"},
+                        "else": {"$return": {"status": 401, "body": {"error": "Unauthorized"}}}
+                    }
+                }
+            }]},
+            "audit": {"pipeline": [{"value": {"$get": "missing"}}]}
+        }))
+        .unwrap();
+        let route = route(json!({
+            "path": "/",
+            "method": "GET",
+            "middleware": ["authenticate", "audit"],
+            "pipeline": [{"value": {"$get": "alsoMissing"}}],
+            "response": {"status": 200, "body": {"$get": "user"}}
+        }));
+
+        // A request without the header
+        let context = Context::new().with_var("headers", json!({"accept": "application/json"}));
+        let response = executor.run_route(context, &route, &middleware).unwrap();
+        assert_eq!(response.status, 401);
+        assert_eq!(response.body, json!({"error": "Unauthorized"}));
+    }
+}
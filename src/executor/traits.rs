@@ -1,8 +1,15 @@
 use serde_json::Value;
+use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 
-use crate::operators::SortOrder;
-use crate::pipeline::ExecutionError;
+use crate::config::DatabaseSchema;
+use crate::database::{
+    Aggregation, Filter, SortSpec, Transaction, TransactionState, UniqueConstraint, Update, check_scalars, check_unique, path,
+};
+use crate::pipeline::{Context, ExecutionError};
 
 /// Trait for database operations
 ///
@@ -11,16 +18,51 @@ use crate::pipeline::ExecutionError;
 /// or swapped for different database backends.
 pub trait DatabaseProvider: Send + Sync {
     /// Query documents from a collection
+    ///
+    /// `None` for the filter matches every document. Filter fields, `select`
+    /// entries and sort keys are dot-paths with the semantics of
+    /// `database::path`.
     fn query(
         &self,
         collection: &str,
-        filter: Option<&HashMap<String, Value>>,
+        filter: Option<&Filter>,
         select: Option<&[String]>,
         limit: Option<u32>,
         skip: Option<u32>,
-        sort: Option<&HashMap<String, SortOrder>>,
+        sort: Option<&SortSpec>,
     ) -> Result<Vec<Value>, ExecutionError>;
 
+    /// Query the documents that sort strictly after a position (keyset pagination)
+    ///
+    /// `after` holds one value per sort key, as returned by
+    /// `SortSpec::key_of`. Matching documents are returned in `sort` order,
+    /// starting with the first one past the position. The default
+    /// implementation sorts every matching document with `query`; backends
+    /// that can seek to the position should override it.
+    fn query_after(
+        &self,
+        collection: &str,
+        filter: Option<&Filter>,
+        sort: &SortSpec,
+        after: &[Value],
+        limit: Option<u32>,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        let docs = self.query(collection, filter, None, None, None, Some(sort))?;
+        Ok(docs
+            .into_iter()
+            .filter(|doc| sort.compare_to_key(doc, after) == Ordering::Greater)
+            .take(limit.map_or(usize::MAX, |limit| limit as usize))
+            .collect())
+    }
+
+    /// Count the documents matching a filter (`None` counts every document)
+    ///
+    /// The default implementation counts the results of `query`. Backends
+    /// that can count without loading the documents should override it.
+    fn count(&self, collection: &str, filter: Option<&Filter>) -> Result<u64, ExecutionError> {
+        Ok(self.query(collection, filter, None, None, None, None)?.len() as u64)
+    }
+
     /// Insert a document into a collection
     fn insert(
         &self,
@@ -29,19 +71,365 @@ pub trait DatabaseProvider: Send + Sync {
     ) -> Result<Value, ExecutionError>;
 
     /// Update documents in a collection
+    ///
+    /// The update is applied to every matching document. Implementations must
+    /// apply it atomically: if it fails for any document, none are changed.
     fn update(
         &self,
         collection: &str,
-        filter: &HashMap<String, Value>,
-        update: &HashMap<String, Value>,
+        filter: &Filter,
+        update: &Update,
     ) -> Result<Vec<Value>, ExecutionError>;
 
+    /// Update the first document matching a filter, or insert one if none matches
+    ///
+    /// `update` is applied to the first match; without a match, `insert` is
+    /// inserted as by `insert`. Implementations must run this as one atomic
+    /// operation, so that no other write can come between the match and the
+    /// change. Returns the matched document before the update (`None` if a
+    /// document was inserted) and the document written.
+    fn upsert(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+        insert: &HashMap<String, Value>,
+    ) -> Result<(Option<Value>, Value), ExecutionError>;
+
+    /// Update the first document matching a filter, in `sort` order if given
+    ///
+    /// Implementations must run this as one atomic operation, like `upsert`.
+    /// Returns the document before and after the update, or `None` if no
+    /// document matches.
+    fn find_one_and_update(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+        sort: Option<&SortSpec>,
+    ) -> Result<Option<(Value, Value)>, ExecutionError>;
+
     /// Delete documents from a collection
     fn delete(
         &self,
         collection: &str,
-        filter: &HashMap<String, Value>,
+        filter: &Filter,
     ) -> Result<Vec<Value>, ExecutionError>;
+
+    /// Run an aggregation pipeline over a collection
+    ///
+    /// The default implementation loads every document with `query` and
+    /// runs the stages in memory. Backends that can do the filtering and
+    /// grouping themselves, such as SQL databases, should override it.
+    fn aggregate(
+        &self,
+        collection: &str,
+        aggregation: &Aggregation,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        aggregation.apply(self.query(collection, None, None, None, None, None)?)
+    }
+
+    /// Start a transaction, returning the provider to make its changes through
+    ///
+    /// Changes made through the returned provider belong to the transaction
+    /// until its `commit` or `rollback`; dropping it before either rolls the
+    /// transaction back. Providers run one transaction at a time: `begin`
+    /// waits for an open transaction to end. Reads and writes made directly
+    /// through the provider never see uncommitted changes and a rollback never
+    /// undoes them; providers without isolation of their own get this by
+    /// making them wait for the transaction to end too.
+    fn begin(&self) -> Result<Box<dyn DatabaseProvider>, ExecutionError>;
+
+    /// Make the changes of the transaction permanent
+    ///
+    /// Called on the provider returned by `begin`. If the changes cannot be
+    /// committed, they are rolled back and the error is returned; either way
+    /// the transaction has ended.
+    fn commit(&self) -> Result<(), ExecutionError>;
+
+    /// Undo every change made in the transaction (see `commit`)
+    fn rollback(&self) -> Result<(), ExecutionError>;
+}
+
+impl<P: DatabaseProvider + ?Sized> DatabaseProvider for Box<P> {
+    fn query(
+        &self,
+        collection: &str,
+        filter: Option<&Filter>,
+        select: Option<&[String]>,
+        limit: Option<u32>,
+        skip: Option<u32>,
+        sort: Option<&SortSpec>,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        (**self).query(collection, filter, select, limit, skip, sort)
+    }
+
+    fn query_after(
+        &self,
+        collection: &str,
+        filter: Option<&Filter>,
+        sort: &SortSpec,
+        after: &[Value],
+        limit: Option<u32>,
+    ) -> Result<Vec<Value>, ExecutionError> {
+        (**self).query_after(collection, filter, sort, after, limit)
+    }
+
+    fn count(&self, collection: &str, filter: Option<&Filter>) -> Result<u64, ExecutionError> {
+        (**self).count(collection, filter)
+    }
+
+    fn insert(&self, collection: &str, document: &HashMap<String, Value>) -> Result<Value, ExecutionError> {
+        (**self).insert(collection, document)
+    }
+
+    fn update(&self, collection: &str, filter: &Filter, update: &Update) -> Result<Vec<Value>, ExecutionError> {
+        (**self).update(collection, filter, update)
+    }
+
+    fn upsert(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+        insert: &HashMap<String, Value>,
+    ) -> Result<(Option<Value>, Value), ExecutionError> {
+        (**self).upsert(collection, filter, update, insert)
+    }
+
+    fn find_one_and_update(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+        sort: Option<&SortSpec>,
+    ) -> Result<Option<(Value, Value)>, ExecutionError> {
+        (**self).find_one_and_update(collection, filter, update, sort)
+    }
+
+    fn delete(&self, collection: &str, filter: &Filter) -> Result<Vec<Value>, ExecutionError> {
+        (**self).delete(collection, filter)
+    }
+
+    fn aggregate(&self, collection: &str, aggregation: &Aggregation) -> Result<Vec<Value>, ExecutionError> {
+        (**self).aggregate(collection, aggregation)
+    }
+
+    fn begin(&self) -> Result<Box<dyn DatabaseProvider>, ExecutionError> {
+        (**self).begin()
+    }
+
+    fn commit(&self) -> Result<(), ExecutionError> {
+        (**self).commit()
+    }
+
+    fn rollback(&self) -> Result<(), ExecutionError> {
+        (**self).rollback()
+    }
+}
+
+/// A boxed future, as returned by `AsyncDatabaseProvider` methods
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Asynchronous variant of `DatabaseProvider`
+///
+/// This is what the executor calls, so that providers doing I/O can yield to
+/// the runtime instead of blocking a worker thread. Every `DatabaseProvider`
+/// is also an `AsyncDatabaseProvider` whose futures complete immediately;
+/// wrap blocking providers in `BlockingDatabase` to run them on tokio's
+/// blocking thread pool instead.
+pub trait AsyncDatabaseProvider: Send + Sync {
+    /// Query documents from a collection (see `DatabaseProvider::query`)
+    fn query<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: Option<&'a Filter>,
+        select: Option<&'a [String]>,
+        limit: Option<u32>,
+        skip: Option<u32>,
+        sort: Option<&'a SortSpec>,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>>;
+
+    /// Query the documents after a position (see `DatabaseProvider::query_after`)
+    fn query_after<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: Option<&'a Filter>,
+        sort: &'a SortSpec,
+        after: &'a [Value],
+        limit: Option<u32>,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>>;
+
+    /// Count the documents matching a filter (see `DatabaseProvider::count`)
+    fn count<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: Option<&'a Filter>,
+    ) -> BoxFuture<'a, Result<u64, ExecutionError>>;
+
+    /// Insert a document into a collection (see `DatabaseProvider::insert`)
+    fn insert<'a>(
+        &'a self,
+        collection: &'a str,
+        document: &'a HashMap<String, Value>,
+    ) -> BoxFuture<'a, Result<Value, ExecutionError>>;
+
+    /// Update documents in a collection (see `DatabaseProvider::update`)
+    fn update<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: &'a Filter,
+        update: &'a Update,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>>;
+
+    /// Update or insert a document atomically (see `DatabaseProvider::upsert`)
+    fn upsert<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: &'a Filter,
+        update: &'a Update,
+        insert: &'a HashMap<String, Value>,
+    ) -> BoxFuture<'a, Result<(Option<Value>, Value), ExecutionError>>;
+
+    /// Update one document atomically (see `DatabaseProvider::find_one_and_update`)
+    fn find_one_and_update<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: &'a Filter,
+        update: &'a Update,
+        sort: Option<&'a SortSpec>,
+    ) -> BoxFuture<'a, Result<Option<(Value, Value)>, ExecutionError>>;
+
+    /// Delete documents from a collection (see `DatabaseProvider::delete`)
+    fn delete<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: &'a Filter,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>>;
+
+    /// Run an aggregation pipeline over a collection (see `DatabaseProvider::aggregate`)
+    fn aggregate<'a>(
+        &'a self,
+        collection: &'a str,
+        aggregation: &'a Aggregation,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>>;
+
+    /// Start a transaction, returning the provider to make its changes through
+    /// (see `DatabaseProvider::begin`)
+    fn begin(&self) -> BoxFuture<'_, Result<Box<dyn AsyncDatabaseProvider>, ExecutionError>>;
+
+    /// Commit the transaction (see `DatabaseProvider::commit`)
+    fn commit(&self) -> BoxFuture<'_, Result<(), ExecutionError>>;
+
+    /// Roll back the transaction (see `DatabaseProvider::rollback`)
+    fn rollback(&self) -> BoxFuture<'_, Result<(), ExecutionError>>;
+}
+
+impl<T: DatabaseProvider + ?Sized> AsyncDatabaseProvider for T {
+    fn query<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: Option<&'a Filter>,
+        select: Option<&'a [String]>,
+        limit: Option<u32>,
+        skip: Option<u32>,
+        sort: Option<&'a SortSpec>,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+        Box::pin(std::future::ready(DatabaseProvider::query(
+            self, collection, filter, select, limit, skip, sort,
+        )))
+    }
+
+    fn query_after<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: Option<&'a Filter>,
+        sort: &'a SortSpec,
+        after: &'a [Value],
+        limit: Option<u32>,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+        Box::pin(std::future::ready(DatabaseProvider::query_after(
+            self, collection, filter, sort, after, limit,
+        )))
+    }
+
+    fn count<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: Option<&'a Filter>,
+    ) -> BoxFuture<'a, Result<u64, ExecutionError>> {
+        Box::pin(std::future::ready(DatabaseProvider::count(self, collection, filter)))
+    }
+
+    fn insert<'a>(
+        &'a self,
+        collection: &'a str,
+        document: &'a HashMap<String, Value>,
+    ) -> BoxFuture<'a, Result<Value, ExecutionError>> {
+        Box::pin(std::future::ready(DatabaseProvider::insert(self, collection, document)))
+    }
+
+    fn update<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: &'a Filter,
+        update: &'a Update,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+        Box::pin(std::future::ready(DatabaseProvider::update(self, collection, filter, update)))
+    }
+
+    fn upsert<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: &'a Filter,
+        update: &'a Update,
+        insert: &'a HashMap<String, Value>,
+    ) -> BoxFuture<'a, Result<(Option<Value>, Value), ExecutionError>> {
+        Box::pin(std::future::ready(DatabaseProvider::upsert(self, collection, filter, update, insert)))
+    }
+
+    fn find_one_and_update<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: &'a Filter,
+        update: &'a Update,
+        sort: Option<&'a SortSpec>,
+    ) -> BoxFuture<'a, Result<Option<(Value, Value)>, ExecutionError>> {
+        Box::pin(std::future::ready(DatabaseProvider::find_one_and_update(
+            self, collection, filter, update, sort,
+        )))
+    }
+
+    fn delete<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: &'a Filter,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+        Box::pin(std::future::ready(DatabaseProvider::delete(self, collection, filter)))
+    }
+
+    fn aggregate<'a>(
+        &'a self,
+        collection: &'a str,
+        aggregation: &'a Aggregation,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+        Box::pin(std::future::ready(DatabaseProvider::aggregate(self, collection, aggregation)))
+    }
+
+    fn begin(&self) -> BoxFuture<'_, Result<Box<dyn AsyncDatabaseProvider>, ExecutionError>> {
+        let transaction = DatabaseProvider::begin(self);
+        Box::pin(std::future::ready(
+            transaction.map(|transaction| Box::new(transaction) as Box<dyn AsyncDatabaseProvider>),
+        ))
+    }
+
+    fn commit(&self) -> BoxFuture<'_, Result<(), ExecutionError>> {
+        Box::pin(std::future::ready(DatabaseProvider::commit(self)))
+    }
+
+    fn rollback(&self) -> BoxFuture<'_, Result<(), ExecutionError>> {
+        Box::pin(std::future::ready(DatabaseProvider::rollback(self)))
+    }
 }
 
 /// Trait for getting the current time
@@ -78,35 +466,295 @@ pub trait RequestContext: Send + Sync {
 
     /// Get the request path
     fn path(&self) -> &str;
+
+    /// Get every value of each query string parameter, in order of appearance
+    ///
+    /// Defaults to the single values returned by `query()`.
+    fn query_all(&self) -> HashMap<String, Vec<String>> {
+        self.query()
+            .iter()
+            .map(|(key, value)| (key.clone(), vec![value.clone()]))
+            .collect()
+    }
+
+    /// Build the initial pipeline context for this request
+    ///
+    /// Seeds the `params`, `query`, `headers`, `body`, `method` and `path`
+    /// variables. Query parameters that appear more than once become arrays.
+    fn to_context(&self) -> Context {
+        let to_object = |map: &HashMap<String, String>| {
+            Value::Object(
+                map.iter()
+                    .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+                    .collect(),
+            )
+        };
+
+        let query = self
+            .query_all()
+            .into_iter()
+            .map(|(key, mut values)| {
+                let value = if values.len() == 1 {
+                    Value::String(values.remove(0))
+                } else {
+                    Value::Array(values.into_iter().map(Value::String).collect())
+                };
+                (key, value)
+            })
+            .collect();
+
+        Context::new()
+            .with_var("params", to_object(self.params()))
+            .with_var("query", Value::Object(query))
+            .with_var("headers", to_object(self.headers()))
+            .with_var("body", self.body().cloned().unwrap_or(Value::Null))
+            .with_var("method", Value::String(self.method().to_string()))
+            .with_var("path", Value::String(self.path().to_string()))
+    }
+}
+
+// Production implementations
+
+/// Time provider backed by the system clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemTimeProvider;
+
+impl TimeProvider for SystemTimeProvider {
+    fn now(&self) -> String {
+        chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    }
+
+    fn unix_timestamp(&self) -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+}
+
+/// Runs a blocking `DatabaseProvider` on tokio's blocking thread pool
+///
+/// Use this for providers that do file or network I/O (such as
+/// `SqliteDatabase`, `PostgresDatabase` or `FileDatabase`) so that a slow
+/// query does not stall the async worker threads. Outside a tokio runtime,
+/// calls run on the thread that polls them.
+pub struct BlockingDatabase<P: ?Sized> {
+    inner: Arc<P>,
+}
+
+impl<P: DatabaseProvider + ?Sized + 'static> BlockingDatabase<P> {
+    /// Wrap a provider
+    pub fn new(inner: Arc<P>) -> Self {
+        Self { inner }
+    }
+
+    /// Run a provider call on the blocking pool of the current runtime, if any
+    fn run<T: Send + 'static>(
+        &self,
+        call: impl FnOnce(&P) -> Result<T, ExecutionError> + Send + 'static,
+    ) -> BoxFuture<'static, Result<T, ExecutionError>> {
+        let inner = Arc::clone(&self.inner);
+        Box::pin(async move {
+            let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+                return call(&inner);
+            };
+            runtime
+                .spawn_blocking(move || call(&inner))
+                .await
+                .map_err(|e| ExecutionError::database_error(format!("Database task failed: {}", e)))?
+        })
+    }
+}
+
+impl<P: DatabaseProvider + ?Sized + 'static> AsyncDatabaseProvider for BlockingDatabase<P> {
+    fn query<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: Option<&'a Filter>,
+        select: Option<&'a [String]>,
+        limit: Option<u32>,
+        skip: Option<u32>,
+        sort: Option<&'a SortSpec>,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+        let collection = collection.to_string();
+        let filter = filter.cloned();
+        let select = select.map(<[String]>::to_vec);
+        let sort = sort.cloned();
+        self.run(move |db| {
+            db.query(&collection, filter.as_ref(), select.as_deref(), limit, skip, sort.as_ref())
+        })
+    }
+
+    fn query_after<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: Option<&'a Filter>,
+        sort: &'a SortSpec,
+        after: &'a [Value],
+        limit: Option<u32>,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+        let collection = collection.to_string();
+        let filter = filter.cloned();
+        let sort = sort.clone();
+        let after = after.to_vec();
+        self.run(move |db| db.query_after(&collection, filter.as_ref(), &sort, &after, limit))
+    }
+
+    fn count<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: Option<&'a Filter>,
+    ) -> BoxFuture<'a, Result<u64, ExecutionError>> {
+        let collection = collection.to_string();
+        let filter = filter.cloned();
+        self.run(move |db| db.count(&collection, filter.as_ref()))
+    }
+
+    fn insert<'a>(
+        &'a self,
+        collection: &'a str,
+        document: &'a HashMap<String, Value>,
+    ) -> BoxFuture<'a, Result<Value, ExecutionError>> {
+        let collection = collection.to_string();
+        let document = document.clone();
+        self.run(move |db| db.insert(&collection, &document))
+    }
+
+    fn update<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: &'a Filter,
+        update: &'a Update,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+        let collection = collection.to_string();
+        let filter = filter.clone();
+        let update = update.clone();
+        self.run(move |db| db.update(&collection, &filter, &update))
+    }
+
+    fn upsert<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: &'a Filter,
+        update: &'a Update,
+        insert: &'a HashMap<String, Value>,
+    ) -> BoxFuture<'a, Result<(Option<Value>, Value), ExecutionError>> {
+        let collection = collection.to_string();
+        let filter = filter.clone();
+        let update = update.clone();
+        let insert = insert.clone();
+        self.run(move |db| db.upsert(&collection, &filter, &update, &insert))
+    }
+
+    fn find_one_and_update<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: &'a Filter,
+        update: &'a Update,
+        sort: Option<&'a SortSpec>,
+    ) -> BoxFuture<'a, Result<Option<(Value, Value)>, ExecutionError>> {
+        let collection = collection.to_string();
+        let filter = filter.clone();
+        let update = update.clone();
+        let sort = sort.cloned();
+        self.run(move |db| db.find_one_and_update(&collection, &filter, &update, sort.as_ref()))
+    }
+
+    fn delete<'a>(
+        &'a self,
+        collection: &'a str,
+        filter: &'a Filter,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+        let collection = collection.to_string();
+        let filter = filter.clone();
+        self.run(move |db| db.delete(&collection, &filter))
+    }
+
+    fn aggregate<'a>(
+        &'a self,
+        collection: &'a str,
+        aggregation: &'a Aggregation,
+    ) -> BoxFuture<'a, Result<Vec<Value>, ExecutionError>> {
+        let collection = collection.to_string();
+        let aggregation = aggregation.clone();
+        self.run(move |db| db.aggregate(&collection, &aggregation))
+    }
+
+    fn begin(&self) -> BoxFuture<'_, Result<Box<dyn AsyncDatabaseProvider>, ExecutionError>> {
+        let transaction = self.run(|db| db.begin());
+        Box::pin(async move {
+            let transaction = BlockingDatabase::<dyn DatabaseProvider>::new(Arc::from(transaction.await?));
+            Ok(Box::new(transaction) as Box<dyn AsyncDatabaseProvider>)
+        })
+    }
+
+    fn commit(&self) -> BoxFuture<'_, Result<(), ExecutionError>> {
+        self.run(|db| db.commit())
+    }
+
+    fn rollback(&self) -> BoxFuture<'_, Result<(), ExecutionError>> {
+        self.run(|db| db.rollback())
+    }
 }
 
 // Mock implementations for testing
 
-use std::sync::{Arc, Mutex};
+
+/// Documents of every collection, by collection name
+type Collections = HashMap<String, Vec<Value>>;
+
+/// A change made to a collection by one `MockDatabase` write
+///
+/// Positions index the collection as it was before the change. Each change
+/// holds what is needed to undo it (see `MockDatabase::undo`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// A document was appended
+    Insert(Value),
+    /// The documents at `positions` were replaced by `after`
+    Update {
+        positions: Vec<usize>,
+        before: Vec<Value>,
+        after: Vec<Value>,
+    },
+    /// The documents at `positions`, in ascending order, were removed
+    Delete { positions: Vec<usize>, docs: Vec<Value> },
+}
 
 /// Mock database provider for testing
 ///
 /// This is a simple in-memory database that supports:
 /// - Collections of JSON documents
-/// - Simple equality filtering
-/// - Sorting, pagination (limit/skip)
-/// - Field projection
-/// - Update with merge semantics
+/// - Filter expressions (see `Filter`) with dot-path field addressing
+/// - Multi-key stable sorting, pagination (limit/skip)
+/// - Field projection, rebuilding nested objects for dot-paths
+/// - Update operators (`$set`, `$inc`, `$push`, ...) applied atomically
+/// - Unique and primary key constraints from collection schemas
+/// - Refusing arrays in fields a collection schema declares scalar
 /// - Delete with audit trail
+/// - Transactions, rolled back by restoring a snapshot taken at `begin`
 #[derive(Clone)]
 pub struct MockDatabase {
     /// Collections stored in memory
     /// Outer HashMap: collection name -> documents
     /// Inner Vec: list of documents in the collection
-    collections: Arc<Mutex<HashMap<String, Vec<Value>>>>,
+    collections: Arc<Mutex<Collections>>,
+    /// Unique constraints per collection, enforced on insert and update
+    constraints: Arc<HashMap<String, Vec<UniqueConstraint>>>,
+    /// Fields declared scalar per collection, which may not hold arrays
+    scalar_fields: Arc<HashMap<String, Vec<String>>>,
     /// ID generator function (defaults to incrementing counter)
     id_generator: Arc<dyn Fn() -> String + Send + Sync>,
+    /// Snapshot of the collections taken when the open transaction began
+    transaction: Arc<TransactionState<Collections>>,
+    /// The transaction this is the provider of, as returned by `begin`
+    owner: Option<Arc<Transaction<Collections>>>,
+    /// Changes made by writes since the last `take_changes`, if recorded
+    journal: Option<Arc<Mutex<Vec<Change>>>>,
 }
 
 impl std::fmt::Debug for MockDatabase {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("MockDatabase")
             .field("collections", &self.collections)
+            .field("constraints", &self.constraints)
             .field("id_generator", &"<function>")
             .finish()
     }
@@ -130,10 +778,32 @@ impl MockDatabase {
 
         Self {
             collections: Arc::new(Mutex::new(HashMap::new())),
+            constraints: Arc::new(HashMap::new()),
+            scalar_fields: Arc::new(HashMap::new()),
             id_generator: Arc::new(id_gen),
+            transaction: Arc::new(TransactionState::new()),
+            owner: None,
+            journal: None,
         }
     }
 
+    /// Start a transaction, returning the database to make its changes through
+    /// (see `DatabaseProvider::begin`)
+    pub(crate) fn begin_transaction(&self) -> Result<MockDatabase, ExecutionError> {
+        let collections = Arc::clone(&self.collections);
+        let transaction = self.transaction.begin(
+            || Ok(self.collections.lock().unwrap().clone()),
+            move |snapshot| {
+                *collections.lock().unwrap() = snapshot;
+                Ok(())
+            },
+        )?;
+        Ok(MockDatabase {
+            owner: Some(Arc::new(transaction)),
+            ..self.clone()
+        })
+    }
+
     /// Add a collection with initial documents
     pub fn with_collection(self, name: &str, documents: Vec<Value>) -> Self {
         let mut collections = self.collections.lock().unwrap();
@@ -142,89 +812,143 @@ impl MockDatabase {
         self
     }
 
-    /// Set a custom ID generator
-    pub fn with_id_generator<F>(mut self, generator: F) -> Self
-    where
-        F: Fn() -> String + Send + Sync + 'static,
-    {
-        self.id_generator = Arc::new(generator);
+    /// The documents of a collection, in storage order
+    pub fn documents(&self, collection: &str) -> Vec<Value> {
+        self.collections.lock().unwrap().get(collection).cloned().unwrap_or_default()
+    }
+
+    /// Record the change made by every write, to be collected with `take_changes`
+    pub fn with_journal(mut self) -> Self {
+        self.journal = Some(Arc::new(Mutex::new(Vec::new())));
         self
     }
 
-    /// Helper: Check if a document matches a simple equality filter
-    fn matches_filter(doc: &Value, filter: &HashMap<String, Value>) -> bool {
-        let obj = match doc.as_object() {
-            Some(o) => o,
-            None => return false,
-        };
+    /// The changes made since the last call, oldest first (see `with_journal`)
+    pub fn take_changes(&self) -> Vec<Change> {
+        self.journal.as_ref().map(|journal| std::mem::take(&mut *journal.lock().unwrap())).unwrap_or_default()
+    }
 
-        // All filter fields must match (implicit AND)
-        for (key, filter_value) in filter {
-            let doc_value = obj.get(key);
-            match (doc_value, filter_value) {
-                (Some(dv), fv) if dv == fv => continue,
-                (None, Value::Null) => continue, // null matches missing field
-                _ => return false,
+    /// Undo changes made to a collection, given oldest first
+    pub fn undo(&self, collection: &str, changes: Vec<Change>) {
+        let mut collections = self.collections.lock().unwrap();
+        let docs = collections.entry(collection.to_string()).or_default();
+        for change in changes.into_iter().rev() {
+            match change {
+                Change::Insert(_) => {
+                    docs.pop();
+                }
+                Change::Update { positions, before, .. } => {
+                    for (position, doc) in positions.into_iter().zip(before) {
+                        docs[position] = doc;
+                    }
+                }
+                Change::Delete { positions, docs: removed } => {
+                    for (position, doc) in positions.into_iter().zip(removed) {
+                        docs.insert(position, doc);
+                    }
+                }
             }
         }
-        true
     }
 
-    /// Helper: Apply field projection (select)
-    fn project_fields(doc: &Value, select: &[String]) -> Value {
-        let obj = match doc.as_object() {
-            Some(o) => o,
-            None => return doc.clone(),
-        };
+    /// Add a change to the journal, if there is one
+    fn journal(&self, change: Change) {
+        if let Some(journal) = &self.journal {
+            journal.lock().unwrap().push(change);
+        }
+    }
 
-        let mut result = serde_json::Map::new();
-        for field in select {
-            if let Some(value) = obj.get(field) {
-                result.insert(field.clone(), value.clone());
-            }
+    /// Enforce the unique constraints and scalar fields declared by a collection schema
+    pub fn with_schema(mut self, collection: &str, schema: &DatabaseSchema) -> Self {
+        Arc::make_mut(&mut self.constraints)
+            .insert(collection.to_string(), schema.unique_constraints(collection));
+        Arc::make_mut(&mut self.scalar_fields).insert(collection.to_string(), schema.scalar_fields());
+        self
+    }
+
+    /// Enforce the unique constraints and scalar fields of every collection schema
+    pub fn with_schemas(self, schemas: &HashMap<String, DatabaseSchema>) -> Self {
+        schemas
+            .iter()
+            .fold(self, |db, (collection, schema)| db.with_schema(collection, schema))
+    }
+
+    /// Unique constraints of a collection
+    fn constraints_for(&self, collection: &str) -> &[UniqueConstraint] {
+        self.constraints.get(collection).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Fields of a collection that may not hold arrays
+    fn scalar_fields_for(&self, collection: &str) -> &[String] {
+        self.scalar_fields.get(collection).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Insert a document into a collection's documents, generating an `_id` if needed
+    fn insert_into(
+        &self,
+        docs: &mut Vec<Value>,
+        collection: &str,
+        document: &HashMap<String, Value>,
+    ) -> Result<Value, ExecutionError> {
+        // Convert HashMap to Value::Object
+        let mut doc_obj = serde_json::Map::new();
+        for (k, v) in document {
+            doc_obj.insert(k.clone(), v.clone());
         }
-        Value::Object(result)
-    }
-
-    /// Helper: Sort documents
-    fn sort_documents(docs: &mut [Value], sort: &HashMap<String, SortOrder>) {
-        // For simplicity, we'll only sort by the first sort field
-        // (supporting multiple sort fields would require more complex logic)
-        if let Some((field, order)) = sort.iter().next() {
-            docs.sort_by(|a, b| {
-                let a_val = a.get(field);
-                let b_val = b.get(field);
-
-                let cmp = match (a_val, b_val) {
-                    (Some(Value::Number(a)), Some(Value::Number(b))) => {
-                        // Compare numbers
-                        if let (Some(a_f), Some(b_f)) = (a.as_f64(), b.as_f64()) {
-                            a_f.partial_cmp(&b_f).unwrap_or(std::cmp::Ordering::Equal)
-                        } else {
-                            std::cmp::Ordering::Equal
-                        }
-                    }
-                    (Some(Value::String(a)), Some(Value::String(b))) => a.cmp(b),
-                    (Some(_), None) => std::cmp::Ordering::Greater,
-                    (None, Some(_)) => std::cmp::Ordering::Less,
-                    _ => std::cmp::Ordering::Equal,
-                };
 
-                match order {
-                    SortOrder::Ascending => cmp,
-                    SortOrder::Descending => cmp.reverse(),
-                }
-            });
+        // Generate ID if not present
+        if !doc_obj.contains_key("_id") {
+            let id = (self.id_generator)();
+            doc_obj.insert("_id".to_string(), Value::String(id));
         }
+
+        let doc_value = Value::Object(doc_obj);
+        check_scalars(self.scalar_fields_for(collection), collection, &doc_value)?;
+
+        // Add to collection, unless it duplicates a unique key
+        docs.push(doc_value.clone());
+        if let Err(err) = check_unique(self.constraints_for(collection), collection, docs, &[docs.len() - 1]) {
+            docs.pop();
+            return Err(err);
+        }
+
+        self.journal(Change::Insert(doc_value.clone()));
+        Ok(doc_value)
     }
 
-    /// Helper: Merge update fields into document (partial update)
-    fn merge_update(doc: &mut Value, update: &HashMap<String, Value>) {
-        if let Some(obj) = doc.as_object_mut() {
-            for (key, value) in update {
-                obj.insert(key.clone(), value.clone());
-            }
+    /// Apply an update to the document at `index`, unless it breaks a unique constraint
+    ///
+    /// Returns the document before and after the update.
+    fn update_at(
+        &self,
+        docs: &mut [Value],
+        collection: &str,
+        index: usize,
+        update: &Update,
+    ) -> Result<(Value, Value), ExecutionError> {
+        let mut after = docs[index].clone();
+        update.apply(&mut after)?;
+        check_scalars(self.scalar_fields_for(collection), collection, &after)?;
+        let before = std::mem::replace(&mut docs[index], after.clone());
+        if let Err(err) = check_unique(self.constraints_for(collection), collection, docs, &[index]) {
+            docs[index] = before;
+            return Err(err);
         }
+        self.journal(Change::Update {
+            positions: vec![index],
+            before: vec![before.clone()],
+            after: vec![after.clone()],
+        });
+        Ok((before, after))
+    }
+
+    /// Set a custom ID generator
+    pub fn with_id_generator<F>(mut self, generator: F) -> Self
+    where
+        F: Fn() -> String + Send + Sync + 'static,
+    {
+        self.id_generator = Arc::new(generator);
+        self
     }
 }
 
@@ -232,25 +956,19 @@ impl DatabaseProvider for MockDatabase {
     fn query(
         &self,
         collection: &str,
-        filter: Option<&HashMap<String, Value>>,
+        filter: Option<&Filter>,
         select: Option<&[String]>,
         limit: Option<u32>,
         skip: Option<u32>,
-        sort: Option<&HashMap<String, SortOrder>>,
+        sort: Option<&SortSpec>,
     ) -> Result<Vec<Value>, ExecutionError> {
-        let collections = self.collections.lock().unwrap();
-
-        // Get the collection (return empty array if not found)
-        let docs = match collections.get(collection) {
-            Some(d) => d.clone(),
-            None => return Ok(vec![]),
-        };
-        drop(collections);
+        // Get the collection (empty if not found)
+        let docs = self.transaction.run(self.owner.as_deref(), |_| Ok(self.documents(collection)))?;
 
         // Apply filter
         let mut filtered: Vec<Value> = if let Some(f) = filter {
             docs.into_iter()
-                .filter(|doc| Self::matches_filter(doc, f))
+                .filter(|doc| f.matches(doc))
                 .collect()
         } else {
             docs
@@ -258,7 +976,7 @@ impl DatabaseProvider for MockDatabase {
 
         // Apply sorting
         if let Some(s) = sort {
-            Self::sort_documents(&mut filtered, s);
+            s.apply(&mut filtered);
         }
 
         // Apply skip
@@ -276,98 +994,180 @@ impl DatabaseProvider for MockDatabase {
         if let Some(fields) = select {
             filtered = filtered
                 .into_iter()
-                .map(|doc| Self::project_fields(&doc, fields))
+                .map(|doc| path::project(&doc, fields))
                 .collect();
         }
 
         Ok(filtered)
     }
 
+    fn count(&self, collection: &str, filter: Option<&Filter>) -> Result<u64, ExecutionError> {
+        self.transaction.run(self.owner.as_deref(), |_| {
+            let collections = self.collections.lock().unwrap();
+            let docs = collections.get(collection).map_or(&[][..], Vec::as_slice);
+            Ok(docs.iter().filter(|doc| filter.is_none_or(|f| f.matches(doc))).count() as u64)
+        })
+    }
+
     fn insert(
         &self,
         collection: &str,
         document: &HashMap<String, Value>,
     ) -> Result<Value, ExecutionError> {
-        let mut collections = self.collections.lock().unwrap();
-
-        // Convert HashMap to Value::Object
-        let mut doc_obj = serde_json::Map::new();
-        for (k, v) in document {
-            doc_obj.insert(k.clone(), v.clone());
-        }
-
-        // Generate ID if not present
-        if !doc_obj.contains_key("_id") {
-            let id = (self.id_generator)();
-            doc_obj.insert("_id".to_string(), Value::String(id));
-        }
-
-        let doc_value = Value::Object(doc_obj);
-
-        // Add to collection (create if doesn't exist)
-        collections
-            .entry(collection.to_string())
-            .or_insert_with(Vec::new)
-            .push(doc_value.clone());
-
-        Ok(doc_value)
+        self.transaction.run(self.owner.as_deref(), |_| {
+            let mut collections = self.collections.lock().unwrap();
+            let docs = collections.entry(collection.to_string()).or_default();
+            self.insert_into(docs, collection, document)
+        })
     }
 
     fn update(
         &self,
         collection: &str,
-        filter: &HashMap<String, Value>,
-        update: &HashMap<String, Value>,
+        filter: &Filter,
+        update: &Update,
     ) -> Result<Vec<Value>, ExecutionError> {
-        let mut collections = self.collections.lock().unwrap();
+        self.transaction.run(self.owner.as_deref(), |_| {
+            let mut collections = self.collections.lock().unwrap();
+
+            // Get the collection (return empty array if not found)
+            let docs = match collections.get_mut(collection) {
+                Some(d) => d,
+                None => return Ok(vec![]),
+            };
+
+            // Apply the update to copies first so a failure leaves every document unchanged
+            let mut updates = vec![];
+            for (index, doc) in docs.iter().enumerate() {
+                if filter.matches(doc) {
+                    let mut updated = doc.clone();
+                    update.apply(&mut updated)?;
+                    check_scalars(self.scalar_fields_for(collection), collection, &updated)?;
+                    updates.push((index, updated));
+                }
+            }
 
-        // Get the collection (return empty array if not found)
-        let docs = match collections.get_mut(collection) {
-            Some(d) => d,
-            None => return Ok(vec![]),
-        };
+            if updates.is_empty() {
+                return Ok(vec![]);
+            }
 
-        let mut updated_docs = vec![];
+            // Write the updates, putting the old documents back if they break a unique constraint
+            let (positions, after): (Vec<usize>, Vec<Value>) = updates.into_iter().unzip();
+            let before: Vec<Value> = positions
+                .iter()
+                .zip(&after)
+                .map(|(&index, updated)| std::mem::replace(&mut docs[index], updated.clone()))
+                .collect();
+            if let Err(err) = check_unique(self.constraints_for(collection), collection, docs, &positions) {
+                for (&index, doc) in positions.iter().zip(before) {
+                    docs[index] = doc;
+                }
+                return Err(err);
+            }
+
+            self.journal(Change::Update {
+                positions,
+                before,
+                after: after.clone(),
+            });
+            Ok(after)
+        })
+    }
 
-        // Find and update matching documents
-        for doc in docs.iter_mut() {
-            if Self::matches_filter(doc, filter) {
-                Self::merge_update(doc, update);
-                updated_docs.push(doc.clone());
+    fn upsert(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+        insert: &HashMap<String, Value>,
+    ) -> Result<(Option<Value>, Value), ExecutionError> {
+        self.transaction.run(self.owner.as_deref(), |_| {
+            // Hold the lock from the match to the write
+            let mut collections = self.collections.lock().unwrap();
+            let docs = collections.entry(collection.to_string()).or_default();
+            match docs.iter().position(|doc| filter.matches(doc)) {
+                Some(index) => {
+                    let (before, after) = self.update_at(docs, collection, index, update)?;
+                    Ok((Some(before), after))
+                }
+                None => Ok((None, self.insert_into(docs, collection, insert)?)),
             }
-        }
+        })
+    }
 
-        Ok(updated_docs)
+    fn find_one_and_update(
+        &self,
+        collection: &str,
+        filter: &Filter,
+        update: &Update,
+        sort: Option<&SortSpec>,
+    ) -> Result<Option<(Value, Value)>, ExecutionError> {
+        self.transaction.run(self.owner.as_deref(), |_| {
+            let mut collections = self.collections.lock().unwrap();
+            let Some(docs) = collections.get_mut(collection) else {
+                return Ok(None);
+            };
+
+            // The first match in sort order, keeping collection order among equals
+            let index = (0..docs.len())
+                .filter(|&index| filter.matches(&docs[index]))
+                .min_by(|&a, &b| sort.map_or(Ordering::Equal, |sort| sort.compare(&docs[a], &docs[b])));
+            index
+                .map(|index| self.update_at(docs, collection, index, update))
+                .transpose()
+        })
     }
 
     fn delete(
         &self,
         collection: &str,
-        filter: &HashMap<String, Value>,
+        filter: &Filter,
     ) -> Result<Vec<Value>, ExecutionError> {
-        let mut collections = self.collections.lock().unwrap();
-
-        // Get the collection (return empty array if not found)
-        let docs = match collections.get_mut(collection) {
-            Some(d) => d,
-            None => return Ok(vec![]),
-        };
+        self.transaction.run(self.owner.as_deref(), |_| {
+            let mut collections = self.collections.lock().unwrap();
+
+            // Get the collection (return empty array if not found)
+            let docs = match collections.get_mut(collection) {
+                Some(d) => d,
+                None => return Ok(vec![]),
+            };
+
+            let mut deleted_docs = vec![];
+            let mut positions = vec![];
+            let mut i = 0;
+
+            // Remove matching documents and collect them
+            while i < docs.len() {
+                if filter.matches(&docs[i]) {
+                    let deleted = docs.remove(i);
+                    positions.push(i + deleted_docs.len());
+                    deleted_docs.push(deleted);
+                    // Don't increment i, as we removed an element
+                } else {
+                    i += 1;
+                }
+            }
 
-        let mut deleted_docs = vec![];
-        let mut i = 0;
-
-        // Remove matching documents and collect them
-        while i < docs.len() {
-            if Self::matches_filter(&docs[i], filter) {
-                let deleted = docs.remove(i);
-                deleted_docs.push(deleted);
-                // Don't increment i, as we removed an element
-            } else {
-                i += 1;
+            if !deleted_docs.is_empty() {
+                self.journal(Change::Delete {
+                    positions,
+                    docs: deleted_docs.clone(),
+                });
             }
-        }
+            Ok(deleted_docs)
+        })
+    }
+
+    fn begin(&self) -> Result<Box<dyn DatabaseProvider>, ExecutionError> {
+        Ok(Box::new(self.begin_transaction()?))
+    }
+
+    fn commit(&self) -> Result<(), ExecutionError> {
+        self.transaction.commit(self.owner.as_deref(), |_snapshot| Ok(()))
+    }
 
-        Ok(deleted_docs)
+    fn rollback(&self) -> Result<(), ExecutionError> {
+        self.transaction.rollback(self.owner.as_deref())
     }
 }
 
@@ -1,12 +1,14 @@
-/// deck - A declarative web server framework
-///
-/// This library provides types and runtime for building web servers
-/// through declarative JSON configuration files instead of imperative code.
+//! deck - A declarative web server framework
+//!
+//! This library provides types and runtime for building web servers
+//! through declarative JSON configuration files instead of imperative code.
 
 pub mod config;
+pub mod database;
 pub mod executor;
 pub mod operators;
 pub mod pipeline;
+pub mod server;
 
 // Re-export commonly used types
 pub use config::{DeckConfig, Route};
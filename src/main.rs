@@ -1,3 +1,53 @@
-fn main() {
-    println!("Hello, world!");
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use deck::DeckConfig;
+
+/// deck - A declarative web server
+#[derive(Debug, Parser)]
+#[command(name = "deck", version, about)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Start an HTTP server from a configuration file
+    Serve {
+        /// Path to the JSON configuration file
+        #[arg(short, long)]
+        config: PathBuf,
+
+        /// Port to listen on
+        #[arg(short, long, default_value_t = 8080)]
+        port: u16,
+
+        /// Address to bind to
+        #[arg(long, default_value = "127.0.0.1")]
+        host: IpAddr,
+    },
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+
+    match cli.command {
+        Command::Serve { config, port, host } => {
+            let config = DeckConfig::from_file(&config).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            });
+
+            let addr = SocketAddr::new(host, port);
+            println!("deck listening on http://{}", addr);
+
+            if let Err(e) = deck::server::serve(config, addr).await {
+                eprintln!("Server error: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
 }
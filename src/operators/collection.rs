@@ -4,6 +4,10 @@ use super::OperatorValue;
 
 /// $map operator - Transform each item in a collection
 ///
+/// Arrays are iterated element by element; objects are iterated as
+/// `{"key": ..., "value": ...}` entries. The current position is available
+/// as "index". Bindings live in a child context and do not leak out.
+///
 /// Example:
 /// ```json
 /// {
@@ -26,10 +30,15 @@ pub struct MapOp {
     /// Operation to perform on each item
     /// Within this operation, the current item is available as "item"
     pub r#do: OperatorValue,
+    /// Variable name for the current item (defaults to "item")
+    #[serde(default, rename = "as", skip_serializing_if = "Option::is_none")]
+    pub r#as: Option<String>,
 }
 
 /// $filter operator - Filter items in a collection
 ///
+/// Supports the same iteration and bindings as `$map`.
+///
 /// Example:
 /// ```json
 /// {
@@ -47,10 +56,15 @@ pub struct FilterOp {
     /// Condition that must be true for items to be included
     /// Within this condition, the current item is available as "item"
     pub r#where: OperatorValue,
+    /// Variable name for the current item (defaults to "item")
+    #[serde(default, rename = "as", skip_serializing_if = "Option::is_none")]
+    pub r#as: Option<String>,
 }
 
 /// $reduce operator - Aggregate/fold a collection
 ///
+/// Supports the same iteration and bindings as `$map`, plus "accumulator".
+///
 /// Example:
 /// ```json
 /// {
@@ -71,4 +85,7 @@ pub struct ReduceOp {
     pub with: OperatorValue,
     /// Initial value for the accumulator
     pub initial: serde_json::Value,
+    /// Variable name for the current item (defaults to "item")
+    #[serde(default, rename = "as", skip_serializing_if = "Option::is_none")]
+    pub r#as: Option<String>,
 }
@@ -28,6 +28,9 @@ pub struct IfOp {
 
 /// $switch operator - Multi-way branching (SQL CASE-like)
 ///
+/// With `on`, each case's `when` is compared to the `on` value for equality.
+/// Without `on`, each case's `when` is a condition and the first truthy case wins.
+///
 /// Example:
 /// ```json
 /// {
@@ -41,11 +44,25 @@ pub struct IfOp {
 ///   }
 /// }
 /// ```
+///
+/// Predicate form:
+/// ```json
+/// {
+///   "$switch": {
+///     "cases": [
+///       {"when": {"$gte": {"left": {"$get": "score"}, "right": 90}}, "then": "A"},
+///       {"when": {"$gte": {"left": {"$get": "score"}, "right": 80}}, "then": "B"}
+///     ],
+///     "default": "C"
+///   }
+/// }
+/// ```
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SwitchOp {
-    /// Value to switch on
-    pub on: OperatorValue,
+    /// Value to switch on (omit to use predicate cases)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on: Option<OperatorValue>,
     /// Case branches
     pub cases: Vec<SwitchCase>,
     /// Default value if no cases match
@@ -57,8 +74,8 @@ pub struct SwitchOp {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SwitchCase {
-    /// Value to match against
-    pub when: serde_json::Value,
+    /// Value to match against `on`, or a condition when `on` is omitted
+    pub when: OperatorValue,
     /// Value to return if matched
     pub then: OperatorValue,
 }
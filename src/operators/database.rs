@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 use super::OperatorValue;
+use crate::database::{Aggregation, Filter, SortSpec, Update};
 
 /// $dbQuery operator - Query documents from a collection
 ///
@@ -10,21 +11,31 @@ use super::OperatorValue;
 /// {
 ///   "$dbQuery": {
 ///     "collection": "posts",
-///     "filter": {"id": {"$get": "params.id"}},
+///     "filter": {"authorId": {"$get": "params.id"}, "views": {"$gte": 100}},
 ///     "select": ["title", "body", "authorId"],
+///     "sort": [{"createdAt": "desc"}, {"_id": "asc"}],
 ///     "limit": 10
 ///   }
 /// }
 /// ```
+///
+/// Setting `after` or `before` (usually to a cursor from the query string)
+/// switches to keyset pagination: the result is
+/// `{"items": [...], "nextCursor": ..., "prevCursor": ...}`, and passing
+/// `nextCursor` as `after` (or `prevCursor` as `before`) fetches the
+/// neighbouring page. Cursors are opaque strings encoding the sort key
+/// values of a document; `_id` is added as the last sort key so that every
+/// position is unique. A `null` or empty cursor starts at the first page.
+/// `skip` cannot be combined with cursors.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct DbQueryOp {
     /// Collection name
     pub collection: String,
-    /// Filter criteria (MongoDB-like query)
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub filter: Option<HashMap<String, OperatorValue>>,
-    /// Fields to select (projection)
+    /// Filter criteria (MongoDB-like query, see `Filter`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter<OperatorValue>>,
+    /// Fields to select (projection); dot-paths such as `meta.views` keep their nesting
     #[serde(skip_serializing_if = "Option::is_none")]
     pub select: Option<Vec<String>>,
     /// Maximum number of results
@@ -33,18 +44,40 @@ pub struct DbQueryOp {
     /// Number of results to skip
     #[serde(skip_serializing_if = "Option::is_none")]
     pub skip: Option<u32>,
-    /// Sort order
+    /// Sort keys in priority order (see `SortSpec`; keys may be dot-paths)
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub sort: Option<HashMap<String, SortOrder>>,
+    pub sort: Option<SortSpec>,
+    /// Return `{items, total, limit, skip}` instead of a bare array, where
+    /// `total` counts every document matching the filter
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub with_total: bool,
+    /// Cursor of the page before the one to return; see below
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<OperatorValue>,
+    /// Cursor of the page after the one to return; see below
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<OperatorValue>,
 }
 
-/// Sort order for database queries
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub enum SortOrder {
-    #[serde(rename = "asc")]
-    Ascending,
-    #[serde(rename = "desc")]
-    Descending,
+/// $dbCount operator - Count the documents matching a filter
+///
+/// Example:
+/// ```json
+/// {
+///   "$dbCount": {
+///     "collection": "posts",
+///     "filter": {"authorId": {"$get": "params.id"}, "status": "published"}
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DbCountOp {
+    /// Collection name
+    pub collection: String,
+    /// Filter criteria (as for `$dbQuery`; omitted counts every document)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filter: Option<Filter<OperatorValue>>,
 }
 
 /// $dbInsert operator - Insert a document into a collection
@@ -70,7 +103,7 @@ pub struct DbInsertOp {
     pub collection: String,
     /// Document to insert
     pub document: HashMap<String, OperatorValue>,
-    /// Whether to validate against schema
+    /// Whether to fill defaults and validate against the collection's `DatabaseSchema`
     #[serde(default)]
     pub validate: bool,
 }
@@ -85,7 +118,9 @@ pub struct DbInsertOp {
 ///     "filter": {"id": {"$get": "params.id"}},
 ///     "update": {
 ///       "title": {"$get": "body.title"},
-///       "updatedAt": {"$now": null}
+///       "updatedAt": {"$now": null},
+///       "$inc": {"meta.revisions": 1},
+///       "$push": {"history": {"$get": "user.id"}}
 ///     }
 ///   }
 /// }
@@ -96,14 +131,99 @@ pub struct DbUpdateOp {
     /// Collection name
     pub collection: String,
     /// Filter criteria for documents to update
-    pub filter: HashMap<String, OperatorValue>,
-    /// Fields to update
-    pub update: HashMap<String, OperatorValue>,
-    /// Whether to validate against schema
+    pub filter: Filter<OperatorValue>,
+    /// Fields to set and update operators to apply (see `Update`)
+    pub update: Update<OperatorValue>,
+    /// Whether to check the written values against the collection's `DatabaseSchema`
+    #[serde(default)]
+    pub validate: bool,
+}
+
+/// $dbUpsert operator - Update a document by key, or insert it if missing
+///
+/// Sets the `document` fields on the first document matching `filter`. If
+/// none matches, inserts a document built from the filter's equality
+/// conditions, then `defaults`, then `document` (later values win), so
+/// `defaults` only apply to new documents. The match and the write happen
+/// as one atomic operation. Returns the document written.
+///
+/// Example:
+/// ```json
+/// {
+///   "$dbUpsert": {
+///     "collection": "webhookEvents",
+///     "filter": {"eventId": {"$get": "body.id"}},
+///     "document": {"status": {"$get": "body.status"}, "updatedAt": {"$now": null}},
+///     "defaults": {"receivedAt": {"$now": null}, "attempts": 0}
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DbUpsertOp {
+    /// Collection name
+    pub collection: String,
+    /// Filter criteria for the document to update
+    pub filter: Filter<OperatorValue>,
+    /// Fields to set on the matched or inserted document
+    pub document: HashMap<String, OperatorValue>,
+    /// Fields to set only when inserting
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub defaults: HashMap<String, OperatorValue>,
+    /// Whether to fill defaults and validate against the collection's `DatabaseSchema`
+    #[serde(default)]
+    pub validate: bool,
+}
+
+/// $dbFindOneAndUpdate operator - Update one document and return it
+///
+/// Applies `update` to the first document matching `filter` (in `sort`
+/// order, if given) as one atomic operation. Returns the document as it was
+/// before the update or, by default, after it; `null` if nothing matched.
+///
+/// Example:
+/// ```json
+/// {
+///   "$dbFindOneAndUpdate": {
+///     "collection": "jobs",
+///     "filter": {"status": "queued"},
+///     "sort": [{"createdAt": "asc"}],
+///     "update": {"status": "running", "$inc": {"attempts": 1}},
+///     "returnDocument": "after"
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DbFindOneAndUpdateOp {
+    /// Collection name
+    pub collection: String,
+    /// Filter criteria for the document to update
+    pub filter: Filter<OperatorValue>,
+    /// Fields to set and update operators to apply (see `Update`)
+    pub update: Update<OperatorValue>,
+    /// Order in which to pick the first match (see `SortSpec`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<SortSpec>,
+    /// Which image of the document to return
+    #[serde(default)]
+    pub return_document: ReturnDocument,
+    /// Whether to check the written values against the collection's `DatabaseSchema`
     #[serde(default)]
     pub validate: bool,
 }
 
+/// The document image returned by `$dbFindOneAndUpdate`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ReturnDocument {
+    /// The document as it was before the update
+    Before,
+    /// The document as written
+    #[default]
+    After,
+}
+
 /// $dbDelete operator - Delete documents from a collection
 ///
 /// Example:
@@ -121,5 +241,94 @@ pub struct DbDeleteOp {
     /// Collection name
     pub collection: String,
     /// Filter criteria for documents to delete
-    pub filter: HashMap<String, OperatorValue>,
+    pub filter: Filter<OperatorValue>,
+}
+
+/// $dbAggregate operator - Summarize the documents of a collection
+///
+/// Runs the stages of `pipeline` in order: `$match` keeps matching documents,
+/// `$group` computes `$count`/`$sum`/`$avg`/`$min`/`$max` aggregates per
+/// group, `$sort` orders and `$limit` truncates (see `Aggregation`).
+///
+/// Example:
+/// ```json
+/// {
+///   "$dbAggregate": {
+///     "collection": "posts",
+///     "pipeline": [
+///       {"$match": {"status": "published", "createdAt": {"$gte": {"$get": "query.since"}}}},
+///       {"$group": {
+///         "by": "authorId",
+///         "aggregates": {"count": {"$count": null}, "avgViews": {"$avg": "views"}}
+///       }},
+///       {"$sort": {"count": "desc"}},
+///       {"$limit": 10}
+///     ]
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DbAggregateOp {
+    /// Collection name
+    pub collection: String,
+    /// Stages to run, in order
+    pub pipeline: Aggregation<OperatorValue>,
+}
+
+/// $transaction operator - Run database operations as one unit
+///
+/// The operations are evaluated in order, and each can read the results of
+/// the earlier ones as `results`. If any of them fails, including with a
+/// `$return`, every change they made is rolled back and the error is passed
+/// on. Otherwise the changes are committed and the results are returned as
+/// an array. A `$transaction` nested in another joins the outer one.
+///
+/// Example:
+/// ```json
+/// {
+///   "$transaction": {
+///     "operations": [
+///       {
+///         "$dbUpdate": {
+///           "collection": "accounts",
+///           "filter": {"_id": "acc-1", "balance": {"$gte": 100}},
+///           "update": {"$inc": {"balance": -100}}
+///         }
+///       },
+///       {
+///         "$if": {
+///           "condition": {"$get": "results.0"},
+///           "then": {
+///             "$dbUpdate": {
+///               "collection": "accounts",
+///               "filter": {"_id": "acc-2"},
+///               "update": {"$inc": {"balance": 100}}
+///             }
+///           },
+///           "else": {"$return": {"status": 409, "body": {"error": "Insufficient funds"}}}
+///         }
+///       }
+///     ],
+///     "onError": "rollback"
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionOp {
+    /// Operations to run as one unit
+    pub operations: Vec<OperatorValue>,
+    /// What to do when an operation fails
+    #[serde(default)]
+    pub on_error: OnError,
+}
+
+/// Error handling for `$transaction`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OnError {
+    /// Undo every change made by the transaction
+    #[default]
+    Rollback,
 }
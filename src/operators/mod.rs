@@ -1,7 +1,7 @@
-/// Operators for the declarative DSL
-///
-/// This module contains all operator types that can be used in
-/// pipeline configurations. All operators use a `$` prefix.
+//! Operators for the declarative DSL
+//!
+//! This module contains all operator types that can be used in
+//! pipeline configurations. All operators use a `$` prefix.
 
 mod conditional;
 mod data;
@@ -11,7 +11,11 @@ mod utility;
 
 pub use conditional::{IfOp, SwitchCase, SwitchOp};
 pub use data::{GetOp, JsonPathOp};
-pub use database::{DbDeleteOp, DbInsertOp, DbQueryOp, DbUpdateOp, SortOrder};
+pub use database::{
+    DbAggregateOp, DbCountOp, DbDeleteOp, DbFindOneAndUpdateOp, DbInsertOp, DbQueryOp, DbUpdateOp, DbUpsertOp,
+    OnError, ReturnDocument, TransactionOp,
+};
+pub use crate::database::SortOrder;
 pub use collection::{FilterOp, MapOp, ReduceOp};
 pub use utility::{ExistsOp, MergeOp, NowOp, RenderStringOp, ReturnOp, ValidateOp};
 
@@ -30,6 +34,13 @@ pub enum OperatorValue {
     Literal(serde_json::Value),
 }
 
+impl Default for OperatorValue {
+    /// The default value is a literal `null`
+    fn default() -> Self {
+        OperatorValue::Literal(serde_json::Value::Null)
+    }
+}
+
 /// Operator enum representing all possible operators
 ///
 /// Each operator is prefixed with `$` in JSON. Uses external tagging
@@ -59,12 +70,22 @@ pub enum Operator {
     // Database operations
     #[serde(rename = "$dbQuery")]
     DbQuery(DbQueryOp),
+    #[serde(rename = "$dbCount")]
+    DbCount(DbCountOp),
     #[serde(rename = "$dbInsert")]
     DbInsert(DbInsertOp),
     #[serde(rename = "$dbUpdate")]
     DbUpdate(DbUpdateOp),
+    #[serde(rename = "$dbUpsert")]
+    DbUpsert(DbUpsertOp),
+    #[serde(rename = "$dbFindOneAndUpdate")]
+    DbFindOneAndUpdate(DbFindOneAndUpdateOp),
     #[serde(rename = "$dbDelete")]
     DbDelete(DbDeleteOp),
+    #[serde(rename = "$dbAggregate")]
+    DbAggregate(DbAggregateOp),
+    #[serde(rename = "$transaction")]
+    Transaction(TransactionOp),
 
     // Utility operators
     #[serde(rename = "$merge")]
@@ -111,4 +132,25 @@ pub enum Operator {
     Multiply { operands: Vec<OperatorValue> },
     #[serde(rename = "$divide")]
     Divide { left: OperatorValue, right: OperatorValue },
+    #[serde(rename = "$mod")]
+    Mod { left: OperatorValue, right: OperatorValue },
+    #[serde(rename = "$pow")]
+    Pow { base: OperatorValue, exponent: OperatorValue },
+    #[serde(rename = "$abs")]
+    Abs { value: OperatorValue },
+    #[serde(rename = "$round")]
+    Round {
+        value: OperatorValue,
+        /// Number of decimal places to keep (defaults to 0)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        places: Option<u32>,
+    },
+    #[serde(rename = "$floor")]
+    Floor { value: OperatorValue },
+    #[serde(rename = "$ceil")]
+    Ceil { value: OperatorValue },
+    #[serde(rename = "$min")]
+    Min { operands: Vec<OperatorValue> },
+    #[serde(rename = "$max")]
+    Max { operands: Vec<OperatorValue> },
 }
@@ -15,6 +15,10 @@ pub struct MergeOp {
 
 /// $exists operator - Check if a value exists (is non-null)
 ///
+/// A path that `$get` cannot find does not exist either, so
+/// `{"$exists": {"$get": "headers.authorization"}}` is `false` for a request
+/// without that header.
+///
 /// Example: `{"$exists": {"$get": "post"}}`
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -25,7 +29,12 @@ pub struct ExistsOp {
 
 /// $renderString operator - Template string rendering
 ///
+/// Placeholders are context paths with optional filters:
+/// `default: <json>`, `upper`, `lower`, `json` and `urlencode`.
+///
 /// Example: `{"$renderString": "Hello {{user.name}}, you have {{user.messageCount}} messages"}`
+///
+/// Example with filters: `{"$renderString": "/search?q={{ query.q | default: \"\" | urlencode }}"}`
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct RenderStringOp {
@@ -48,12 +57,13 @@ pub struct RenderStringOp {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ReturnOp {
-    /// HTTP status code
-    pub status: u16,
+    /// HTTP status code (must evaluate to an integer in 100-599)
+    pub status: OperatorValue,
     /// Response headers
     #[serde(default)]
     pub headers: HashMap<String, OperatorValue>,
-    /// Response body
+    /// Response body (defaults to null)
+    #[serde(default)]
     pub body: OperatorValue,
 }
 
@@ -89,16 +99,10 @@ pub struct ValidateOp {
 /// $now operator - Get current timestamp
 ///
 /// Example: `{"$now": null}`
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct NowOp {
     /// Null value (operator takes no parameters)
     /// This field will deserialize from null in JSON
     #[serde(skip_serializing_if = "Option::is_none")]
     pub value: Option<()>,
 }
-
-impl Default for NowOp {
-    fn default() -> Self {
-        Self { value: None }
-    }
-}
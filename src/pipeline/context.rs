@@ -1,21 +1,28 @@
 use serde_json::Value;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 /// Execution context that stores variables and their values
 ///
 /// The context is immutable - methods that modify it return a new Context.
 /// This makes it easier to reason about state and enables time-travel debugging.
+///
+/// Cloning is cheap: the variables are shared until one is set, and the
+/// bindings of scoped operators like `$map` are layered on top (see `bind`).
 #[derive(Debug, Clone, Default)]
 pub struct Context {
     /// Variable storage
-    variables: HashMap<String, Value>,
+    variables: Arc<HashMap<String, Value>>,
+    /// Scoped bindings that shadow `variables`, innermost last
+    scope: Vec<(String, Value)>,
 }
 
 impl Context {
     /// Create a new empty context
     pub fn new() -> Self {
         Self {
-            variables: HashMap::new(),
+            variables: Arc::new(HashMap::new()),
+            scope: Vec::new(),
         }
     }
 
@@ -31,18 +38,36 @@ impl Context {
     ///     .with_var("count", json!(42));
     /// ```
     pub fn with_var(mut self, name: impl Into<String>, value: Value) -> Self {
-        self.variables.insert(name.into(), value);
+        self.set_var(name, value);
         self
     }
 
     /// Set a variable in the context (mutable version)
+    ///
+    /// The variable is always set underneath the scoped bindings, so a
+    /// binding of the same name (see `bind`) still shadows it.
     pub fn set_var(&mut self, name: impl Into<String>, value: Value) {
-        self.variables.insert(name.into(), value);
+        Arc::make_mut(&mut self.variables).insert(name.into(), value);
+    }
+
+    /// Bind a variable in a child scope, shadowing any variable of that name
+    ///
+    /// Binding a name already bound in the scope replaces its value, so a
+    /// loop can rebind its item on one child context without copying the
+    /// variables underneath.
+    pub fn bind(&mut self, name: &str, value: Value) {
+        match self.scope.iter_mut().rev().find(|(bound, _)| bound == name) {
+            Some((_, bound)) => *bound = value,
+            None => self.scope.push((name.to_string(), value)),
+        }
     }
 
     /// Get a variable by name (top-level only)
     pub fn get(&self, name: &str) -> Option<&Value> {
-        self.variables.get(name)
+        match self.scope.iter().rev().find(|(bound, _)| bound == name) {
+            Some((_, value)) => Some(value),
+            None => self.variables.get(name),
+        }
     }
 
     /// Get a value using a JSON path (e.g., "user.email" or "params.id")
@@ -74,7 +99,7 @@ impl Context {
         }
 
         // Start with the root variable
-        let mut current = self.variables.get(parts[0])?;
+        let mut current = self.get(parts[0])?;
 
         // Traverse the path
         for part in &parts[1..] {
@@ -93,13 +118,23 @@ impl Context {
     }
 
     /// Get all variables as a reference to the internal HashMap
+    ///
+    /// Scoped bindings are not included; see `visible_variables`.
     pub fn variables(&self) -> &HashMap<String, Value> {
         &self.variables
     }
 
+    /// Get all variables, with scoped bindings in place of what they shadow
+    pub fn visible_variables(&self) -> HashMap<&str, &Value> {
+        let mut variables: HashMap<&str, &Value> =
+            self.variables.iter().map(|(name, value)| (name.as_str(), value)).collect();
+        variables.extend(self.scope.iter().map(|(name, value)| (name.as_str(), value)));
+        variables
+    }
+
     /// Check if a variable exists
     pub fn has(&self, name: &str) -> bool {
-        self.variables.contains_key(name)
+        self.get(name).is_some()
     }
 
     /// Check if a path exists
@@ -190,6 +225,44 @@ mod tests {
         assert_eq!(ctx.get_path("count.something"), None);
     }
 
+    #[test]
+    fn test_bind_shadows_without_changing_parent() {
+        let parent = Context::new()
+            .with_var("item", json!("outer"))
+            .with_var("user", json!({"id": "123"}));
+
+        let mut child = parent.clone();
+        child.bind("item", json!({"n": 1}));
+        child.bind("index", json!(0));
+        assert_eq!(child.get_path("item.n"), Some(&json!(1)));
+        assert_eq!(child.get_path("user.id"), Some(&json!("123")));
+
+        // Rebinding replaces the scoped value in place
+        child.bind("item", json!({"n": 2}));
+        assert_eq!(child.get_path("item.n"), Some(&json!(2)));
+        assert_eq!(child.scope.len(), 2);
+        assert_eq!(child.variables().len(), 2);
+        assert_eq!(child.visible_variables().len(), 3);
+        assert_eq!(child.visible_variables()["item"], &json!({"n": 2}));
+
+        // The parent and its variables are untouched, and still shared
+        assert_eq!(parent.get("item"), Some(&json!("outer")));
+        assert!(!parent.has("index"));
+        assert!(Arc::ptr_eq(&parent.variables, &child.variables));
+    }
+
+    #[test]
+    fn test_set_var_under_binding() {
+        let mut ctx = Context::new().with_var("item", json!("outer"));
+        ctx.bind("item", json!("bound"));
+
+        // Setting writes the variable underneath, which the binding shadows
+        ctx.set_var("item", json!("set"));
+        assert_eq!(ctx.get("item"), Some(&json!("bound")));
+        assert_eq!(ctx.variables()["item"], json!("set"));
+        assert_eq!(ctx.visible_variables()["item"], &json!("bound"));
+    }
+
     #[test]
     fn test_has() {
         let ctx = Context::new()
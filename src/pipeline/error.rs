@@ -18,6 +18,8 @@ pub enum ExecutionError {
     /// Database operation failed
     DatabaseError {
         message: String,
+        /// Name of the unique or primary key constraint the operation violated
+        constraint: Option<String>,
     },
 
     /// Validation failed
@@ -40,6 +42,11 @@ pub enum ExecutionError {
     /// Division by zero
     DivisionByZero,
 
+    /// Integer arithmetic overflowed the supported range
+    ArithmeticOverflow {
+        operator: String,
+    },
+
     /// Array index out of bounds
     IndexOutOfBounds {
         index: usize,
@@ -92,6 +99,15 @@ impl ExecutionError {
     pub fn database_error(message: impl Into<String>) -> Self {
         Self::DatabaseError {
             message: message.into(),
+            constraint: None,
+        }
+    }
+
+    /// Create a DatabaseError for a violated unique or primary key constraint
+    pub fn constraint_violation(constraint: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::DatabaseError {
+            message: message.into(),
+            constraint: Some(constraint.into()),
         }
     }
 
@@ -103,6 +119,28 @@ impl ExecutionError {
         }
     }
 
+    /// Create a TemplateError
+    pub fn template_error(message: impl Into<String>) -> Self {
+        Self::TemplateError {
+            message: message.into(),
+        }
+    }
+
+    /// Create an InvalidOperator error
+    pub fn invalid_operator(operator: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::InvalidOperator {
+            operator: operator.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Create an ArithmeticOverflow error
+    pub fn arithmetic_overflow(operator: impl Into<String>) -> Self {
+        Self::ArithmeticOverflow {
+            operator: operator.into(),
+        }
+    }
+
     /// Create a Custom error
     pub fn custom(message: impl Into<String>) -> Self {
         Self::Custom {
@@ -128,7 +166,7 @@ impl fmt::Display for ExecutionError {
                 }
                 Ok(())
             }
-            ExecutionError::DatabaseError { message } => {
+            ExecutionError::DatabaseError { message, .. } => {
                 write!(f, "Database error: {}", message)
             }
             ExecutionError::ValidationError { message, errors } => {
@@ -150,6 +188,9 @@ impl fmt::Display for ExecutionError {
             ExecutionError::DivisionByZero => {
                 write!(f, "Division by zero")
             }
+            ExecutionError::ArithmeticOverflow { operator } => {
+                write!(f, "Arithmetic overflow in {}", operator)
+            }
             ExecutionError::IndexOutOfBounds { index, length } => {
                 write!(f, "Index out of bounds: {} (length: {})", index, length)
             }
@@ -205,9 +246,37 @@ mod tests {
         assert!(display.contains("Field 'name' is required"));
     }
 
+    #[test]
+    fn test_constraint_violation() {
+        let err = ExecutionError::constraint_violation("users_email_key", "Duplicate email");
+        assert_eq!(err.to_string(), "Database error: Duplicate email");
+        assert!(matches!(
+            err,
+            ExecutionError::DatabaseError { constraint: Some(ref name), .. } if name == "users_email_key"
+        ));
+    }
+
+    #[test]
+    fn test_template_error() {
+        let err = ExecutionError::template_error("Unknown filter 'shout'");
+        assert_eq!(err.to_string(), "Template error: Unknown filter 'shout'");
+    }
+
     #[test]
     fn test_division_by_zero() {
         let err = ExecutionError::DivisionByZero;
         assert_eq!(err.to_string(), "Division by zero");
     }
+
+    #[test]
+    fn test_invalid_operator() {
+        let err = ExecutionError::invalid_operator("$switch", "No case matched");
+        assert_eq!(err.to_string(), "Invalid operator '$switch': No case matched");
+    }
+
+    #[test]
+    fn test_arithmetic_overflow() {
+        let err = ExecutionError::arithmetic_overflow("$add");
+        assert_eq!(err.to_string(), "Arithmetic overflow in $add");
+    }
 }
@@ -1,7 +1,7 @@
-/// Pipeline execution types
-///
-/// This module contains types for representing and executing
-/// pipelines of operations.
+//! Pipeline execution types
+//!
+//! This module contains types for representing and executing
+//! pipelines of operations.
 
 mod context;
 mod error;
@@ -0,0 +1,436 @@
+//! HTTP server
+//!
+//! This module turns a `DeckConfig` into an axum router. Every configured
+//! route is registered with its method and answered by running the route's
+//! pipeline through the `Executor`.
+
+mod request;
+
+pub use request::AxumRequestContext;
+
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::Router;
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{HeaderName, HeaderValue, StatusCode, header};
+use axum::response::{IntoResponse, Response as HttpResponse};
+use axum::routing::{MethodFilter, MethodRouter};
+use serde_json::{Value, json};
+
+use crate::config::{DatabaseSchema, DeckConfig, HttpMethod, Middleware, Route};
+use crate::database;
+use crate::executor::{Executor, RouteResponse};
+use crate::executor::traits::{
+    AsyncDatabaseProvider, BlockingDatabase, DatabaseProvider, MockDatabase, RequestContext, SystemTimeProvider, TimeProvider,
+};
+use crate::pipeline::ExecutionError;
+
+/// Dependencies shared by all route handlers
+#[derive(Clone)]
+pub struct AppState {
+    /// Database provider used by database operators
+    pub database: Arc<dyn AsyncDatabaseProvider>,
+    /// Time provider used by $now
+    pub time: Arc<dyn TimeProvider>,
+}
+
+impl AppState {
+    /// Create state with an in-memory database and the system clock
+    pub fn in_memory() -> Self {
+        Self {
+            database: Arc::new(MockDatabase::new()),
+            time: Arc::new(SystemTimeProvider),
+        }
+    }
+}
+
+/// Build an axum router with one handler per configured route
+pub fn build_router(config: DeckConfig, state: AppState) -> Router {
+    // Group routes by path so that several methods can share a path
+    let mut by_path: BTreeMap<String, MethodRouter> = BTreeMap::new();
+    let middleware = Arc::new(config.middleware);
+    let schemas = Arc::new(config.schemas);
+    let database_schemas = Arc::new(config.database.map(|database| database.schemas).unwrap_or_default());
+
+    for route in config.routes {
+        let path = to_axum_path(&route.path);
+        let filter = method_filter(route.method);
+        let route = Arc::new(route);
+        let middleware = Arc::clone(&middleware);
+        let schemas = Arc::clone(&schemas);
+        let database_schemas = Arc::clone(&database_schemas);
+        let state = state.clone();
+
+        let handler = move |request: Request| {
+            let route = Arc::clone(&route);
+            let middleware = Arc::clone(&middleware);
+            let schemas = Arc::clone(&schemas);
+            let database_schemas = Arc::clone(&database_schemas);
+            let state = state.clone();
+            async move {
+                handle_request(&state, &route, &middleware, &schemas, &database_schemas, request).await
+            }
+        };
+
+        let method_router = by_path.remove(&path).unwrap_or_default();
+        by_path.insert(path, method_router.on(filter, handler));
+    }
+
+    by_path
+        .into_iter()
+        .fold(Router::new(), |router, (path, method_router)| {
+            router.route(&path, method_router)
+        })
+}
+
+/// Serve a configuration on the given address until the process is stopped
+pub async fn serve(config: DeckConfig, addr: SocketAddr) -> std::io::Result<()> {
+    let database = database::connect(&config.database.clone().unwrap_or_default()).map_err(std::io::Error::other)?;
+    // Providers block on I/O, so keep their calls off the async worker threads
+    let database: Arc<dyn DatabaseProvider> = Arc::from(database);
+    let state = AppState {
+        database: Arc::new(BlockingDatabase::new(database)),
+        time: Arc::new(SystemTimeProvider),
+    };
+    let app = build_router(config, state);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, app).await
+}
+
+/// Answer a single request for a route
+async fn handle_request(
+    state: &AppState,
+    route: &Route,
+    middleware: &HashMap<String, Middleware>,
+    schemas: &HashMap<String, Value>,
+    database_schemas: &HashMap<String, DatabaseSchema>,
+    request: Request,
+) -> HttpResponse {
+    let request = match AxumRequestContext::from_request(&route.path, request).await {
+        Ok(request) => request,
+        Err(err) => return error_response(&err),
+    };
+
+    let executor = Executor::new(&*state.database, &*state.time, &request)
+        .with_schemas(schemas)
+        .with_database_schemas(database_schemas);
+
+    match executor.run_route_async(request.to_context(), route, middleware).await {
+        Ok(response) => into_http_response(response),
+        Err(err) => error_response(&err),
+    }
+}
+
+/// Convert an evaluated response into an axum response
+fn into_http_response(rendered: RouteResponse) -> HttpResponse {
+    let status = StatusCode::from_u16(rendered.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+
+    let mut response = match rendered.body {
+        Value::Null => HttpResponse::new(Body::empty()),
+        body => {
+            let mut response = HttpResponse::new(Body::from(body.to_string()));
+            response.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+            response
+        }
+    };
+    *response.status_mut() = status;
+
+    for (name, value) in rendered.headers {
+        let value = match value {
+            Value::String(s) => s,
+            other => other.to_string(),
+        };
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::try_from(name.as_str()),
+            HeaderValue::try_from(value.as_str()),
+        ) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+
+    response
+}
+
+/// Convert an execution error into a JSON error response
+fn error_response(err: &ExecutionError) -> HttpResponse {
+    let (status, body) = match err {
+        ExecutionError::ValidationError { message, errors } => (
+            StatusCode::BAD_REQUEST,
+            json!({"error": message, "details": errors}),
+        ),
+        ExecutionError::DatabaseError {
+            message,
+            constraint: Some(constraint),
+        } => (
+            StatusCode::CONFLICT,
+            json!({"error": message, "constraint": constraint}),
+        ),
+        _ => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            json!({"error": err.to_string()}),
+        ),
+    };
+    (status, axum::Json(body)).into_response()
+}
+
+/// Convert a `:param` style route path into axum's `{param}` syntax
+fn to_axum_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| {
+            if let Some(name) = segment.strip_prefix(':') {
+                format!("{{{}}}", name)
+            } else if let Some(name) = segment.strip_prefix('*') {
+                format!("{{*{}}}", name)
+            } else {
+                segment.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Map a configured HTTP method onto an axum method filter
+fn method_filter(method: HttpMethod) -> MethodFilter {
+    match method {
+        HttpMethod::Get => MethodFilter::GET,
+        HttpMethod::Post => MethodFilter::POST,
+        HttpMethod::Put => MethodFilter::PUT,
+        HttpMethod::Delete => MethodFilter::DELETE,
+        HttpMethod::Patch => MethodFilter::PATCH,
+        HttpMethod::Head => MethodFilter::HEAD,
+        HttpMethod::Options => MethodFilter::OPTIONS,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::Method;
+    use tower::ServiceExt;
+
+    fn test_state() -> AppState {
+        AppState {
+            database: Arc::new(MockDatabase::new().with_collection(
+                "posts",
+                vec![json!({"_id": "1", "title": "First Post"})],
+            )),
+            time: Arc::new(crate::executor::traits::FixedTimeProvider::new(
+                "2025-01-01T00:00:00Z",
+                1735689600,
+            )),
+        }
+    }
+
+    async fn send(router: Router, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+        let body = match body {
+            Some(b) => Body::from(b.to_string()),
+            None => Body::empty(),
+        };
+        let request = Request::builder().method(method).uri(uri).body(body).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let value = if bytes.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&bytes).unwrap()
+        };
+        (status, value)
+    }
+
+    #[test]
+    fn test_to_axum_path() {
+        assert_eq!(to_axum_path("/api/posts/:id"), "/api/posts/{id}");
+        assert_eq!(to_axum_path("/files/*rest"), "/files/{*rest}");
+        assert_eq!(to_axum_path("/health"), "/health");
+    }
+
+    #[tokio::test]
+    async fn test_serve_route_with_params() {
+        let config = DeckConfig::from_json(r#"{
+            "routes": [{
+                "path": "/api/posts/:id",
+                "method": "GET",
+                "pipeline": [{
+                    "name": "posts",
+                    "value": {"$dbQuery": {"collection": "posts", "filter": {"_id": {"$get": "params.id"}}}}
+                }],
+                "response": {"status": 200, "body": {"$get": "posts"}}
+            }]
+        }"#).unwrap();
+        let router = build_router(config, test_state());
+
+        let (status, body) = send(router, Method::GET, "/api/posts/1", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!([{"_id": "1", "title": "First Post"}]));
+    }
+
+    #[tokio::test]
+    async fn test_serve_methods_share_path() {
+        let config = DeckConfig::from_json(r#"{
+            "routes": [
+                {"path": "/items", "method": "GET", "response": {"status": 200, "body": "list"}},
+                {"path": "/items", "method": "POST", "response": {"status": 201, "body": {"$get": "body"}}}
+            ]
+        }"#).unwrap();
+        let router = build_router(config, test_state());
+
+        let (status, body) = send(router.clone(), Method::GET, "/items", None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, json!("list"));
+
+        let (status, body) = send(router.clone(), Method::POST, "/items", Some(json!({"a": 1}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body, json!({"a": 1}));
+
+        let (status, _) = send(router, Method::DELETE, "/items", None).await;
+        assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn test_serve_invalid_json_body() {
+        let config = DeckConfig::from_json(r#"{
+            "routes": [{"path": "/items", "method": "POST", "response": {"status": 201, "body": null}}]
+        }"#).unwrap();
+        let router = build_router(config, test_state());
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/items")
+            .body(Body::from("{not json"))
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_serve_validate_named_schema() {
+        let config = DeckConfig::from_json(r##"{
+            "schemas": {"createItem": {"type": "object", "required": ["name"]}},
+            "routes": [{
+                "path": "/items",
+                "method": "POST",
+                "pipeline": [{"name": "item", "value": {"$validate": {"data": {"$get": "body"}, "schema": {"$ref": "#/schemas/createItem"}}}}],
+                "response": {"status": 201, "body": {"$get": "item"}}
+            }]
+        }"##).unwrap();
+        let router = build_router(config, test_state());
+
+        let (status, body) = send(router.clone(), Method::POST, "/items", Some(json!({"name": "a"}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body, json!({"name": "a"}));
+
+        let (status, _) = send(router, Method::POST, "/items", Some(json!({}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_serve_db_insert_schema_validation() {
+        let config = DeckConfig::from_json(r#"{
+            "database": {"schemas": {"items": {"fields": {
+                "name": {"type": "string", "required": true},
+                "qty": {"type": "number", "default": 1}
+            }}}},
+            "routes": [{
+                "path": "/items",
+                "method": "POST",
+                "pipeline": [{"name": "item", "value": {"$dbInsert": {
+                    "collection": "items",
+                    "document": {"name": {"$get": "body.name"}},
+                    "validate": true
+                }}}],
+                "response": {"status": 201, "body": {"$get": "item"}}
+            }]
+        }"#).unwrap();
+        let router = build_router(config, test_state());
+
+        let (status, body) = send(router.clone(), Method::POST, "/items", Some(json!({"name": "a"}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(body["qty"], json!(1));
+
+        let (status, body) = send(router, Method::POST, "/items", Some(json!({"name": 5}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["details"], json!(["name: expected string, got number"]));
+    }
+
+    #[tokio::test]
+    async fn test_serve_unique_violation_is_conflict() {
+        let config = DeckConfig::from_json(r#"{
+            "routes": [{
+                "path": "/users",
+                "method": "POST",
+                "pipeline": [{"name": "user", "value": {"$dbInsert": {
+                    "collection": "users",
+                    "document": {"email": {"$get": "body.email"}}
+                }}}],
+                "response": {"status": 201, "body": {"$get": "user"}}
+            }]
+        }"#).unwrap();
+        let schema = serde_json::from_value(json!({"fields": {"email": {"type": "string", "unique": true}}})).unwrap();
+        let state = AppState {
+            database: Arc::new(MockDatabase::new().with_schema("users", &schema)),
+            ..test_state()
+        };
+        let router = build_router(config, state);
+
+        let (status, _) = send(router.clone(), Method::POST, "/users", Some(json!({"email": "a@x"}))).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let (status, body) = send(router, Method::POST, "/users", Some(json!({"email": "a@x"}))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body["constraint"], json!("users_email_key"));
+    }
+
+    #[tokio::test]
+    async fn test_serve_middleware_without_header() {
+        let config = DeckConfig::from_json(r#"{
+            "middleware": {"authenticate": {"pipeline": [{
+                "name": "user",
+                "value": {
+                    "$if": {
+                        "condition": {"$exists": {"$get": "headers.authorization"}},
+                        "then": {"$get": "headers.authorization"},
+                        "else": {"$return": {"status": 401, "body": {"error": "Unauthorized"}}}
+                    }
+                }
+            }]}},
+            "routes": [{
+                "path": "/me",
+                "method": "GET",
+                "middleware": ["authenticate"],
+                "response": {"status": 200, "body": {"$get": "user"}}
+            }]
+        }"#).unwrap();
+        let router = build_router(config, test_state());
+
+        let (status, body) = send(router.clone(), Method::GET, "/me", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body, json!({"error": "Unauthorized"}));
+
+        let request = Request::builder()
+            .uri("/me")
+            .header("Authorization", "Bearer abc")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_serve_execution_error() {
+        let config = DeckConfig::from_json(r#"{
+            "routes": [{"path": "/missing", "method": "GET", "response": {"status": 200, "body": {"$get": "nothing"}}}]
+        }"#).unwrap();
+        let router = build_router(config, test_state());
+
+        let (status, body) = send(router, Method::GET, "/missing", None).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body, json!({"error": "Path not found: nothing"}));
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::HashMap;
+
+use axum::extract::Request;
+use axum::http::request::Parts;
+use percent_encoding::percent_decode_str;
+use serde_json::Value;
+
+use crate::executor::traits::RequestContext;
+use crate::pipeline::ExecutionError;
+
+/// Maximum accepted request body size in bytes
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Request context built from an axum request
+///
+/// - `params` are extracted by matching the request path against the
+///   route's `:param` pattern, with percent-decoding applied
+/// - `query` is decoded as `application/x-www-form-urlencoded`; when a key
+///   repeats, `query()` holds its first value and `query_all()` holds all
+/// - `headers` names are lowercase; repeated headers are joined with `", "`
+/// - `body` is parsed as JSON (an empty body is `None`)
+#[derive(Debug, Clone, Default)]
+pub struct AxumRequestContext {
+    params: HashMap<String, String>,
+    query: HashMap<String, String>,
+    query_all: HashMap<String, Vec<String>>,
+    headers: HashMap<String, String>,
+    body: Option<Value>,
+    method: String,
+    path: String,
+}
+
+impl AxumRequestContext {
+    /// Build a request context from an axum request matched by `route_path`
+    ///
+    /// Returns a `ValidationError` if the body cannot be read or is not valid JSON.
+    pub async fn from_request(route_path: &str, request: Request) -> Result<Self, ExecutionError> {
+        let (parts, body) = request.into_parts();
+        let bytes = axum::body::to_bytes(body, MAX_BODY_BYTES)
+            .await
+            .map_err(|e| {
+                ExecutionError::validation_error("Failed to read request body", vec![e.to_string()])
+            })?;
+        Self::from_parts(route_path, &parts, &bytes)
+    }
+
+    /// Build a request context from request parts and the raw body bytes
+    pub fn from_parts(route_path: &str, parts: &Parts, body: &[u8]) -> Result<Self, ExecutionError> {
+        let path = parts.uri.path().to_string();
+        let params = match_path_params(route_path, &path).unwrap_or_default();
+
+        let mut query: HashMap<String, String> = HashMap::new();
+        let mut query_all: HashMap<String, Vec<String>> = HashMap::new();
+        if let Some(raw_query) = parts.uri.query() {
+            for (key, value) in form_urlencoded::parse(raw_query.as_bytes()) {
+                query
+                    .entry(key.to_string())
+                    .or_insert_with(|| value.to_string());
+                query_all
+                    .entry(key.into_owned())
+                    .or_default()
+                    .push(value.into_owned());
+            }
+        }
+
+        let mut headers: HashMap<String, String> = HashMap::new();
+        for (name, value) in &parts.headers {
+            let value = String::from_utf8_lossy(value.as_bytes()).into_owned();
+            headers
+                .entry(name.as_str().to_ascii_lowercase())
+                .and_modify(|existing| {
+                    existing.push_str(", ");
+                    existing.push_str(&value);
+                })
+                .or_insert(value);
+        }
+
+        let body = if body.is_empty() {
+            None
+        } else {
+            let value = serde_json::from_slice(body).map_err(|e| {
+                ExecutionError::validation_error("Request body is not valid JSON", vec![e.to_string()])
+            })?;
+            Some(value)
+        };
+
+        Ok(Self {
+            params,
+            query,
+            query_all,
+            headers,
+            body,
+            method: parts.method.to_string(),
+            path,
+        })
+    }
+}
+
+impl RequestContext for AxumRequestContext {
+    fn params(&self) -> &HashMap<String, String> {
+        &self.params
+    }
+
+    fn query(&self) -> &HashMap<String, String> {
+        &self.query
+    }
+
+    fn headers(&self) -> &HashMap<String, String> {
+        &self.headers
+    }
+
+    fn body(&self) -> Option<&Value> {
+        self.body.as_ref()
+    }
+
+    fn method(&self) -> &str {
+        &self.method
+    }
+
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn query_all(&self) -> HashMap<String, Vec<String>> {
+        self.query_all.clone()
+    }
+}
+
+/// Match a request path against a `:param` route pattern
+///
+/// Returns the percent-decoded parameters, or `None` if the path does not
+/// match. A trailing `*name` segment captures the rest of the path.
+fn match_path_params(pattern: &str, path: &str) -> Option<HashMap<String, String>> {
+    let decode = |segment: &str| percent_decode_str(segment).decode_utf8_lossy().into_owned();
+
+    let mut params = HashMap::new();
+    let mut path_segments = path.trim_start_matches('/').split('/');
+
+    for pattern_segment in pattern.trim_start_matches('/').split('/') {
+        if let Some(name) = pattern_segment.strip_prefix('*') {
+            let rest: Vec<&str> = path_segments.by_ref().collect();
+            params.insert(name.to_string(), decode(&rest.join("/")));
+            return Some(params);
+        }
+
+        let segment = path_segments.next()?;
+        if let Some(name) = pattern_segment.strip_prefix(':') {
+            params.insert(name.to_string(), decode(segment));
+        } else if pattern_segment != segment {
+            return None;
+        }
+    }
+
+    if path_segments.next().is_some() {
+        return None;
+    }
+    Some(params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parts(method: &str, uri: &str, headers: &[(&str, &str)]) -> Parts {
+        let mut builder = Request::builder().method(method).uri(uri);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(()).unwrap().into_parts().0
+    }
+
+    #[test]
+    fn test_match_path_params() {
+        let params = match_path_params("/api/posts/:id", "/api/posts/42").unwrap();
+        assert_eq!(params.get("id").unwrap(), "42");
+
+        let params = match_path_params("/users/:user/posts/:post", "/users/a%20b/posts/7").unwrap();
+        assert_eq!(params.get("user").unwrap(), "a b");
+        assert_eq!(params.get("post").unwrap(), "7");
+
+        let params = match_path_params("/files/*rest", "/files/a/b/c.txt").unwrap();
+        assert_eq!(params.get("rest").unwrap(), "a/b/c.txt");
+
+        assert!(match_path_params("/api/posts/:id", "/api/users/42").is_none());
+        assert!(match_path_params("/api/posts/:id", "/api/posts/42/extra").is_none());
+    }
+
+    #[test]
+    fn test_query_decoding() {
+        let parts = parts("GET", "/search?q=hello%20world&tag=a&tag=b&name=J+Doe", &[]);
+        let request = AxumRequestContext::from_parts("/search", &parts, b"").unwrap();
+
+        assert_eq!(request.query().get("q").unwrap(), "hello world");
+        assert_eq!(request.query().get("name").unwrap(), "J Doe");
+        assert_eq!(request.query().get("tag").unwrap(), "a");
+        assert_eq!(
+            request.query_all().get("tag").unwrap(),
+            &vec!["a".to_string(), "b".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_headers_lowercase_and_joined() {
+        let parts = parts(
+            "GET",
+            "/",
+            &[("Authorization", "Bearer abc"), ("Accept", "text/html"), ("Accept", "application/json")],
+        );
+        let request = AxumRequestContext::from_parts("/", &parts, b"").unwrap();
+
+        assert_eq!(request.headers().get("authorization").unwrap(), "Bearer abc");
+        assert_eq!(request.headers().get("accept").unwrap(), "text/html, application/json");
+    }
+
+    #[test]
+    fn test_body_parsing() {
+        let parts = parts("POST", "/posts", &[]);
+
+        let request = AxumRequestContext::from_parts("/posts", &parts, br#"{"title": "Hi"}"#).unwrap();
+        assert_eq!(request.body(), Some(&json!({"title": "Hi"})));
+
+        let request = AxumRequestContext::from_parts("/posts", &parts, b"").unwrap();
+        assert_eq!(request.body(), None);
+
+        let result = AxumRequestContext::from_parts("/posts", &parts, b"{not json");
+        assert!(matches!(result, Err(ExecutionError::ValidationError { .. })));
+    }
+
+    #[test]
+    fn test_to_context() {
+        let parts = parts("POST", "/api/posts/7?tag=a&tag=b&page=2", &[("X-Request-Id", "r1")]);
+        let request =
+            AxumRequestContext::from_parts("/api/posts/:id", &parts, br#"{"title": "Hi"}"#).unwrap();
+        let context = request.to_context();
+
+        assert_eq!(context.get_path("params.id"), Some(&json!("7")));
+        assert_eq!(context.get_path("query.page"), Some(&json!("2")));
+        assert_eq!(context.get_path("query.tag"), Some(&json!(["a", "b"])));
+        assert_eq!(context.get_path("headers.x-request-id"), Some(&json!("r1")));
+        assert_eq!(context.get_path("body.title"), Some(&json!("Hi")));
+        assert_eq!(context.get("method"), Some(&json!("POST")));
+        assert_eq!(context.get("path"), Some(&json!("/api/posts/7")));
+    }
+}